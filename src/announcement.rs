@@ -0,0 +1,415 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Datelike, NaiveTime, Timelike, TimeZone, Utc};
+use date_component::date_component::calculate;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use serenity::model::id::GuildId;
+use serenity::prelude::TypeMapKey;
+use serenity::utils::Colour;
+
+use crate::sink::{Embed, EmbedAuthor, EmbedField};
+use crate::utils::data::{DataFileError, load_toml_dir};
+
+/// The templates shipped with the bot, used until the data files are read.
+const BUILTIN: &str = include_str!("../data/announcements/monokuma.toml");
+
+/// What the templates can hold between braces, like `{time}`.
+pub const PLACEHOLDERS: [&str; 5] = ["time", "date", "day_number", "guild", "member_count"];
+
+const WEEKDAYS: [&str; 7] = ["lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche"];
+const MONTHS: [&str; 12] = [
+    "janvier", "février", "mars", "avril", "mai", "juin",
+    "juillet", "août", "septembre", "octobre", "novembre", "décembre",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncementKind {
+    Morning,
+    Evening,
+}
+
+impl AnnouncementKind {
+    pub const ALL: [AnnouncementKind; 2] = [AnnouncementKind::Morning, AnnouncementKind::Evening];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnnouncementKind::Morning => "morning",
+            AnnouncementKind::Evening => "evening",
+        }
+    }
+}
+
+/// Writes a time the way Monokuma says it: "7h", "22h30".
+pub fn french_hour(time: NaiveTime) -> String {
+    match time.minute() {
+        0 => format!("{}h", time.hour()),
+        minute => format!("{}h{:02}", time.hour(), minute),
+    }
+}
+
+/// Writes a date in full: "samedi 1 octobre 2022".
+pub fn french_date<T: Datelike>(date: &T) -> String {
+    format!(
+        "{} {} {} {}",
+        WEEKDAYS[date.weekday().num_days_from_monday() as usize],
+        date.day(),
+        MONTHS[date.month0() as usize],
+        date.year()
+    )
+}
+
+/// The `{name}` placeholders of `text` with where they are, those that are not a word are
+/// left out.
+fn placeholders(text: &str) -> Vec<(usize, usize, &str)> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(start) = text[from..].find('{').map(|start| from + start) {
+        let end = match text[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = &text[start + 1..end];
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            found.push((start, end + 1, name));
+            from = end + 1;
+        } else {
+            from = start + 1;
+        }
+    }
+    found
+}
+
+/// The values of the placeholders for an announcement sent at `time`.
+#[derive(Debug, Clone)]
+pub struct Placeholders {
+    pub time: DateTime<chrono_tz::Tz>,
+    /// The name of the guild and its number of members, when the bot knows them.
+    pub guild: Option<(String, u64)>,
+}
+
+impl Placeholders {
+    fn value(&self, name: &str) -> Option<String> {
+        match name {
+            "time" => Some(french_hour(self.time.time())),
+            "date" => Some(french_date(&self.time)),
+            "day_number" => {
+                let opening = Utc.ymd(2016, 4, 10).and_hms(10, 0, 0);
+                Some(calculate(&opening, &self.time.with_timezone(&Utc)).interval_days.to_string())
+            }
+            "guild" => Some(self.guild.as_ref().map_or("le serveur".to_string(), |(name, _)| name.clone())),
+            "member_count" => Some(self.guild.as_ref().map_or("?".to_string(), |(_, members)| members.to_string())),
+            _ => None,
+        }
+    }
+
+    /// `text` with its placeholders replaced, the unknown ones are kept as they are.
+    pub fn fill(&self, text: &str) -> String {
+        let mut filled = String::with_capacity(text.len());
+        let mut from = 0;
+        for (start, end, name) in placeholders(text) {
+            if let Some(value) = self.value(name) {
+                filled.push_str(&text[from..start]);
+                filled.push_str(&value);
+                from = end;
+            }
+        }
+        filled.push_str(&text[from..]);
+        filled
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateAuthor {
+    pub name: String,
+    pub icon_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub inline: bool,
+}
+
+/// An announcement of the data files, turned into an embed when it is sent.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    pub kind: AnnouncementKind,
+    pub author: Option<TemplateAuthor>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<TemplateField>,
+    pub footer: Option<String>,
+    /// One of them is shown, picked at random.
+    #[serde(default)]
+    pub images: Vec<String>,
+}
+
+impl Template {
+    /// Every text of the template that can hold placeholders.
+    fn texts(&self) -> Vec<&str> {
+        let mut texts: Vec<&str> = [&self.title, &self.description, &self.footer].into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        texts.extend(self.author.iter().map(|author| author.name.as_str()));
+        texts.extend(self.fields.iter().flat_map(|field| [field.name.as_str(), field.value.as_str()]));
+        texts
+    }
+
+    pub fn render<R: Rng>(&self, placeholders: &Placeholders, color: Colour, rng: &mut R) -> Embed {
+        let fill = |text: &Option<String>| text.as_deref().map(|text| placeholders.fill(text));
+        Embed {
+            author: self.author.as_ref().map(|author| EmbedAuthor {
+                name: placeholders.fill(&author.name),
+                icon_url: author.icon_url.clone(),
+            }),
+            title: fill(&self.title),
+            description: fill(&self.description),
+            color: Some(color),
+            fields: self.fields.iter()
+                .map(|field| EmbedField {
+                    name: placeholders.fill(&field.name),
+                    value: placeholders.fill(&field.value),
+                    inline: field.inline,
+                })
+                .collect(),
+            image: self.images.choose(rng).cloned(),
+            footer: fill(&self.footer),
+            ..Default::default()
+        }
+    }
+}
+
+/// Raw content of an announcement file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateFile {
+    #[serde(default)]
+    announcements: Vec<Template>,
+}
+
+/// Parses an announcement file, `path` being only used in the errors.
+pub fn parse_templates(path: &str, content: &str) -> Result<Vec<Template>, DataFileError> {
+    let file: TemplateFile = toml::from_str(content).map_err(|err| DataFileError::Parse(path.to_string(), err))?;
+    for (index, template) in file.announcements.iter().enumerate() {
+        let texts = template.texts();
+        if texts.iter().all(|text| text.trim().is_empty()) {
+            return Err(DataFileError::Invalid(path.to_string(), format!("announcement {} has no text", index + 1)));
+        }
+        let unknown = texts.iter()
+            .flat_map(|text| placeholders(text))
+            .find(|(_, _, name)| !PLACEHOLDERS.contains(name));
+        if let Some((_, _, name)) = unknown {
+            return Err(DataFileError::Invalid(
+                path.to_string(), format!("announcement {} has an unknown placeholder {{{}}}", index + 1, name)
+            ));
+        }
+    }
+    Ok(file.announcements)
+}
+
+/// Reads the `*.toml` files of `directory`, in the order of their names. There has to be a
+/// template of each kind.
+pub fn load_templates(directory: &Path) -> Result<Vec<Template>, DataFileError> {
+    let templates = load_toml_dir(directory, parse_templates)?;
+    if let Some(kind) = AnnouncementKind::ALL.iter().find(|kind| templates.iter().all(|template| template.kind != **kind)) {
+        return Err(DataFileError::Invalid(
+            directory.display().to_string(), format!("there is no {} announcement", kind.name())
+        ));
+    }
+    Ok(templates)
+}
+
+/// The announcement templates read from `directory`, shared by the announcement jobs and
+/// read again on demand. The built-in ones are used until they are read successfully.
+#[derive(Debug, Clone)]
+pub struct AnnouncementTemplates {
+    directory: PathBuf,
+    templates: Arc<RwLock<Arc<Vec<Template>>>>,
+}
+
+impl TypeMapKey for AnnouncementTemplates {
+    type Value = AnnouncementTemplates;
+}
+
+impl AnnouncementTemplates {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        let builtin = parse_templates("the built-in announcements", BUILTIN)
+            .expect("The built-in announcements are invalid");
+        Self { directory: directory.into(), templates: Arc::new(RwLock::new(Arc::new(builtin))) }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Reads the templates from the data files, returns how many there are. The templates in
+    /// use are kept if the files are invalid.
+    pub fn reload(&self) -> Result<usize, DataFileError> {
+        let templates = load_templates(&self.directory)?;
+        let count = templates.len();
+        *self.templates.write().expect("Announcement templates lock poisoned") = Arc::new(templates);
+        Ok(count)
+    }
+
+    fn current(&self) -> Arc<Vec<Template>> {
+        self.templates.read().expect("Announcement templates lock poisoned").clone()
+    }
+
+    /// One of the templates of `kind`, picked at random.
+    pub fn pick<R: Rng>(&self, kind: AnnouncementKind, rng: &mut R) -> Option<Template> {
+        let templates = self.current();
+        let of_kind: Vec<&Template> = templates.iter().filter(|template| template.kind == kind).collect();
+        of_kind.choose(rng).map(|template| (*template).clone())
+    }
+
+    /// The author of the first template that has one, for the other embeds Monokuma sends.
+    /// Its name is shown as it is written.
+    pub fn author(&self) -> Option<EmbedAuthor> {
+        self.current().iter()
+            .find_map(|template| template.author.as_ref())
+            .map(|author| EmbedAuthor { name: author.name.clone(), icon_url: author.icon_url.clone() })
+    }
+
+    /// The images of every template, without duplicates.
+    pub fn images(&self) -> Vec<String> {
+        let mut images: Vec<String> = Vec::new();
+        for image in self.current().iter().flat_map(|template| &template.images) {
+            if !images.contains(image) {
+                images.push(image.clone());
+            }
+        }
+        images
+    }
+
+    /// One of the `images`, picked at random.
+    pub fn image<R: Rng>(&self, rng: &mut R) -> Option<String> {
+        self.images().choose(rng).cloned()
+    }
+}
+
+/// What the announcements need to know about a guild.
+pub trait GuildInfo: Send + Sync {
+    /// The name of the guild and its number of members, `None` if the bot does not know it.
+    fn describe(&self, guild: GuildId) -> Option<(String, u64)>;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveTime, TimeZone};
+    use chrono_tz::Europe::Paris;
+
+    use crate::announcement::{AnnouncementKind, AnnouncementTemplates, french_date, french_hour, load_templates, parse_templates, Placeholders};
+    use crate::mirai_bot::color::MIRAI_BOT_COLOR;
+    use crate::utils::data::DataFileError;
+
+    const TEMPLATES: &str = r#"
+[[announcements]]
+kind = "morning"
+title = "Bienvenue sur {guild}"
+description = "Nous sommes {member_count}, le {date} à {time}. {inconnu sans accolade"
+footer = "Jour {day_number}"
+images = ["a.png"]
+
+[[announcements.fields]]
+name = "{guild}"
+value = "{{time}}"
+inline = true
+"#;
+
+    #[test]
+    fn test_french_hour() {
+        assert_eq!(french_hour(NaiveTime::from_hms(7, 0, 0)), "7h");
+        assert_eq!(french_hour(NaiveTime::from_hms(22, 5, 0)), "22h05");
+    }
+
+    #[test]
+    fn test_french_date() {
+        assert_eq!(french_date(&Paris.ymd(2022, 10, 1)), "samedi 1 octobre 2022");
+        assert_eq!(french_date(&Paris.ymd(2023, 8, 14)), "lundi 14 août 2023");
+    }
+
+    #[test]
+    fn test_render() {
+        let templates = parse_templates("test.toml", TEMPLATES).unwrap();
+        let placeholders = Placeholders {
+            time: Paris.ymd(2022, 10, 1).and_hms(7, 30, 0),
+            guild: Some(("Pic de l'Espoir".to_string(), 16)),
+        };
+
+        let embed = templates[0].render(&placeholders, MIRAI_BOT_COLOR, &mut rand::thread_rng());
+        assert_eq!(embed.title.as_deref(), Some("Bienvenue sur Pic de l'Espoir"));
+        assert_eq!(
+            embed.description.as_deref(),
+            Some("Nous sommes 16, le samedi 1 octobre 2022 à 7h30. {inconnu sans accolade")
+        );
+        assert_eq!(embed.footer.as_deref(), Some("Jour 2364"));
+        assert_eq!(embed.fields[0].name, "Pic de l'Espoir");
+        assert_eq!(embed.fields[0].value, "{7h30}");
+        assert!(embed.fields[0].inline);
+        assert_eq!(embed.image.as_deref(), Some("a.png"));
+        assert_eq!(embed.author, None);
+        assert_eq!(embed.color, Some(MIRAI_BOT_COLOR));
+
+        let placeholders = Placeholders { guild: None, ..placeholders };
+        let embed = templates[0].render(&placeholders, MIRAI_BOT_COLOR, &mut rand::thread_rng());
+        assert_eq!(embed.title.as_deref(), Some("Bienvenue sur le serveur"));
+        assert!(embed.description.unwrap().starts_with("Nous sommes ?,"));
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let unknown = "[[announcements]]\nkind = \"evening\"\ntitle = \"Il est {heure}\"";
+        assert!(matches!(parse_templates("test.toml", unknown), Err(DataFileError::Invalid(_, reason)) if reason.contains("{heure}")));
+        let empty = "[[announcements]]\nkind = \"evening\"";
+        assert!(matches!(parse_templates("test.toml", empty), Err(DataFileError::Invalid(..))));
+        let kind = "[[announcements]]\nkind = \"noon\"\ntitle = \"Midi\"";
+        assert!(matches!(parse_templates("test.toml", kind), Err(DataFileError::Parse(..))));
+    }
+
+    #[test]
+    fn test_builtin_templates() {
+        let templates = AnnouncementTemplates::new("nowhere");
+        for kind in AnnouncementKind::ALL {
+            assert_eq!(templates.pick(kind, &mut rand::thread_rng()).unwrap().kind, kind);
+        }
+        assert!(matches!(templates.reload(), Err(DataFileError::Io(..))));
+        assert!(templates.pick(AnnouncementKind::Morning, &mut rand::thread_rng()).is_some());
+
+        assert_eq!(templates.author().unwrap().name, "Monokuma");
+        let images = templates.images();
+        assert_eq!(images.len(), 7);
+        assert!(images.contains(&templates.image(&mut rand::thread_rng()).unwrap()));
+    }
+
+    #[test]
+    fn test_reload_templates() {
+        let directory = std::env::temp_dir().join(format!("mirai_announcements_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("morning.toml"), TEMPLATES).unwrap();
+
+        let templates = AnnouncementTemplates::new(&directory);
+        assert!(matches!(load_templates(&directory), Err(DataFileError::Invalid(_, reason)) if reason.contains("evening")));
+        assert!(templates.reload().is_err());
+
+        std::fs::write(directory.join("evening.toml"), "[[announcements]]\nkind = \"evening\"\ntitle = \"Bonne nuit\"").unwrap();
+        std::fs::write(directory.join("notes.txt"), "not a template").unwrap();
+        assert_eq!(templates.reload().unwrap(), 2);
+        let evening = templates.pick(AnnouncementKind::Evening, &mut rand::thread_rng()).unwrap();
+        assert_eq!(evening.title.as_deref(), Some("Bonne nuit"));
+        assert_eq!(templates.author(), None);
+        assert_eq!(templates.images(), vec!["a.png".to_string()]);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,11 +1,40 @@
+use std::path::Path;
+use std::sync::Arc;
+
 use serenity::Client;
-use serenity::framework::StandardFramework;
-use serenity::model::id::UserId;
+use serenity::client::ClientBuilder;
+use serenity::http::{Http, HttpBuilder};
+use serenity::model::id::{GuildId, UserId};
 use serenity::prelude::{GatewayIntents, TypeMapKey};
+use serenity::utils::Colour;
 use uuid::Uuid;
 
 use crate::{bot_handler};
-use crate::log::{MiraiLog, MiraiLogger};
+use crate::announcement::AnnouncementTemplates;
+use crate::class_trial::TrialStore;
+use crate::config::{BotConfig, DEFAULT_DATA_DIR};
+use crate::database::Database;
+use crate::debate::{DebateBank, DebateStore};
+use crate::flashcards::FlashcardStore;
+use crate::jobs::{CatchUp, JobStore, Scheduler};
+use crate::log::{self, LogForwarder, LogTarget, MiraiLog, MiraiLogger};
+use crate::mirai_bot::class_trial::ClassTrials;
+use crate::mirai_bot::color::MIRAI_BOT_COLOR;
+use crate::mirai_bot::debate::DebateSessions;
+use crate::mirai_bot::message_handler::command_framework;
+use crate::mirai_bot::monokuma_announcement::MonokumaAnnouncements;
+use crate::mirai_bot::night_mode::NightMode;
+use crate::mirai_bot::night_watch::NightWatch;
+use crate::mirai_bot::quiz::QuizSessions;
+use crate::mirai_bot::slash::SlashScope;
+use crate::night_mode::NightLockStore;
+use crate::night_watch::NightWatchStore;
+use crate::permissions::{AdminStore, BotPermissions};
+use crate::quiz::trivia::{TriviaBank, TriviaStore};
+use crate::settings::GuildSettingsStore;
+use crate::shutdown::Shutdown;
+use crate::sink::SerenitySink;
+use crate::utils::time::{SharedClock, SystemClock};
 
 pub const BOT_TIMEZONE: chrono_tz::Tz = chrono_tz::Europe::Paris;
 
@@ -13,8 +42,20 @@ pub struct DiscordBot {
     pub id: Uuid,
     pub token: String,
     pub prefix: String,
+    pub api_url: Option<String>,
     pub creator: Option<UserId>,
     pub admins: Vec<UserId>,
+    pub guilds: Vec<GuildId>,
+    pub timezone: chrono_tz::Tz,
+    pub color: Colour,
+    pub catch_up: CatchUp,
+    pub log_target: Option<LogTarget>,
+    pub slash_commands: SlashScope,
+    pub settings: GuildSettingsStore,
+    pub database: Database,
+    pub data_dir: String,
+    pub clock: SharedClock,
+    pub shutdown: Shutdown,
     pub client: Option<Client>,
 }
 
@@ -32,12 +73,39 @@ impl DiscordBot {
             id: Uuid::new_v4(),
             token: default_token,
             prefix: default_prefix,
+            api_url: None,
             creator: None,
             admins: Vec::new(),
+            guilds: Vec::new(),
+            timezone: BOT_TIMEZONE,
+            color: MIRAI_BOT_COLOR,
+            catch_up: CatchUp::default(),
+            log_target: None,
+            slash_commands: SlashScope::default(),
+            settings: GuildSettingsStore::in_memory(),
+            database: Database::in_memory().expect("Could not open an in-memory database"),
+            data_dir: DEFAULT_DATA_DIR.to_string(),
+            clock: SystemClock::shared(),
+            shutdown: Shutdown::new(),
             client: None,
         }
     }
 
+    pub fn from_config(config: &BotConfig) -> Self {
+        Self::new()
+            .set_token(config.token.as_str())
+            .set_prefix(config.prefix.as_str())
+            .set_creator(config.creator)
+            .set_admins(config.admins.clone())
+            .set_guilds(config.guilds.clone())
+            .set_timezone(config.timezone)
+            .set_color(config.color)
+            .set_catch_up(config.catch_up)
+            .set_log_target(config.log.discord)
+            .set_slash_commands(config.slash_commands)
+            .set_data_dir(config.data_dir.as_str())
+    }
+
     pub fn set_token(mut self, token: &str) -> Self {
         self.token = token.to_string();
         self
@@ -48,29 +116,255 @@ impl DiscordBot {
         self
     }
 
+    /// Sends the REST requests to `api_url` instead of discord.com, the gateway URL is
+    /// then the one this server returns. Serenity only honours it with its ratelimiter
+    /// disabled, rate limits are then up to that server.
+    pub fn set_api_url(mut self, api_url: &str) -> Self {
+        self.api_url = Some(api_url.to_string());
+        self
+    }
+
+    pub fn set_creator(mut self, creator: Option<UserId>) -> Self {
+        self.creator = creator;
+        self
+    }
+
+    pub fn set_admins(mut self, admins: Vec<UserId>) -> Self {
+        self.admins = admins;
+        self
+    }
+
+    pub fn set_guilds(mut self, guilds: Vec<GuildId>) -> Self {
+        self.guilds = guilds;
+        self
+    }
+
+    pub fn set_timezone(mut self, timezone: chrono_tz::Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    pub fn set_color(mut self, color: Colour) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn set_catch_up(mut self, catch_up: CatchUp) -> Self {
+        self.catch_up = catch_up;
+        self
+    }
+
+    pub fn set_log_target(mut self, log_target: Option<LogTarget>) -> Self {
+        self.log_target = log_target;
+        self
+    }
+
+    pub fn set_slash_commands(mut self, slash_commands: SlashScope) -> Self {
+        self.slash_commands = slash_commands;
+        self
+    }
+
+    pub fn set_settings_store(mut self, settings: GuildSettingsStore) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn set_database(mut self, database: Database) -> Self {
+        self.database = database;
+        self
+    }
+
+    pub fn set_data_dir(mut self, data_dir: &str) -> Self {
+        self.data_dir = data_dir.to_string();
+        self
+    }
+
+    /// Guilds the bot works in, an empty `guilds` list means every guild it is invited to.
+    pub fn targets_guild(&self, guild_id: GuildId) -> bool {
+        self.guilds.is_empty() || self.guilds.contains(&guild_id)
+    }
+
     pub async fn setup_client(&mut self) -> bool {
         MiraiLogger::debug(format!("Starting DiscordBot {}", self.id));
 
-        let discord_framework = StandardFramework::new()
-            .configure(|c| c.with_whitespace(true).prefix(self.prefix.as_str()));
+        let admin_store = match AdminStore::new(self.database.clone()) {
+            Ok(admin_store) => admin_store,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not open the bot admins table: {}", err));
+                return false;
+            }
+        };
+        let permissions = BotPermissions::new(self.creator, self.admins.clone(), admin_store);
+
+        let jobs = match JobStore::new(self.database.clone()) {
+            Ok(jobs) => jobs,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not open the scheduled jobs table: {}", err));
+                return false;
+            }
+        };
+
+        let flashcards = match FlashcardStore::new(self.database.clone()) {
+            Ok(flashcards) => flashcards,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not open the flashcard tables: {}", err));
+                return false;
+            }
+        };
+
+        let trivia = match TriviaStore::new(self.database.clone()) {
+            Ok(trivia) => trivia,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not open the trivia tables: {}", err));
+                return false;
+            }
+        };
+        let debates = match DebateStore::new(self.database.clone()) {
+            Ok(debates) => debates,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not open the debate tables: {}", err));
+                return false;
+            }
+        };
+        let night_mode = match NightLockStore::new(self.database.clone()) {
+            Ok(locks) => NightMode::new(locks, self.clock.clone(), self.shutdown.clone()),
+            Err(err) => {
+                MiraiLogger::error(format!("Could not open the night lock tables: {}", err));
+                return false;
+            }
+        };
+        let night_watch = match NightWatchStore::new(self.database.clone()) {
+            Ok(offences) => NightWatch::new(offences),
+            Err(err) => {
+                MiraiLogger::error(format!("Could not open the night watch tables: {}", err));
+                return false;
+            }
+        };
+        let templates = AnnouncementTemplates::new(Path::new(&self.data_dir).join("announcements"));
+        match templates.reload() {
+            Ok(count) => {
+                MiraiLogger::info(format!("Loaded {} announcement template(s) from {}", count, templates.directory().display()));
+            }
+            Err(err) => MiraiLogger::warn(format!("Using the built-in announcements, could not load them: {}", err)),
+        }
+
+        let trials = match TrialStore::new(self.database.clone()) {
+            Ok(trials) => ClassTrials::new(trials, self.clock.clone(), templates.clone(), self.shutdown.clone()),
+            Err(err) => {
+                MiraiLogger::error(format!("Could not open the class trial tables: {}", err));
+                return false;
+            }
+        };
+        let trivia_path = Path::new(&self.data_dir).join("trivia");
+        let trivia_bank = match TriviaBank::load(&trivia_path) {
+            Ok(trivia_bank) if trivia_bank.is_empty() => {
+                MiraiLogger::warn(format!("No trivia questions found in {}", trivia_path.display()));
+                trivia_bank
+            }
+            Ok(trivia_bank) => {
+                MiraiLogger::info(format!("Loaded {} trivia question(s) from {}", trivia_bank.len(), trivia_path.display()));
+                trivia_bank
+            }
+            Err(err) => {
+                MiraiLogger::warn(format!("No trivia questions, could not load them: {}", err));
+                TriviaBank::default()
+            }
+        };
+
+        let debate_path = Path::new(&self.data_dir).join("debates");
+        let debate_bank = match DebateBank::load(&debate_path) {
+            Ok(debate_bank) if debate_bank.is_empty() => {
+                MiraiLogger::warn(format!("No debates found in {}", debate_path.display()));
+                debate_bank
+            }
+            Ok(debate_bank) => {
+                MiraiLogger::info(format!("Loaded {} debate(s) from {}", debate_bank.len(), debate_path.display()));
+                debate_bank
+            }
+            Err(err) => {
+                MiraiLogger::warn(format!("No debates, could not load them: {}", err));
+                DebateBank::default()
+            }
+        };
+
+        let discord_framework = command_framework(self.prefix.as_str()).await;
 
         let intents = GatewayIntents::GUILD_MESSAGES
             | GatewayIntents::GUILDS
             | GatewayIntents::GUILD_MEMBERS
+            | GatewayIntents::GUILD_MESSAGE_REACTIONS
             | GatewayIntents::DIRECT_MESSAGES
             | GatewayIntents::MESSAGE_CONTENT;
 
-        let client = Client::builder(self.token.as_str(), intents)
+        let mut http = HttpBuilder::new(self.token.as_str());
+        if let Some(api_url) = &self.api_url {
+            http = match http.proxy(api_url.as_str()) {
+                Ok(http) => http.ratelimiter_disabled(true),
+                Err(err) => {
+                    MiraiLogger::error(format!("Invalid API url {}: {}", api_url, err));
+                    return false;
+                }
+            };
+        }
+
+        let client = match ClientBuilder::new_with_http(http.build(), intents)
             .event_handler(bot_handler::Handler)
             .framework(discord_framework)
-            .await
-            .expect("Error creating client");
+            .await {
+            Ok(client) => client,
+            Err(err) => {
+                MiraiLogger::error(format!("Error creating client: {}", err));
+                return false;
+            }
+        };
+
+        {
+            let mut data = client.data.write().await;
+            data.insert::<DiscordBot>(self.clone());
+            data.insert::<GuildSettingsStore>(self.settings.clone());
+            data.insert::<BotPermissions>(permissions);
+            data.insert::<FlashcardStore>(flashcards);
+            data.insert::<QuizSessions>(QuizSessions::default());
+            data.insert::<TriviaBank>(trivia_bank);
+            data.insert::<TriviaStore>(trivia);
+            data.insert::<ClassTrials>(trials);
+            data.insert::<DebateSessions>(DebateSessions::default());
+            data.insert::<DebateBank>(debate_bank);
+            data.insert::<DebateStore>(debates);
+            data.insert::<NightMode>(night_mode);
+            data.insert::<NightWatch>(night_watch);
+            data.insert::<AnnouncementTemplates>(templates);
+            data.insert::<MonokumaAnnouncements>(MonokumaAnnouncements::new(
+                self.shutdown.clone(), Scheduler::new(jobs, self.clock.clone())
+            ));
+        }
 
-        client.data.write().await.insert::<DiscordBot>(self.clone());
+        if let Some(target) = self.log_target {
+            self.forward_logs(client.cache_and_http.http.clone(), target);
+        }
 
         self.client = Some(client);
         true
     }
+
+    /// Mirrors the warnings and errors to `target` until the bot shuts down.
+    fn forward_logs(&self, http: Arc<Http>, target: LogTarget) {
+        let receiver = log::forward::register();
+        let creator = self.creator;
+        let shutdown = self.shutdown.child_token();
+
+        self.shutdown.spawn(async move {
+            match log::forward::target_channel(&http, target, creator).await {
+                Ok(Some(channel)) => {
+                    MiraiLogger::info(format!("Forwarding warnings and errors to {}", channel));
+                    LogForwarder::new(SerenitySink::shared(http), channel).run(receiver, shutdown).await;
+                }
+                Ok(None) => MiraiLogger::warn("No creator to forward the logs to".to_string()),
+                Err(err) => MiraiLogger::error(format!("Could not open the log channel: {}", err)),
+            }
+            log::forward::unregister();
+        });
+    }
 }
 
 impl Default for DiscordBot {
@@ -84,7 +378,19 @@ impl Clone for DiscordBot {
             token: self.token.clone(),
             admins: self.admins.clone(),
             prefix: self.prefix.clone(),
+            api_url: self.api_url.clone(),
             creator: self.creator,
+            guilds: self.guilds.clone(),
+            timezone: self.timezone,
+            color: self.color,
+            catch_up: self.catch_up,
+            log_target: self.log_target,
+            slash_commands: self.slash_commands,
+            settings: self.settings.clone(),
+            database: self.database.clone(),
+            data_dir: self.data_dir.clone(),
+            clock: self.clock.clone(),
+            shutdown: self.shutdown.clone(),
             client: None,
         }
     }
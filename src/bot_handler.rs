@@ -2,13 +2,21 @@ use serenity::client::{Context, EventHandler};
 use serenity::async_trait;
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
-use serenity::model::guild::Member;
+use serenity::model::application::interaction::Interaction;
+use serenity::model::guild::{Guild, Member, UnavailableGuild};
+use serenity::model::id::GuildId;
 
 use crate::bot::DiscordBot;
-use crate::log::{MiraiLog, MiraiLogger};
-use crate::mirai_bot::guild::MIRAI_TEAM_GUILD_ID;
-use crate::mirai_bot::monokuma_announcement::setup_monokuma_announcement;
+use crate::log::{LogContext, MiraiLog, MiraiLogger, with_context};
+use crate::mirai_bot::class_trial::ClassTrials;
+use crate::mirai_bot::monokuma_announcement::MonokumaAnnouncements;
+use crate::mirai_bot::night_mode::NightMode;
+use crate::mirai_bot::night_watch::NightWatch;
 use crate::mirai_bot::on_new_member::on_new_member;
+use crate::mirai_bot::slash::{self, SlashScope};
+use crate::permissions::BotPermissions;
+use crate::settings::{Feature, GuildSettings, GuildSettingsStore};
+use crate::sink::SerenitySink;
 use crate::utils;
 
 pub struct Handler;
@@ -16,47 +24,146 @@ pub struct Handler;
 #[async_trait]
 impl EventHandler for Handler {
     async fn guild_member_addition(&self, _ctx: Context, _new_member: Member) {
-        let guild_name = _new_member.guild_id.name(&_ctx.cache).unwrap_or_default();
-        MiraiLogger::info(
-            format!("{} joined {}", _new_member.display_name(), guild_name).trim().to_string()
-        );
-
-        if let Some(system_channel) = utils::guild_fcts::find_guild_system_channel(
-            &_ctx.cache, _new_member.guild_id
-        ) {
-            if let Err(err) = on_new_member(&_ctx.http, system_channel, _new_member).await {
-                MiraiLogger::error(format!("Error on new member: {}", err));
+        let context = LogContext::new()
+            .set_guild(Some(_new_member.guild_id))
+            .set_user(_new_member.user.id);
+        with_context(context, async move {
+            let guild_name = _new_member.guild_id.name(&_ctx.cache).unwrap_or_default();
+            MiraiLogger::info(
+                format!("{} joined {}", _new_member.display_name(), guild_name).trim().to_string()
+            );
+
+            let bot = _ctx.data.read().await.get::<DiscordBot>()
+                .expect("Did not find DiscordBot").clone();
+            let settings = match utils::guild_fcts::guild_settings(&_ctx, _new_member.guild_id).await {
+                Some(settings) if settings.is_enabled(Feature::Welcome) => settings,
+                _ => return,
+            };
+
+            if let Some(welcome_channel) = settings.welcome_channel.or_else(|| {
+                utils::guild_fcts::find_guild_system_channel(&_ctx.cache, _new_member.guild_id)
+            }) {
+                let sink = SerenitySink::shared(_ctx.http.clone());
+                if let Err(err) = on_new_member(
+                    sink.as_ref(), &bot, &settings, welcome_channel, &_new_member
+                ).await {
+                    MiraiLogger::error(format!("Error on new member: {}", err));
+                }
+            }
+        }).await
+    }
+
+    async fn guild_create(&self, ctx: Context, guild: Guild, is_new: bool) {
+        let context = LogContext::new().set_guild(Some(guild.id));
+        with_context(context, async move {
+            let bot = ctx.data.read().await.get::<DiscordBot>()
+                .expect("Did not find DiscordBot").clone();
+            if is_new && bot.slash_commands == SlashScope::Guilds && bot.targets_guild(guild.id) {
+                slash::register_commands(&ctx, bot.slash_commands, &[guild.id]).await;
             }
-        }
 
+            let store = ctx.data.read().await.get::<GuildSettingsStore>()
+                .expect("Did not find GuildSettingsStore").clone();
 
+            match store.get(guild.id) {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    MiraiLogger::info(format!("Creating default settings for guild {}", guild.name));
+                    if let Err(err) = store.save(&GuildSettings::new(guild.id)) {
+                        MiraiLogger::error(format!("Could not save settings of guild {}: {}", guild.name, err));
+                    }
+                }
+                Err(err) => {
+                    MiraiLogger::error(format!("Could not read settings of guild {}: {}", guild.name, err));
+                }
+            }
+
+            let announcements = ctx.data.read().await.get::<MonokumaAnnouncements>()
+                .expect("Did not find MonokumaAnnouncements").clone();
+            if !announcements.is_running(guild.id) {
+                announcements.start(&ctx, guild.id, guild.system_channel_id).await;
+            }
+            let night_mode = ctx.data.read().await.get::<NightMode>()
+                .expect("Did not find NightMode").clone();
+            if !night_mode.is_running(guild.id) {
+                night_mode.start(&ctx, guild.id).await;
+            }
+        }).await
     }
 
-    async fn message(&self, ctx: Context, msg: Message) {
-        if !msg.is_own(&ctx.cache) {
-            let mut debug_msg = format!(
-                "Received new message by [{}] {}: {}",
-                msg.author.id,
-                msg.author.name,
-                msg.content
-            );
+    async fn guild_delete(&self, ctx: Context, incomplete: UnavailableGuild, _full: Option<Guild>) {
+        let context = LogContext::new().set_guild(Some(incomplete.id));
+        with_context(context, async move {
+            // An unavailable guild is an outage, not the bot being removed from it.
+            if incomplete.unavailable {
+                return;
+            }
 
-            if let Some(guild_id) = msg.guild_id {
-                if let Some(guild_name) = guild_id.name(&ctx.cache) {
-                    if let Some(channel_name) = msg.channel_id.name(&ctx.cache).await {
-                        debug_msg = format!(
-                            "Received new message on {} - {} by [{}] {}: {}",
-                            guild_name,
-                            channel_name,
-                            msg.author.id,
-                            msg.author.name,
-                            msg.content
-                        );
+            MiraiLogger::info(format!("Removed from guild {}, forgetting its settings", incomplete.id));
+            let announcements = ctx.data.read().await.get::<MonokumaAnnouncements>()
+                .expect("Did not find MonokumaAnnouncements").clone();
+            announcements.forget(incomplete.id);
+            let night_mode = ctx.data.read().await.get::<NightMode>()
+                .expect("Did not find NightMode").clone();
+            night_mode.forget(incomplete.id);
+            let night_watch = ctx.data.read().await.get::<NightWatch>()
+                .expect("Did not find NightWatch").clone();
+            night_watch.forget(incomplete.id);
+
+            let store = ctx.data.read().await.get::<GuildSettingsStore>()
+                .expect("Did not find GuildSettingsStore").clone();
+
+            if let Err(err) = store.remove(incomplete.id) {
+                MiraiLogger::error(format!("Could not remove settings of guild {}: {}", incomplete.id, err));
+            }
+        }).await
+    }
+
+    async fn message(&self, ctx: Context, msg: Message) {
+        let context = LogContext::new()
+            .set_guild(msg.guild_id)
+            .set_channel(msg.channel_id)
+            .set_user(msg.author.id);
+        with_context(context, async move {
+            if !msg.is_own(&ctx.cache) {
+                let mut debug_msg = format!(
+                    "Received new message by [{}] {}: {}",
+                    msg.author.id,
+                    msg.author.name,
+                    msg.content
+                );
+
+                if let Some(guild_id) = msg.guild_id {
+                    if let Some(guild_name) = guild_id.name(&ctx.cache) {
+                        if let Some(channel_name) = msg.channel_id.name(&ctx.cache).await {
+                            debug_msg = format!(
+                                "Received new message on {} - {} by [{}] {}: {}",
+                                guild_name,
+                                channel_name,
+                                msg.author.id,
+                                msg.author.name,
+                                msg.content
+                            );
+                        }
                     }
                 }
+
+                MiraiLogger::debug(debug_msg);
+
+                let night_watch = ctx.data.read().await.get::<NightWatch>()
+                    .expect("Did not find NightWatch").clone();
+                night_watch.on_message(&ctx, &msg).await;
             }
+        }).await
+    }
 
-            MiraiLogger::debug(debug_msg);
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::ApplicationCommand(command) = interaction {
+            let context = LogContext::new()
+                .set_guild(command.guild_id)
+                .set_channel(command.channel_id)
+                .set_user(command.user.id);
+            with_context(context, slash::dispatch(&ctx, &command)).await
         }
     }
 
@@ -66,9 +173,12 @@ impl EventHandler for Handler {
         let bot = ctx.data.read().await.get::<DiscordBot>()
             .expect("Did not find DiscordBot").clone();
 
-        match bot.admins.len() {
+        let permissions = ctx.data.read().await.get::<BotPermissions>()
+            .expect("Did not find BotPermissions").clone();
+
+        match permissions.admins().len() {
             0 => {
-                MiraiLogger::info(format!("There are no admins on this bot"));
+                MiraiLogger::info("There are no admins on this bot".to_string());
             }
             number_of_admins => {
                 MiraiLogger::info(format!("There are {} admins on this bot owo", number_of_admins));
@@ -88,13 +198,13 @@ impl EventHandler for Handler {
 
         match ctx.cache.guilds().len() {
             0 => {
-                MiraiLogger::info(format!("No guilds on cache"));
+                MiraiLogger::info("No guilds on cache".to_string());
             },
             1 => {
                 if let Some(guild_name) = ctx.cache.guilds()[0].name(&ctx.cache) {
                     MiraiLogger::info(format!("There is one guild on cache ! It's {}.", guild_name));
                 } else {
-                    MiraiLogger::info(format!("There is one guild on cache !"));
+                    MiraiLogger::info("There is one guild on cache !".to_string());
                 }
             },
             guild_nb => {
@@ -102,21 +212,41 @@ impl EventHandler for Handler {
             }
         }
 
-        let mirai_team_guild = ctx.http.get_guild(MIRAI_TEAM_GUILD_ID.0).await;
+        let announcements = ctx.data.read().await.get::<MonokumaAnnouncements>()
+            .expect("Did not find MonokumaAnnouncements").clone();
+        let night_mode = ctx.data.read().await.get::<NightMode>()
+            .expect("Did not find NightMode").clone();
+
+        // Trials go on across reconnects and restarts, their state being in the database.
+        let trials = ctx.data.read().await.get::<ClassTrials>()
+            .expect("Did not find ClassTrials").clone();
+        trials.resume(SerenitySink::shared(ctx.http.clone()), bot.color, &bot.prefix);
+
+        let guild_ids: Vec<GuildId> = ready.guilds.iter()
+            .map(|guild| guild.id)
+            .filter(|id| bot.targets_guild(*id))
+            .collect();
+        slash::register_commands(&ctx, bot.slash_commands, &guild_ids).await;
+
+        for guild_id in guild_ids {
+            let guild = match ctx.http.get_guild(guild_id.0).await {
+                Ok(guild) => guild,
+                Err(err) => {
+                    MiraiLogger::error(format!("Could not fetch guild {}: {}", guild_id, err));
+                    continue;
+                }
+            };
 
-        if let Ok(guild) = mirai_team_guild {
             MiraiLogger::debug(format!("Found guild {}", guild.name));
-            if let Some(system_channel) = guild.system_channel_id {
-
-                setup_monokuma_announcement(system_channel).await;
-
-                /*if let Err(err) = system_channel.send_message(&ctx.http, |msg| {
-                    msg.content("Hello! I'm the ultimate flashcard bot :3");
-                    msg
-                }).await {
-                    MiraiLogger::error(format!("Could not send message to guild {}: {}", guild.name, err));
-                }*/
-            }
+            announcements.start(&ctx, guild.id, guild.system_channel_id).await;
+            night_mode.start(&ctx, guild.id).await;
+
+            /*if let Err(err) = system_channel.send_message(&ctx.http, |msg| {
+                msg.content("Hello! I'm the ultimate flashcard bot :3");
+                msg
+            }).await {
+                MiraiLogger::error(format!("Could not send message to guild {}: {}", guild.name, err));
+            }*/
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,126 @@
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serenity::model::id::UserId;
+
+use crate::class_trial::Player;
+
+pub const VICTIMS: [&str; 6] = [
+    "Sayaka Maizono", "Leon Kuwata", "Teruteru Hanamura", "Mahiru Koizumi", "Rantaro Amami", "Kirumi Tojo",
+];
+pub const LOCATIONS: [&str; 6] = [
+    "la bibliothèque", "le gymnase", "la cafétéria", "la salle d'art", "la laverie", "la piscine",
+];
+pub const WEAPONS: [&str; 6] = [
+    "une batte de baseball", "un couteau de cuisine", "un haltère", "une corde", "un marteau", "une bouteille de vin",
+];
+
+/// The murder the players have to solve.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub victim: String,
+    pub crime_scene: String,
+    pub weapon: String,
+    pub blackened: UserId,
+}
+
+/// Where a player was during the murder and what they had on them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alibi {
+    pub location: String,
+    pub item: String,
+}
+
+impl Case {
+    /// Picks the blackened among `players` and gives everyone an alibi. The blackened was on
+    /// the crime scene with the weapon, innocents may share one of the two but never both.
+    pub fn assign<R: Rng>(players: &mut [Player], rng: &mut R) -> Self {
+        let case = Self {
+            victim: VICTIMS.choose(rng).expect("No victims").to_string(),
+            crime_scene: LOCATIONS.choose(rng).expect("No locations").to_string(),
+            weapon: WEAPONS.choose(rng).expect("No weapons").to_string(),
+            blackened: players.choose(rng).expect("No players").user,
+        };
+
+        for player in players.iter_mut() {
+            player.alibi = Some(match player.user == case.blackened {
+                true => Alibi { location: case.crime_scene.clone(), item: case.weapon.clone() },
+                false => loop {
+                    let alibi = Alibi {
+                        location: LOCATIONS.choose(rng).expect("No locations").to_string(),
+                        item: WEAPONS.choose(rng).expect("No weapons").to_string(),
+                    };
+                    if alibi.location != case.crime_scene || alibi.item != case.weapon {
+                        break alibi;
+                    }
+                },
+            });
+        }
+        case
+    }
+}
+
+/// A true fact about a player, sent in DM to another one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Clue {
+    Location(UserId, String),
+    Item(UserId, String),
+}
+
+impl Clue {
+    /// A clue about a random player other than `receiver`.
+    pub fn draw<R: Rng>(players: &[Player], receiver: UserId, rng: &mut R) -> Option<Self> {
+        let others: Vec<&Player> = players.iter().filter(|player| player.user != receiver).collect();
+        let about = others.choose(rng)?;
+        let alibi = about.alibi.as_ref()?;
+        Some(match rng.gen_bool(0.5) {
+            true => Clue::Location(about.user, alibi.location.clone()),
+            false => Clue::Item(about.user, alibi.item.clone()),
+        })
+    }
+
+    pub fn text(&self) -> String {
+        match self {
+            Clue::Location(user, location) => format!("<@{}> a été vu(e) dans {} au moment du meurtre.", user, location),
+            Clue::Item(user, item) => format!("<@{}> avait {} sur lui ou sur elle.", user, item),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serenity::model::id::UserId;
+
+    use crate::class_trial::Player;
+    use crate::class_trial::case::{Case, Clue};
+
+    #[test]
+    fn test_assign() {
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut players: Vec<Player> = (1..=6).map(|id| Player::new(UserId(id))).collect();
+            let case = Case::assign(&mut players, &mut rng);
+
+            for player in &players {
+                let alibi = player.alibi.as_ref().unwrap();
+                let guilty = alibi.location == case.crime_scene && alibi.item == case.weapon;
+                assert_eq!(guilty, player.user == case.blackened);
+            }
+
+            let clue = Clue::draw(&players, UserId(1), &mut rng).unwrap();
+            match clue {
+                Clue::Location(user, _) | Clue::Item(user, _) => assert_ne!(user, UserId(1)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_clue_text() {
+        assert_eq!(
+            Clue::Location(UserId(1), "la piscine".to_string()).text(),
+            "<@1> a été vu(e) dans la piscine au moment du meurtre."
+        );
+        assert_eq!(Clue::draw(&[Player::new(UserId(1))], UserId(1), &mut rand::thread_rng()), None);
+    }
+}
@@ -0,0 +1,562 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use rusqlite::{Connection, OptionalExtension, params, Row};
+use rusqlite::types::Type;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use crate::database::{Database, time_from_column, time_to_column};
+
+pub(crate) mod case;
+
+pub use case::{Alibi, Case, Clue};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS class_trials (
+    channel_id INTEGER PRIMARY KEY,
+    guild_id INTEGER NOT NULL,
+    host INTEGER NOT NULL,
+    phase TEXT NOT NULL,
+    round INTEGER NOT NULL,
+    ends_at TEXT,
+    victim TEXT,
+    crime_scene TEXT,
+    weapon TEXT,
+    blackened INTEGER
+);
+CREATE TABLE IF NOT EXISTS class_trial_players (
+    channel_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    dm_channel INTEGER,
+    location TEXT,
+    item TEXT,
+    vote INTEGER,
+    PRIMARY KEY (channel_id, user_id)
+);";
+
+pub const MIN_PLAYERS: usize = 3;
+/// Most players in a trial, everyone gets a clue by DM each round.
+pub const MAX_PLAYERS: usize = 12;
+/// Discussion rounds before the vote, each one starting with new clues.
+pub const ROUNDS: u32 = 2;
+pub const DISCUSSION: std::time::Duration = std::time::Duration::from_secs(180);
+pub const VOTE: std::time::Duration = std::time::Duration::from_secs(60);
+/// A lobby the host does not start in time is closed.
+pub const LOBBY: std::time::Duration = std::time::Duration::from_secs(30 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Players join until the host starts the trial.
+    Lobby,
+    /// The discussion of a round, from 1 to `ROUNDS`.
+    Investigation(u32),
+    Vote,
+    Over,
+}
+
+impl Phase {
+    fn name(&self) -> &'static str {
+        match self {
+            Phase::Lobby => "lobby",
+            Phase::Investigation(_) => "investigation",
+            Phase::Vote => "vote",
+            Phase::Over => "over",
+        }
+    }
+
+    fn round(&self) -> u32 {
+        match self {
+            Phase::Investigation(round) => *round,
+            _ => 0,
+        }
+    }
+
+    fn from_columns(name: &str, round: u32) -> Option<Self> {
+        match name {
+            "lobby" => Some(Phase::Lobby),
+            "investigation" => Some(Phase::Investigation(round)),
+            "vote" => Some(Phase::Vote),
+            "over" => Some(Phase::Over),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Player {
+    pub user: UserId,
+    /// Where the clues are sent.
+    pub dm_channel: Option<ChannelId>,
+    pub alibi: Option<Alibi>,
+    pub vote: Option<UserId>,
+}
+
+impl Player {
+    pub fn new(user: UserId) -> Self {
+        Self { user, dm_channel: None, alibi: None, vote: None }
+    }
+
+    pub fn set_dm_channel(mut self, dm_channel: Option<ChannelId>) -> Self {
+        self.dm_channel = dm_channel;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum TrialError {
+    Database(rusqlite::Error),
+    AlreadyRunning,
+    NoTrial,
+    Started,
+    NotVoting,
+    AlreadyJoined,
+    NotJoined,
+    Full,
+    NotEnoughPlayers,
+    NotHost(UserId),
+    HostLeaving,
+    UnknownSuspect(UserId),
+    SelfVote,
+}
+
+impl fmt::Display for TrialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrialError::Database(err) => write!(f, "erreur de base de données : {}", err),
+            TrialError::AlreadyRunning => write!(f, "Un procès est déjà en cours dans ce salon."),
+            TrialError::NoTrial => write!(f, "Aucun procès n'est en cours dans ce salon."),
+            TrialError::Started => write!(f, "Le procès a déjà commencé."),
+            TrialError::NotVoting => write!(f, "Ce n'est pas l'heure du vote."),
+            TrialError::AlreadyJoined => write!(f, "Tu participes déjà au procès."),
+            TrialError::NotJoined => write!(f, "Tu ne participes pas au procès."),
+            TrialError::Full => write!(f, "Le procès est complet, {} élèves au plus.", MAX_PLAYERS),
+            TrialError::NotEnoughPlayers => write!(f, "Il faut au moins {} élèves pour commencer.", MIN_PLAYERS),
+            TrialError::NotHost(host) => write!(f, "Seul(e) <@{}>, qui a ouvert le procès, peut le lancer.", host),
+            TrialError::HostLeaving => write!(f, "Tu as ouvert le procès, annule-le plutôt."),
+            TrialError::UnknownSuspect(user) => write!(f, "<@{}> ne participe pas au procès.", user),
+            TrialError::SelfVote => write!(f, "Tu ne peux pas voter contre toi-même."),
+        }
+    }
+}
+
+impl std::error::Error for TrialError {}
+
+impl From<rusqlite::Error> for TrialError {
+    fn from(err: rusqlite::Error) -> Self { TrialError::Database(err) }
+}
+
+pub type TrialResult<T> = Result<T, TrialError>;
+
+/// A Class Trial of a channel, from its lobby to the verdict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trial {
+    pub channel: ChannelId,
+    pub guild: GuildId,
+    pub host: UserId,
+    pub phase: Phase,
+    /// When the current phase ends, the lobby closing if the host does not start the trial
+    /// before.
+    pub ends_at: Option<DateTime<Utc>>,
+    pub case: Option<Case>,
+    pub players: Vec<Player>,
+}
+
+impl Trial {
+    /// A lobby opened by `host` at `now`, who is its first player.
+    pub fn new(channel: ChannelId, guild: GuildId, host: Player, now: DateTime<Utc>) -> Self {
+        let mut trial = Self {
+            channel,
+            guild,
+            host: host.user,
+            phase: Phase::Lobby,
+            ends_at: None,
+            case: None,
+            players: vec![host],
+        };
+        trial.enter(Phase::Lobby, now);
+        trial
+    }
+
+    pub fn player(&self, user: UserId) -> Option<&Player> {
+        self.players.iter().find(|player| player.user == user)
+    }
+
+    pub fn join(&mut self, player: Player) -> TrialResult<()> {
+        if self.phase != Phase::Lobby {
+            return Err(TrialError::Started);
+        }
+        if self.player(player.user).is_some() {
+            return Err(TrialError::AlreadyJoined);
+        }
+        if self.players.len() >= MAX_PLAYERS {
+            return Err(TrialError::Full);
+        }
+        self.players.push(player);
+        Ok(())
+    }
+
+    pub fn leave(&mut self, user: UserId) -> TrialResult<()> {
+        if self.phase != Phase::Lobby {
+            return Err(TrialError::Started);
+        }
+        if user == self.host {
+            return Err(TrialError::HostLeaving);
+        }
+        let before = self.players.len();
+        self.players.retain(|player| player.user != user);
+        match self.players.len() < before {
+            true => Ok(()),
+            false => Err(TrialError::NotJoined),
+        }
+    }
+
+    /// Starts the first discussion, once `by` the host.
+    pub fn begin<R: Rng>(&mut self, by: UserId, now: DateTime<Utc>, rng: &mut R) -> TrialResult<()> {
+        if self.phase != Phase::Lobby {
+            return Err(TrialError::Started);
+        }
+        if by != self.host {
+            return Err(TrialError::NotHost(self.host));
+        }
+        if self.players.len() < MIN_PLAYERS {
+            return Err(TrialError::NotEnoughPlayers);
+        }
+        self.case = Some(Case::assign(&mut self.players, rng));
+        self.enter(Phase::Investigation(1), now);
+        Ok(())
+    }
+
+    fn enter(&mut self, phase: Phase, now: DateTime<Utc>) {
+        let duration = match phase {
+            Phase::Investigation(_) => Some(DISCUSSION),
+            Phase::Vote => Some(VOTE),
+            Phase::Lobby => Some(LOBBY),
+            Phase::Over => None,
+        };
+        self.phase = phase;
+        self.ends_at = duration.map(|duration| now + chrono::Duration::from_std(duration).expect("Phase too long"));
+    }
+
+    /// Moves on once the current phase is over: the next discussion, the vote, the verdict.
+    /// A lobby that was not started is over without a case.
+    pub fn next_phase(&mut self, now: DateTime<Utc>) {
+        let next = match self.phase {
+            Phase::Investigation(round) if round < ROUNDS => Phase::Investigation(round + 1),
+            Phase::Investigation(_) => Phase::Vote,
+            Phase::Lobby | Phase::Vote | Phase::Over => Phase::Over,
+        };
+        self.enter(next, now);
+    }
+
+    /// Records the vote of `voter` against `suspect`, players can change their mind.
+    pub fn vote(&mut self, voter: UserId, suspect: UserId) -> TrialResult<()> {
+        if self.phase != Phase::Vote {
+            return Err(TrialError::NotVoting);
+        }
+        if self.player(suspect).is_none() {
+            return Err(TrialError::UnknownSuspect(suspect));
+        }
+        if voter == suspect {
+            return Err(TrialError::SelfVote);
+        }
+        let player = self.players.iter_mut().find(|player| player.user == voter).ok_or(TrialError::NotJoined)?;
+        player.vote = Some(suspect);
+        Ok(())
+    }
+
+    /// Votes each suspect got, the most voted first.
+    pub fn votes(&self) -> Vec<(UserId, usize)> {
+        let mut votes: Vec<(UserId, usize)> = Vec::new();
+        for suspect in self.players.iter().filter_map(|player| player.vote) {
+            match votes.iter_mut().find(|(user, _)| *user == suspect) {
+                Some((_, count)) => *count += 1,
+                None => votes.push((suspect, 1)),
+            }
+        }
+        votes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        votes
+    }
+
+    /// Who received the most votes, nobody when no one voted or the lead is tied.
+    pub fn accused(&self) -> Option<UserId> {
+        match self.votes().as_slice() {
+            [(first, most), rest @ ..] if rest.first().is_none_or(|(_, second)| second < most) => Some(*first),
+            _ => None,
+        }
+    }
+}
+
+fn id_column(row: &Row, index: usize) -> rusqlite::Result<Option<u64>> {
+    Ok(row.get::<_, Option<i64>>(index)?.map(|id| id as u64))
+}
+
+fn trial_from_row(row: &Row) -> rusqlite::Result<Trial> {
+    let phase_name: String = row.get(3)?;
+    let phase = Phase::from_columns(&phase_name, row.get(4)?).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(3, Type::Text, format!("unknown phase {}", phase_name).into())
+    })?;
+    let case = match (row.get::<_, Option<String>>(6)?, row.get(7)?, row.get(8)?, id_column(row, 9)?) {
+        (Some(victim), Some(crime_scene), Some(weapon), Some(blackened)) => {
+            Some(Case { victim, crime_scene, weapon, blackened: UserId(blackened) })
+        }
+        _ => None,
+    };
+
+    Ok(Trial {
+        channel: ChannelId(row.get::<_, i64>(0)? as u64),
+        guild: GuildId(row.get::<_, i64>(1)? as u64),
+        host: UserId(row.get::<_, i64>(2)? as u64),
+        phase,
+        ends_at: row.get::<_, Option<String>>(5)?.map(|time| time_from_column(5, time)).transpose()?,
+        case,
+        players: Vec::new(),
+    })
+}
+
+fn player_from_row(row: &Row) -> rusqlite::Result<Player> {
+    let alibi = match (row.get::<_, Option<String>>(2)?, row.get::<_, Option<String>>(3)?) {
+        (Some(location), Some(item)) => Some(Alibi { location, item }),
+        _ => None,
+    };
+    Ok(Player {
+        user: UserId(row.get::<_, i64>(0)? as u64),
+        dm_channel: id_column(row, 1)?.map(ChannelId),
+        alibi,
+        vote: id_column(row, 4)?.map(UserId),
+    })
+}
+
+const TRIAL_COLUMNS: &str =
+    "channel_id, guild_id, host, phase, round, ends_at, victim, crime_scene, weapon, blackened";
+
+fn read_trial(connection: &Connection, channel: ChannelId) -> rusqlite::Result<Option<Trial>> {
+    let trial = connection.query_row(
+        &format!("SELECT {} FROM class_trials WHERE channel_id = ?1", TRIAL_COLUMNS),
+        params![channel.0 as i64],
+        trial_from_row,
+    ).optional()?;
+    trial.map(|trial| read_players(connection, trial)).transpose()
+}
+
+fn read_players(connection: &Connection, mut trial: Trial) -> rusqlite::Result<Trial> {
+    let mut statement = connection.prepare(
+        "SELECT user_id, dm_channel, location, item, vote FROM class_trial_players
+         WHERE channel_id = ?1 ORDER BY position"
+    )?;
+    trial.players = statement.query_map(params![trial.channel.0 as i64], player_from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(trial)
+}
+
+fn write_trial(connection: &Connection, trial: &Trial) -> rusqlite::Result<()> {
+    let id = |user: Option<UserId>| user.map(|user| user.0 as i64);
+    let case = trial.case.as_ref();
+    connection.execute(
+        &format!("INSERT OR REPLACE INTO class_trials ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)", TRIAL_COLUMNS),
+        params![
+            trial.channel.0 as i64,
+            trial.guild.0 as i64,
+            trial.host.0 as i64,
+            trial.phase.name(),
+            trial.phase.round(),
+            trial.ends_at.map(time_to_column),
+            case.map(|case| case.victim.as_str()),
+            case.map(|case| case.crime_scene.as_str()),
+            case.map(|case| case.weapon.as_str()),
+            id(case.map(|case| case.blackened)),
+        ],
+    )?;
+    connection.execute("DELETE FROM class_trial_players WHERE channel_id = ?1", params![trial.channel.0 as i64])?;
+    for (position, player) in trial.players.iter().enumerate() {
+        let alibi = player.alibi.as_ref();
+        connection.execute(
+            "INSERT INTO class_trial_players (channel_id, user_id, position, dm_channel, location, item, vote)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                trial.channel.0 as i64,
+                player.user.0 as i64,
+                position as i64,
+                player.dm_channel.map(|channel| channel.0 as i64),
+                alibi.map(|alibi| alibi.location.as_str()),
+                alibi.map(|alibi| alibi.item.as_str()),
+                id(player.vote),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// The trials of every channel, in the `class_trial*` tables, so that they go on after the
+/// bot reconnects or restarts.
+#[derive(Clone)]
+pub struct TrialStore {
+    database: Database,
+}
+
+impl TrialStore {
+    pub fn new(database: Database) -> rusqlite::Result<Self> {
+        database.with_connection(|connection| connection.execute_batch(SCHEMA))?;
+        Ok(Self { database })
+    }
+
+    /// Saves a new trial, unless its channel already has one.
+    pub fn create(&self, trial: &Trial) -> TrialResult<()> {
+        self.database.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            if read_trial(&transaction, trial.channel)?.is_some() {
+                return Ok(false);
+            }
+            write_trial(&transaction, trial)?;
+            transaction.commit()?;
+            Ok(true)
+        })?.then_some(()).ok_or(TrialError::AlreadyRunning)
+    }
+
+    pub fn get(&self, channel: ChannelId) -> TrialResult<Trial> {
+        self.database.with_connection(|connection| read_trial(connection, channel))?.ok_or(TrialError::NoTrial)
+    }
+
+    /// Every trial saved, in their lobby or running.
+    pub fn all(&self) -> TrialResult<Vec<Trial>> {
+        Ok(self.database.with_connection(|connection| {
+            let mut statement = connection.prepare(&format!("SELECT {} FROM class_trials", TRIAL_COLUMNS))?;
+            let trials = statement.query_map([], trial_from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+            trials.into_iter().map(|trial| read_players(connection, trial)).collect()
+        })?)
+    }
+
+    /// Changes the trial of `channel` with `change`, nothing else touching it in between.
+    /// Nothing is saved if `change` fails.
+    pub fn update<T, F>(&self, channel: ChannelId, change: F) -> TrialResult<(Trial, T)>
+        where F: FnOnce(&mut Trial) -> TrialResult<T> {
+        self.database.with_connection(|connection| {
+            let mut trial = match read_trial(connection, channel)? {
+                Some(trial) => trial,
+                None => return Ok(Err(TrialError::NoTrial)),
+            };
+            match change(&mut trial) {
+                Ok(value) => {
+                    let transaction = connection.unchecked_transaction()?;
+                    write_trial(&transaction, &trial)?;
+                    transaction.commit()?;
+                    Ok(Ok((trial, value)))
+                }
+                Err(err) => Ok(Err(err)),
+            }
+        })?
+    }
+
+    pub fn delete(&self, channel: ChannelId) -> TrialResult<()> {
+        self.database.with_connection(|connection| {
+            connection.execute("DELETE FROM class_trial_players WHERE channel_id = ?1", params![channel.0 as i64])?;
+            connection.execute("DELETE FROM class_trials WHERE channel_id = ?1", params![channel.0 as i64])
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serenity::model::id::{ChannelId, GuildId, UserId};
+
+    use crate::class_trial::{Phase, Player, ROUNDS, Trial, TrialError, TrialStore};
+    use crate::database::Database;
+
+    fn lobby(players: u64) -> Trial {
+        let opened = Utc.ymd(2022, 10, 1).and_hms(19, 50, 0);
+        let mut trial = Trial::new(ChannelId(10), GuildId(1), Player::new(UserId(1)).set_dm_channel(Some(ChannelId(101))), opened);
+        for user in 2..=players {
+            trial.join(Player::new(UserId(user)).set_dm_channel(Some(ChannelId(100 + user)))).unwrap();
+        }
+        trial
+    }
+
+    #[test]
+    fn test_lobby() {
+        let mut trial = lobby(2);
+        let now = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(trial.ends_at, Some(Utc.ymd(2022, 10, 1).and_hms(20, 20, 0)));
+
+        assert!(matches!(trial.join(Player::new(UserId(2))), Err(TrialError::AlreadyJoined)));
+        assert!(matches!(trial.begin(UserId(1), now, &mut rng), Err(TrialError::NotEnoughPlayers)));
+        trial.join(Player::new(UserId(3))).unwrap();
+        assert!(matches!(trial.leave(UserId(1)), Err(TrialError::HostLeaving)));
+        assert!(matches!(trial.leave(UserId(4)), Err(TrialError::NotJoined)));
+        assert!(matches!(trial.begin(UserId(2), now, &mut rng), Err(TrialError::NotHost(UserId(1)))));
+
+        trial.begin(UserId(1), now, &mut rng).unwrap();
+        assert_eq!(trial.phase, Phase::Investigation(1));
+        assert_eq!(trial.ends_at, Some(now + Duration::minutes(3)));
+        assert!(trial.players.iter().all(|player| player.alibi.is_some()));
+        assert!(matches!(trial.join(Player::new(UserId(5))), Err(TrialError::Started)));
+    }
+
+    #[test]
+    fn test_lobby_closing() {
+        let mut trial = lobby(3);
+        trial.next_phase(Utc.ymd(2022, 10, 1).and_hms(20, 20, 0));
+        assert_eq!((trial.phase, trial.ends_at, trial.case.is_none()), (Phase::Over, None, true));
+        assert!(matches!(trial.join(Player::new(UserId(4))), Err(TrialError::Started)));
+    }
+
+    #[test]
+    fn test_phases_and_votes() {
+        let mut trial = lobby(4);
+        let now = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        trial.begin(UserId(1), now, &mut StdRng::seed_from_u64(1)).unwrap();
+        assert!(matches!(trial.vote(UserId(1), UserId(2)), Err(TrialError::NotVoting)));
+
+        for _ in 1..ROUNDS {
+            trial.next_phase(now);
+        }
+        assert_eq!(trial.phase, Phase::Investigation(ROUNDS));
+        trial.next_phase(now);
+        assert_eq!((trial.phase, trial.ends_at), (Phase::Vote, Some(now + Duration::minutes(1))));
+
+        assert_eq!(trial.accused(), None);
+        assert!(matches!(trial.vote(UserId(1), UserId(1)), Err(TrialError::SelfVote)));
+        assert!(matches!(trial.vote(UserId(1), UserId(9)), Err(TrialError::UnknownSuspect(UserId(9)))));
+        assert!(matches!(trial.vote(UserId(9), UserId(1)), Err(TrialError::NotJoined)));
+        trial.vote(UserId(1), UserId(2)).unwrap();
+        trial.vote(UserId(2), UserId(3)).unwrap();
+        assert_eq!(trial.accused(), None);
+        trial.vote(UserId(3), UserId(2)).unwrap();
+        assert_eq!(trial.accused(), Some(UserId(2)));
+        assert_eq!(trial.votes(), vec![(UserId(2), 2), (UserId(3), 1)]);
+
+        trial.next_phase(now);
+        assert_eq!((trial.phase, trial.ends_at), (Phase::Over, None));
+    }
+
+    #[test]
+    fn test_store() {
+        let store = TrialStore::new(Database::in_memory().unwrap()).unwrap();
+        let now = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        let trial = lobby(2);
+        store.create(&trial).unwrap();
+        assert!(matches!(store.create(&trial), Err(TrialError::AlreadyRunning)));
+        assert_eq!(store.get(ChannelId(10)).unwrap(), trial);
+        assert!(matches!(store.get(ChannelId(11)), Err(TrialError::NoTrial)));
+
+        assert!(matches!(
+            store.update(ChannelId(10), |trial| trial.join(Player::new(UserId(2)))),
+            Err(TrialError::AlreadyJoined)
+        ));
+        let (started, _) = store.update(ChannelId(10), |trial| {
+            trial.join(Player::new(UserId(3)))?;
+            trial.begin(UserId(1), now, &mut StdRng::seed_from_u64(1))
+        }).unwrap();
+        assert_eq!(store.get(ChannelId(10)).unwrap(), started);
+        assert_eq!(store.all().unwrap(), vec![started]);
+
+        store.delete(ChannelId(10)).unwrap();
+        assert!(store.all().unwrap().is_empty());
+        assert!(matches!(store.update(ChannelId(10), |_| Ok(())), Err(TrialError::NoTrial)));
+    }
+}
@@ -0,0 +1,435 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::utils::Colour;
+
+use crate::bot::BOT_TIMEZONE;
+use crate::jobs::CatchUp;
+use crate::log::{
+    DEFAULT_MAX_FILE_SIZE, DEFAULT_MAX_FILES, LogFilter, LogFormat, LogSettings, LogTarget, MiraiLog, MiraiLogger,
+};
+use crate::mirai_bot::color::MIRAI_BOT_COLOR;
+use crate::mirai_bot::guild::MIRAI_TEAM_GUILD_ID;
+use crate::mirai_bot::slash::SlashScope;
+
+pub const DEFAULT_CONFIG_PATH: &str = "mirai_bot.toml";
+pub const DEFAULT_PREFIX: &str = "/";
+pub const DEFAULT_DATABASE_PATH: &str = "mirai_bot.db";
+pub const DEFAULT_DATA_DIR: &str = "data";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(&'static str, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "could not read {}: {}", path, err),
+            ConfigError::Parse(err) => write!(f, "could not parse configuration: {}", err),
+            ConfigError::Invalid(key, reason) => write!(f, "invalid value for `{}`: {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Raw content of the TOML file, every key is optional so that environment
+/// variables can fill the gaps. Not `Debug`, it holds the token.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    token: Option<String>,
+    prefix: Option<String>,
+    creator: Option<u64>,
+    admins: Option<Vec<u64>>,
+    guilds: Option<Vec<u64>>,
+    timezone: Option<String>,
+    database: Option<String>,
+    data_dir: Option<String>,
+    catch_up: Option<String>,
+    slash_commands: Option<String>,
+    #[serde(default)]
+    colors: ColorsFile,
+    #[serde(default)]
+    log: LogFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColorsFile {
+    primary: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LogFile {
+    level: Option<String>,
+    format: Option<String>,
+    file: Option<String>,
+    max_file_size: Option<u64>,
+    max_files: Option<usize>,
+    channel: Option<u64>,
+    dm_creator: Option<bool>,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct BotConfig {
+    pub token: String,
+    pub prefix: String,
+    pub creator: Option<UserId>,
+    pub admins: Vec<UserId>,
+    pub guilds: Vec<GuildId>,
+    pub timezone: chrono_tz::Tz,
+    pub color: Colour,
+    pub database: String,
+    pub data_dir: String,
+    pub catch_up: CatchUp,
+    pub slash_commands: SlashScope,
+    pub log: LogSettings,
+}
+
+/// Leaves the token out, the configuration may end up in the logs.
+impl fmt::Debug for BotConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BotConfig")
+            .field("token", &"<redacted>")
+            .field("prefix", &self.prefix)
+            .field("creator", &self.creator)
+            .field("admins", &self.admins)
+            .field("guilds", &self.guilds)
+            .field("timezone", &self.timezone)
+            .field("color", &self.color)
+            .field("database", &self.database)
+            .field("data_dir", &self.data_dir)
+            .field("catch_up", &self.catch_up)
+            .field("slash_commands", &self.slash_commands)
+            .field("log", &self.log)
+            .finish()
+    }
+}
+
+impl BotConfig {
+    /// Loads the configuration from the file given by the `bot_config` env variable, or
+    /// `mirai_bot.toml` if it exists, then applies the environment overrides.
+    pub fn load() -> Result<Self, ConfigError> {
+        let env = |key: &str| std::env::var(key).ok();
+        let path = match std::env::var("bot_config") {
+            Ok(path) => path,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => DEFAULT_CONFIG_PATH.to_string(),
+            Err(_) => {
+                MiraiLogger::warn(format!(
+                    "No {} found, reading the configuration from env variables only",
+                    DEFAULT_CONFIG_PATH
+                ));
+                return Self::from_file(ConfigFile::default(), env);
+            }
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| ConfigError::Io(path.clone(), err))?;
+        Self::from_toml(&content, env)
+    }
+
+    /// Parses a TOML document and applies the overrides returned by `env`.
+    pub fn from_toml<F>(content: &str, env: F) -> Result<Self, ConfigError>
+        where F: Fn(&str) -> Option<String> {
+        let file: ConfigFile = toml::from_str(content).map_err(ConfigError::Parse)?;
+        Self::from_file(file, env)
+    }
+
+    fn from_file<F>(mut file: ConfigFile, env: F) -> Result<Self, ConfigError>
+        where F: Fn(&str) -> Option<String> {
+        if let Some(token) = env("bot_token") {
+            file.token = Some(token);
+        }
+        if let Some(prefix) = env("bot_prefix") {
+            file.prefix = Some(prefix);
+        }
+        if let Some(creator) = env("bot_creator") {
+            file.creator = Some(parse_id("creator", &creator)?);
+        }
+        if let Some(admins) = env("bot_admins") {
+            file.admins = Some(parse_id_list("admins", &admins)?);
+        }
+        if let Some(guilds) = env("bot_guilds") {
+            file.guilds = Some(parse_id_list("guilds", &guilds)?);
+        }
+        if let Some(timezone) = env("bot_timezone") {
+            file.timezone = Some(timezone);
+        }
+        if let Some(color) = env("bot_color") {
+            file.colors.primary = Some(color);
+        }
+        if let Some(database) = env("bot_database") {
+            file.database = Some(database);
+        }
+        if let Some(data_dir) = env("bot_data_dir") {
+            file.data_dir = Some(data_dir);
+        }
+        if let Some(catch_up) = env("bot_catch_up") {
+            file.catch_up = Some(catch_up);
+        }
+        if let Some(slash_commands) = env("bot_slash_commands") {
+            file.slash_commands = Some(slash_commands);
+        }
+        if let Some(level) = env("bot_log") {
+            file.log.level = Some(level);
+        }
+        if let Some(format) = env("bot_log_format") {
+            file.log.format = Some(format);
+        }
+        if let Some(log_file) = env("bot_log_file") {
+            file.log.file = Some(log_file);
+        }
+        if let Some(channel) = env("bot_log_channel") {
+            file.log.channel = Some(parse_id("log.channel", &channel)?);
+        }
+
+        let token = file.token.unwrap_or_default().trim().to_string();
+        if token.is_empty() {
+            return Err(ConfigError::Invalid(
+                "token",
+                "no bot token given, set `token` or the bot_token env variable".to_string(),
+            ));
+        }
+
+        let prefix = file.prefix.unwrap_or_else(|| DEFAULT_PREFIX.to_string());
+        if prefix.trim().is_empty() {
+            return Err(ConfigError::Invalid("prefix", "prefix cannot be empty".to_string()));
+        }
+
+        let timezone = match file.timezone {
+            Some(name) => chrono_tz::Tz::from_str(&name)
+                .map_err(|_| ConfigError::Invalid("timezone", format!("unknown timezone {}", name)))?,
+            None => BOT_TIMEZONE,
+        };
+
+        let color = match file.colors.primary {
+            Some(hex) => parse_color("colors.primary", &hex)?,
+            None => MIRAI_BOT_COLOR,
+        };
+
+        let catch_up = match file.catch_up {
+            Some(name) => CatchUp::from_name(name.trim()).ok_or_else(|| {
+                ConfigError::Invalid("catch_up", format!("{} is not one of skip, once or all", name))
+            })?,
+            None => CatchUp::default(),
+        };
+
+        let slash_commands = match file.slash_commands {
+            Some(name) => SlashScope::from_name(name.trim()).ok_or_else(|| {
+                ConfigError::Invalid("slash_commands", format!("{} is not one of off, global or guilds", name))
+            })?,
+            None => SlashScope::default(),
+        };
+
+        let discord = match (file.log.channel, file.log.dm_creator.unwrap_or(false)) {
+            (Some(_), true) => return Err(ConfigError::Invalid(
+                "log.dm_creator",
+                "logs go either to `log.channel` or to the creator".to_string(),
+            )),
+            (None, true) if file.creator.is_none() => return Err(ConfigError::Invalid(
+                "log.dm_creator",
+                "no creator to send the logs to, set `creator`".to_string(),
+            )),
+            (Some(channel), false) => Some(LogTarget::Channel(ChannelId(channel))),
+            (None, true) => Some(LogTarget::Creator),
+            (None, false) => None,
+        };
+
+        let log = LogSettings {
+            filter: match file.log.level {
+                Some(spec) => LogFilter::parse(&spec).map_err(|err| ConfigError::Invalid("log.level", err))?,
+                None => LogFilter::default(),
+            },
+            format: match file.log.format {
+                Some(name) => LogFormat::from_name(name.trim()).ok_or_else(|| {
+                    ConfigError::Invalid("log.format", format!("{} is not one of text or json", name))
+                })?,
+                None => LogFormat::Text,
+            },
+            file: file.log.file.filter(|path| !path.trim().is_empty()),
+            max_file_size: file.log.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE),
+            max_files: file.log.max_files.unwrap_or(DEFAULT_MAX_FILES),
+            discord,
+        };
+
+        Ok(Self {
+            token,
+            prefix,
+            creator: file.creator.map(UserId),
+            admins: file.admins.unwrap_or_default().into_iter().map(UserId).collect(),
+            guilds: file.guilds
+                .map(|ids| ids.into_iter().map(GuildId).collect())
+                .unwrap_or_else(|| vec![MIRAI_TEAM_GUILD_ID]),
+            timezone,
+            color,
+            database: file.database.unwrap_or_else(|| DEFAULT_DATABASE_PATH.to_string()),
+            data_dir: file.data_dir.unwrap_or_else(|| DEFAULT_DATA_DIR.to_string()),
+            catch_up,
+            slash_commands,
+            log,
+        })
+    }
+}
+
+fn parse_id(key: &'static str, value: &str) -> Result<u64, ConfigError> {
+    value.trim().parse::<u64>()
+        .map_err(|_| ConfigError::Invalid(key, format!("{} is not a discord id", value.trim())))
+}
+
+fn parse_id_list(key: &'static str, value: &str) -> Result<Vec<u64>, ConfigError> {
+    value.split(',')
+        .filter(|id| !id.trim().is_empty())
+        .map(|id| parse_id(key, id))
+        .collect()
+}
+
+/// Parses colors written as `#5afcf7` or `5afcf7`.
+fn parse_color(key: &'static str, value: &str) -> Result<Colour, ConfigError> {
+    let hex = value.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return Err(ConfigError::Invalid(key, format!("{} is not a #rrggbb color", value)));
+    }
+
+    u32::from_str_radix(hex, 16)
+        .map(Colour::new)
+        .map_err(|_| ConfigError::Invalid(key, format!("{} is not a #rrggbb color", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::{ChannelId, GuildId, UserId};
+    use serenity::utils::Colour;
+
+    use crate::bot::BOT_TIMEZONE;
+    use crate::config::{BotConfig, ConfigError};
+    use crate::jobs::CatchUp;
+    use crate::log::{LogFilter, LogFormat, LogSettings, LogTarget};
+    use crate::mirai_bot::guild::MIRAI_TEAM_GUILD_ID;
+    use crate::mirai_bot::slash::SlashScope;
+
+    fn no_env(_: &str) -> Option<String> { None }
+
+    #[test]
+    fn test_full_config() {
+        let config = BotConfig::from_toml(r##"
+            token = "abc"
+            prefix = "!"
+            creator = 42
+            admins = [1, 2]
+            guilds = [3]
+            timezone = "America/New_York"
+            database = "/tmp/mirai.db"
+            data_dir = "/srv/mirai"
+            catch_up = "all"
+            slash_commands = "guilds"
+
+            [colors]
+            primary = "#ff0000"
+
+            [log]
+            level = "warn,jobs=debug"
+            format = "json"
+            file = "/tmp/mirai.log"
+            max_files = 2
+            channel = 99
+        "##, no_env).unwrap();
+
+        assert_eq!(config.token, "abc");
+        assert_eq!(config.prefix, "!");
+        assert_eq!(config.creator, Some(UserId(42)));
+        assert_eq!(config.admins, vec![UserId(1), UserId(2)]);
+        assert_eq!(config.guilds, vec![GuildId(3)]);
+        assert_eq!(config.timezone, chrono_tz::America::New_York);
+        assert_eq!(config.color, Colour::from_rgb(255, 0, 0));
+        assert_eq!(config.database, "/tmp/mirai.db");
+        assert_eq!(config.data_dir, "/srv/mirai");
+        assert_eq!(config.catch_up, CatchUp::All);
+        assert_eq!(config.slash_commands, SlashScope::Guilds);
+        assert_eq!(config.log.filter, LogFilter::parse("warn,jobs=debug").unwrap());
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.log.file.as_deref(), Some("/tmp/mirai.log"));
+        assert_eq!(config.log.max_files, 2);
+        assert_eq!(config.log.discord, Some(LogTarget::Channel(ChannelId(99))));
+    }
+
+    #[test]
+    fn test_debug_hides_token() {
+        let config = BotConfig::from_toml("token = \"secret-token\"", no_env).unwrap();
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("secret-token"));
+        assert!(debug.contains("<redacted>"));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = BotConfig::from_toml("token = \"abc\"", no_env).unwrap();
+
+        assert_eq!(config.prefix, "/");
+        assert_eq!(config.creator, None);
+        assert!(config.admins.is_empty());
+        assert_eq!(config.guilds, vec![MIRAI_TEAM_GUILD_ID]);
+        assert_eq!(config.timezone, BOT_TIMEZONE);
+        assert_eq!(config.database, "mirai_bot.db");
+        assert_eq!(config.data_dir, "data");
+        assert_eq!(config.catch_up, CatchUp::Once);
+        assert_eq!(config.slash_commands, SlashScope::Global);
+        assert_eq!(config.log, LogSettings::default());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let env = |key: &str| match key {
+            "bot_token" => Some("from_env".to_string()),
+            "bot_admins" => Some("10, 11".to_string()),
+            "bot_color" => Some("00ff00".to_string()),
+            _ => None,
+        };
+        let config = BotConfig::from_toml("token = \"from_file\"\nadmins = [1]", env).unwrap();
+
+        assert_eq!(config.token, "from_env");
+        assert_eq!(config.admins, vec![UserId(10), UserId(11)]);
+        assert_eq!(config.color, Colour::from_rgb(0, 255, 0));
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        assert!(matches!(BotConfig::from_toml("", no_env), Err(ConfigError::Invalid("token", _))));
+        assert!(matches!(
+            BotConfig::from_toml("token = \"a\"\nprefix = \" \"", no_env),
+            Err(ConfigError::Invalid("prefix", _))
+        ));
+        assert!(matches!(
+            BotConfig::from_toml("token = \"a\"\ntimezone = \"Mars/Olympus\"", no_env),
+            Err(ConfigError::Invalid("timezone", _))
+        ));
+        assert!(matches!(
+            BotConfig::from_toml("token = \"a\"\n[colors]\nprimary = \"blue\"", no_env),
+            Err(ConfigError::Invalid("colors.primary", _))
+        ));
+        assert!(matches!(
+            BotConfig::from_toml("token = \"a\"\ncatch_up = \"twice\"", no_env),
+            Err(ConfigError::Invalid("catch_up", _))
+        ));
+        assert!(matches!(
+            BotConfig::from_toml("token = \"a\"\nslash_commands = \"everywhere\"", no_env),
+            Err(ConfigError::Invalid("slash_commands", _))
+        ));
+        assert!(matches!(
+            BotConfig::from_toml("token = \"a\"\n[log]\nlevel = \"jobs=loud\"", no_env),
+            Err(ConfigError::Invalid("log.level", _))
+        ));
+        assert!(matches!(
+            BotConfig::from_toml("token = \"a\"\n[log]\ndm_creator = true", no_env),
+            Err(ConfigError::Invalid("log.dm_creator", _))
+        ));
+        assert!(matches!(BotConfig::from_toml("tokn = \"a\"", no_env), Err(ConfigError::Parse(_))));
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::Connection;
+use rusqlite::types::Type;
+
+/// Shared handle on the bot's SQLite database. Every store built on top of it creates
+/// its own tables when it is opened.
+#[derive(Clone)]
+pub struct Database {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        Ok(Self::from_connection(Connection::open(path)?))
+    }
+
+    pub fn in_memory() -> rusqlite::Result<Self> {
+        Ok(Self::from_connection(Connection::open_in_memory()?))
+    }
+
+    fn from_connection(connection: Connection) -> Self {
+        Self { connection: Arc::new(Mutex::new(connection)) }
+    }
+
+    pub fn with_connection<T, F>(&self, f: F) -> rusqlite::Result<T>
+        where F: FnOnce(&Connection) -> rusqlite::Result<T> {
+        let connection = self.connection.lock().expect("Database mutex poisoned");
+        f(&connection)
+    }
+}
+
+/// Adds a column to a table created by an older version of the bot.
+pub fn add_column_if_missing(
+    connection: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let mut statement = connection.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = statement.query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        connection.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))?;
+    }
+    Ok(())
+}
+
+/// Times are compared as strings in SQL, they are all stored in this format.
+pub fn time_to_column(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Reads a time stored as RFC 3339 in the column `index`.
+pub fn time_from_column(index: usize, value: String) -> rusqlite::Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(&value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(err)))
+}
@@ -0,0 +1,475 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+use rusqlite::params;
+use serde::Deserialize;
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::TypeMapKey;
+
+use crate::database::Database;
+use crate::quiz::fuzzy;
+use crate::utils::data::{DataFileError, load_toml_dir};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS debate_scores (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    played INTEGER NOT NULL,
+    wins INTEGER NOT NULL,
+    misses INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, user_id)
+);";
+
+/// Most Truth Bullets in a debate, there is one button per bullet under a statement.
+pub const MAX_BULLETS: usize = 5;
+/// Wrong shots a player can take before being out of the debate.
+pub const MAX_MISSES: u32 = 3;
+/// Points for breaking the contradiction, and lost for each wrong shot.
+pub const WIN_POINTS: i64 = 10;
+pub const MISS_PENALTY: i64 = 2;
+
+/// Evidence the players shoot at a statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthBullet {
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    pub speaker: String,
+    pub text: String,
+    /// The part of the text that can be shot, if any.
+    pub weak_point: Option<String>,
+}
+
+impl Statement {
+    /// The text with its weak point in bold, the way Discord shows it.
+    pub fn marked(&self) -> String {
+        match &self.weak_point {
+            Some(weak_point) => self.text.replacen(weak_point.as_str(), &format!("**{}**", weak_point), 1),
+            None => self.text.clone(),
+        }
+    }
+}
+
+/// A debate of the data files: statements streamed in a loop, one of them contradicted by
+/// one of the bullets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scenario {
+    pub id: String,
+    pub title: String,
+    pub context: String,
+    pub bullets: Vec<TruthBullet>,
+    pub statements: Vec<Statement>,
+    /// The statement to shoot and the bullet to shoot it with, as indices.
+    pub contradiction: (usize, usize),
+}
+
+impl Scenario {
+    /// The bullet named `text`, by its number from 1 or its name with a few typos.
+    pub fn bullet(&self, text: &str) -> Option<usize> {
+        if let Ok(number) = text.trim().parse::<usize>() {
+            return (1..=self.bullets.len()).contains(&number).then(|| number - 1);
+        }
+        self.bullets.iter().position(|bullet| fuzzy::matches(text, &bullet.name))
+    }
+
+    pub fn answer(&self) -> (&Statement, &TruthBullet) {
+        let (statement, bullet) = self.contradiction;
+        (&self.statements[statement], &self.bullets[bullet])
+    }
+}
+
+/// Raw content of a debate file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DebateFile {
+    #[serde(default)]
+    debates: Vec<ScenarioEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioEntry {
+    id: String,
+    title: String,
+    context: String,
+    bullets: Vec<BulletEntry>,
+    statements: Vec<StatementEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BulletEntry {
+    name: String,
+    description: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StatementEntry {
+    speaker: String,
+    text: String,
+    weak_point: Option<String>,
+    contradicted_by: Option<String>,
+}
+
+/// Parses a debate file, `path` being only used in the errors.
+pub fn parse_scenarios(path: &str, content: &str) -> Result<Vec<Scenario>, DataFileError> {
+    let file: DebateFile = toml::from_str(content).map_err(|err| DataFileError::Parse(path.to_string(), err))?;
+    let invalid = |reason: String| DataFileError::Invalid(path.to_string(), reason);
+
+    file.debates.into_iter().map(|entry| {
+        let id = entry.id.trim().to_string();
+        if id.is_empty() {
+            return Err(invalid("a debate has no id".to_string()));
+        }
+        if entry.bullets.is_empty() || entry.bullets.len() > MAX_BULLETS {
+            return Err(invalid(format!("{} needs between 1 and {} bullets", id, MAX_BULLETS)));
+        }
+        if entry.statements.len() < 2 {
+            return Err(invalid(format!("{} needs at least two statements", id)));
+        }
+
+        let bullets: Vec<TruthBullet> = entry.bullets.into_iter()
+            .map(|bullet| TruthBullet { name: bullet.name.trim().to_string(), description: bullet.description.trim().to_string() })
+            .collect();
+        let mut contradictions = Vec::new();
+        let mut statements = Vec::new();
+        for (index, statement) in entry.statements.into_iter().enumerate() {
+            let weak_point = statement.weak_point.map(|weak_point| weak_point.trim().to_string());
+            if weak_point.as_ref().is_some_and(|weak_point| weak_point.is_empty() || !statement.text.contains(weak_point.as_str())) {
+                return Err(invalid(format!("{}: the weak point of statement {} is not in its text", id, index + 1)));
+            }
+            if let Some(name) = statement.contradicted_by {
+                let bullet = bullets.iter().position(|bullet| bullet.name == name.trim())
+                    .ok_or_else(|| invalid(format!("{}: there is no bullet named {}", id, name)))?;
+                if weak_point.is_none() {
+                    return Err(invalid(format!("{}: statement {} is contradicted but has no weak point", id, index + 1)));
+                }
+                contradictions.push((index, bullet));
+            }
+            statements.push(Statement {
+                speaker: statement.speaker.trim().to_string(),
+                text: statement.text.trim().to_string(),
+                weak_point,
+            });
+        }
+
+        match contradictions.as_slice() {
+            [contradiction] => Ok(Scenario {
+                contradiction: *contradiction,
+                id,
+                title: entry.title.trim().to_string(),
+                context: entry.context.trim().to_string(),
+                bullets,
+                statements,
+            }),
+            _ => Err(invalid(format!("{} needs exactly one contradicted statement", id))),
+        }
+    }).collect()
+}
+
+/// Every debate read from the data files, shared by the commands.
+#[derive(Debug, Clone, Default)]
+pub struct DebateBank {
+    scenarios: Arc<Vec<Arc<Scenario>>>,
+}
+
+impl TypeMapKey for DebateBank {
+    type Value = DebateBank;
+}
+
+impl DebateBank {
+    pub fn new(scenarios: Vec<Scenario>) -> Result<Self, DataFileError> {
+        let mut ids = HashSet::new();
+        if let Some(scenario) = scenarios.iter().find(|scenario| !ids.insert(scenario.id.as_str())) {
+            return Err(DataFileError::Invalid(
+                "the debate bank".to_string(), format!("{} is the id of two debates", scenario.id)
+            ));
+        }
+        Ok(Self { scenarios: Arc::new(scenarios.into_iter().map(Arc::new).collect()) })
+    }
+
+    /// Reads the `*.toml` files of `directory`, in the order of their names.
+    pub fn load<P: AsRef<Path>>(directory: P) -> Result<Self, DataFileError> {
+        Self::new(load_toml_dir(directory.as_ref(), parse_scenarios)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.scenarios.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenarios.is_empty()
+    }
+
+    pub fn all(&self) -> &[Arc<Scenario>] {
+        &self.scenarios
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Scenario>> {
+        self.scenarios.iter().find(|scenario| scenario.id == id.trim()).cloned()
+    }
+
+    pub fn random<R: Rng>(&self, rng: &mut R) -> Option<Arc<Scenario>> {
+        self.scenarios.choose(rng).cloned()
+    }
+}
+
+/// What happened to a shot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shot {
+    /// The contradiction is broken, the debate is won.
+    Hit,
+    /// The wrong statement or the wrong bullet, with the shots the player has left.
+    Miss(u32),
+    /// Nothing to shoot in this statement, the shot does not count.
+    NoWeakPoint,
+    /// The player missed too often to shoot again.
+    OutOfShots,
+    /// Someone already won.
+    Over,
+}
+
+/// The shots of a running debate.
+#[derive(Debug, Clone)]
+pub struct Debate {
+    pub scenario: Arc<Scenario>,
+    misses: HashMap<UserId, u32>,
+    winner: Option<UserId>,
+}
+
+impl Debate {
+    pub fn new(scenario: Arc<Scenario>) -> Self {
+        Self { scenario, misses: HashMap::new(), winner: None }
+    }
+
+    /// `user` shoots `bullet` at `statement`.
+    pub fn shoot(&mut self, user: UserId, statement: usize, bullet: usize) -> Shot {
+        if self.winner.is_some() {
+            return Shot::Over;
+        }
+        if self.misses.get(&user).is_some_and(|misses| *misses >= MAX_MISSES) {
+            return Shot::OutOfShots;
+        }
+        if self.scenario.statements.get(statement).is_none_or(|statement| statement.weak_point.is_none()) {
+            return Shot::NoWeakPoint;
+        }
+        if (statement, bullet) == self.scenario.contradiction {
+            self.winner = Some(user);
+            self.misses.entry(user).or_insert(0);
+            return Shot::Hit;
+        }
+        let misses = self.misses.entry(user).or_insert(0);
+        *misses += 1;
+        Shot::Miss(MAX_MISSES - *misses)
+    }
+
+    pub fn winner(&self) -> Option<UserId> {
+        self.winner
+    }
+
+    /// Everyone who shot, with their wrong shots.
+    pub fn players(&self) -> &HashMap<UserId, u32> {
+        &self.misses
+    }
+}
+
+/// The debates a member played on a guild.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DebateScore {
+    pub played: u32,
+    pub wins: u32,
+    pub misses: u32,
+}
+
+impl DebateScore {
+    pub fn points(&self) -> i64 {
+        self.wins as i64 * WIN_POINTS - self.misses as i64 * MISS_PENALTY
+    }
+}
+
+/// The scores of the members in the `debate_scores` table.
+#[derive(Clone)]
+pub struct DebateStore {
+    database: Database,
+}
+
+impl TypeMapKey for DebateStore {
+    type Value = DebateStore;
+}
+
+impl DebateStore {
+    pub fn new(database: Database) -> rusqlite::Result<Self> {
+        database.with_connection(|connection| connection.execute_batch(SCHEMA))?;
+        Ok(Self { database })
+    }
+
+    /// Adds the shots of a finished debate to the scores of its players.
+    pub fn record(&self, guild: GuildId, debate: &Debate) -> rusqlite::Result<()> {
+        self.database.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            for (user, misses) in debate.players() {
+                let win = u32::from(debate.winner() == Some(*user));
+                transaction.execute(
+                    "INSERT INTO debate_scores (guild_id, user_id, played, wins, misses) VALUES (?1, ?2, 1, ?3, ?4)
+                     ON CONFLICT (guild_id, user_id) DO UPDATE
+                     SET played = played + 1, wins = wins + ?3, misses = misses + ?4",
+                    params![guild.0 as i64, user.0 as i64, win, misses],
+                )?;
+            }
+            transaction.commit()
+        })
+    }
+
+    pub fn score(&self, guild: GuildId, user: UserId) -> rusqlite::Result<DebateScore> {
+        self.database.with_connection(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT played, wins, misses FROM debate_scores WHERE guild_id = ?1 AND user_id = ?2"
+            )?;
+            let mut rows = statement.query_map(params![guild.0 as i64, user.0 as i64], |row| {
+                Ok(DebateScore { played: row.get(0)?, wins: row.get(1)?, misses: row.get(2)? })
+            })?;
+            rows.next().unwrap_or(Ok(DebateScore::default()))
+        })
+    }
+
+    /// The `limit` best members of `guild`, best first.
+    pub fn leaderboard(&self, guild: GuildId, limit: usize) -> rusqlite::Result<Vec<(UserId, DebateScore)>> {
+        self.database.with_connection(|connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT user_id, played, wins, misses FROM debate_scores WHERE guild_id = ?1
+                 ORDER BY wins * {} - misses * {} DESC, wins DESC, user_id LIMIT ?2",
+                WIN_POINTS, MISS_PENALTY
+            ))?;
+            let leaderboard = statement.query_map(params![guild.0 as i64, limit as i64], |row| {
+                Ok((
+                    UserId(row.get::<_, i64>(0)? as u64),
+                    DebateScore { played: row.get(1)?, wins: row.get(2)?, misses: row.get(3)? },
+                ))
+            })?
+                .collect();
+            leaderboard
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serenity::model::id::{GuildId, UserId};
+
+    use crate::database::Database;
+    use crate::debate::{Debate, DebateBank, DebateScore, DebateStore, MAX_MISSES, parse_scenarios, Shot};
+    use crate::utils::data::DataFileError;
+
+    const DEBATES: &str = r#"
+        [[debates]]
+        id = "test-cuisine"
+        title = "Le couteau de la cuisine"
+        context = "Un couteau a disparu de la cuisine."
+
+        [[debates.bullets]]
+        name = "Inventaire de la cuisine"
+        description = "Un couteau manquait dès le matin."
+
+        [[debates.bullets]]
+        name = "Témoignage de Hifumi"
+        description = "Hifumi était à la laverie toute la soirée."
+
+        [[debates.statements]]
+        speaker = "Celestia Ludenberg"
+        text = "Le couteau a été pris pendant la soirée."
+        weak_point = "pendant la soirée"
+        contradicted_by = "Inventaire de la cuisine"
+
+        [[debates.statements]]
+        speaker = "Hifumi Yamada"
+        text = "Je n'ai rien vu, j'étais occupé."
+
+        [[debates.statements]]
+        speaker = "Mondo Owada"
+        text = "C'est forcément Hifumi qui l'a pris !"
+        weak_point = "Hifumi"
+    "#;
+
+    #[test]
+    fn test_parse_scenarios() {
+        let scenarios = parse_scenarios("test.toml", DEBATES).unwrap();
+        assert_eq!(scenarios.len(), 1);
+        let scenario = &scenarios[0];
+        assert_eq!(scenario.contradiction, (0, 0));
+        assert_eq!(scenario.statements[0].marked(), "Le couteau a été pris **pendant la soirée**.");
+        assert_eq!(scenario.statements[1].marked(), "Je n'ai rien vu, j'étais occupé.");
+        assert_eq!(scenario.bullet("2"), Some(1));
+        assert_eq!(scenario.bullet("3"), None);
+        assert_eq!(scenario.bullet("inventaire de la cusine"), Some(0));
+        assert_eq!(scenario.bullet("Monokuma"), None);
+
+        let invalid = |content: String| matches!(parse_scenarios("test.toml", &content), Err(DataFileError::Invalid(_, _)));
+        assert!(invalid(DEBATES.replace("weak_point = \"pendant la soirée\"", "weak_point = \"le matin\"")));
+        assert!(invalid(DEBATES.replace("contradicted_by = \"Inventaire de la cuisine\"", "")));
+        assert!(invalid(DEBATES.replace("contradicted_by = \"Inventaire de la cuisine\"", "contradicted_by = \"Monokuma\"")));
+        assert!(invalid(DEBATES.replace("weak_point = \"pendant la soirée\"", "")));
+        assert!(matches!(parse_scenarios("test.toml", "[[debates]]\nid = 1"), Err(DataFileError::Parse(_, _))));
+
+        assert!(DebateBank::new(vec![scenario.clone(), scenario.clone()]).is_err());
+    }
+
+    #[test]
+    fn test_data_files() {
+        let bank = DebateBank::load("data/debates").unwrap();
+        assert!(!bank.is_empty());
+        assert!(bank.get(&bank.all()[0].id).is_some());
+    }
+
+    #[test]
+    fn test_shoot() {
+        let scenario = Arc::new(parse_scenarios("test.toml", DEBATES).unwrap().remove(0));
+        let mut debate = Debate::new(scenario);
+        let (makoto, kyoko) = (UserId(1), UserId(2));
+
+        assert_eq!(debate.shoot(makoto, 1, 0), Shot::NoWeakPoint);
+        assert!(debate.players().is_empty());
+        for left in (0..MAX_MISSES).rev() {
+            assert_eq!(debate.shoot(makoto, 2, 1), Shot::Miss(left));
+        }
+        assert_eq!(debate.shoot(makoto, 0, 0), Shot::OutOfShots);
+
+        assert_eq!(debate.shoot(kyoko, 0, 1), Shot::Miss(MAX_MISSES - 1));
+        assert_eq!(debate.shoot(kyoko, 0, 0), Shot::Hit);
+        assert_eq!(debate.winner(), Some(kyoko));
+        assert_eq!(debate.shoot(kyoko, 0, 0), Shot::Over);
+        assert_eq!(debate.players()[&makoto], MAX_MISSES);
+    }
+
+    #[test]
+    fn test_store() {
+        let store = DebateStore::new(Database::in_memory().unwrap()).unwrap();
+        let scenario = Arc::new(parse_scenarios("test.toml", DEBATES).unwrap().remove(0));
+        let (guild, makoto, kyoko) = (GuildId(1), UserId(1), UserId(2));
+
+        for _ in 0..2 {
+            let mut debate = Debate::new(scenario.clone());
+            debate.shoot(makoto, 2, 0);
+            debate.shoot(kyoko, 0, 0);
+            store.record(guild, &debate).unwrap();
+        }
+
+        assert_eq!(store.score(guild, kyoko).unwrap(), DebateScore { played: 2, wins: 2, misses: 0 });
+        let score = store.score(guild, makoto).unwrap();
+        assert_eq!(score, DebateScore { played: 2, wins: 0, misses: 2 });
+        assert_eq!(score.points(), -4);
+        assert_eq!(store.score(GuildId(2), kyoko).unwrap(), DebateScore::default());
+
+        let leaderboard = store.leaderboard(guild, 10).unwrap();
+        assert_eq!(leaderboard.iter().map(|(user, _)| *user).collect::<Vec<_>>(), vec![kyoko, makoto]);
+        assert_eq!(store.leaderboard(guild, 1).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,362 @@
+use std::fmt;
+use std::io::{Cursor, Read};
+
+use rusqlite::{Connection, OpenFlags};
+use zip::ZipArchive;
+
+use crate::flashcards::{Card, card_side, MAX_DECK_NAME};
+
+/// Largest file read, Discord lets bots download bigger ones.
+pub const MAX_IMPORT_SIZE: u64 = 8 * 1024 * 1024;
+/// Largest Anki collection unpacked from a package, whatever its compressed size.
+pub const MAX_COLLECTION_SIZE: u64 = 64 * 1024 * 1024;
+/// Most cards imported at once.
+pub const MAX_IMPORT_CARDS: usize = 2000;
+
+/// First rows skipped when they name the columns instead of being a card.
+const HEADERS: [(&str, &str); 3] = [("question", "réponse"), ("recto", "verso"), ("front", "back")];
+
+/// The collections an Anki package may hold, from the newest format we can read. Recent
+/// versions of Anki only write a `collection.anki21b` compressed with zstd unless told to
+/// stay compatible with the older ones.
+const ANKI_COLLECTIONS: [&str; 2] = ["collection.anki21", "collection.anki2"];
+const ANKI_RECENT_COLLECTION: &str = "collection.anki21b";
+const ANKI_FIELD_SEPARATOR: char = '\u{1f}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Csv,
+    Tsv,
+    Anki,
+}
+
+impl ImportFormat {
+    /// Guesses the format of a file from its name.
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let extension = filename.rsplit_once('.')?.1.to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(ImportFormat::Csv),
+            "tsv" | "txt" => Some(ImportFormat::Tsv),
+            "apkg" => Some(ImportFormat::Anki),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    UnknownFormat(String),
+    TooLarge,
+    Invalid(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::UnknownFormat(filename) => write!(
+                f, "Je ne sais pas lire `{}`, envoie un fichier `.csv`, `.tsv` ou `.apkg`.", filename
+            ),
+            ImportError::TooLarge => write!(
+                f, "Le fichier est trop gros, {} Mo au plus.", MAX_IMPORT_SIZE / (1024 * 1024)
+            ),
+            ImportError::Invalid(reason) => write!(f, "Le fichier est illisible : {}.", reason),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// A card read from a file, not saved yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewCard {
+    pub front: String,
+    pub back: String,
+}
+
+/// A row of the file that could not be turned into a card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    /// Where it is in the file, like `ligne 4`.
+    pub location: String,
+    pub reason: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} : {}", self.location, self.reason)
+    }
+}
+
+/// What was read from a file: the cards and the rows that were skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Import {
+    pub cards: Vec<NewCard>,
+    pub errors: Vec<RowError>,
+}
+
+impl Import {
+    fn push(&mut self, location: String, front: &str, back: &str) {
+        if self.cards.len() >= MAX_IMPORT_CARDS {
+            self.errors.push(RowError { location, reason: format!("plus de {} fiches", MAX_IMPORT_CARDS) });
+            return;
+        }
+        match (card_side(front), card_side(back)) {
+            (Ok(front), Ok(back)) => self.cards.push(NewCard { front, back }),
+            (Err(reason), _) | (_, Err(reason)) => self.errors.push(RowError { location, reason }),
+        }
+    }
+}
+
+fn is_header(front: &str, back: &str) -> bool {
+    let (front, back) = (front.trim().to_lowercase(), back.trim().to_lowercase());
+    HEADERS.iter().any(|(header_front, header_back)| front == *header_front && back == *header_back)
+}
+
+/// Reads the cards of a file sent by a user, the format being guessed from its name.
+pub fn read_cards(filename: &str, data: &[u8]) -> Result<Import, ImportError> {
+    if data.len() as u64 > MAX_IMPORT_SIZE {
+        return Err(ImportError::TooLarge);
+    }
+    match ImportFormat::from_filename(filename) {
+        Some(ImportFormat::Csv) => read_delimited(data, b','),
+        Some(ImportFormat::Tsv) => read_delimited(data, b'\t'),
+        Some(ImportFormat::Anki) => read_anki(data),
+        None => Err(ImportError::UnknownFormat(filename.to_string())),
+    }
+}
+
+/// One card per row, the question in the first column and the answer in the second. Rows
+/// with more columns keep their first two, for files exported with tags or notes.
+pub fn read_delimited(data: &[u8], delimiter: u8) -> Result<Import, ImportError> {
+    let data = data.strip_prefix("\u{feff}".as_bytes()).unwrap_or(data);
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(data);
+
+    let mut import = Import::default();
+    for (index, record) in reader.records().enumerate() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                let line = err.position().map(|position| position.line()).unwrap_or(index as u64 + 1);
+                import.errors.push(RowError { location: format!("ligne {}", line), reason: err.to_string() });
+                continue;
+            }
+        };
+        let location = format!("ligne {}", record.position().map(|position| position.line()).unwrap_or(index as u64 + 1));
+
+        match (record.get(0), record.get(1)) {
+            (Some(front), Some(back)) if index == 0 && is_header(front, back) => {}
+            (Some(front), Some(back)) => import.push(location, front, back),
+            (Some(front), None) if front.trim().is_empty() => {}
+            _ => import.errors.push(RowError { location, reason: "il manque la réponse".to_string() }),
+        }
+    }
+    Ok(import)
+}
+
+/// Turns the HTML of an Anki field into plain text.
+fn anki_text(field: &str) -> String {
+    let mut text = String::new();
+    let mut tag: Option<String> = None;
+    for c in field.chars() {
+        match (&mut tag, c) {
+            (None, '<') => tag = Some(String::new()),
+            (Some(name), '>') => {
+                let name = name.trim_start_matches('/').split_whitespace().next().unwrap_or_default().to_lowercase();
+                if matches!(name.as_str(), "br" | "br/" | "div" | "p" | "li") && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                tag = None;
+            }
+            (Some(name), c) => name.push(c),
+            (None, c) => text.push(c),
+        }
+    }
+
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// An `.apkg` is a zip holding the SQLite collection of Anki, it is copied to a temporary
+/// file to be opened. Each note becomes a card made of its first two fields.
+pub fn read_anki(data: &[u8]) -> Result<Import, ImportError> {
+    let mut archive = ZipArchive::new(Cursor::new(data))
+        .map_err(|err| ImportError::Invalid(format!("ce n'est pas un paquet Anki ({})", err)))?;
+
+    let name = match ANKI_COLLECTIONS.iter().find(|name| archive.file_names().any(|file| file == **name)) {
+        Some(name) => *name,
+        None if archive.file_names().any(|file| file == ANKI_RECENT_COLLECTION) => {
+            return Err(ImportError::Invalid(
+                "ce paquet vient d'une version récente d'Anki, exporte-le avec « Support older Anki versions »"
+                    .to_string()
+            ));
+        }
+        None => return Err(ImportError::Invalid("il n'y a pas de collection dans ce paquet".to_string())),
+    };
+
+    let file = archive.by_name(name).map_err(|err| ImportError::Invalid(err.to_string()))?;
+    let size = file.size();
+    let collection = read_bounded(file, size, MAX_COLLECTION_SIZE)?;
+
+    let path = std::env::temp_dir().join(format!("mirai_import_{}.anki2", uuid::Uuid::new_v4()));
+    std::fs::write(&path, &collection).map_err(|err| ImportError::Invalid(err.to_string()))?;
+    let notes = read_anki_notes(&path);
+    let _ = std::fs::remove_file(&path);
+
+    let notes = notes.map_err(|err| ImportError::Invalid(format!("collection Anki invalide ({})", err)))?;
+    let mut import = Import::default();
+    for (index, fields) in notes.iter().enumerate() {
+        let location = format!("note {}", index + 1);
+        let fields: Vec<&str> = fields.split(ANKI_FIELD_SEPARATOR).collect();
+        match fields.as_slice() {
+            [front, back, ..] => import.push(location, &anki_text(front), &anki_text(back)),
+            _ => import.errors.push(RowError { location, reason: "la note n'a qu'un champ".to_string() }),
+        }
+    }
+    Ok(import)
+}
+
+/// Reads an entry of `size` bytes as told by its header, refusing to unpack more than `cap`
+/// bytes even when the header lies.
+fn read_bounded<R: Read>(reader: R, size: u64, cap: u64) -> Result<Vec<u8>, ImportError> {
+    let too_big = || ImportError::Invalid(format!("la collection dépasse {} Mo une fois décompressée", cap / (1024 * 1024)));
+    if size > cap {
+        return Err(too_big());
+    }
+    let mut data = Vec::new();
+    reader.take(cap + 1).read_to_end(&mut data).map_err(|err| ImportError::Invalid(err.to_string()))?;
+    match data.len() as u64 > cap {
+        true => Err(too_big()),
+        false => Ok(data),
+    }
+}
+
+fn read_anki_notes(path: &std::path::Path) -> rusqlite::Result<Vec<String>> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare("SELECT flds FROM notes ORDER BY id")?;
+    let notes = statement.query_map([], |row| row.get(0))?.collect();
+    notes
+}
+
+/// The cards as CSV, with a header row that `read_cards` skips.
+pub fn export_csv(cards: &[Card]) -> Vec<u8> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    // Writing to a Vec cannot fail.
+    writer.write_record([HEADERS[0].0, HEADERS[0].1]).expect("Could not write CSV");
+    for card in cards {
+        writer.write_record([&card.front, &card.back]).expect("Could not write CSV");
+    }
+    writer.into_inner().expect("Could not write CSV")
+}
+
+/// The name of the file a deck is exported to.
+pub fn export_filename(deck: &str) -> String {
+    let name: String = deck.chars()
+        .take(MAX_DECK_NAME)
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    format!("{}.csv", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use rusqlite::{Connection, params};
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use crate::flashcards::Card;
+    use crate::flashcards::import::{
+        anki_text, export_csv, export_filename, ImportError, ImportFormat, NewCard, read_anki, read_bounded,
+        read_cards, read_delimited,
+    };
+
+    fn card(front: &str, back: &str) -> NewCard {
+        NewCard { front: front.to_string(), back: back.to_string() }
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(ImportFormat::from_filename("chimie.CSV"), Some(ImportFormat::Csv));
+        assert_eq!(ImportFormat::from_filename("chimie.tsv"), Some(ImportFormat::Tsv));
+        assert_eq!(ImportFormat::from_filename("chimie.apkg"), Some(ImportFormat::Anki));
+        assert_eq!(ImportFormat::from_filename("chimie"), None);
+        assert_eq!(read_cards("chimie.pdf", b""), Err(ImportError::UnknownFormat("chimie.pdf".to_string())));
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let data = "\u{feff}Question,Réponse\nH2O,Eau\n\"NaCl\",\"Sel, de table\"\nCO2\n,vide\n\nO2,Dioxygène,gaz\n";
+        let import = read_delimited(data.as_bytes(), b',').unwrap();
+
+        assert_eq!(import.cards, vec![card("H2O", "Eau"), card("NaCl", "Sel, de table"), card("O2", "Dioxygène")]);
+        let errors: Vec<String> = import.errors.iter().map(|error| error.to_string()).collect();
+        assert_eq!(errors, vec!["ligne 4 : il manque la réponse", "ligne 5 : un côté est vide"]);
+
+        let import = read_delimited("H2O\tEau\n".as_bytes(), b'\t').unwrap();
+        assert_eq!(import.cards, vec![card("H2O", "Eau")]);
+    }
+
+    #[test]
+    fn test_export() {
+        let cards = vec![
+            Card { id: 1, deck_id: 1, front: "H2O".to_string(), back: "Eau".to_string() },
+            Card { id: 2, deck_id: 1, front: "NaCl".to_string(), back: "Sel, de table".to_string() },
+        ];
+        let data = export_csv(&cards);
+        assert_eq!(String::from_utf8(data.clone()).unwrap(), "question,réponse\nH2O,Eau\nNaCl,\"Sel, de table\"\n");
+        assert_eq!(read_cards("chimie.csv", &data).unwrap().cards, vec![card("H2O", "Eau"), card("NaCl", "Sel, de table")]);
+        assert_eq!(export_filename("Chimie / Bio"), "Chimie___Bio.csv");
+    }
+
+    #[test]
+    fn test_anki_text() {
+        assert_eq!(anki_text("<b>H<sub>2</sub>O</b>"), "H2O");
+        assert_eq!(anki_text("Eau<br>liquide&nbsp;&amp; douce"), "Eau\nliquide & douce");
+        assert_eq!(anki_text("<div>Sel</div><div>de table</div>"), "Sel\nde table");
+    }
+
+    #[test]
+    fn test_read_bounded() {
+        assert_eq!(read_bounded(&[1u8; 10][..], 10, 10).unwrap(), vec![1; 10]);
+        assert!(matches!(read_bounded(&[1u8; 10][..], 11, 10), Err(ImportError::Invalid(_))));
+        // The header says the entry is small, the data does not.
+        assert!(matches!(read_bounded(&[1u8; 11][..], 1, 10), Err(ImportError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_read_anki() {
+        let path = std::env::temp_dir().join(format!("mirai_test_{}.anki2", uuid::Uuid::new_v4()));
+        let connection = Connection::open(&path).unwrap();
+        connection.execute_batch("CREATE TABLE notes (id INTEGER PRIMARY KEY, flds TEXT NOT NULL)").unwrap();
+        for (id, fields) in [(1, "H<sub>2</sub>O\u{1f}Eau"), (2, "Seul"), (3, "NaCl\u{1f}Sel\u{1f}chimie")] {
+            connection.execute("INSERT INTO notes (id, flds) VALUES (?1, ?2)", params![id, fields]).unwrap();
+        }
+        drop(connection);
+        let collection = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut package = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        package.start_file("collection.anki2", FileOptions::default()).unwrap();
+        package.write_all(&collection).unwrap();
+        package.start_file("media", FileOptions::default()).unwrap();
+        package.write_all(b"{}").unwrap();
+        let package = package.finish().unwrap().into_inner();
+
+        let import = read_anki(&package).unwrap();
+        assert_eq!(import.cards, vec![card("H2O", "Eau"), card("NaCl", "Sel")]);
+        assert_eq!(import.errors[0].to_string(), "note 2 : la note n'a qu'un champ");
+
+        assert!(matches!(read_anki(b"not a zip"), Err(ImportError::Invalid(_))));
+    }
+}
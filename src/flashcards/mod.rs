@@ -0,0 +1,396 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params, Row};
+use serenity::model::id::UserId;
+use serenity::prelude::TypeMapKey;
+
+use crate::database::{Database, time_from_column, time_to_column};
+
+pub(crate) mod import;
+pub(crate) mod sm2;
+
+pub use import::{Import, NewCard};
+pub use sm2::{Grade, Review};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS flashcard_decks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    owner INTEGER NOT NULL,
+    name TEXT NOT NULL COLLATE NOCASE,
+    created_at TEXT NOT NULL,
+    UNIQUE (owner, name)
+);
+CREATE TABLE IF NOT EXISTS flashcard_cards (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    deck_id INTEGER NOT NULL,
+    front TEXT NOT NULL,
+    back TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS flashcard_reviews (
+    user_id INTEGER NOT NULL,
+    card_id INTEGER NOT NULL,
+    ease REAL NOT NULL,
+    interval INTEGER NOT NULL,
+    repetitions INTEGER NOT NULL,
+    due TEXT NOT NULL,
+    PRIMARY KEY (user_id, card_id)
+);";
+
+/// Longest deck name, so that it fits in embed titles.
+pub const MAX_DECK_NAME: usize = 64;
+/// Longest side of a card, the review embeds show both sides.
+pub const MAX_CARD_SIDE: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deck {
+    pub id: i64,
+    pub owner: UserId,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Card {
+    pub id: i64,
+    pub deck_id: i64,
+    pub front: String,
+    pub back: String,
+}
+
+/// A deck with how many cards it holds and how many its owner has to review.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeckStats {
+    pub deck: Deck,
+    pub cards: usize,
+    pub due: usize,
+}
+
+#[derive(Debug)]
+pub enum FlashcardError {
+    Database(rusqlite::Error),
+    DeckExists(String),
+    UnknownDeck(String),
+    UnknownCard(i64),
+    InvalidDeckName,
+    InvalidCard(String),
+}
+
+impl fmt::Display for FlashcardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlashcardError::Database(err) => write!(f, "erreur de base de données : {}", err),
+            FlashcardError::DeckExists(name) => write!(f, "Tu as déjà un paquet `{}`.", name),
+            FlashcardError::UnknownDeck(name) => write!(f, "Tu n'as pas de paquet `{}`.", name),
+            FlashcardError::UnknownCard(id) => write!(f, "Tu n'as pas de fiche n°{}.", id),
+            FlashcardError::InvalidDeckName => write!(
+                f, "Le nom d'un paquet doit faire entre 1 et {} caractères.", MAX_DECK_NAME
+            ),
+            FlashcardError::InvalidCard(reason) => write!(f, "Fiche invalide : {}.", reason),
+        }
+    }
+}
+
+impl std::error::Error for FlashcardError {}
+
+impl From<rusqlite::Error> for FlashcardError {
+    fn from(err: rusqlite::Error) -> Self { FlashcardError::Database(err) }
+}
+
+pub type FlashcardResult<T> = Result<T, FlashcardError>;
+
+fn deck_from_row(row: &Row) -> rusqlite::Result<Deck> {
+    Ok(Deck { id: row.get(0)?, owner: UserId(row.get::<_, i64>(1)? as u64), name: row.get(2)? })
+}
+
+fn card_from_row(row: &Row) -> rusqlite::Result<Card> {
+    Ok(Card { id: row.get(0)?, deck_id: row.get(1)?, front: row.get(2)?, back: row.get(3)? })
+}
+
+/// Trims a card side and checks it can be shown, the error being why it cannot.
+pub fn card_side(side: &str) -> Result<String, String> {
+    let side = side.trim();
+    match side.chars().count() {
+        0 => Err("un côté est vide".to_string()),
+        count if count > MAX_CARD_SIDE => Err(format!("un côté dépasse {} caractères", MAX_CARD_SIDE)),
+        _ => Ok(side.to_string()),
+    }
+}
+
+/// Decks, cards and the review intervals of each user, in the `flashcard_*` tables. A card
+/// is new to a user until they grade it for the first time.
+#[derive(Clone)]
+pub struct FlashcardStore {
+    database: Database,
+}
+
+impl TypeMapKey for FlashcardStore {
+    type Value = FlashcardStore;
+}
+
+/// `name` trimmed, as long as it is a valid deck name.
+pub fn deck_name(name: &str) -> FlashcardResult<&str> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_DECK_NAME {
+        return Err(FlashcardError::InvalidDeckName);
+    }
+    Ok(name)
+}
+
+impl FlashcardStore {
+    pub fn new(database: Database) -> rusqlite::Result<Self> {
+        database.with_connection(|connection| connection.execute_batch(SCHEMA))?;
+        Ok(Self { database })
+    }
+
+    pub fn create_deck(&self, owner: UserId, name: &str, now: DateTime<Utc>) -> FlashcardResult<Deck> {
+        let name = deck_name(name)?;
+        let id = self.database.with_connection(|connection| {
+            let inserted = connection.execute(
+                "INSERT OR IGNORE INTO flashcard_decks (owner, name, created_at) VALUES (?1, ?2, ?3)",
+                params![owner.0 as i64, name, time_to_column(now)],
+            )?;
+            Ok((inserted > 0).then(|| connection.last_insert_rowid()))
+        })?;
+
+        match id {
+            Some(id) => Ok(Deck { id, owner, name: name.to_string() }),
+            None => Err(FlashcardError::DeckExists(name.to_string())),
+        }
+    }
+
+    /// The deck of `owner` called `name`, whatever its case.
+    pub fn deck(&self, owner: UserId, name: &str) -> FlashcardResult<Deck> {
+        let name = name.trim();
+        self.database.with_connection(|connection| {
+            connection.query_row(
+                "SELECT id, owner, name FROM flashcard_decks WHERE owner = ?1 AND name = ?2",
+                params![owner.0 as i64, name],
+                deck_from_row,
+            ).optional()
+        })?.ok_or_else(|| FlashcardError::UnknownDeck(name.to_string()))
+    }
+
+    /// The deck of `owner` called `name`, created if they have none.
+    pub fn deck_or_create(&self, owner: UserId, name: &str, now: DateTime<Utc>) -> FlashcardResult<Deck> {
+        match self.deck(owner, name) {
+            Err(FlashcardError::UnknownDeck(_)) => self.create_deck(owner, name, now),
+            deck => deck,
+        }
+    }
+
+    pub fn decks(&self, owner: UserId, now: DateTime<Utc>) -> FlashcardResult<Vec<DeckStats>> {
+        Ok(self.database.with_connection(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT d.id, d.owner, d.name, COUNT(c.id),
+                 COUNT(c.id) - COUNT(r.card_id) + COALESCE(SUM(r.due <= ?2), 0)
+                 FROM flashcard_decks d
+                 LEFT JOIN flashcard_cards c ON c.deck_id = d.id
+                 LEFT JOIN flashcard_reviews r ON r.card_id = c.id AND r.user_id = d.owner
+                 WHERE d.owner = ?1
+                 GROUP BY d.id ORDER BY d.name"
+            )?;
+            let decks = statement.query_map(params![owner.0 as i64, time_to_column(now)], |row| Ok(DeckStats {
+                deck: deck_from_row(row)?,
+                cards: row.get::<_, i64>(3)? as usize,
+                due: row.get::<_, i64>(4)? as usize,
+            }))?.collect();
+            decks
+        })?)
+    }
+
+    /// Deletes a deck with its cards and everyone's reviews of them.
+    pub fn delete_deck(&self, deck: &Deck) -> FlashcardResult<()> {
+        self.database.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            transaction.execute(
+                "DELETE FROM flashcard_reviews WHERE card_id IN (SELECT id FROM flashcard_cards WHERE deck_id = ?1)",
+                params![deck.id],
+            )?;
+            transaction.execute("DELETE FROM flashcard_cards WHERE deck_id = ?1", params![deck.id])?;
+            transaction.execute("DELETE FROM flashcard_decks WHERE id = ?1", params![deck.id])?;
+            transaction.commit()
+        })?;
+        Ok(())
+    }
+
+    pub fn add_card(&self, deck: &Deck, front: &str, back: &str) -> FlashcardResult<Card> {
+        let front = card_side(front).map_err(FlashcardError::InvalidCard)?;
+        let back = card_side(back).map_err(FlashcardError::InvalidCard)?;
+        let id = self.database.with_connection(|connection| insert_card(connection, deck.id, &front, &back))?;
+        Ok(Card { id, deck_id: deck.id, front, back })
+    }
+
+    /// Adds the cards read from a file, all of them or none.
+    pub fn add_cards(&self, deck: &Deck, cards: &[NewCard]) -> FlashcardResult<usize> {
+        self.database.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            for card in cards {
+                insert_card(&transaction, deck.id, &card.front, &card.back)?;
+            }
+            transaction.commit()
+        })?;
+        Ok(cards.len())
+    }
+
+    pub fn cards(&self, deck: &Deck) -> FlashcardResult<Vec<Card>> {
+        Ok(self.database.with_connection(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT id, deck_id, front, back FROM flashcard_cards WHERE deck_id = ?1 ORDER BY id"
+            )?;
+            let cards = statement.query_map(params![deck.id], card_from_row)?.collect();
+            cards
+        })?)
+    }
+
+    /// Removes a card from one of the decks of `owner`.
+    pub fn remove_card(&self, owner: UserId, card_id: i64) -> FlashcardResult<Card> {
+        let card = self.database.with_connection(|connection| {
+            connection.query_row(
+                "SELECT c.id, c.deck_id, c.front, c.back FROM flashcard_cards c
+                 JOIN flashcard_decks d ON d.id = c.deck_id
+                 WHERE c.id = ?1 AND d.owner = ?2",
+                params![card_id, owner.0 as i64],
+                card_from_row,
+            ).optional()
+        })?.ok_or(FlashcardError::UnknownCard(card_id))?;
+
+        self.database.with_connection(|connection| {
+            connection.execute("DELETE FROM flashcard_reviews WHERE card_id = ?1", params![card.id])?;
+            connection.execute("DELETE FROM flashcard_cards WHERE id = ?1", params![card.id])
+        })?;
+        Ok(card)
+    }
+
+    /// The cards of `deck` that `user` has to review at `now`, the late ones first and then
+    /// the ones they never saw.
+    pub fn due_cards(
+        &self,
+        user: UserId,
+        deck: &Deck,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> FlashcardResult<Vec<(Card, Review)>> {
+        Ok(self.database.with_connection(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT c.id, c.deck_id, c.front, c.back, r.ease, r.interval, r.repetitions, r.due
+                 FROM flashcard_cards c
+                 LEFT JOIN flashcard_reviews r ON r.card_id = c.id AND r.user_id = ?1
+                 WHERE c.deck_id = ?2 AND (r.due IS NULL OR r.due <= ?3)
+                 ORDER BY r.due IS NULL, r.due, c.id
+                 LIMIT ?4"
+            )?;
+            let cards = statement.query_map(
+                params![user.0 as i64, deck.id, time_to_column(now), limit as i64],
+                |row| {
+                    let review = match row.get::<_, Option<String>>(7)? {
+                        Some(due) => Review {
+                            ease: row.get(4)?,
+                            interval: row.get(5)?,
+                            repetitions: row.get(6)?,
+                            due: time_from_column(7, due)?,
+                        },
+                        None => Review::new(now),
+                    };
+                    Ok((card_from_row(row)?, review))
+                },
+            )?.collect();
+            cards
+        })?)
+    }
+
+    pub fn save_review(&self, user: UserId, card: &Card, review: &Review) -> FlashcardResult<()> {
+        self.database.with_connection(|connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO flashcard_reviews (user_id, card_id, ease, interval, repetitions, due)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    user.0 as i64,
+                    card.id,
+                    review.ease,
+                    review.interval,
+                    review.repetitions,
+                    time_to_column(review.due),
+                ],
+            )
+        })?;
+        Ok(())
+    }
+}
+
+fn insert_card(connection: &Connection, deck_id: i64, front: &str, back: &str) -> rusqlite::Result<i64> {
+    connection.execute(
+        "INSERT INTO flashcard_cards (deck_id, front, back) VALUES (?1, ?2, ?3)",
+        params![deck_id, front, back],
+    )?;
+    Ok(connection.last_insert_rowid())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use serenity::model::id::UserId;
+
+    use crate::database::Database;
+    use crate::flashcards::{FlashcardError, FlashcardStore, Grade, MAX_DECK_NAME};
+
+    #[test]
+    fn test_decks() {
+        let store = FlashcardStore::new(Database::in_memory().unwrap()).unwrap();
+        let now = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        let (makoto, kyoko) = (UserId(1), UserId(2));
+
+        let deck = store.create_deck(makoto, " Chimie ", now).unwrap();
+        assert_eq!(deck.name, "Chimie");
+        assert!(matches!(store.create_deck(makoto, "chimie", now), Err(FlashcardError::DeckExists(_))));
+        assert!(matches!(store.create_deck(makoto, "  ", now), Err(FlashcardError::InvalidDeckName)));
+        assert!(matches!(store.create_deck(makoto, &"a".repeat(MAX_DECK_NAME + 1), now), Err(FlashcardError::InvalidDeckName)));
+        store.create_deck(kyoko, "Chimie", now).unwrap();
+
+        assert_eq!(store.deck(makoto, "CHIMIE").unwrap(), deck);
+        assert!(matches!(store.deck(makoto, "Histoire"), Err(FlashcardError::UnknownDeck(_))));
+
+        let card = store.add_card(&deck, "H2O", "Eau").unwrap();
+        store.add_card(&deck, "NaCl", "Sel").unwrap();
+        assert!(matches!(store.add_card(&deck, "CO2", ""), Err(FlashcardError::InvalidCard(_))));
+        assert_eq!(store.cards(&deck).unwrap().len(), 2);
+
+        let stats = store.decks(makoto, now).unwrap();
+        assert_eq!((stats.len(), stats[0].cards, stats[0].due), (1, 2, 2));
+
+        assert!(matches!(store.remove_card(kyoko, card.id), Err(FlashcardError::UnknownCard(_))));
+        assert_eq!(store.remove_card(makoto, card.id).unwrap(), card);
+        assert_eq!(store.cards(&deck).unwrap().len(), 1);
+
+        store.delete_deck(&deck).unwrap();
+        assert!(store.decks(makoto, now).unwrap().is_empty());
+        assert_eq!(store.decks(kyoko, now).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_reviews() {
+        let store = FlashcardStore::new(Database::in_memory().unwrap()).unwrap();
+        let now = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        let user = UserId(1);
+        let deck = store.create_deck(user, "Chimie", now).unwrap();
+        let water = store.add_card(&deck, "H2O", "Eau").unwrap();
+        let salt = store.add_card(&deck, "NaCl", "Sel").unwrap();
+
+        let due = store.due_cards(user, &deck, now, 10).unwrap();
+        assert_eq!(due.len(), 2);
+        let (card, review) = &due[0];
+        assert_eq!(card, &water);
+        store.save_review(user, card, &review.grade(Grade::Good, now)).unwrap();
+        store.save_review(user, &salt, &due[1].1.grade(Grade::Easy, now)).unwrap();
+
+        assert!(store.due_cards(user, &deck, now, 10).unwrap().is_empty());
+        assert_eq!(store.decks(user, now).unwrap()[0].due, 0);
+        assert_eq!(store.due_cards(UserId(2), &deck, now, 1).unwrap().len(), 1);
+
+        let tomorrow = now + Duration::days(1);
+        let due = store.due_cards(user, &deck, tomorrow, 10).unwrap();
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[0].1.repetitions, 1);
+        assert_eq!(due[0].1.due, tomorrow);
+        assert_eq!(store.decks(user, tomorrow).unwrap()[0].due, 2);
+    }
+}
@@ -0,0 +1,142 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// Ease of a card that was never reviewed.
+pub const INITIAL_EASE: f64 = 2.5;
+/// SM-2 never lets a card get harder than this.
+pub const MIN_EASE: f64 = 1.3;
+
+/// How well a card was remembered, as graded by the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grade {
+    /// Forgotten, the card starts over.
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl Grade {
+    pub const ALL: [Grade; 4] = [Grade::Again, Grade::Hard, Grade::Good, Grade::Easy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Grade::Again => "again",
+            Grade::Hard => "hard",
+            Grade::Good => "good",
+            Grade::Easy => "easy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|grade| grade.name() == name)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Grade::Again => "À revoir",
+            Grade::Hard => "Difficile",
+            Grade::Good => "Bien",
+            Grade::Easy => "Facile",
+        }
+    }
+
+    /// The 0 to 5 quality of SM-2.
+    pub fn quality(&self) -> u8 {
+        match self {
+            Grade::Again => 1,
+            Grade::Hard => 3,
+            Grade::Good => 4,
+            Grade::Easy => 5,
+        }
+    }
+}
+
+/// Where a user stands with a card.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Review {
+    pub ease: f64,
+    /// Days until the next review.
+    pub interval: u32,
+    /// Reviews in a row the card was remembered.
+    pub repetitions: u32,
+    pub due: DateTime<Utc>,
+}
+
+impl Review {
+    /// A card seen for the first time, due at once.
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { ease: INITIAL_EASE, interval: 0, repetitions: 0, due: now }
+    }
+
+    /// The review after grading the card at `now`.
+    pub fn grade(&self, grade: Grade, now: DateTime<Utc>) -> Self {
+        let quality = grade.quality() as f64;
+        let (interval, repetitions) = match grade {
+            Grade::Again => (1, 0),
+            _ => {
+                let interval = match self.repetitions {
+                    0 => 1,
+                    1 => 6,
+                    _ => (self.interval as f64 * self.ease).round() as u32,
+                };
+                (interval, self.repetitions + 1)
+            }
+        };
+        let ease = (self.ease + 0.1 - (5.0 - quality) * (0.08 + (5.0 - quality) * 0.02)).max(MIN_EASE);
+
+        Self { ease, interval, repetitions, due: now + Duration::days(interval as i64) }
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.due <= now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::flashcards::sm2::{Grade, MIN_EASE, Review};
+
+    #[test]
+    fn test_grade() {
+        let now = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        let review = Review::new(now);
+        assert!(review.is_due(now));
+
+        let first = review.grade(Grade::Good, now);
+        assert_eq!((first.interval, first.repetitions), (1, 1));
+        assert_eq!(first.due, now + Duration::days(1));
+        assert!((first.ease - 2.5).abs() < 1e-9);
+
+        let second = first.grade(Grade::Easy, first.due);
+        assert_eq!((second.interval, second.repetitions), (6, 2));
+        assert!((second.ease - 2.6).abs() < 1e-9);
+
+        let third = second.grade(Grade::Hard, second.due);
+        assert_eq!(third.interval, 16);
+        assert!((third.ease - 2.46).abs() < 1e-9);
+        assert!(!third.is_due(second.due + Duration::days(15)));
+
+        let forgotten = third.grade(Grade::Again, third.due);
+        assert_eq!((forgotten.interval, forgotten.repetitions), (1, 0));
+        assert!(forgotten.ease < third.ease);
+    }
+
+    #[test]
+    fn test_min_ease() {
+        let now = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        let mut review = Review::new(now);
+        for _ in 0..10 {
+            review = review.grade(Grade::Again, now);
+        }
+        assert_eq!(review.ease, MIN_EASE);
+    }
+
+    #[test]
+    fn test_grade_names() {
+        for grade in Grade::ALL {
+            assert_eq!(Grade::from_name(grade.name()), Some(grade));
+        }
+    }
+}
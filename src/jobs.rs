@@ -0,0 +1,468 @@
+use std::future::Future;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{OptionalExtension, params};
+use rusqlite::types::Type;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::database::{Database, time_from_column};
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::utils::time::{Schedule, SharedClock, sleep_until};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS scheduled_jobs (
+    name TEXT PRIMARY KEY,
+    schedule TEXT NOT NULL,
+    catch_up TEXT NOT NULL,
+    last_run TEXT,
+    next_run TEXT
+);";
+
+/// Most missed runs replayed by `CatchUp::All`, the oldest ones are dropped beyond that.
+pub const MAX_CATCH_UP_RUNS: usize = 24;
+
+/// What a job does with the runs that were due while the bot was down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CatchUp {
+    Skip,
+    #[default]
+    Once,
+    All,
+}
+
+impl CatchUp {
+    pub const ALL: [CatchUp; 3] = [CatchUp::Skip, CatchUp::Once, CatchUp::All];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CatchUp::Skip => "skip",
+            CatchUp::Once => "once",
+            CatchUp::All => "all",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|catch_up| catch_up.name() == name)
+    }
+
+    /// Missed runs to replay, `missed` being sorted from the oldest to the latest.
+    pub fn select(&self, mut missed: Vec<DateTime<Utc>>) -> Vec<DateTime<Utc>> {
+        match self {
+            CatchUp::Skip => Vec::new(),
+            CatchUp::Once => missed.pop().into_iter().collect(),
+            CatchUp::All => missed.split_off(missed.len().saturating_sub(MAX_CATCH_UP_RUNS)),
+        }
+    }
+}
+
+/// What is remembered of a job between two runs of the bot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobState {
+    pub schedule: String,
+    pub catch_up: CatchUp,
+    pub last_run: Option<DateTime<Utc>>,
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+fn optional_time_from_column(index: usize, value: Option<String>) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    value.map(|value| time_from_column(index, value)).transpose()
+}
+
+/// Jobs persisted in the `scheduled_jobs` table, times are stored as RFC 3339 strings.
+#[derive(Clone)]
+pub struct JobStore {
+    database: Database,
+}
+
+impl JobStore {
+    pub fn new(database: Database) -> rusqlite::Result<Self> {
+        database.with_connection(|connection| connection.execute_batch(SCHEMA))?;
+        Ok(Self { database })
+    }
+
+    pub fn get(&self, name: &str) -> rusqlite::Result<Option<JobState>> {
+        self.database.with_connection(|connection| {
+            connection.query_row(
+                "SELECT schedule, catch_up, last_run, next_run FROM scheduled_jobs WHERE name = ?1",
+                params![name],
+                |row| {
+                    let catch_up = row.get::<_, String>(1)?;
+                    Ok(JobState {
+                        schedule: row.get(0)?,
+                        catch_up: CatchUp::from_name(&catch_up).ok_or_else(|| {
+                            rusqlite::Error::FromSqlConversionFailure(
+                                1, Type::Text, format!("unknown catch-up policy {}", catch_up).into()
+                            )
+                        })?,
+                        last_run: optional_time_from_column(2, row.get(2)?)?,
+                        next_run: optional_time_from_column(3, row.get(3)?)?,
+                    })
+                },
+            ).optional()
+        })
+    }
+
+    pub fn save(&self, name: &str, state: &JobState) -> rusqlite::Result<()> {
+        self.database.with_connection(|connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO scheduled_jobs (name, schedule, catch_up, last_run, next_run)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    name,
+                    state.schedule,
+                    state.catch_up.name(),
+                    state.last_run.map(|time| time.to_rfc3339()),
+                    state.next_run.map(|time| time.to_rfc3339()),
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> rusqlite::Result<()> {
+        self.database.with_connection(|connection| {
+            connection.execute("DELETE FROM scheduled_jobs WHERE name = ?1", params![name])
+        })?;
+        Ok(())
+    }
+}
+
+/// Runs jobs on a clock and records their runs in a store.
+#[derive(Clone)]
+pub struct Scheduler {
+    store: JobStore,
+    clock: SharedClock,
+}
+
+impl Scheduler {
+    pub fn new(store: JobStore, clock: SharedClock) -> Self {
+        Self { store, clock }
+    }
+
+    /// Forgets the past runs of a job that will not run anymore.
+    pub fn forget(&self, name: &str) -> rusqlite::Result<()> {
+        self.store.remove(name)
+    }
+
+    pub fn spawn<F, Fut>(&self, job: Job, cancel: CancellationToken, task: F) -> JoinHandle<()>
+        where F: FnMut(DateTime<Utc>) -> Fut + Send + 'static, Fut: Future<Output = ()> + Send + 'static {
+        tokio::spawn(job.run(self.clone(), cancel, task))
+    }
+}
+
+/// Every occurrence of `schedule` from `first_missed` to `now`, both included.
+pub fn missed_runs(
+    schedule: &Schedule,
+    timezone: chrono_tz::Tz,
+    first_missed: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Vec<DateTime<Utc>> {
+    if first_missed > now {
+        return Vec::new();
+    }
+
+    let mut runs = vec![first_missed];
+    let mut last = first_missed;
+    while let Some(run) = schedule.next_after(last, timezone).filter(|run| *run <= now) {
+        runs.push(run);
+        last = run;
+    }
+    runs
+}
+
+/// A task run at every occurrence of a schedule, whose runs are persisted so that a restart
+/// neither loses nor repeats them.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub name: String,
+    pub schedule: Schedule,
+    pub timezone: chrono_tz::Tz,
+    pub catch_up: CatchUp,
+}
+
+impl Job {
+    pub fn new(name: String, schedule: Schedule, timezone: chrono_tz::Tz, catch_up: CatchUp) -> Self {
+        Self { name, schedule, timezone, catch_up }
+    }
+
+    /// Stored state of the job. A job whose schedule changed starts over, its old runs
+    /// do not mean anything anymore.
+    fn load_state(&self, store: &JobStore) -> JobState {
+        let fresh = JobState {
+            schedule: self.schedule.expression().to_string(),
+            catch_up: self.catch_up,
+            last_run: None,
+            next_run: None,
+        };
+
+        match store.get(&self.name) {
+            Ok(Some(state)) if state.schedule == fresh.schedule => JobState { catch_up: self.catch_up, ..state },
+            Ok(_) => fresh,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not read the state of job {}: {}", self.name, err));
+                fresh
+            }
+        }
+    }
+
+    fn save_state(&self, store: &JobStore, state: &JobState) {
+        if let Err(err) = store.save(&self.name, state) {
+            MiraiLogger::error(format!("Could not save the state of job {}: {}", self.name, err));
+        }
+    }
+
+    /// Runs that were due before `now` and should still be run, according to the policy.
+    fn catch_up_runs(&self, state: &JobState, now: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        let first_missed = match state.next_run {
+            Some(next_run) if next_run <= now => next_run,
+            _ => return Vec::new(),
+        };
+
+        let missed = missed_runs(&self.schedule, self.timezone, first_missed, now);
+        let missed_count = missed.len();
+        let runs = self.catch_up.select(missed);
+        if runs.len() < missed_count {
+            MiraiLogger::info(format!(
+                "Job {} skips {} of its {} missed runs", self.name, missed_count - runs.len(), missed_count
+            ));
+        }
+        runs
+    }
+
+    /// Runs `task` at every occurrence of the schedule until `cancel` is cancelled, after
+    /// replaying the runs missed while the bot was down. A run is recorded once `task`
+    /// returns, so only a crash in the middle of a run can repeat it.
+    pub async fn run<F, Fut>(self, scheduler: Scheduler, cancel: CancellationToken, mut task: F)
+        where F: FnMut(DateTime<Utc>) -> Fut, Fut: Future<Output = ()> {
+        let Scheduler { store, clock } = scheduler;
+        let mut state = self.load_state(&store);
+
+        for run in self.catch_up_runs(&state, clock.now()) {
+            if cancel.is_cancelled() {
+                return;
+            }
+            MiraiLogger::info(format!("Job {} catches up its run of {}", self.name, run));
+            task(run).await;
+            state.last_run = Some(run);
+            self.save_state(&store, &state);
+        }
+
+        loop {
+            let now = clock.now();
+            let after = state.last_run.map_or(now, |last_run| last_run.max(now));
+            let next_run = match self.schedule.next_after(after, self.timezone) {
+                Some(next_run) => next_run,
+                None => {
+                    MiraiLogger::warn(format!("Schedule {} of job {} never fires", self.schedule, self.name));
+                    break;
+                }
+            };
+            state.next_run = Some(next_run);
+            self.save_state(&store, &state);
+            MiraiLogger::debug(format!("Job {} expected at {}", self.name, next_run));
+
+            if !sleep_until(clock.as_ref(), next_run, &cancel).await {
+                break;
+            }
+            task(next_run).await;
+            state.last_run = Some(next_run);
+            self.save_state(&store, &state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use tokio::sync::mpsc;
+    use tokio::task::JoinHandle;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::database::Database;
+    use crate::jobs::{CatchUp, Job, JobState, JobStore, MAX_CATCH_UP_RUNS, missed_runs, Scheduler};
+    use crate::utils::time::clock::fake::FakeClock;
+    use crate::utils::time::{Schedule, wait_until};
+
+    fn store() -> JobStore {
+        JobStore::new(Database::in_memory().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_store() {
+        let store = store();
+        assert_eq!(store.get("job").unwrap(), None);
+
+        let state = JobState {
+            schedule: "0 7 * * *".to_string(),
+            catch_up: CatchUp::All,
+            last_run: Some(Utc.ymd(2022, 10, 1).and_hms(5, 0, 0)),
+            next_run: Some(Utc.ymd(2022, 10, 2).and_hms(5, 0, 0)),
+        };
+        store.save("job", &state).unwrap();
+        assert_eq!(store.get("job").unwrap(), Some(state));
+
+        store.remove("job").unwrap();
+        assert_eq!(store.get("job").unwrap(), None);
+    }
+
+    #[test]
+    fn test_missed_runs() {
+        let schedule = Schedule::daily(7, 0);
+        let paris = chrono_tz::Europe::Paris;
+        let first_missed = Utc.ymd(2022, 10, 1).and_hms(5, 0, 0);
+
+        assert!(missed_runs(&schedule, paris, first_missed, first_missed - Duration::minutes(1)).is_empty());
+        assert_eq!(missed_runs(&schedule, paris, first_missed, first_missed), vec![first_missed]);
+        assert_eq!(
+            missed_runs(&schedule, paris, first_missed, Utc.ymd(2022, 10, 3).and_hms(12, 0, 0)),
+            vec![first_missed, Utc.ymd(2022, 10, 2).and_hms(5, 0, 0), Utc.ymd(2022, 10, 3).and_hms(5, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn test_catch_up_policies() {
+        let missed: Vec<_> = (0..30)
+            .map(|day| Utc.ymd(2022, 10, 1).and_hms(5, 0, 0) + Duration::days(day))
+            .collect();
+
+        assert!(CatchUp::Skip.select(missed.clone()).is_empty());
+        assert_eq!(CatchUp::Once.select(missed.clone()), vec![missed[29]]);
+        assert_eq!(CatchUp::All.select(missed.clone()), missed[30 - MAX_CATCH_UP_RUNS..].to_vec());
+        assert_eq!(CatchUp::All.select(missed[..2].to_vec()), missed[..2].to_vec());
+
+        for catch_up in CatchUp::ALL {
+            assert_eq!(CatchUp::from_name(catch_up.name()), Some(catch_up));
+        }
+    }
+
+    #[test]
+    fn test_changed_schedule_starts_over() {
+        let store = store();
+        let job = Job::new("job".to_string(), Schedule::daily(7, 0), chrono_tz::UTC, CatchUp::Once);
+        let mut state = job.load_state(&store);
+        state.next_run = Some(Utc.ymd(2022, 10, 1).and_hms(7, 0, 0));
+        store.save("job", &state).unwrap();
+
+        assert_eq!(job.load_state(&store), state);
+        let moved = Job::new("job".to_string(), Schedule::daily(8, 0), chrono_tz::UTC, CatchUp::Once);
+        assert_eq!(moved.load_state(&store).next_run, None);
+    }
+
+    /// Waits for the job to schedule a run other than `previous`.
+    async fn scheduled_run(store: &JobStore, name: &str, previous: Option<DateTime<Utc>>) -> DateTime<Utc> {
+        let mut next_run = None;
+        wait_until(|| {
+            next_run = store.get(name).unwrap().and_then(|state| state.next_run).filter(|run| Some(*run) != previous);
+            next_run.is_some()
+        }).await;
+        next_run.unwrap()
+    }
+
+    fn spawn_recording(
+        scheduler: &Scheduler,
+        job: Job,
+        cancel: &CancellationToken,
+    ) -> (JoinHandle<()>, mpsc::UnboundedReceiver<DateTime<Utc>>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let handle = scheduler.spawn(job, cancel.clone(), move |run| {
+            sender.send(run).unwrap();
+            async {}
+        });
+        (handle, receiver)
+    }
+
+    #[tokio::test]
+    async fn test_run_catches_up() {
+        let store = store();
+        let clock = FakeClock::new(Utc.ymd(2022, 10, 1).and_hms(12, 0, 30));
+        let scheduler = Scheduler::new(store.clone(), clock.clone());
+        let job = Job::new("job".to_string(), Schedule::parse("* * * * *").unwrap(), chrono_tz::UTC, CatchUp::All);
+        store.save("job", &JobState {
+            schedule: job.schedule.expression().to_string(),
+            catch_up: CatchUp::All,
+            last_run: None,
+            next_run: Some(Utc.ymd(2022, 10, 1).and_hms(11, 57, 0)),
+        }).unwrap();
+
+        let cancel = CancellationToken::new();
+        let (handle, mut runs) = spawn_recording(&scheduler, job, &cancel);
+        for minute in 57..60 {
+            assert_eq!(runs.recv().await, Some(Utc.ymd(2022, 10, 1).and_hms(11, minute, 0)));
+        }
+        assert_eq!(runs.recv().await, Some(Utc.ymd(2022, 10, 1).and_hms(12, 0, 0)));
+        assert_eq!(scheduled_run(&store, "job", None).await, Utc.ymd(2022, 10, 1).and_hms(12, 1, 0));
+
+        cancel.cancel();
+        handle.await.unwrap();
+        let state = store.get("job").unwrap().unwrap();
+        assert_eq!(state.last_run, Some(Utc.ymd(2022, 10, 1).and_hms(12, 0, 0)));
+        assert!(runs.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_across_dst() {
+        let store = store();
+        let paris = chrono_tz::Europe::Paris;
+        // Paris switches to summer time on the night of the 27th of March 2022.
+        let clock = FakeClock::new(Utc.ymd(2022, 3, 26).and_hms(12, 0, 0));
+        let scheduler = Scheduler::new(store.clone(), clock.clone());
+        let cancel = CancellationToken::new();
+
+        let morning = Job::new("morning".to_string(), Schedule::daily(7, 0), paris, CatchUp::Once);
+        let evening = Job::new("evening".to_string(), Schedule::daily(22, 0), paris, CatchUp::Once);
+        let (morning_handle, mut mornings) = spawn_recording(&scheduler, morning, &cancel);
+        let (evening_handle, mut evenings) = spawn_recording(&scheduler, evening, &cancel);
+
+        let evening_run = scheduled_run(&store, "evening", None).await;
+        assert_eq!(evening_run, Utc.ymd(2022, 3, 26).and_hms(21, 0, 0));
+        let morning_run = scheduled_run(&store, "morning", None).await;
+        assert_eq!(morning_run, Utc.ymd(2022, 3, 27).and_hms(5, 0, 0));
+
+        clock.set(evening_run);
+        assert_eq!(evenings.recv().await, Some(evening_run));
+        let evening_run = scheduled_run(&store, "evening", Some(evening_run)).await;
+        assert_eq!(evening_run, Utc.ymd(2022, 3, 27).and_hms(20, 0, 0));
+
+        clock.set(morning_run);
+        assert_eq!(mornings.recv().await, Some(morning_run));
+        assert_eq!(
+            scheduled_run(&store, "morning", Some(morning_run)).await,
+            Utc.ymd(2022, 3, 28).and_hms(5, 0, 0)
+        );
+
+        clock.advance(Duration::hours(15));
+        assert_eq!(evenings.recv().await, Some(evening_run));
+
+        cancel.cancel();
+        morning_handle.await.unwrap();
+        evening_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restart_does_not_repeat_runs() {
+        let store = store();
+        let clock = FakeClock::new(Utc.ymd(2022, 10, 1).and_hms(4, 59, 0));
+        let scheduler = Scheduler::new(store.clone(), clock.clone());
+        let job = || Job::new("job".to_string(), Schedule::daily(7, 0), chrono_tz::Europe::Paris, CatchUp::Once);
+
+        let cancel = CancellationToken::new();
+        let (handle, mut runs) = spawn_recording(&scheduler, job(), &cancel);
+        let run = scheduled_run(&store, "job", None).await;
+        clock.set(run);
+        assert_eq!(runs.recv().await, Some(run));
+        let next_run = scheduled_run(&store, "job", Some(run)).await;
+        cancel.cancel();
+        handle.await.unwrap();
+
+        // Restarting a few seconds after the run neither repeats nor loses it.
+        clock.advance(Duration::seconds(20));
+        let cancel = CancellationToken::new();
+        let (handle, mut runs) = spawn_recording(&scheduler, job(), &cancel);
+        tokio::task::yield_now().await;
+        assert_eq!(store.get("job").unwrap().unwrap().next_run, Some(next_run));
+        assert!(runs.try_recv().is_err());
+        cancel.cancel();
+        handle.await.unwrap();
+    }
+}
@@ -0,0 +1,22 @@
+pub mod announcement;
+pub mod bot;
+pub mod bot_handler;
+pub mod class_trial;
+pub mod config;
+pub mod database;
+pub mod debate;
+pub mod flashcards;
+pub mod jobs;
+pub mod log;
+pub mod night_mode;
+pub mod night_watch;
+pub mod permissions;
+pub mod quiz;
+pub mod utils;
+pub mod mirai_bot;
+pub mod settings;
+pub mod shutdown;
+pub mod sink;
+
+extern crate chrono;
+extern crate chrono_tz;
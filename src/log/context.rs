@@ -0,0 +1,55 @@
+use std::future::Future;
+
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+tokio::task_local! {
+    static CONTEXT: LogContext;
+}
+
+/// What a handler is working on, attached to every log written while it runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogContext {
+    pub guild: Option<GuildId>,
+    pub channel: Option<ChannelId>,
+    pub user: Option<UserId>,
+}
+
+impl LogContext {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn set_guild(mut self, guild: Option<GuildId>) -> Self {
+        self.guild = guild;
+        self
+    }
+
+    pub fn set_channel(mut self, channel: ChannelId) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    pub fn set_user(mut self, user: UserId) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    /// Context of the task being run, empty outside of `with_context`.
+    pub fn current() -> Self {
+        CONTEXT.try_with(Clone::clone).unwrap_or_default()
+    }
+
+    /// `key=value` pairs of the known fields.
+    pub fn fields(&self) -> Vec<(&'static str, u64)> {
+        [
+            ("guild", self.guild.map(|id| id.0)),
+            ("channel", self.channel.map(|id| id.0)),
+            ("user", self.user.map(|id| id.0)),
+        ].into_iter()
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .collect()
+    }
+}
+
+/// Runs `future` with `context` attached to its logs.
+pub async fn with_context<F: Future>(context: LogContext, future: F) -> F::Output {
+    CONTEXT.scope(context, future).await
+}
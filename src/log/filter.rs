@@ -0,0 +1,135 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub const ALL: [Level; 4] = [Level::Error, Level::Warn, Level::Info, Level::Debug];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|level| level.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Most verbose level logged, `None` turns the logs off.
+type MaxLevel = Option<Level>;
+
+fn parse_max_level(name: &str) -> Result<MaxLevel, String> {
+    match name.trim() {
+        "off" => Ok(None),
+        name => Level::from_name(name).map(Some).ok_or_else(|| format!("unknown log level {}", name)),
+    }
+}
+
+fn max_level_name(level: MaxLevel) -> String {
+    level.map_or("off".to_string(), |level| level.name().to_lowercase())
+}
+
+/// Which levels get logged for which module, written like `info,bot_handler=debug,jobs=off`:
+/// a default level followed by module overrides. The most precise module wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    default: MaxLevel,
+    modules: Vec<(String, MaxLevel)>,
+}
+
+impl LogFilter {
+    pub const fn new(default: Level) -> Self {
+        Self { default: Some(default), modules: Vec::new() }
+    }
+
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = Self::new(Level::Info);
+
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let module = module.trim().to_string();
+                    if module.is_empty() {
+                        return Err(format!("missing module in {}", directive));
+                    }
+                    filter.modules.retain(|(other, _)| *other != module);
+                    filter.modules.push((module, parse_max_level(level)?));
+                }
+                None => filter.default = parse_max_level(directive)?,
+            }
+        }
+
+        Ok(filter)
+    }
+
+    pub fn enabled(&self, module: &str, level: Level) -> bool {
+        let max_level = self.modules.iter()
+            .filter(|(prefix, _)| {
+                module == prefix || module.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, level)| *level);
+
+        max_level.is_some_and(|max_level| level <= max_level)
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self { Self::new(Level::Info) }
+}
+
+impl FromStr for LogFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Self::parse(s) }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", max_level_name(self.default))?;
+        for (module, level) in &self.modules {
+            write!(f, ",{}={}", module, max_level_name(*level))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log::filter::{Level, LogFilter};
+
+    #[test]
+    fn test_filter() {
+        let filter = LogFilter::parse("warn, bot_handler=debug, mirai_bot=info, mirai_bot::commands=off").unwrap();
+
+        assert!(filter.enabled("jobs", Level::Warn));
+        assert!(!filter.enabled("jobs", Level::Info));
+        assert!(filter.enabled("bot_handler", Level::Debug));
+        assert!(!filter.enabled("bot_handler_extra", Level::Info));
+        assert!(filter.enabled("mirai_bot::monokuma_announcement", Level::Info));
+        assert!(!filter.enabled("mirai_bot::monokuma_announcement", Level::Debug));
+        assert!(!filter.enabled("mirai_bot::commands::admin", Level::Error));
+        assert_eq!(filter.to_string(), "warn,bot_handler=debug,mirai_bot=info,mirai_bot::commands=off");
+
+        assert_eq!(LogFilter::parse("").unwrap(), LogFilter::default());
+        assert!(LogFilter::parse("loud").is_err());
+        assert!(LogFilter::parse("=debug").is_err());
+    }
+}
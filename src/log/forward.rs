@@ -0,0 +1,314 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serenity::model::id::{ChannelId, UserId};
+use serenity::utils::Colour;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::log::{Level, LogContext, MiraiLog, MiraiLogger};
+use crate::sink::{Embed, MAX_DESCRIPTION_LENGTH, SharedSink, shorten};
+
+/// Records waiting to be sent, the ones logged once the queue is full are dropped.
+const QUEUE_SIZE: usize = 100;
+const MAX_MESSAGE_LENGTH: usize = 300;
+/// Module of the forwarder, its own failures would be forwarded again.
+const FORWARDER_MODULE: &str = "log::forward";
+
+pub const DEFAULT_BATCH_DELAY: Duration = Duration::from_secs(5);
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+const ERROR_COLOR: Colour = Colour::new(0xe74c3c);
+const WARN_COLOR: Colour = Colour::new(0xe67e22);
+
+/// Where the warnings and errors are mirrored on Discord.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogTarget {
+    Channel(ChannelId),
+    /// A DM to the creator of the bot.
+    Creator,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub level: Level,
+    pub module: String,
+    pub message: String,
+    pub context: LogContext,
+}
+
+static FORWARD: Mutex<Option<mpsc::Sender<LogRecord>>> = Mutex::new(None);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts queueing the warnings and errors, they are sent by the forwarder reading `receiver`.
+pub fn register() -> mpsc::Receiver<LogRecord> {
+    let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+    *FORWARD.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(sender);
+    receiver
+}
+
+pub fn unregister() {
+    *FORWARD.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+pub(super) fn forward(level: Level, module: &str, context: &LogContext, message: &str) {
+    if level > Level::Warn || module == FORWARDER_MODULE {
+        return;
+    }
+
+    let forward = FORWARD.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(sender) = forward.as_ref() {
+        let record = LogRecord {
+            level,
+            module: module.to_string(),
+            message: message.to_string(),
+            context: context.clone(),
+        };
+        if sender.try_send(record).is_err() {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// One embed for a batch of records, the records that do not fit are counted in the footer.
+pub fn batch_embed(records: &[LogRecord], dropped: usize) -> Embed {
+    let errors = records.iter().filter(|record| record.level == Level::Error).count();
+    let warnings = records.len() - errors;
+
+    let mut description = String::new();
+    let mut length = 0;
+    let mut skipped = dropped;
+    for record in records {
+        let fields: Vec<String> = record.context.fields().iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        let context = match fields.is_empty() {
+            true => String::new(),
+            false => format!(" [{}]", fields.join(" ")),
+        };
+        let line = format!(
+            "**{}** `{}`{} {}\n",
+            record.level, record.module, context, shorten(&record.message, MAX_MESSAGE_LENGTH)
+        );
+
+        let line_length = line.chars().count();
+        if length + line_length > MAX_DESCRIPTION_LENGTH {
+            skipped += 1;
+        } else {
+            description.push_str(&line);
+            length += line_length;
+        }
+    }
+
+    Embed {
+        title: Some(format!("{} erreur(s), {} avertissement(s)", errors, warnings)),
+        description: Some(description.trim_end().to_string()),
+        color: Some(if errors > 0 { ERROR_COLOR } else { WARN_COLOR }),
+        footer: match skipped {
+            0 => None,
+            skipped => Some(format!("{} log(s) non affiché(s)", skipped)),
+        },
+        ..Default::default()
+    }
+}
+
+/// Mirrors the warnings and errors to a Discord channel. Records are grouped for
+/// `batch_delay` and at most one message is sent every `min_interval`.
+pub struct LogForwarder {
+    sink: SharedSink,
+    channel: ChannelId,
+    batch_delay: Duration,
+    min_interval: Duration,
+}
+
+impl LogForwarder {
+    pub fn new(sink: SharedSink, channel: ChannelId) -> Self {
+        Self {
+            sink,
+            channel,
+            batch_delay: DEFAULT_BATCH_DELAY,
+            min_interval: DEFAULT_MIN_INTERVAL,
+        }
+    }
+
+    pub fn set_batch_delay(mut self, batch_delay: Duration) -> Self {
+        self.batch_delay = batch_delay;
+        self
+    }
+
+    pub fn set_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// Sends the records until `cancel` is cancelled, then sends what is left in one last
+    /// batch, the errors logged while the bot shuts down included.
+    pub async fn run(self, mut receiver: mpsc::Receiver<LogRecord>, cancel: CancellationToken) {
+        let mut records = Vec::new();
+        loop {
+            let first = tokio::select! {
+                _ = cancel.cancelled() => break,
+                record = receiver.recv() => match record {
+                    Some(record) => record,
+                    None => break,
+                },
+            };
+
+            records.push(first);
+            let batch_end = tokio::time::sleep(self.batch_delay);
+            tokio::pin!(batch_end);
+            loop {
+                tokio::select! {
+                    _ = &mut batch_end => break,
+                    _ = cancel.cancelled() => break,
+                    record = receiver.recv() => match record {
+                        Some(record) => records.push(record),
+                        None => break,
+                    },
+                }
+            }
+            if cancel.is_cancelled() {
+                break;
+            }
+
+            self.send(&records).await;
+            records.clear();
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = tokio::time::sleep(self.min_interval) => {}
+            }
+        }
+
+        while let Ok(record) = receiver.try_recv() {
+            records.push(record);
+        }
+        if !records.is_empty() {
+            self.send(&records).await;
+        }
+    }
+
+    async fn send(&self, records: &[LogRecord]) {
+        let embed = batch_embed(records, DROPPED.swap(0, Ordering::Relaxed));
+        if let Err(err) = self.sink.send_embed(self.channel, embed).await {
+            MiraiLogger::error(format!("Could not forward {} logs to {}: {}", records.len(), self.channel, err));
+        }
+    }
+}
+
+/// Channel the logs of `target` go to, opening the DM channel with the creator if needed.
+pub async fn target_channel(
+    http: &serenity::http::Http,
+    target: LogTarget,
+    creator: Option<UserId>,
+) -> serenity::Result<Option<ChannelId>> {
+    match (target, creator) {
+        (LogTarget::Channel(channel), _) => Ok(Some(channel)),
+        (LogTarget::Creator, Some(creator)) => Ok(Some(creator.create_dm_channel(http).await?.id)),
+        (LogTarget::Creator, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use serenity::model::id::{ChannelId, GuildId};
+    use tokio::sync::mpsc;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::log::{Level, LogContext};
+    use crate::log::forward::{batch_embed, LogForwarder, LogRecord};
+    use crate::sink::MAX_DESCRIPTION_LENGTH;
+    use crate::sink::recording::RecordingSink;
+    use crate::utils::time::wait_until;
+
+    fn record(level: Level, message: &str) -> LogRecord {
+        LogRecord {
+            level,
+            module: "mirai_bot::monokuma_announcement".to_string(),
+            message: message.to_string(),
+            context: LogContext::new().set_guild(Some(GuildId(1))),
+        }
+    }
+
+    #[test]
+    fn test_batch_embed() {
+        let embed = batch_embed(&[
+            record(Level::Warn, "Slow answer"),
+            record(Level::Error, "Could not send monokuma evening announcement"),
+        ], 0);
+
+        assert_eq!(embed.title.as_deref(), Some("1 erreur(s), 1 avertissement(s)"));
+        assert_eq!(
+            embed.description.as_deref(),
+            Some("**WARN** `mirai_bot::monokuma_announcement` [guild=1] Slow answer\n\
+                  **ERROR** `mirai_bot::monokuma_announcement` [guild=1] Could not send monokuma evening announcement")
+        );
+        assert_eq!(embed.footer, None);
+
+        let records = vec![record(Level::Warn, &"a".repeat(1000)); 30];
+        let embed = batch_embed(&records, 2);
+        assert!(embed.description.unwrap().chars().count() <= MAX_DESCRIPTION_LENGTH);
+        assert_eq!(embed.footer.as_deref(), Some("21 log(s) non affiché(s)"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_forwarder_batches() {
+        let sink = Arc::new(RecordingSink::default());
+        let (sender, receiver) = mpsc::channel(10);
+        let cancel = CancellationToken::new();
+        let forwarder = LogForwarder::new(sink.clone(), ChannelId(7))
+            .set_batch_delay(Duration::from_secs(5))
+            .set_min_interval(Duration::from_secs(60));
+        let task = tokio::spawn(forwarder.run(receiver, cancel.clone()));
+
+        sender.send(record(Level::Error, "first")).await.unwrap();
+        sender.send(record(Level::Warn, "second")).await.unwrap();
+        tokio::task::yield_now().await;
+        tokio::time::advance(Duration::from_secs(5)).await;
+        wait_until(|| sink.sent().len() == 1).await;
+        let sent = sink.sent();
+        assert_eq!(sent[0].0, ChannelId(7));
+        assert_eq!(sent[0].1.title.as_deref(), Some("1 erreur(s), 1 avertissement(s)"));
+
+        // The third record waits for the minimum interval.
+        sender.send(record(Level::Error, "third")).await.unwrap();
+        tokio::time::advance(Duration::from_secs(30)).await;
+        tokio::task::yield_now().await;
+        assert_eq!(sink.sent().len(), 1);
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        tokio::task::yield_now().await;
+        tokio::time::advance(Duration::from_secs(5)).await;
+        wait_until(|| sink.sent().len() == 2).await;
+        assert_eq!(sink.sent()[1].1.title.as_deref(), Some("1 erreur(s), 0 avertissement(s)"));
+
+        cancel.cancel();
+        task.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_forwarder_flushes_on_cancel() {
+        let sink = Arc::new(RecordingSink::default());
+        let (sender, receiver) = mpsc::channel(10);
+        let cancel = CancellationToken::new();
+        let forwarder = LogForwarder::new(sink.clone(), ChannelId(7));
+        let task = tokio::spawn(forwarder.run(receiver, cancel.clone()));
+
+        sender.send(record(Level::Error, "first")).await.unwrap();
+        tokio::task::yield_now().await;
+        tokio::time::advance(Duration::from_secs(5)).await;
+        wait_until(|| sink.sent().len() == 1).await;
+
+        // Waiting for the minimum interval when the bot shuts down.
+        sender.send(record(Level::Warn, "second")).await.unwrap();
+        sender.send(record(Level::Error, "shutting down")).await.unwrap();
+        cancel.cancel();
+        task.await.unwrap();
+        let sent = sink.sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[1].1.title.as_deref(), Some("1 erreur(s), 1 avertissement(s)"));
+    }
+}
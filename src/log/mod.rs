@@ -0,0 +1,238 @@
+use std::io::Write;
+use std::panic::Location;
+use std::sync::Mutex;
+
+use serde_json::json;
+use serenity::model::Timestamp;
+
+use crate::utils::time::{FRENCH_TIME_FORMAT, local_timestamp_now};
+
+pub(crate) mod context;
+pub(crate) mod filter;
+pub(crate) mod forward;
+pub(crate) mod rotation;
+
+pub use context::{LogContext, with_context};
+pub use filter::{Level, LogFilter};
+pub use forward::{LogForwarder, LogTarget};
+pub use rotation::RotatingFile;
+
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+pub const DEFAULT_MAX_FILES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// How the bot logs, from the `[log]` section of the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogSettings {
+    pub filter: LogFilter,
+    pub format: LogFormat,
+    pub file: Option<String>,
+    pub max_file_size: u64,
+    pub max_files: usize,
+    /// Where the warnings and errors are mirrored on Discord, if anywhere.
+    pub discord: Option<LogTarget>,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            filter: LogFilter::default(),
+            format: LogFormat::Text,
+            file: None,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            discord: None,
+        }
+    }
+}
+
+struct Logger {
+    filter: LogFilter,
+    format: LogFormat,
+    file: Option<RotatingFile>,
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    filter: LogFilter::new(Level::Info),
+    format: LogFormat::Text,
+    file: None,
+});
+
+fn logger() -> std::sync::MutexGuard<'static, Logger> {
+    // A panic while logging must not silence the logs of the other threads.
+    LOGGER.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Applies the settings, opening the log file if there is one.
+pub fn init(settings: &LogSettings) -> std::io::Result<()> {
+    let file = match &settings.file {
+        Some(path) => Some(RotatingFile::open(path, settings.max_file_size, settings.max_files)?),
+        None => None,
+    };
+
+    let mut logger = logger();
+    logger.filter = settings.filter.clone();
+    logger.format = settings.format;
+    logger.file = file;
+    Ok(())
+}
+
+/// Changes which logs are written while the bot runs.
+pub fn set_filter(filter: LogFilter) {
+    logger().filter = filter;
+}
+
+pub fn filter() -> LogFilter {
+    logger().filter.clone()
+}
+
+/// Module of a source file of the crate: `src/mirai_bot/commands/mod.rs` is
+/// `mirai_bot::commands`.
+fn module_of(file: &str) -> String {
+    let path = file.replace('\\', "/");
+    let path = path.split_once("src/").map_or(path.as_str(), |(_, path)| path);
+    let path = path.trim_end_matches(".rs").trim_end_matches("/mod");
+    path.replace('/', "::")
+}
+
+fn format_text(level: Level, module: &str, context: &LogContext, message: &str) -> String {
+    let fields: Vec<String> = context.fields().iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let context = match fields.is_empty() {
+        true => String::new(),
+        false => format!(" [{}]", fields.join(" ")),
+    };
+
+    format!(
+        "{} {}{} {} - {}",
+        local_timestamp_now().format(FRENCH_TIME_FORMAT), level, context, module, message
+    )
+}
+
+fn format_json(level: Level, module: &str, context: &LogContext, message: &str) -> String {
+    let mut record = json!({
+        "timestamp": Timestamp::now().to_rfc3339(),
+        "level": level.name(),
+        "module": module,
+        "message": message,
+    });
+    for (key, value) in context.fields() {
+        record[key] = json!(value.to_string());
+    }
+    record.to_string()
+}
+
+fn log(level: Level, file: &str, message: &str) {
+    let module = module_of(file);
+    let mut logger = logger();
+    if !logger.filter.enabled(&module, level) {
+        return;
+    }
+
+    let context = LogContext::current();
+    let line = match logger.format {
+        LogFormat::Text => format_text(level, &module, &context, message),
+        LogFormat::Json => format_json(level, &module, &context, message),
+    };
+
+    match level {
+        Level::Error | Level::Warn => eprintln!("{}", line),
+        Level::Info | Level::Debug => println!("{}", line),
+    }
+
+    if let Some(file) = logger.file.as_mut() {
+        if let Err(err) = file.write_line(&line) {
+            eprintln!("Could not write to the log file: {}", err);
+        }
+    }
+    drop(logger);
+
+    forward::forward(level, &module, &context, message);
+}
+
+pub struct MiraiLogger;
+
+pub trait MiraiLog {
+    fn info(l: String);
+    fn error(l: String);
+    fn warn(l: String);
+    fn debug(l: String);
+    fn flush();
+}
+
+impl MiraiLog for MiraiLogger {
+    #[track_caller]
+    fn info(l: String) {
+        log(Level::Info, Location::caller().file(), &l);
+    }
+
+    #[track_caller]
+    fn error(l: String) {
+        log(Level::Error, Location::caller().file(), &l);
+    }
+
+    #[track_caller]
+    fn warn(l: String) {
+        log(Level::Warn, Location::caller().file(), &l);
+    }
+
+    #[track_caller]
+    fn debug(l: String) {
+        log(Level::Debug, Location::caller().file(), &l);
+    }
+
+    fn flush() {
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+        if let Some(file) = logger().file.as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::{ChannelId, GuildId, UserId};
+
+    use crate::log::{format_json, format_text, Level, LogContext, module_of, with_context};
+
+    #[test]
+    fn test_module_of() {
+        assert_eq!(module_of("src/bot_handler.rs"), "bot_handler");
+        assert_eq!(module_of("src/mirai_bot/commands/mod.rs"), "mirai_bot::commands");
+        assert_eq!(module_of("/home/mirai/bot/src/utils/time/schedule.rs"), "utils::time::schedule");
+    }
+
+    #[tokio::test]
+    async fn test_context() {
+        assert_eq!(LogContext::current(), LogContext::new());
+
+        let context = LogContext::new().set_guild(Some(GuildId(1))).set_channel(ChannelId(2)).set_user(UserId(3));
+        let current = with_context(context.clone(), async { LogContext::current() }).await;
+        assert_eq!(current, context);
+
+        assert!(format_text(Level::Warn, "jobs", &context, "hello").ends_with(" WARN [guild=1 channel=2 user=3] jobs - hello"));
+        let record: serde_json::Value = serde_json::from_str(&format_json(Level::Info, "jobs", &context, "hello")).unwrap();
+        assert_eq!(record["level"], "INFO");
+        assert_eq!(record["module"], "jobs");
+        assert_eq!(record["message"], "hello");
+        assert_eq!(record["guild"], "1");
+        assert_eq!(record["user"], "3");
+    }
+}
@@ -1,22 +1,54 @@
-mod bot;
-mod bot_handler;
-mod log;
-mod utils;
-mod mirai_bot;
-
-extern crate chrono;
-extern crate chrono_tz;
+use r_playground_er::{bot, config, database, log, settings, shutdown};
+use r_playground_er::log::{MiraiLog, MiraiLogger};
 
 #[tokio::main]
 async fn main() {
-    let mut mirai_bot = bot::DiscordBot::new()
-        .set_token(std::env::var("bot_token").expect("bot_token env variable not set").as_str())
-        .set_prefix("/");
+    let config = match config::BotConfig::load() {
+        Ok(config) => config,
+        Err(err) => {
+            MiraiLogger::error(format!("Invalid configuration: {}", err));
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(err) = log::init(&config.log) {
+        MiraiLogger::error(format!("Could not open the log file: {}", err));
+        std::process::exit(1);
+    }
+
+    let database = match database::Database::open(&config.database) {
+        Ok(database) => database,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not open database {}: {}", config.database, err));
+            std::process::exit(1);
+        }
+    };
+
+    let settings = match settings::GuildSettingsStore::sqlite(database.clone()) {
+        Ok(settings) => settings,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not open the guild settings: {}", err));
+            std::process::exit(1);
+        }
+    };
+
+    let mut mirai_bot = bot::DiscordBot::from_config(&config)
+        .set_settings_store(settings)
+        .set_database(database);
+
+    if !mirai_bot.setup_client().await {
+        std::process::exit(1);
+    }
 
-    mirai_bot.setup_client().await;
+    let mut client = mirai_bot.client.expect("Client is not correctly initialized");
+    let data = client.data.clone();
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown::wait_for_signal().await;
+        shutdown::graceful_shutdown(data, shard_manager).await;
+    });
 
-    if let Err(why) = mirai_bot.client.expect("Client is not correctly initialized").start()
-        .await {
+    if let Err(why) = client.start().await {
         eprintln!("Client error: {:?}", why);
     }
 }
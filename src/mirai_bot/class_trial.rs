@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serenity::model::id::{ChannelId, UserId};
+use serenity::prelude::TypeMapKey;
+use serenity::utils::Colour;
+use tokio_util::sync::CancellationToken;
+
+use crate::announcement::AnnouncementTemplates;
+use crate::class_trial::{Clue, DISCUSSION, LOBBY, Phase, Player, ROUNDS, Trial, TrialError, TrialStore, VOTE, MAX_PLAYERS, MIN_PLAYERS};
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::shutdown::Shutdown;
+use crate::sink::{Embed, EmbedField, SharedSink};
+use crate::utils::time::{SharedClock, sleep_until};
+
+/// Writes a phase length the way Monokuma says it: "3 minutes", "1 minute".
+fn minutes(duration: Duration) -> String {
+    match duration.as_secs() / 60 {
+        1 => "1 minute".to_string(),
+        minutes => format!("{} minutes", minutes),
+    }
+}
+
+fn mentions(users: impl Iterator<Item = UserId>) -> String {
+    users.map(|user| format!("<@{}>", user)).collect::<Vec<_>>().join(", ")
+}
+
+/// Sent by Monokuma, as the announcements show him.
+fn monokuma_embed(templates: &AnnouncementTemplates, color: Colour, name: &str, value: String) -> Embed {
+    Embed {
+        author: templates.author(),
+        color: Some(color),
+        fields: vec![EmbedField { name: name.to_string(), value, inline: false }],
+        ..Default::default()
+    }
+}
+
+pub fn lobby_embed(trial: &Trial, templates: &AnnouncementTemplates, color: Colour, prefix: &str) -> Embed {
+    let mut embed = monokuma_embed(templates, color, "Un procès de classe se prépare !", format!(
+        "<@{}> ouvre un procès. Rejoignez-le avec `{}trial join`, il commencera avec `{}trial begin` d'ici {}.\n\nÉlèves inscrits : {}",
+        trial.host, prefix, prefix, minutes(LOBBY), mentions(trial.players.iter().map(|player| player.user))
+    ));
+    embed.footer = Some(format!("{}/{} élèves, il en faut au moins {}", trial.players.len(), MAX_PLAYERS, MIN_PLAYERS));
+    embed
+}
+
+fn closed_embed(templates: &AnnouncementTemplates, color: Colour) -> Embed {
+    monokuma_embed(templates, color, "Le procès est annulé !", format!(
+        "Personne n'a lancé le procès en {}, Monokuma range le tribunal.", minutes(LOBBY)
+    ))
+}
+
+fn investigation_embed(trial: &Trial, round: u32, templates: &AnnouncementTemplates, color: Colour) -> Embed {
+    let case = trial.case.as_ref().expect("A running trial has a case");
+    let mut embed = match round {
+        1 => monokuma_embed(templates, color, "Un cadavre a été découvert !", format!(
+            "{} a été retrouvé(e) dans {}, tué(e) avec {}.\n\nLe coupable se cache parmi vous : {}.\n
+Chacun a reçu un indice en privé. Vous avez {} pour mener l'enquête !",
+            case.victim, case.crime_scene, case.weapon,
+            mentions(trial.players.iter().map(|player| player.user)), minutes(DISCUSSION)
+        )),
+        _ => monokuma_embed(templates, color, "Les débats continuent !", format!(
+            "De nouveaux indices ont été envoyés en privé. Encore {} de discussion avant le vote !",
+            minutes(DISCUSSION)
+        )),
+    };
+    embed.image = templates.image(&mut rand::thread_rng());
+    embed.footer = Some(format!("Phase de discussion {}/{}", round, ROUNDS));
+    embed
+}
+
+fn clue_embed(trial: &Trial, player: &Player, round: u32, clue: Option<Clue>, templates: &AnnouncementTemplates, color: Colour) -> Embed {
+    let case = trial.case.as_ref().expect("A running trial has a case");
+    let mut embed = Embed {
+        author: templates.author(),
+        color: Some(color),
+        description: Some(format!("Procès de classe de <#{}>, phase de discussion {}/{}.", trial.channel, round, ROUNDS)),
+        ..Default::default()
+    };
+
+    if round == 1 {
+        let alibi = player.alibi.as_ref().expect("Players of a running trial have an alibi");
+        let role = match player.user == case.blackened {
+            true => format!(
+                "Tu es le coupable ! Tu as tué {} dans {} avec {}. Ne te fais pas démasquer…",
+                case.victim, case.crime_scene, case.weapon
+            ),
+            false => format!("Tu es innocent(e). Au moment du meurtre, tu étais dans {} avec {}.", alibi.location, alibi.item),
+        };
+        embed.fields.push(EmbedField { name: "Ton rôle".to_string(), value: role, inline: false });
+    }
+    if let Some(clue) = clue {
+        embed.fields.push(EmbedField { name: "Indice".to_string(), value: clue.text(), inline: false });
+    }
+    embed
+}
+
+fn vote_embed(templates: &AnnouncementTemplates, color: Colour, prefix: &str) -> Embed {
+    monokuma_embed(templates, color, "L'heure du vote a sonné !", format!(
+        "Qui est le coupable ? Votez avec `{}trial vote @membre`, vous avez {}.\n
+Si personne n'a plus de voix que les autres, le coupable s'en tire !",
+        prefix, minutes(VOTE)
+    ))
+}
+
+fn verdict_embeds(trial: &Trial, templates: &AnnouncementTemplates, color: Colour) -> [Embed; 2] {
+    let case = trial.case.as_ref().expect("A running trial has a case");
+    let votes = match trial.votes().as_slice() {
+        [] => "Personne n'a voté.".to_string(),
+        votes => votes.iter()
+            .map(|(user, count)| format!("<@{}> : {} voix", user, count))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    let (verdict, punishment) = match trial.accused() {
+        Some(accused) if accused == case.blackened => (
+            format!("La classe a désigné <@{}>… et c'est bien le coupable !", accused),
+            format!("<@{}> est puni(e) pour le meurtre de {}. Les autres vivront un jour de plus…", case.blackened, case.victim),
+        ),
+        Some(accused) => (
+            format!("La classe a désigné <@{}>… Raté ! Le coupable était <@{}>.", accused, case.blackened),
+            format!("Tous les innocents sont punis ! <@{}> a gagné le droit de quitter l'académie.", case.blackened),
+        ),
+        None => (
+            format!("La classe n'a désigné personne… Le coupable était <@{}>.", case.blackened),
+            format!("Tous les innocents sont punis ! <@{}> a gagné le droit de quitter l'académie.", case.blackened),
+        ),
+    };
+
+    let mut execution = monokuma_embed(templates, color, "C'est l'heure de la punition !", punishment);
+    execution.image = templates.image(&mut rand::thread_rng());
+    [monokuma_embed(templates, color, "Le verdict est tombé !", format!("{}\n\n{}", votes, verdict)), execution]
+}
+
+async fn send(sink: &SharedSink, channel: ChannelId, embed: Embed) {
+    if let Err(err) = sink.send_embed(channel, embed).await {
+        MiraiLogger::error(format!("Could not announce the class trial of {}: {}", channel, err));
+    }
+}
+
+/// Posts what starts the current phase of `trial`, then sends each player a clue in DM
+/// during the discussions.
+pub async fn announce_phase(
+    sink: &SharedSink,
+    trial: &Trial,
+    templates: &AnnouncementTemplates,
+    color: Colour,
+    prefix: &str,
+    rng: &mut StdRng,
+) {
+    let round = match trial.phase {
+        Phase::Lobby => return send(sink, trial.channel, lobby_embed(trial, templates, color, prefix)).await,
+        Phase::Vote => return send(sink, trial.channel, vote_embed(templates, color, prefix)).await,
+        Phase::Over if trial.case.is_none() => return send(sink, trial.channel, closed_embed(templates, color)).await,
+        Phase::Over => {
+            for embed in verdict_embeds(trial, templates, color) {
+                send(sink, trial.channel, embed).await;
+            }
+            return;
+        }
+        Phase::Investigation(round) => round,
+    };
+
+    send(sink, trial.channel, investigation_embed(trial, round, templates, color)).await;
+    let mut unreachable = Vec::new();
+    for player in &trial.players {
+        let clue = Clue::draw(&trial.players, player.user, rng);
+        let sent = match player.dm_channel {
+            Some(dm_channel) => sink.send_embed(dm_channel, clue_embed(trial, player, round, clue, templates, color)).await
+                .map_err(|err| MiraiLogger::warn(format!("Could not send a clue to {}: {}", player.user, err)))
+                .is_ok(),
+            None => false,
+        };
+        if !sent {
+            unreachable.push(player.user);
+        }
+    }
+    if !unreachable.is_empty() {
+        send(sink, trial.channel, monokuma_embed(templates, color, "Courrier retourné", format!(
+            "Je n'ai pas pu envoyer d'indice à {}. Ouvrez vos messages privés !", mentions(unreachable.into_iter())
+        ))).await;
+    }
+}
+
+/// Moves the trial of `channel` from phase to phase until the verdict, or closes its lobby.
+/// Everything is read from the store, so that a trial picks up where it was after a restart.
+async fn run_trial(
+    sink: SharedSink,
+    trials: &ClassTrials,
+    channel: ChannelId,
+    color: Colour,
+    prefix: String,
+    task: u64,
+    cancel: CancellationToken,
+) {
+    let ClassTrials { store, clock, templates, .. } = trials;
+    let mut rng = StdRng::from_entropy();
+    loop {
+        let trial = match store.get(channel) {
+            Ok(trial) => trial,
+            Err(TrialError::NoTrial) => return,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not read the class trial of {}: {}", channel, err));
+                return;
+            }
+        };
+        let ends_at = match (trial.phase, trial.ends_at) {
+            (Phase::Over, _) => break,
+            (_, Some(ends_at)) => ends_at,
+            (_, None) => return,
+        };
+        if !sleep_until(clock.as_ref(), ends_at, &cancel).await {
+            return;
+        }
+
+        // The trial may have been cancelled, or even replaced, while sleeping.
+        let next = store.update(channel, |trial| {
+            if trial.ends_at == Some(ends_at) {
+                trial.next_phase(clock.now());
+            }
+            Ok(trial.ends_at != Some(ends_at))
+        });
+        match next {
+            Ok((trial, true)) => announce_phase(&sink, &trial, templates, color, &prefix, &mut rng).await,
+            Ok((_, false)) | Err(TrialError::NoTrial) => return,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not move the class trial of {} on: {}", channel, err));
+                return;
+            }
+        }
+    }
+
+    MiraiLogger::info(format!("The class trial of {} is over", channel));
+    trials.finish(channel, task);
+}
+
+/// The tasks running the class trials, one per channel. Each gets a child of the shutdown
+/// token so that it can be stopped on its own.
+#[derive(Clone)]
+pub struct ClassTrials {
+    store: TrialStore,
+    clock: SharedClock,
+    templates: AnnouncementTemplates,
+    shutdown: Shutdown,
+    /// The task of each channel, known by an id so that a task only ever removes itself.
+    running: Arc<Mutex<HashMap<ChannelId, (u64, CancellationToken)>>>,
+    next_task: Arc<AtomicU64>,
+}
+
+impl TypeMapKey for ClassTrials {
+    type Value = ClassTrials;
+}
+
+impl ClassTrials {
+    pub fn new(store: TrialStore, clock: SharedClock, templates: AnnouncementTemplates, shutdown: Shutdown) -> Self {
+        Self {
+            store,
+            clock,
+            templates,
+            shutdown,
+            running: Arc::new(Mutex::new(HashMap::new())),
+            next_task: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn store(&self) -> &TrialStore {
+        &self.store
+    }
+
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    pub fn templates(&self) -> &AnnouncementTemplates {
+        &self.templates
+    }
+
+    pub fn is_running(&self, channel: ChannelId) -> bool {
+        self.running.lock().expect("Class trial tasks lock poisoned").contains_key(&channel)
+    }
+
+    /// Runs the trial of `channel` until its verdict or the end of its lobby, unless it
+    /// already runs.
+    pub fn spawn(&self, sink: SharedSink, channel: ChannelId, color: Colour, prefix: &str) {
+        let (task, cancel) = {
+            let mut running = self.running.lock().expect("Class trial tasks lock poisoned");
+            if running.contains_key(&channel) {
+                return;
+            }
+            let task = self.next_task.fetch_add(1, Ordering::Relaxed);
+            let cancel = self.shutdown.child_token();
+            running.insert(channel, (task, cancel.clone()));
+            (task, cancel)
+        };
+
+        let trials = self.clone();
+        let prefix = prefix.to_string();
+        self.shutdown.spawn(async move {
+            run_trial(sink, &trials, channel, color, prefix, task, cancel).await;
+            trials.forget(channel, task);
+        });
+    }
+
+    /// Runs the trial of `channel` again, for its task to wait for the new end of its phase.
+    pub fn restart(&self, sink: SharedSink, channel: ChannelId, color: Colour, prefix: &str) {
+        self.stop(channel);
+        self.spawn(sink, channel, color, prefix);
+    }
+
+    /// Stops the task of `channel`, returns false if none was running.
+    pub fn stop(&self, channel: ChannelId) -> bool {
+        match self.running.lock().expect("Class trial tasks lock poisoned").remove(&channel) {
+            Some((_, cancel)) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Deletes the trial `task` ran to its end. Done under the lock of the tasks, a new trial
+    /// of the channel can only be spawned once `task` is gone.
+    fn finish(&self, channel: ChannelId, task: u64) {
+        let mut running = self.running.lock().expect("Class trial tasks lock poisoned");
+        if let Err(err) = self.store.delete(channel) {
+            MiraiLogger::error(format!("Could not delete the class trial of {}: {}", channel, err));
+        }
+        if running.get(&channel).is_some_and(|(running, _)| *running == task) {
+            running.remove(&channel);
+        }
+    }
+
+    /// Removes `task`, unless it was stopped and maybe replaced already.
+    fn forget(&self, channel: ChannelId, task: u64) {
+        let mut running = self.running.lock().expect("Class trial tasks lock poisoned");
+        if running.get(&channel).is_some_and(|(running, _)| *running == task) {
+            running.remove(&channel);
+        }
+    }
+
+    /// Starts again the trials that were running when the bot stopped or lost its
+    /// connection, the ones already running are left alone.
+    pub fn resume(&self, sink: SharedSink, color: Colour, prefix: &str) {
+        let trials = match self.store.all() {
+            Ok(trials) => trials,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not read the class trials to resume: {}", err));
+                return;
+            }
+        };
+        for trial in trials {
+            if !self.is_running(trial.channel) {
+                MiraiLogger::info(format!("Resuming the class trial of {}", trial.channel));
+                self.spawn(sink.clone(), trial.channel, color, prefix);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serenity::model::id::{ChannelId, GuildId, UserId};
+
+    use crate::announcement::AnnouncementTemplates;
+    use crate::class_trial::{DISCUSSION, LOBBY, Player, Trial, TrialStore, VOTE};
+    use crate::database::Database;
+    use crate::mirai_bot::class_trial::{ClassTrials, minutes};
+    use crate::mirai_bot::color::MIRAI_BOT_COLOR;
+    use crate::shutdown::Shutdown;
+    use crate::sink::recording::RecordingSink;
+    use crate::utils::time::Clock;
+    use crate::utils::time::clock::fake::FakeClock;
+    use crate::utils::time::wait_until;
+
+    #[test]
+    fn test_minutes() {
+        assert_eq!(minutes(DISCUSSION), "3 minutes");
+        assert_eq!(minutes(VOTE), "1 minute");
+    }
+
+    #[tokio::test]
+    async fn test_resumed_trial() {
+        let start = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        let clock = FakeClock::new(start);
+        let store = TrialStore::new(Database::in_memory().unwrap()).unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let channel = ChannelId(10);
+
+        // A trial in its first discussion when the bot restarted, player 3 has closed DMs.
+        let mut trial = Trial::new(channel, GuildId(1), Player::new(UserId(1)).set_dm_channel(Some(ChannelId(101))), start);
+        trial.join(Player::new(UserId(2)).set_dm_channel(Some(ChannelId(102)))).unwrap();
+        trial.join(Player::new(UserId(3))).unwrap();
+        trial.begin(UserId(1), start, &mut StdRng::seed_from_u64(1)).unwrap();
+        store.create(&trial).unwrap();
+        let blackened = trial.case.as_ref().unwrap().blackened;
+
+        let trials = ClassTrials::new(store.clone(), clock.clone(), AnnouncementTemplates::new("nowhere"), Shutdown::new());
+        trials.resume(sink.clone(), MIRAI_BOT_COLOR, "/");
+        trials.resume(sink.clone(), MIRAI_BOT_COLOR, "/");
+        assert!(trials.is_running(channel));
+
+        clock.advance(chrono::Duration::from_std(DISCUSSION).unwrap());
+        wait_until(|| sink.sent().len() >= 4).await;
+        let sent = sink.sent();
+        assert_eq!(sent[0].1.fields[0].name, "Les débats continuent !");
+        assert_eq!(sent[0].1.footer.as_deref(), Some("Phase de discussion 2/2"));
+        assert_eq!((sent[1].0, sent[2].0), (ChannelId(101), ChannelId(102)));
+        assert_eq!(sent[1].1.fields[0].name, "Indice");
+        assert_eq!(sent[3].1.fields[0].value, "Je n'ai pas pu envoyer d'indice à <@3>. Ouvrez vos messages privés !");
+
+        clock.advance(chrono::Duration::from_std(DISCUSSION).unwrap());
+        wait_until(|| sink.sent().len() >= 5).await;
+        assert_eq!(sink.sent()[4].1.fields[0].name, "L'heure du vote a sonné !");
+
+        for voter in [1, 2, 3] {
+            let suspect = match UserId(voter) == blackened {
+                true => UserId(voter % 3 + 1),
+                false => blackened,
+            };
+            store.update(channel, |trial| trial.vote(UserId(voter), suspect)).unwrap();
+        }
+        clock.advance(chrono::Duration::from_std(VOTE).unwrap());
+        wait_until(|| sink.sent().len() >= 7).await;
+        let sent = sink.sent();
+        assert_eq!(
+            sent[5].1.fields[0].value.lines().last(),
+            Some(format!("La classe a désigné <@{}>… et c'est bien le coupable !", blackened).as_str())
+        );
+        assert_eq!(sent[6].1.fields[0].name, "C'est l'heure de la punition !");
+
+        wait_until(|| !trials.is_running(channel)).await;
+        assert!(store.all().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stop_trial() {
+        let start = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        let store = TrialStore::new(Database::in_memory().unwrap()).unwrap();
+        let mut trial = Trial::new(ChannelId(10), GuildId(1), Player::new(UserId(1)), start);
+        trial.join(Player::new(UserId(2))).unwrap();
+        trial.join(Player::new(UserId(3))).unwrap();
+        trial.begin(UserId(1), start, &mut StdRng::seed_from_u64(1)).unwrap();
+        store.create(&trial).unwrap();
+
+        let trials = ClassTrials::new(store, FakeClock::new(start), AnnouncementTemplates::new("nowhere"), Shutdown::new());
+        let sink = Arc::new(RecordingSink::default());
+        trials.spawn(sink.clone(), ChannelId(10), MIRAI_BOT_COLOR, "/");
+        assert!(trials.stop(ChannelId(10)));
+        assert!(!trials.stop(ChannelId(10)));
+        assert!(!trials.is_running(ChannelId(10)));
+        assert!(sink.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_lobby() {
+        let start = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        let clock = FakeClock::new(start);
+        let store = TrialStore::new(Database::in_memory().unwrap()).unwrap();
+        let trials = ClassTrials::new(store.clone(), clock.clone(), AnnouncementTemplates::new("nowhere"), Shutdown::new());
+        let sink = Arc::new(RecordingSink::default());
+
+        // Started in time, the task follows the first discussion instead of the lobby.
+        let mut trial = Trial::new(ChannelId(10), GuildId(1), Player::new(UserId(1)), start);
+        trial.join(Player::new(UserId(2))).unwrap();
+        trial.join(Player::new(UserId(3))).unwrap();
+        store.create(&trial).unwrap();
+        trials.spawn(sink.clone(), ChannelId(10), MIRAI_BOT_COLOR, "/");
+        store.update(ChannelId(10), |trial| trial.begin(UserId(1), start, &mut StdRng::seed_from_u64(1))).unwrap();
+        trials.restart(sink.clone(), ChannelId(10), MIRAI_BOT_COLOR, "/");
+        clock.advance(chrono::Duration::from_std(DISCUSSION).unwrap());
+        wait_until(|| !sink.sent().is_empty()).await;
+        assert_eq!(sink.sent()[0].1.fields[0].name, "Les débats continuent !");
+        assert!(trials.stop(ChannelId(10)));
+
+        // Left alone, a lobby is closed.
+        store.create(&Trial::new(ChannelId(11), GuildId(1), Player::new(UserId(1)), clock.now())).unwrap();
+        trials.spawn(sink.clone(), ChannelId(11), MIRAI_BOT_COLOR, "/");
+        clock.advance(chrono::Duration::from_std(LOBBY).unwrap());
+        wait_until(|| !trials.is_running(ChannelId(11))).await;
+        let sent = sink.sent();
+        assert_eq!(sent.last().unwrap().0, ChannelId(11));
+        assert_eq!(sent.last().unwrap().1.fields[0].name, "Le procès est annulé !");
+        assert!(store.get(ChannelId(11)).is_err());
+
+        // The channel is free for a new trial as soon as the last one is over.
+        store.create(&Trial::new(ChannelId(11), GuildId(1), Player::new(UserId(1)), clock.now())).unwrap();
+        trials.spawn(sink.clone(), ChannelId(11), MIRAI_BOT_COLOR, "/");
+        assert!(trials.is_running(ChannelId(11)));
+    }
+}
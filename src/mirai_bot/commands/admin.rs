@@ -0,0 +1,151 @@
+use serenity::client::Context;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::standard::macros::{command, group};
+use serenity::model::channel::Message;
+use serenity::model::id::UserId;
+
+use crate::announcement::AnnouncementTemplates;
+use crate::log::{self, LogFilter, MiraiLog, MiraiLogger};
+use crate::mirai_bot::commands::{ADMIN_CHECK, OWNER_CHECK, reply};
+use crate::permissions::BotPermissions;
+
+#[group("Administration")]
+#[description = "La gestion des administrateurs du bot."]
+#[prefix = "admin"]
+#[checks(Admin)]
+#[commands(add, remove, list, log, templates)]
+struct Admin;
+
+async fn bot_permissions(ctx: &Context) -> BotPermissions {
+    ctx.data.read().await.get::<BotPermissions>()
+        .expect("Did not find BotPermissions").clone()
+}
+
+#[command]
+#[checks(Owner)]
+#[description = "Ajoute un administrateur du bot."]
+#[usage = "@membre"]
+#[num_args(1)]
+async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user_id = match args.single::<UserId>() {
+        Ok(user_id) => user_id,
+        Err(_) => {
+            reply(ctx, msg, "Administrateurs", "Mentionne le membre à ajouter.").await;
+            return Ok(());
+        }
+    };
+
+    match bot_permissions(ctx).await.add_admin(user_id, msg.author.id) {
+        Ok(true) => {
+            MiraiLogger::info(format!("{} added {} to the bot admins", msg.author.id, user_id));
+            reply(ctx, msg, "Administrateurs", &format!("<@{}> est maintenant administrateur.", user_id)).await;
+        }
+        Ok(false) => {
+            reply(ctx, msg, "Administrateurs", &format!("<@{}> est déjà administrateur.", user_id)).await;
+        }
+        Err(err) => {
+            MiraiLogger::error(format!("Could not add {} to the bot admins: {}", user_id, err));
+            reply(ctx, msg, "Administrateurs", "Impossible d'enregistrer l'administrateur.").await;
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[checks(Owner)]
+#[description = "Retire un administrateur du bot."]
+#[usage = "@membre"]
+#[num_args(1)]
+async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user_id = match args.single::<UserId>() {
+        Ok(user_id) => user_id,
+        Err(_) => {
+            reply(ctx, msg, "Administrateurs", "Mentionne le membre à retirer.").await;
+            return Ok(());
+        }
+    };
+
+    let permissions = bot_permissions(ctx).await;
+    if permissions.is_config_admin(user_id) {
+        reply(
+            ctx, msg, "Administrateurs",
+            &format!("<@{}> est administrateur dans la configuration du bot, il faut l'en retirer.", user_id),
+        ).await;
+        return Ok(());
+    }
+
+    match permissions.remove_admin(user_id) {
+        Ok(true) => {
+            MiraiLogger::info(format!("{} removed {} from the bot admins", msg.author.id, user_id));
+            reply(ctx, msg, "Administrateurs", &format!("<@{}> n'est plus administrateur.", user_id)).await;
+        }
+        Ok(false) => {
+            reply(ctx, msg, "Administrateurs", &format!("<@{}> n'est pas administrateur.", user_id)).await;
+        }
+        Err(err) => {
+            MiraiLogger::error(format!("Could not remove {} from the bot admins: {}", user_id, err));
+            reply(ctx, msg, "Administrateurs", "Impossible de retirer l'administrateur.").await;
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description = "Liste les administrateurs du bot."]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let admins = bot_permissions(ctx).await.admins();
+
+    let description = match admins.is_empty() {
+        true => "Il n'y a aucun administrateur.".to_string(),
+        false => admins.iter().map(|id| format!("• <@{}>", id)).collect::<Vec<_>>().join("\n"),
+    };
+    reply(ctx, msg, "Administrateurs", &description).await;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Affiche ou change le filtre des logs, par exemple `warn,bot_handler=debug`."]
+#[usage = "[filtre]"]
+async fn log(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let spec = args.rest().trim();
+    if spec.is_empty() {
+        reply(ctx, msg, "Logs", &format!("Filtre actuel : `{}`", log::filter())).await;
+        return Ok(());
+    }
+
+    match LogFilter::parse(spec) {
+        Ok(filter) => {
+            MiraiLogger::info(format!("{} set the log filter to {}", msg.author.id, filter));
+            reply(ctx, msg, "Logs", &format!("Nouveau filtre : `{}`", filter)).await;
+            log::set_filter(filter);
+        }
+        Err(err) => {
+            reply(ctx, msg, "Logs", &format!("Filtre invalide : {}", err)).await;
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description = "Relit les modèles des annonces de Monokuma depuis les fichiers de données."]
+async fn templates(ctx: &Context, msg: &Message) -> CommandResult {
+    let templates = ctx.data.read().await.get::<AnnouncementTemplates>()
+        .expect("Did not find AnnouncementTemplates").clone();
+
+    match templates.reload() {
+        Ok(count) => {
+            MiraiLogger::info(format!("{} reloaded {} announcement template(s)", msg.author.id, count));
+            reply(ctx, msg, "Annonces", &format!("{} modèle(s) d'annonce chargé(s).", count)).await;
+        }
+        Err(err) => {
+            MiraiLogger::warn(format!("Could not reload the announcement templates: {}", err));
+            reply(ctx, msg, "Annonces", &format!("Les modèles actuels sont gardés, {}", err)).await;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serenity::client::Context;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::standard::macros::{command, group};
+use serenity::model::channel::Message;
+use serenity::model::id::UserId;
+
+use crate::bot::DiscordBot;
+use crate::class_trial::{MAX_PLAYERS, Phase, Player, ROUNDS, Trial, TrialError};
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::mirai_bot::class_trial::{announce_phase, ClassTrials, lobby_embed};
+use crate::mirai_bot::commands::{MODERATOR_LEVEL, reply};
+use crate::permissions::BotPermissions;
+use crate::sink::SerenitySink;
+
+const TITLE: &str = "Procès de classe";
+
+#[group("Procès de classe")]
+#[description = "Le procès de classe de Monokuma : un coupable se cache parmi les joueurs, à vous de le démasquer."]
+#[prefix = "trial"]
+#[only_in(guilds)]
+#[commands(open, join, leave, begin, vote, status, cancel)]
+struct ClassTrial;
+
+async fn class_trials(ctx: &Context) -> ClassTrials {
+    ctx.data.read().await.get::<ClassTrials>().expect("Did not find ClassTrials").clone()
+}
+
+async fn reply_error(ctx: &Context, msg: &Message, err: TrialError) {
+    match err {
+        TrialError::Database(err) => {
+            MiraiLogger::error(format!("Could not save the class trial of {}: {}", msg.channel_id, err));
+            reply(ctx, msg, TITLE, "Impossible d'enregistrer le procès.").await;
+        }
+        err => reply(ctx, msg, TITLE, &err.to_string()).await,
+    }
+}
+
+/// The author as a player, with the DM channel their clues go to.
+async fn author_player(ctx: &Context, msg: &Message) -> Player {
+    let dm_channel = match msg.author.create_dm_channel(ctx).await {
+        Ok(channel) => Some(channel.id),
+        Err(err) => {
+            MiraiLogger::warn(format!("Could not open a DM channel with {}: {}", msg.author.id, err));
+            None
+        }
+    };
+    Player::new(msg.author.id).set_dm_channel(dm_channel)
+}
+
+#[command]
+#[description = "Ouvre un procès dans le salon, les autres joueurs le rejoignent avant que tu le lances."]
+async fn open(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild = msg.guild_id.expect("Class trials only run in guilds");
+    let trials = class_trials(ctx).await;
+    let trial = Trial::new(msg.channel_id, guild, author_player(ctx, msg).await, trials.clock().now());
+    if let Err(err) = trials.store().create(&trial) {
+        reply_error(ctx, msg, err).await;
+        return Ok(());
+    }
+
+    MiraiLogger::info(format!("{} opened a class trial on {}", msg.author.id, msg.channel_id));
+    let bot = ctx.data.read().await.get::<DiscordBot>().expect("Did not find DiscordBot").clone();
+    let sink = SerenitySink::shared(ctx.http.clone());
+    if let Err(err) = sink.send_embed(msg.channel_id, lobby_embed(&trial, trials.templates(), bot.color, &bot.prefix)).await {
+        MiraiLogger::error(format!("Could not announce the class trial of {}: {}", msg.channel_id, err));
+    }
+    trials.spawn(sink, msg.channel_id, bot.color, &bot.prefix);
+    Ok(())
+}
+
+#[command]
+#[description = "Rejoins le procès du salon avant qu'il commence. Ouvre tes messages privés pour recevoir les indices."]
+async fn join(ctx: &Context, msg: &Message) -> CommandResult {
+    let player = author_player(ctx, msg).await;
+    match class_trials(ctx).await.store().update(msg.channel_id, |trial| trial.join(player)) {
+        Ok((trial, _)) => reply(ctx, msg, TITLE, &format!(
+            "<@{}> rejoint le procès, {}/{} élèves.", msg.author.id, trial.players.len(), MAX_PLAYERS
+        )).await,
+        Err(err) => reply_error(ctx, msg, err).await,
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Quitte le procès du salon avant qu'il commence."]
+async fn leave(ctx: &Context, msg: &Message) -> CommandResult {
+    match class_trials(ctx).await.store().update(msg.channel_id, |trial| trial.leave(msg.author.id)) {
+        Ok((trial, _)) => reply(ctx, msg, TITLE, &format!(
+            "<@{}> quitte le procès, {}/{} élèves.", msg.author.id, trial.players.len(), MAX_PLAYERS
+        )).await,
+        Err(err) => reply_error(ctx, msg, err).await,
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Lance le procès que tu as ouvert : Monokuma choisit le coupable en secret et envoie les indices."]
+async fn begin(ctx: &Context, msg: &Message) -> CommandResult {
+    let trials = class_trials(ctx).await;
+    let now = trials.clock().now();
+    let trial = match trials.store().update(msg.channel_id, |trial| {
+        trial.begin(msg.author.id, now, &mut rand::thread_rng())
+    }) {
+        Ok((trial, _)) => trial,
+        Err(err) => {
+            reply_error(ctx, msg, err).await;
+            return Ok(());
+        }
+    };
+
+    MiraiLogger::info(format!("The class trial of {} begins with {} players", msg.channel_id, trial.players.len()));
+    let bot = ctx.data.read().await.get::<DiscordBot>().expect("Did not find DiscordBot").clone();
+    let sink = SerenitySink::shared(ctx.http.clone());
+    announce_phase(&sink, &trial, trials.templates(), bot.color, &bot.prefix, &mut StdRng::from_entropy()).await;
+    trials.restart(sink, msg.channel_id, bot.color, &bot.prefix);
+    Ok(())
+}
+
+#[command]
+#[description = "Vote contre l'élève que tu crois coupable, tu peux changer d'avis jusqu'à la fin du vote."]
+#[usage = "@membre"]
+#[num_args(1)]
+async fn vote(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let suspect = match args.single::<UserId>() {
+        Ok(suspect) => suspect,
+        Err(_) => {
+            reply(ctx, msg, TITLE, "Mentionne l'élève que tu accuses.").await;
+            return Ok(());
+        }
+    };
+    match class_trials(ctx).await.store().update(msg.channel_id, |trial| trial.vote(msg.author.id, suspect)) {
+        Ok(_) => reply(ctx, msg, TITLE, &format!("Ton vote contre <@{}> est enregistré.", suspect)).await,
+        Err(err) => reply_error(ctx, msg, err).await,
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Montre où en est le procès du salon."]
+async fn status(ctx: &Context, msg: &Message) -> CommandResult {
+    let trial = match class_trials(ctx).await.store().get(msg.channel_id) {
+        Ok(trial) => trial,
+        Err(err) => {
+            reply_error(ctx, msg, err).await;
+            return Ok(());
+        }
+    };
+
+    let phase = match trial.phase {
+        Phase::Lobby => "Les élèves rejoignent le procès.".to_string(),
+        Phase::Investigation(round) => format!("Phase de discussion {}/{}.", round, ROUNDS),
+        Phase::Vote => format!(
+            "Vote en cours, {}/{} élèves ont voté.",
+            trial.players.iter().filter(|player| player.vote.is_some()).count(), trial.players.len()
+        ),
+        Phase::Over => "Le verdict va tomber.".to_string(),
+    };
+    let ends = match trial.ends_at {
+        Some(ends_at) => format!(" Fin <t:{}:R>.", ends_at.timestamp()),
+        None => String::new(),
+    };
+    let players = trial.players.iter().map(|player| format!("<@{}>", player.user)).collect::<Vec<_>>().join(", ");
+    reply(ctx, msg, TITLE, &format!("{}{}\n\nÉlèves : {}", phase, ends, players)).await;
+    Ok(())
+}
+
+#[command]
+#[description = "Annule le procès du salon, si c'est toi qui l'as ouvert ou si tu es modérateur."]
+async fn cancel(ctx: &Context, msg: &Message) -> CommandResult {
+    let trials = class_trials(ctx).await;
+    let trial = match trials.store().get(msg.channel_id) {
+        Ok(trial) => trial,
+        Err(err) => {
+            reply_error(ctx, msg, err).await;
+            return Ok(());
+        }
+    };
+    if trial.host != msg.author.id {
+        let permissions = ctx.data.read().await.get::<BotPermissions>()
+            .expect("Did not find BotPermissions").clone();
+        if let Err(denied) = permissions.check(ctx, msg.author.id, msg.guild_id, MODERATOR_LEVEL).await {
+            reply(ctx, msg, TITLE, &denied.to_string()).await;
+            return Ok(());
+        }
+    }
+
+    trials.stop(msg.channel_id);
+    if let Err(err) = trials.store().delete(msg.channel_id) {
+        reply_error(ctx, msg, err).await;
+        return Ok(());
+    }
+    MiraiLogger::info(format!("{} cancelled the class trial of {}", msg.author.id, msg.channel_id));
+    reply(ctx, msg, TITLE, "Monokuma range le tribunal, le procès est annulé.").await;
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use serenity::client::Context;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::standard::macros::{command, group};
+use serenity::model::channel::Message;
+
+use crate::bot::DiscordBot;
+use crate::debate::{DebateBank, DebateStore};
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::mirai_bot::commands::{member_or_author, reply, stop_session};
+use crate::mirai_bot::debate::{DebateSessions, start_debate};
+
+const TITLE: &str = "Débat non-stop";
+/// Members shown by the leaderboard.
+const LEADERBOARD_SIZE: usize = 10;
+
+#[group("Débat non-stop")]
+#[description = "Les déclarations défilent, tire sur celle qui est fausse avec la bonne balle de vérité."]
+#[prefix = "debate"]
+#[only_in(guilds)]
+#[commands(start, list, shoot, stats, top, stop)]
+struct Debate;
+
+#[command]
+#[description = "Lance un débat dans le salon, choisi au hasard si tu n'en donnes pas l'identifiant."]
+#[usage = "[identifiant]"]
+#[example = "academie-cuisine"]
+#[max_args(1)]
+async fn start(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let bank = ctx.data.read().await.get::<DebateBank>().expect("Did not find DebateBank").clone();
+    let scenario = match args.is_empty() {
+        true => bank.random(&mut rand::thread_rng()),
+        false => bank.get(args.rest()),
+    };
+    let scenario = match scenario {
+        Some(scenario) => scenario,
+        None if bank.is_empty() => {
+            reply(ctx, msg, TITLE, "Aucun débat n'est disponible.").await;
+            return Ok(());
+        }
+        None => {
+            reply(ctx, msg, TITLE, &format!("Aucun débat ne s'appelle `{}`.", args.rest())).await;
+            return Ok(());
+        }
+    };
+
+    let debate = match start_debate(ctx, msg.channel_id, msg.author.id, scenario).await {
+        Some(debate) => debate,
+        None => {
+            reply(ctx, msg, TITLE, "Un débat est déjà en cours dans ce salon.").await;
+            return Ok(());
+        }
+    };
+
+    let (store, shutdown) = {
+        let data = ctx.data.read().await;
+        let store = data.get::<DebateStore>().expect("Did not find DebateStore").clone();
+        (store, data.get::<DiscordBot>().expect("Did not find DiscordBot").shutdown.clone())
+    };
+    let guild = msg.guild_id.expect("Debates only run in guilds");
+    shutdown.spawn(async move {
+        match debate.await {
+            Ok(debate) => {
+                if let Err(err) = store.record(guild, &debate) {
+                    MiraiLogger::error(format!("Could not save the debate scores of {}: {}", guild, err));
+                }
+            }
+            Err(err) => MiraiLogger::error(format!("A debate on {} failed: {}", guild, err)),
+        }
+    });
+    Ok(())
+}
+
+#[command]
+#[description = "Liste les débats disponibles."]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let bank = ctx.data.read().await.get::<DebateBank>().expect("Did not find DebateBank").clone();
+    let description = match bank.is_empty() {
+        true => "Aucun débat n'est disponible.".to_string(),
+        false => bank.all().iter()
+            .map(|scenario| format!("`{}` — {}", scenario.id, scenario.title))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    reply(ctx, msg, TITLE, &description).await;
+    Ok(())
+}
+
+#[command]
+#[description = "Tire une balle de vérité, par son numéro ou son nom, sur la déclaration affichée."]
+#[usage = "<balle>"]
+#[example = "2"]
+#[min_args(1)]
+async fn shoot(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let sessions = ctx.data.read().await.get::<DebateSessions>().expect("Did not find DebateSessions").clone();
+    let scenario = match sessions.scenario(msg.channel_id) {
+        Some(scenario) => scenario,
+        None => {
+            reply(ctx, msg, TITLE, "Aucun débat n'est en cours dans ce salon.").await;
+            return Ok(());
+        }
+    };
+    match scenario.bullet(args.rest()) {
+        Some(bullet) => {
+            sessions.shoot(msg.channel_id, msg.author.id, bullet);
+        }
+        None => reply(ctx, msg, TITLE, &format!(
+            "`{}` n'est pas une balle de vérité de ce débat, donne son numéro de 1 à {} ou son nom.",
+            args.rest(), scenario.bullets.len()
+        )).await,
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Montre le score de débat d'un membre sur ce serveur."]
+#[usage = "[@membre]"]
+#[max_args(1)]
+async fn stats(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user = match member_or_author(msg, &mut args) {
+        Some(user) => user,
+        None => {
+            reply(ctx, msg, TITLE, "Mentionne le membre dont tu veux le score.").await;
+            return Ok(());
+        }
+    };
+    let guild = msg.guild_id.expect("Debate scores only exist in guilds");
+    let store = ctx.data.read().await.get::<DebateStore>().expect("Did not find DebateStore").clone();
+
+    let score = match store.score(guild, user) {
+        Ok(score) => score,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not read the debate score of {}: {}", user, err));
+            reply(ctx, msg, TITLE, "Impossible de lire le score.").await;
+            return Ok(());
+        }
+    };
+    let description = match score.played {
+        0 => format!("<@{}> n'a encore participé à aucun débat.", user),
+        played => format!(
+            "<@{}> a **{} point(s)** : {} débat(s), {} contradiction(s) brisée(s), {} tir(s) raté(s).",
+            user, score.points(), played, score.wins, score.misses
+        ),
+    };
+    reply(ctx, msg, TITLE, &description).await;
+    Ok(())
+}
+
+#[command]
+#[description = "Montre les meilleurs débatteurs du serveur."]
+async fn top(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild = msg.guild_id.expect("Debate scores only exist in guilds");
+    let store = ctx.data.read().await.get::<DebateStore>().expect("Did not find DebateStore").clone();
+
+    let leaderboard = match store.leaderboard(guild, LEADERBOARD_SIZE) {
+        Ok(leaderboard) => leaderboard,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not read the debate leaderboard of {}: {}", guild, err));
+            reply(ctx, msg, TITLE, "Impossible de lire le classement.").await;
+            return Ok(());
+        }
+    };
+    let description = match leaderboard.is_empty() {
+        true => "Personne n'a encore débattu sur ce serveur.".to_string(),
+        false => leaderboard.iter().enumerate()
+            .map(|(index, (user, score))| format!("{}. <@{}> — {} point(s)", index + 1, user, score.points()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    reply(ctx, msg, TITLE, &description).await;
+    Ok(())
+}
+
+#[command]
+#[description = "Arrête le débat du salon, si c'est toi qui l'as lancé ou si tu es modérateur."]
+async fn stop(ctx: &Context, msg: &Message) -> CommandResult {
+    let sessions = ctx.data.read().await.get::<DebateSessions>().expect("Did not find DebateSessions").clone();
+    stop_session(ctx, msg, &sessions, TITLE, "Aucun débat n'est en cours dans ce salon.").await;
+    Ok(())
+}
@@ -0,0 +1,423 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serenity::builder::CreateEmbed;
+use serenity::client::Context;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::standard::macros::{command, group};
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::channel::{AttachmentType, Message};
+use serenity::utils::Colour;
+
+use crate::bot::DiscordBot;
+use crate::flashcards::{deck_name, FlashcardError, FlashcardStore, Import};
+use crate::flashcards::import::{export_csv, export_filename, ImportError, MAX_IMPORT_SIZE, read_cards};
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::mirai_bot::commands::reply;
+use crate::mirai_bot::pagination::{Pages, send_pages};
+use crate::mirai_bot::review::start_review;
+use crate::sink::{Embed, EmbedField, MAX_FIELD_LENGTH, shorten};
+
+const TITLE: &str = "Fiches";
+const CARDS_PER_PAGE: usize = 10;
+/// Longest side shown in the list of the cards of a deck.
+const LIST_SIDE: usize = 80;
+/// Cards and skipped rows shown before an import.
+const PREVIEW_CARDS: usize = 5;
+const PREVIEW_ERRORS: usize = 10;
+/// An import not confirmed by then is cancelled.
+const IMPORT_TIMEOUT: Duration = Duration::from_secs(120);
+const IMPORT_CONFIRM: &str = "import_confirm";
+const IMPORT_CANCEL: &str = "import_cancel";
+
+#[group("Fiches")]
+#[description = "Des paquets de fiches à réviser, revues de plus en plus espacées quand tu les connais."]
+#[prefix = "cards"]
+#[commands(decks, create, delete, add, list, remove, review, import, export)]
+struct Flashcards;
+
+async fn flashcard_store(ctx: &Context) -> FlashcardStore {
+    ctx.data.read().await.get::<FlashcardStore>()
+        .expect("Did not find FlashcardStore").clone()
+}
+
+async fn now(ctx: &Context) -> DateTime<Utc> {
+    ctx.data.read().await.get::<DiscordBot>()
+        .expect("Did not find DiscordBot").clock.now()
+}
+
+async fn flashcard_error(ctx: &Context, msg: &Message, err: FlashcardError) {
+    match err {
+        FlashcardError::Database(err) => {
+            MiraiLogger::error(format!("Flashcards of {} failed: {}", msg.author.id, err));
+            reply(ctx, msg, TITLE, "Impossible d'accéder aux fiches.").await;
+        }
+        err => reply(ctx, msg, TITLE, &err.to_string()).await,
+    }
+}
+
+/// The first line of a card side, with an ellipsis if there is more.
+fn first_line(text: &str, max: usize) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    match line.len() < text.len() && line.chars().count() < max {
+        true => format!("{}…", line),
+        false => shorten(line, max),
+    }
+}
+
+/// What will be imported into `deck`, with the rows of the file that will be skipped.
+fn import_preview(deck: &str, import: &Import, color: Colour) -> Embed {
+    let mut fields = Vec::new();
+    if !import.cards.is_empty() {
+        let cards: Vec<String> = import.cards.iter().take(PREVIEW_CARDS)
+            .map(|card| format!("{} → {}", first_line(&card.front, LIST_SIDE), first_line(&card.back, LIST_SIDE)))
+            .collect();
+        fields.push(EmbedField { name: "Aperçu".to_string(), value: cards.join("\n"), inline: false });
+    }
+    if !import.errors.is_empty() {
+        let mut errors: Vec<String> = import.errors.iter().take(PREVIEW_ERRORS).map(|error| error.to_string()).collect();
+        if import.errors.len() > PREVIEW_ERRORS {
+            errors.push(format!("… et {} autre(s)", import.errors.len() - PREVIEW_ERRORS));
+        }
+        fields.push(EmbedField {
+            name: format!("Lignes ignorées ({})", import.errors.len()),
+            value: shorten(&errors.join("\n"), MAX_FIELD_LENGTH),
+            inline: false,
+        });
+    }
+
+    let description = match import.cards.len() {
+        0 => "Aucune fiche n'a pu être lue dans ce fichier.".to_string(),
+        count => format!("{} fiche(s) prête(s) à être ajoutée(s) au paquet `{}`.", count, deck),
+    };
+    Embed {
+        title: Some(format!("Import — {}", deck)),
+        description: Some(description),
+        color: Some(color),
+        fields,
+        ..Default::default()
+    }
+}
+
+fn create_embed(embed: &Embed) -> CreateEmbed {
+    let mut created = CreateEmbed::default();
+    embed.build(&mut created);
+    created
+}
+
+#[command]
+#[description = "Liste tes paquets et les fiches à réviser."]
+async fn decks(ctx: &Context, msg: &Message) -> CommandResult {
+    let decks = match flashcard_store(ctx).await.decks(msg.author.id, now(ctx).await) {
+        Ok(decks) => decks,
+        Err(err) => {
+            flashcard_error(ctx, msg, err).await;
+            return Ok(());
+        }
+    };
+
+    let description = match decks.is_empty() {
+        true => "Tu n'as pas encore de paquet.".to_string(),
+        false => decks.iter()
+            .map(|stats| format!("**{}** — {} fiche(s), {} à réviser", stats.deck.name, stats.cards, stats.due))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    reply(ctx, msg, TITLE, &description).await;
+    Ok(())
+}
+
+#[command]
+#[description = "Crée un paquet de fiches."]
+#[usage = "<nom>"]
+#[example = "Chimie"]
+#[min_args(1)]
+async fn create(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    match flashcard_store(ctx).await.create_deck(msg.author.id, args.rest(), now(ctx).await) {
+        Ok(deck) => {
+            MiraiLogger::info(format!("{} created deck {}", msg.author.id, deck.id));
+            reply(ctx, msg, TITLE, &format!("Paquet `{}` créé.", deck.name)).await;
+        }
+        Err(err) => flashcard_error(ctx, msg, err).await,
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Supprime un de tes paquets, avec ses fiches."]
+#[usage = "<nom>"]
+#[min_args(1)]
+async fn delete(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let store = flashcard_store(ctx).await;
+    let deleted = store.deck(msg.author.id, args.rest())
+        .and_then(|deck| store.delete_deck(&deck).map(|_| deck));
+
+    match deleted {
+        Ok(deck) => {
+            MiraiLogger::info(format!("{} deleted deck {}", msg.author.id, deck.id));
+            reply(ctx, msg, TITLE, &format!("Paquet `{}` supprimé.", deck.name)).await;
+        }
+        Err(err) => flashcard_error(ctx, msg, err).await,
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Ajoute une fiche à un de tes paquets."]
+#[usage = "<paquet> | <question> | <réponse>"]
+#[example = "Chimie | H2O | Eau"]
+#[min_args(1)]
+async fn add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let parts: Vec<&str> = args.rest().splitn(3, '|').collect();
+    if parts.len() < 3 {
+        reply(ctx, msg, TITLE, "Sépare le paquet, la question et la réponse par `|`.").await;
+        return Ok(());
+    }
+
+    let store = flashcard_store(ctx).await;
+    let added = store.deck(msg.author.id, parts[0])
+        .and_then(|deck| store.add_card(&deck, parts[1], parts[2]).map(|card| (deck, card)));
+    match added {
+        Ok((deck, card)) => {
+            reply(ctx, msg, TITLE, &format!("Fiche n°{} ajoutée à `{}`.", card.id, deck.name)).await;
+        }
+        Err(err) => flashcard_error(ctx, msg, err).await,
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Montre les fiches d'un de tes paquets."]
+#[usage = "<paquet>"]
+#[min_args(1)]
+async fn list(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let store = flashcard_store(ctx).await;
+    let deck = match store.deck(msg.author.id, args.rest()) {
+        Ok(deck) => deck,
+        Err(err) => {
+            flashcard_error(ctx, msg, err).await;
+            return Ok(());
+        }
+    };
+    let cards = match store.cards(&deck) {
+        Ok(cards) => cards,
+        Err(err) => {
+            flashcard_error(ctx, msg, err).await;
+            return Ok(());
+        }
+    };
+
+    if cards.is_empty() {
+        reply(ctx, msg, TITLE, &format!("Le paquet `{}` est vide.", deck.name)).await;
+        return Ok(());
+    }
+
+    let color = ctx.data.read().await.get::<DiscordBot>()
+        .expect("Did not find DiscordBot").color;
+    let pages = cards.chunks(CARDS_PER_PAGE).map(|cards| Embed {
+        title: Some(format!("{} — {}", TITLE, deck.name)),
+        description: Some(cards.iter()
+            .map(|card| format!("`n°{}` {} → {}", card.id, first_line(&card.front, LIST_SIDE), first_line(&card.back, LIST_SIDE)))
+            .collect::<Vec<_>>()
+            .join("\n")),
+        color: Some(color),
+        ..Default::default()
+    }).collect();
+    send_pages(ctx, msg, Pages::new(pages)).await;
+    Ok(())
+}
+
+#[command]
+#[description = "Retire une fiche d'un de tes paquets, par son numéro."]
+#[usage = "<numéro>"]
+#[example = "12"]
+#[num_args(1)]
+async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let card_id = match args.single::<i64>() {
+        Ok(card_id) => card_id,
+        Err(_) => {
+            reply(ctx, msg, TITLE, "Donne le numéro de la fiche, il est affiché par `cards list`.").await;
+            return Ok(());
+        }
+    };
+
+    match flashcard_store(ctx).await.remove_card(msg.author.id, card_id) {
+        Ok(card) => reply(ctx, msg, TITLE, &format!("Fiche n°{} retirée.", card.id)).await,
+        Err(err) => flashcard_error(ctx, msg, err).await,
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Révise les fiches à revoir d'un de tes paquets, ici ou en message privé."]
+#[usage = "<paquet>"]
+#[example = "Chimie"]
+#[min_args(1)]
+async fn review(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let store = flashcard_store(ctx).await;
+    let deck = match store.deck(msg.author.id, args.rest()) {
+        Ok(deck) => deck,
+        Err(err) => {
+            flashcard_error(ctx, msg, err).await;
+            return Ok(());
+        }
+    };
+
+    if !start_review(ctx, msg, store, deck.clone()).await {
+        reply(ctx, msg, TITLE, &format!("Rien à réviser dans `{}` pour l'instant.", deck.name)).await;
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Ajoute à un de tes paquets les fiches d'un fichier joint : CSV ou TSV avec la question puis la \
+réponse sur chaque ligne, ou paquet Anki `.apkg`. Le paquet est créé s'il n'existe pas."]
+#[usage = "<paquet>"]
+#[example = "Chimie"]
+#[min_args(1)]
+async fn import(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let attachment = match msg.attachments.first() {
+        Some(attachment) => attachment,
+        None => {
+            reply(ctx, msg, TITLE, "Joins à ta commande un fichier `.csv`, `.tsv` ou `.apkg`.").await;
+            return Ok(());
+        }
+    };
+    if attachment.size > MAX_IMPORT_SIZE {
+        reply(ctx, msg, TITLE, &ImportError::TooLarge.to_string()).await;
+        return Ok(());
+    }
+    // Checked before downloading, the name also goes in the title of the preview.
+    let deck_name = match deck_name(args.rest()) {
+        Ok(name) => name.to_string(),
+        Err(err) => {
+            reply(ctx, msg, TITLE, &err.to_string()).await;
+            return Ok(());
+        }
+    };
+
+    let data = match attachment.download().await {
+        Ok(data) => data,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not download {} from {}: {}", attachment.url, msg.author.id, err));
+            reply(ctx, msg, TITLE, "Impossible de télécharger le fichier.").await;
+            return Ok(());
+        }
+    };
+    let filename = attachment.filename.clone();
+    let import = match tokio::task::spawn_blocking(move || read_cards(&filename, &data)).await? {
+        Ok(import) => import,
+        Err(err) => {
+            reply(ctx, msg, TITLE, &err.to_string()).await;
+            return Ok(());
+        }
+    };
+
+    let color = ctx.data.read().await.get::<DiscordBot>()
+        .expect("Did not find DiscordBot").color;
+    let preview = import_preview(&deck_name, &import, color);
+    if import.cards.is_empty() {
+        msg.channel_id.send_message(&ctx.http, |m| m.reference_message(msg).set_embed(create_embed(&preview))).await?;
+        return Ok(());
+    }
+
+    let mut question = msg.channel_id.send_message(&ctx.http, |m| {
+        m.reference_message(msg).set_embed(create_embed(&preview)).components(|c| c.create_action_row(|row| {
+            row.create_button(|button| button.custom_id(IMPORT_CONFIRM).label("Importer").style(ButtonStyle::Success));
+            row.create_button(|button| button.custom_id(IMPORT_CANCEL).label("Annuler").style(ButtonStyle::Secondary))
+        }))
+    }).await?;
+
+    let click = question.await_component_interaction(ctx)
+        .author_id(msg.author.id)
+        .timeout(IMPORT_TIMEOUT)
+        .await;
+    let outcome = match &click {
+        Some(click) if click.data.custom_id == IMPORT_CONFIRM => {
+            let store = flashcard_store(ctx).await;
+            let imported = store.deck_or_create(msg.author.id, &deck_name, now(ctx).await)
+                .and_then(|deck| store.add_cards(&deck, &import.cards).map(|count| (deck, count)));
+            match imported {
+                Ok((deck, count)) => {
+                    MiraiLogger::info(format!("{} imported {} card(s) into deck {}", msg.author.id, count, deck.id));
+                    format!("{} fiche(s) ajoutée(s) au paquet `{}`.", count, deck.name)
+                }
+                Err(FlashcardError::Database(err)) => {
+                    MiraiLogger::error(format!("Could not import cards for {}: {}", msg.author.id, err));
+                    "Impossible d'enregistrer les fiches.".to_string()
+                }
+                Err(err) => err.to_string(),
+            }
+        }
+        Some(_) => "Import annulé.".to_string(),
+        None => "Import annulé, tu n'as pas répondu à temps.".to_string(),
+    };
+
+    let embed = create_embed(&Embed { description: Some(outcome), fields: Vec::new(), ..preview });
+    match click {
+        Some(click) => {
+            click.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|data| data.set_embed(embed).components(|c| c))
+            }).await?;
+        }
+        None => {
+            question.edit(&ctx.http, |m| m.set_embed(embed).components(|c| c)).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Envoie les fiches d'un de tes paquets dans un fichier CSV, que `cards import` sait relire."]
+#[usage = "<paquet>"]
+#[example = "Chimie"]
+#[min_args(1)]
+async fn export(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let store = flashcard_store(ctx).await;
+    let exported = store.deck(msg.author.id, args.rest())
+        .and_then(|deck| store.cards(&deck).map(|cards| (deck, cards)));
+    let (deck, cards) = match exported {
+        Ok(exported) => exported,
+        Err(err) => {
+            flashcard_error(ctx, msg, err).await;
+            return Ok(());
+        }
+    };
+
+    let file = AttachmentType::Bytes { data: Cow::Owned(export_csv(&cards)), filename: export_filename(&deck.name) };
+    if let Err(err) = msg.channel_id.send_files(&ctx.http, [file], |m| {
+        m.reference_message(msg).content(format!("{} fiche(s) du paquet `{}`.", cards.len(), deck.name))
+    }).await {
+        MiraiLogger::error(format!("Could not send the export of deck {} to {}: {}", deck.id, msg.author.id, err));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::utils::Colour;
+
+    use crate::flashcards::import::read_delimited;
+    use crate::mirai_bot::commands::flashcards::{first_line, import_preview};
+
+    #[test]
+    fn test_first_line() {
+        assert_eq!(first_line("Eau", 10), "Eau");
+        assert_eq!(first_line("Chlorure de sodium", 9), "Chlorure…");
+        assert_eq!(first_line("Eau\nH2O", 10), "Eau…");
+    }
+
+    #[test]
+    fn test_import_preview() {
+        let import = read_delimited("H2O,Eau\nCO2\nNaCl,Sel\n".as_bytes(), b',').unwrap();
+        let preview = import_preview("Chimie", &import, Colour::new(0));
+
+        assert_eq!(preview.description.as_deref(), Some("2 fiche(s) prête(s) à être ajoutée(s) au paquet `Chimie`."));
+        assert_eq!(preview.fields[0].value, "H2O → Eau\nNaCl → Sel");
+        assert_eq!(preview.fields[1].name, "Lignes ignorées (1)");
+        assert_eq!(preview.fields[1].value, "ligne 2 : il manque la réponse");
+    }
+}
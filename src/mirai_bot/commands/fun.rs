@@ -0,0 +1,170 @@
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serenity::client::Context;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::standard::macros::{command, group};
+use serenity::model::channel::Message;
+
+use crate::mirai_bot::commands::Reply;
+
+const MAX_DICE: u32 = 100;
+const MAX_FACES: u32 = 1000;
+
+#[group("Divertissement")]
+#[description = "De quoi passer le temps entre deux procès."]
+#[commands(roll, choose, coin)]
+struct Fun;
+
+/// Dice written as `NdM`, `dM` being one die.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dice {
+    pub count: u32,
+    pub faces: u32,
+}
+
+impl Dice {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (count, faces) = text.trim().to_lowercase().split_once('d')
+            .map(|(count, faces)| (count.to_string(), faces.to_string()))
+            .ok_or_else(|| format!("`{}` n'est pas de la forme `2d6`", text.trim()))?;
+
+        let count = match count.as_str() {
+            "" => 1,
+            count => count.parse::<u32>().map_err(|_| format!("`{}` n'est pas un nombre de dés", count))?,
+        };
+        let faces = faces.parse::<u32>().map_err(|_| format!("`{}` n'est pas un nombre de faces", faces))?;
+
+        if !(1..=MAX_DICE).contains(&count) {
+            return Err(format!("le nombre de dés doit être entre 1 et {}", MAX_DICE));
+        }
+        if !(2..=MAX_FACES).contains(&faces) {
+            return Err(format!("le nombre de faces doit être entre 2 et {}", MAX_FACES));
+        }
+
+        Ok(Self { count, faces })
+    }
+
+    pub fn roll<R: Rng>(&self, rng: &mut R) -> Vec<u32> {
+        (0..self.count).map(|_| rng.gen_range(1..=self.faces)).collect()
+    }
+}
+
+impl Default for Dice {
+    fn default() -> Self { Self { count: 1, faces: 6 } }
+}
+
+/// Choices separated by `|`, the empty ones are ignored.
+pub fn parse_choices(text: &str) -> Vec<String> {
+    text.split('|')
+        .map(str::trim)
+        .filter(|choice| !choice.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Rolls `dice`, one six-sided die if it is empty.
+pub fn roll_reply(dice: &str) -> Reply {
+    let dice = match dice.trim() {
+        "" => Dice::default(),
+        text => match Dice::parse(text) {
+            Ok(dice) => dice,
+            Err(err) => return Reply::new("Dés", format!("Dés invalides : {}.", err)),
+        },
+    };
+
+    let rolls = dice.roll(&mut rand::thread_rng());
+    let total: u32 = rolls.iter().sum();
+    let description = match rolls.len() {
+        1 => format!("🎲 {}", total),
+        _ => format!(
+            "🎲 {} = **{}**",
+            rolls.iter().map(u32::to_string).collect::<Vec<_>>().join(" + "),
+            total
+        ),
+    };
+    Reply::new("Dés", description)
+}
+
+pub fn choose_reply(choices: &str) -> Reply {
+    let choices = parse_choices(choices);
+    if choices.len() < 2 {
+        return Reply::new("Choix", "Donne au moins deux propositions séparées par `|`.");
+    }
+
+    let choice = choices.choose(&mut rand::thread_rng()).expect("There are at least two choices");
+    Reply::new("Choix", format!("Je choisis… **{}** !", choice))
+}
+
+pub fn coin_reply() -> Reply {
+    let side = if rand::thread_rng().gen_bool(0.5) { "Pile" } else { "Face" };
+    Reply::new("Pièce", format!("🪙 {} !", side))
+}
+
+#[command]
+#[aliases("dice")]
+#[bucket = "fun"]
+#[description = "Lance des dés."]
+#[usage = "[dés]"]
+#[example = "2d6"]
+#[max_args(1)]
+async fn roll(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    roll_reply(args.rest()).send(ctx, msg).await;
+    Ok(())
+}
+
+#[command]
+#[aliases("pick")]
+#[bucket = "fun"]
+#[description = "Choisit au hasard parmi des propositions séparées par `|`."]
+#[usage = "<choix> | <choix> | ..."]
+#[example = "pizza | sushis | ramen"]
+#[min_args(1)]
+async fn choose(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    choose_reply(args.rest()).send(ctx, msg).await;
+    Ok(())
+}
+
+#[command]
+#[aliases("flip")]
+#[bucket = "fun"]
+#[description = "Lance une pièce."]
+async fn coin(ctx: &Context, msg: &Message) -> CommandResult {
+    coin_reply().send(ctx, msg).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::mirai_bot::commands::fun::{choose_reply, Dice, parse_choices, roll_reply};
+
+    #[test]
+    fn test_dice() {
+        assert_eq!(Dice::parse("2d6"), Ok(Dice { count: 2, faces: 6 }));
+        assert_eq!(Dice::parse(" D20 "), Ok(Dice { count: 1, faces: 20 }));
+        assert!(Dice::parse("6").is_err());
+        assert!(Dice::parse("0d6").is_err());
+        assert!(Dice::parse("2d1").is_err());
+        assert!(Dice::parse("101d6").is_err());
+        assert!(Dice::parse("xd6").is_err());
+
+        let rolls = Dice { count: 50, faces: 4 }.roll(&mut StdRng::seed_from_u64(0));
+        assert_eq!(rolls.len(), 50);
+        assert!(rolls.iter().all(|roll| (1..=4).contains(roll)));
+    }
+
+    #[test]
+    fn test_choices() {
+        assert_eq!(parse_choices("pizza | sushis |  | ramen"), vec!["pizza", "sushis", "ramen"]);
+        assert!(parse_choices(" | ").is_empty());
+    }
+
+    #[test]
+    fn test_replies() {
+        assert_eq!(roll_reply("3d1").description, "Dés invalides : le nombre de faces doit être entre 2 et 1000.");
+        assert_eq!(choose_reply("pizza").description, "Donne au moins deux propositions séparées par `|`.");
+        assert!(["Je choisis… **a** !", "Je choisis… **b** !"].contains(&choose_reply("a | b").description.as_str()));
+    }
+}
@@ -0,0 +1,64 @@
+use serenity::client::Context;
+use serenity::framework::standard::CommandResult;
+use serenity::framework::standard::macros::{command, group};
+use serenity::model::channel::Message;
+
+use crate::bot::DiscordBot;
+use crate::mirai_bot::commands::Reply;
+use crate::utils::time::FRENCH_TIME_FORMAT;
+
+#[group("Général")]
+#[description = "Les commandes de base du bot."]
+#[commands(ping, info, time)]
+struct General;
+
+pub fn ping_reply() -> Reply {
+    Reply::new("Ping", "Pong !")
+}
+
+pub async fn info_reply(ctx: &Context) -> Reply {
+    let bot = ctx.data.read().await.get::<DiscordBot>()
+        .expect("Did not find DiscordBot").clone();
+
+    let creator = match bot.creator {
+        Some(creator) => format!("<@{}>", creator),
+        None => "inconnu".to_string(),
+    };
+    Reply::new("Mirai", format!(
+        "Version : {}\nPréfixe : `{}`\nCréateur : {}\nServeurs : {}\n\nTape `{}help` pour voir les commandes.",
+        env!("CARGO_PKG_VERSION"),
+        bot.prefix,
+        creator,
+        ctx.cache.guild_count(),
+        bot.prefix
+    ))
+}
+
+pub async fn time_reply(ctx: &Context) -> Reply {
+    let bot = ctx.data.read().await.get::<DiscordBot>()
+        .expect("Did not find DiscordBot").clone();
+
+    let now = bot.clock.now().with_timezone(&bot.timezone);
+    Reply::new("Heure", format!("Nous sommes le {} ({}).", now.format(FRENCH_TIME_FORMAT), bot.timezone))
+}
+
+#[command]
+#[description = "Vérifie que le bot répond."]
+async fn ping(ctx: &Context, msg: &Message) -> CommandResult {
+    ping_reply().send(ctx, msg).await;
+    Ok(())
+}
+
+#[command]
+#[description = "Présente le bot."]
+async fn info(ctx: &Context, msg: &Message) -> CommandResult {
+    info_reply(ctx).await.send(ctx, msg).await;
+    Ok(())
+}
+
+#[command]
+#[description = "Donne l'heure du bot."]
+async fn time(ctx: &Context, msg: &Message) -> CommandResult {
+    time_reply(ctx).await.send(ctx, msg).await;
+    Ok(())
+}
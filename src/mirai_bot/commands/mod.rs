@@ -0,0 +1,148 @@
+use serenity::client::Context;
+use serenity::framework::standard::{Args, Check, CommandOptions, Reason};
+use serenity::framework::standard::macros::check;
+use serenity::model::channel::Message;
+use serenity::model::id::UserId;
+use serenity::model::Permissions;
+
+use crate::bot::DiscordBot;
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::mirai_bot::sessions::ChannelSessions;
+use crate::permissions::{BotPermissions, PermissionLevel};
+
+pub(crate) mod admin;
+pub(crate) mod class_trial;
+pub(crate) mod debate;
+pub(crate) mod flashcards;
+pub(crate) mod fun;
+pub(crate) mod general;
+pub(crate) mod moderation;
+pub(crate) mod quiz;
+pub(crate) mod settings;
+
+/// What a command answers, shared by its prefix and slash versions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub title: String,
+    pub description: String,
+}
+
+impl Reply {
+    pub fn new(title: &str, description: impl Into<String>) -> Self {
+        Self { title: title.to_string(), description: description.into() }
+    }
+
+    pub async fn send(&self, ctx: &Context, msg: &Message) {
+        reply(ctx, msg, &self.title, &self.description).await;
+    }
+}
+
+/// Answers with an embed in the bot's style.
+pub async fn reply(ctx: &Context, msg: &Message, title: &str, description: &str) {
+    let color = ctx.data.read().await.get::<DiscordBot>()
+        .expect("Did not find DiscordBot").color;
+
+    if let Err(err) = msg.channel_id.send_message(&ctx.http, |m| {
+        m.reference_message(msg);
+        m.embed(|embed| {
+            embed.color(color);
+            embed.title(title);
+            embed.description(description);
+            embed
+        });
+        m
+    }).await {
+        MiraiLogger::error(format!("Could not reply to {} on {}: {}", msg.author.id, msg.channel_id, err));
+    }
+}
+
+/// The member mentioned in `args`, or the author if there are none. `None` if the argument
+/// is not a mention.
+pub fn member_or_author(msg: &Message, args: &mut Args) -> Option<UserId> {
+    match args.is_empty() {
+        true => Some(msg.author.id),
+        false => args.single::<UserId>().ok(),
+    }
+}
+
+/// Stops the game of `sessions` running in the channel, if the author started it or is a
+/// moderator. `idle` is the reply when none runs.
+pub async fn stop_session<T>(ctx: &Context, msg: &Message, sessions: &ChannelSessions<T>, title: &str, idle: &str) {
+    let host = match sessions.host(msg.channel_id) {
+        Some(host) => host,
+        None => {
+            reply(ctx, msg, title, idle).await;
+            return;
+        }
+    };
+    if host != msg.author.id {
+        let permissions = ctx.data.read().await.get::<BotPermissions>().expect("Did not find BotPermissions").clone();
+        if let Err(denied) = permissions.check(ctx, msg.author.id, msg.guild_id, MODERATOR_LEVEL).await {
+            reply(ctx, msg, title, &denied.to_string()).await;
+            return;
+        }
+    }
+
+    if sessions.stop(msg.channel_id) {
+        MiraiLogger::info(format!("{} stopped the game on {}", msg.author.id, msg.channel_id));
+    }
+}
+
+const OWNER_LEVEL: PermissionLevel = PermissionLevel::BotOwner;
+const ADMIN_LEVEL: PermissionLevel = PermissionLevel::BotAdmin;
+const MANAGER_LEVEL: PermissionLevel = PermissionLevel::Guild(Permissions::MANAGE_GUILD);
+const MODERATOR_LEVEL: PermissionLevel = PermissionLevel::Guild(Permissions::MANAGE_MESSAGES);
+const CHANNELS_LEVEL: PermissionLevel = PermissionLevel::Guild(Permissions::MANAGE_CHANNELS);
+
+/// What one of the checks below requires, for the help to show and filter the commands.
+pub fn required_level(check: &Check) -> Option<PermissionLevel> {
+    match check.name {
+        "Owner" => Some(OWNER_LEVEL),
+        "Admin" => Some(ADMIN_LEVEL),
+        "Manager" => Some(MANAGER_LEVEL),
+        "Moderator" => Some(MODERATOR_LEVEL),
+        "Channels" => Some(CHANNELS_LEVEL),
+        _ => None,
+    }
+}
+
+async fn check_level(ctx: &Context, msg: &Message, level: PermissionLevel) -> Result<(), Reason> {
+    let permissions = ctx.data.read().await.get::<BotPermissions>()
+        .expect("Did not find BotPermissions").clone();
+
+    permissions.check(ctx, msg.author.id, msg.guild_id, level).await
+        .map_err(|denied| Reason::UserAndLog {
+            user: denied.to_string(),
+            log: format!("{} was denied {:?}: {:?}", msg.author.id, level, denied),
+        })
+}
+
+#[check]
+#[name = "Owner"]
+async fn owner_check(ctx: &Context, msg: &Message, _: &mut Args, _: &CommandOptions) -> Result<(), Reason> {
+    check_level(ctx, msg, OWNER_LEVEL).await
+}
+
+#[check]
+#[name = "Admin"]
+async fn admin_check(ctx: &Context, msg: &Message, _: &mut Args, _: &CommandOptions) -> Result<(), Reason> {
+    check_level(ctx, msg, ADMIN_LEVEL).await
+}
+
+#[check]
+#[name = "Moderator"]
+async fn moderator_check(ctx: &Context, msg: &Message, _: &mut Args, _: &CommandOptions) -> Result<(), Reason> {
+    check_level(ctx, msg, MODERATOR_LEVEL).await
+}
+
+#[check]
+#[name = "Channels"]
+async fn channel_manager_check(ctx: &Context, msg: &Message, _: &mut Args, _: &CommandOptions) -> Result<(), Reason> {
+    check_level(ctx, msg, CHANNELS_LEVEL).await
+}
+
+#[check]
+#[name = "Manager"]
+async fn manager_check(ctx: &Context, msg: &Message, _: &mut Args, _: &CommandOptions) -> Result<(), Reason> {
+    check_level(ctx, msg, MANAGER_LEVEL).await
+}
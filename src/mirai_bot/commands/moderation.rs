@@ -0,0 +1,88 @@
+use serenity::client::Context;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::standard::macros::{command, group};
+use serenity::model::channel::Message;
+
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::mirai_bot::commands::{CHANNELS_CHECK, MODERATOR_CHECK, reply};
+
+/// Discord only bulk deletes up to 100 messages.
+const MAX_CLEAR: u64 = 100;
+/// Longest slow mode Discord allows, 6 hours.
+const MAX_SLOWMODE: u64 = 21600;
+
+#[group("Modération")]
+#[description = "Pour garder le serveur propre."]
+#[only_in(guilds)]
+#[checks(Moderator)]
+#[commands(clear, slowmode)]
+struct Moderation;
+
+#[command]
+#[aliases("purge")]
+#[description = "Supprime les derniers messages du salon."]
+#[usage = "<nombre>"]
+#[example = "20"]
+#[num_args(1)]
+async fn clear(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let count = match args.single::<u64>() {
+        Ok(count) if (1..=MAX_CLEAR).contains(&count) => count,
+        _ => {
+            reply(ctx, msg, "Modération", &format!("Donne un nombre de messages entre 1 et {}.", MAX_CLEAR)).await;
+            return Ok(());
+        }
+    };
+
+    let messages = msg.channel_id.messages(&ctx.http, |retriever| retriever.before(msg.id).limit(count)).await?;
+    let deleted = match messages.as_slice() {
+        [] => Ok(()),
+        [message] => message.delete(&ctx.http).await,
+        messages => msg.channel_id.delete_messages(&ctx.http, messages).await,
+    };
+
+    match deleted {
+        Ok(()) => {
+            MiraiLogger::info(format!("{} cleared {} messages in {}", msg.author.id, messages.len(), msg.channel_id));
+            reply(ctx, msg, "Modération", &format!("{} message(s) supprimé(s).", messages.len())).await;
+        }
+        Err(err) => {
+            MiraiLogger::error(format!("Could not clear messages in {}: {}", msg.channel_id, err));
+            reply(
+                ctx, msg, "Modération",
+                "Impossible de supprimer les messages, ceux de plus de deux semaines ne peuvent pas l'être.",
+            ).await;
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[checks(Channels)]
+#[description = "Règle le mode lent du salon, `0` le désactive."]
+#[usage = "<secondes>"]
+#[example = "30"]
+#[num_args(1)]
+async fn slowmode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let seconds = match args.single::<u64>() {
+        Ok(seconds) if seconds <= MAX_SLOWMODE => seconds,
+        _ => {
+            reply(ctx, msg, "Modération", &format!("Donne une durée entre 0 et {} secondes.", MAX_SLOWMODE)).await;
+            return Ok(());
+        }
+    };
+
+    if let Err(err) = msg.channel_id.edit(&ctx.http, |channel| channel.rate_limit_per_user(seconds)).await {
+        MiraiLogger::error(format!("Could not set the slow mode of {}: {}", msg.channel_id, err));
+        reply(ctx, msg, "Modération", "Impossible de changer le mode lent du salon.").await;
+        return Ok(());
+    }
+
+    let description = match seconds {
+        0 => "Le mode lent est désactivé.".to_string(),
+        seconds => format!("Le mode lent est de {} seconde(s).", seconds),
+    };
+    reply(ctx, msg, "Modération", &description).await;
+
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use serenity::client::Context;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::standard::macros::{command, group};
+use serenity::model::channel::Message;
+use serenity::model::id::UserId;
+
+use crate::bot::DiscordBot;
+use crate::flashcards::{FlashcardError, FlashcardStore};
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::mirai_bot::commands::{member_or_author, reply, stop_session};
+use crate::mirai_bot::quiz::{self, ANSWER_WINDOW, QuizSessions, start_quiz};
+use crate::quiz::{DEFAULT_QUESTIONS, MAX_QUESTIONS, questions_from_cards};
+use crate::quiz::trivia::{Game, TriviaBank, TriviaFilter, TriviaStore};
+
+const TITLE: &str = "Quiz";
+
+#[group("Quiz")]
+#[description = "Des questions posées dans le salon, le premier à répondre marque le point."]
+#[prefix = "quiz"]
+#[only_in(guilds)]
+#[commands(trivia, deck, choices, stats, stop)]
+struct Quiz;
+
+/// Splits a trailing number of questions off the arguments, like in `Chimie 10`.
+pub fn split_count(text: &str) -> (&str, Option<usize>) {
+    let text = text.trim();
+    match text.rsplit_once(char::is_whitespace) {
+        Some((rest, count)) => match count.parse::<usize>() {
+            Ok(count) => (rest.trim(), Some(count)),
+            Err(_) => (text, None),
+        },
+        None => (text, None),
+    }
+}
+
+/// The number of questions asked for, if there are not too many.
+fn question_count(count: Option<usize>) -> Result<usize, String> {
+    match count.unwrap_or(DEFAULT_QUESTIONS) {
+        count if (1..=MAX_QUESTIONS).contains(&count) => Ok(count),
+        _ => Err(format!("Un quiz a entre 1 et {} questions.", MAX_QUESTIONS)),
+    }
+}
+
+#[command]
+#[aliases("dr")]
+#[description = "Lance un quiz sur Danganronpa, sans spoilers à moins de les demander. Les questions déjà posées dans le salon ne reviennent qu'une fois toutes posées."]
+#[usage = "[dr1|dr2|v3] [facile|moyen|difficile] [histoire|fin] [questions]"]
+#[example = "dr2 difficile 10"]
+async fn trivia(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (filter, count) = match TriviaFilter::parse(args.rest()) {
+        Ok(parsed) => parsed,
+        Err(word) => {
+            reply(ctx, msg, TITLE, &format!(
+                "`{}` n'est ni un jeu (dr1, dr2, v3), ni une difficulté (facile, moyen, difficile), ni un niveau de spoilers (aucun, histoire, fin).",
+                word
+            )).await;
+            return Ok(());
+        }
+    };
+    let count = match question_count(count) {
+        Ok(count) => count,
+        Err(reason) => {
+            reply(ctx, msg, TITLE, &reason).await;
+            return Ok(());
+        }
+    };
+
+    let (bank, store, sessions, bot) = {
+        let data = ctx.data.read().await;
+        (
+            data.get::<TriviaBank>().expect("Did not find TriviaBank").clone(),
+            data.get::<TriviaStore>().expect("Did not find TriviaStore").clone(),
+            data.get::<QuizSessions>().expect("Did not find QuizSessions").clone(),
+            data.get::<DiscordBot>().expect("Did not find DiscordBot").clone(),
+        )
+    };
+    // Checked before picking, the questions would count as asked otherwise.
+    if sessions.host(msg.channel_id).is_some() {
+        reply(ctx, msg, TITLE, "Un quiz est déjà en cours dans ce salon.").await;
+        return Ok(());
+    }
+    let candidates = bank.matching(&filter);
+    if candidates.is_empty() {
+        reply(ctx, msg, TITLE, "Aucune question ne correspond à ces critères.").await;
+        return Ok(());
+    }
+
+    let picked = {
+        let mut rng = rand::thread_rng();
+        store.pick(msg.channel_id, &candidates, count, bot.clock.now(), &mut rng)
+            .map(|picked| {
+                let questions = picked.iter().map(|question| question.question(&mut rng)).collect::<Vec<_>>();
+                (picked.iter().map(|question| question.game).collect::<Vec<Game>>(), questions)
+            })
+    };
+    let (games, questions) = match picked {
+        Ok(picked) => picked,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not pick trivia questions on {}: {}", msg.channel_id, err));
+            reply(ctx, msg, TITLE, "Impossible de choisir les questions.").await;
+            return Ok(());
+        }
+    };
+
+    let title = match filter.game {
+        Some(game) => format!("{} — {}", TITLE, game.label()),
+        None => format!("{} — Danganronpa", TITLE),
+    };
+    let quiz = quiz::Quiz { title, questions, window: ANSWER_WINDOW, color: bot.color };
+    let scoreboard = match start_quiz(ctx, msg.channel_id, msg.author.id, quiz).await {
+        Some(scoreboard) => scoreboard,
+        None => {
+            reply(ctx, msg, TITLE, "Un quiz est déjà en cours dans ce salon.").await;
+            return Ok(());
+        }
+    };
+
+    let guild = msg.guild_id.expect("Trivia quizzes only run in guilds");
+    bot.shutdown.spawn(async move {
+        match scoreboard.await {
+            Ok(scoreboard) => {
+                let rounds: Vec<(Game, Vec<UserId>)> = games.into_iter().zip(scoreboard.rounds().iter().cloned()).collect();
+                if let Err(err) = store.record(guild, &rounds) {
+                    MiraiLogger::error(format!("Could not save the trivia stats of {}: {}", guild, err));
+                }
+            }
+            Err(err) => MiraiLogger::error(format!("A trivia quiz on {} failed: {}", guild, err)),
+        }
+    });
+    Ok(())
+}
+
+#[command]
+#[description = "Montre combien de questions Danganronpa un membre a trouvées sur ce serveur."]
+#[usage = "[@membre]"]
+#[max_args(1)]
+async fn stats(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user = match member_or_author(msg, &mut args) {
+        Some(user) => user,
+        None => {
+            reply(ctx, msg, TITLE, "Mentionne le membre dont tu veux les statistiques.").await;
+            return Ok(());
+        }
+    };
+    let guild = msg.guild_id.expect("Trivia stats only exist in guilds");
+    let store = ctx.data.read().await.get::<TriviaStore>()
+        .expect("Did not find TriviaStore").clone();
+
+    let stats = match store.stats(guild, user) {
+        Ok(stats) => stats,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not read the trivia stats of {}: {}", user, err));
+            reply(ctx, msg, TITLE, "Impossible de lire les statistiques.").await;
+            return Ok(());
+        }
+    };
+    let description = match stats.is_empty() {
+        true => format!("<@{}> n'a encore trouvé aucune question.", user),
+        false => {
+            let total: u32 = stats.iter().map(|(_, correct)| correct).sum();
+            let games = stats.iter()
+                .map(|(game, correct)| format!("**{}** : {}", game.label(), correct))
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!("<@{}> a trouvé {} question(s).\n\n{}", user, total, games)
+        }
+    };
+    reply(ctx, msg, TITLE, &description).await;
+    Ok(())
+}
+
+/// Starts a quiz on the cards of one of the author's decks.
+async fn deck_quiz(ctx: &Context, msg: &Message, args: &Args, multiple_choice: bool) {
+    let (deck_name, count) = split_count(args.rest());
+    let count = match question_count(count) {
+        Ok(count) => count,
+        Err(reason) => {
+            reply(ctx, msg, TITLE, &reason).await;
+            return;
+        }
+    };
+
+    let store = ctx.data.read().await.get::<FlashcardStore>()
+        .expect("Did not find FlashcardStore").clone();
+    let cards = store.deck(msg.author.id, deck_name).and_then(|deck| store.cards(&deck));
+    let cards = match cards {
+        Ok(cards) => cards,
+        Err(FlashcardError::Database(err)) => {
+            MiraiLogger::error(format!("Could not read the cards of {} for a quiz: {}", msg.author.id, err));
+            reply(ctx, msg, TITLE, "Impossible de lire le paquet.").await;
+            return;
+        }
+        Err(err) => {
+            reply(ctx, msg, TITLE, &err.to_string()).await;
+            return;
+        }
+    };
+    if cards.is_empty() {
+        reply(ctx, msg, TITLE, "Ce paquet est vide.").await;
+        return;
+    }
+
+    let questions = questions_from_cards(&cards, count, multiple_choice, &mut rand::thread_rng());
+    let color = ctx.data.read().await.get::<DiscordBot>()
+        .expect("Did not find DiscordBot").color;
+    let quiz = quiz::Quiz { title: format!("{} — {}", TITLE, deck_name), questions, window: ANSWER_WINDOW, color };
+
+    if start_quiz(ctx, msg.channel_id, msg.author.id, quiz).await.is_none() {
+        reply(ctx, msg, TITLE, "Un quiz est déjà en cours dans ce salon.").await;
+    }
+}
+
+#[command]
+#[description = "Lance un quiz sur un de tes paquets de fiches, la réponse s'écrit dans le salon."]
+#[usage = "<paquet> [questions]"]
+#[example = "Chimie 10"]
+#[min_args(1)]
+async fn deck(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    deck_quiz(ctx, msg, &args, false).await;
+    Ok(())
+}
+
+#[command]
+#[description = "Lance un quiz à choix multiples sur un de tes paquets, la réponse se donne avec une réaction."]
+#[usage = "<paquet> [questions]"]
+#[example = "Chimie 10"]
+#[min_args(1)]
+async fn choices(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    deck_quiz(ctx, msg, &args, true).await;
+    Ok(())
+}
+
+#[command]
+#[description = "Arrête le quiz du salon, si c'est toi qui l'as lancé ou si tu es modérateur."]
+async fn stop(ctx: &Context, msg: &Message) -> CommandResult {
+    let sessions = ctx.data.read().await.get::<QuizSessions>().expect("Did not find QuizSessions").clone();
+    stop_session(ctx, msg, &sessions, TITLE, "Aucun quiz n'est en cours dans ce salon.").await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mirai_bot::commands::quiz::split_count;
+
+    #[test]
+    fn test_split_count() {
+        assert_eq!(split_count("Chimie 10"), ("Chimie", Some(10)));
+        assert_eq!(split_count(" Chimie organique "), ("Chimie organique", None));
+        assert_eq!(split_count("Chimie"), ("Chimie", None));
+    }
+}
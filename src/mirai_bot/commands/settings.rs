@@ -0,0 +1,325 @@
+use serenity::client::Context;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::standard::macros::{command, group};
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, RoleId};
+
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::mirai_bot::commands::{MANAGER_CHECK, reply};
+use crate::mirai_bot::monokuma_announcement::MonokumaAnnouncements;
+use crate::mirai_bot::night_mode::{NightMode, role_list};
+use crate::settings::{Feature, GuildSettings, GuildSettingsStore};
+use crate::utils::guild_fcts::{find_guild_system_channel, guild_settings, is_guild_channel, is_guild_role};
+use crate::utils::time::Schedule;
+
+#[group("Paramètres")]
+#[description = "Les réglages du bot sur le serveur."]
+#[prefix = "settings"]
+#[only_in(guilds)]
+#[checks(Manager)]
+#[commands(show, channel, feature, schedule, night, exempt)]
+struct Settings;
+
+fn channel_mention(channel: Option<ChannelId>) -> String {
+    match channel {
+        Some(channel) => format!("<#{}>", channel),
+        None => "salon système".to_string(),
+    }
+}
+
+fn channel_list(channels: &[ChannelId]) -> String {
+    match channels.is_empty() {
+        true => "aucun".to_string(),
+        false => channels.iter().map(|channel| format!("<#{}>", channel)).collect::<Vec<_>>().join(", "),
+    }
+}
+
+/// Adds `item` to `items` or removes it, as asked by `action`. `None` for an unknown action.
+fn edit_list<T: PartialEq>(items: &mut Vec<T>, action: &str, item: T) -> Option<bool> {
+    match action {
+        "add" => {
+            if !items.contains(&item) {
+                items.push(item);
+            }
+            Some(true)
+        }
+        "remove" => {
+            items.retain(|existing| *existing != item);
+            Some(false)
+        }
+        _ => None,
+    }
+}
+
+async fn save_settings(ctx: &Context, msg: &Message, settings: &GuildSettings) -> bool {
+    let store = ctx.data.read().await.get::<GuildSettingsStore>()
+        .expect("Did not find GuildSettingsStore").clone();
+
+    if let Err(err) = store.save(settings) {
+        MiraiLogger::error(format!("Could not save settings of guild {}: {}", settings.guild_id, err));
+        reply(ctx, msg, "Paramètres", "Impossible d'enregistrer les paramètres.").await;
+        return false;
+    }
+
+    let announcements = ctx.data.read().await.get::<MonokumaAnnouncements>()
+        .expect("Did not find MonokumaAnnouncements").clone();
+    let system_channel = find_guild_system_channel(&ctx.cache, settings.guild_id);
+    announcements.start(ctx, settings.guild_id, system_channel).await;
+    let night_mode = ctx.data.read().await.get::<NightMode>()
+        .expect("Did not find NightMode").clone();
+    night_mode.start(ctx, settings.guild_id).await;
+    true
+}
+
+#[command]
+#[description = "Affiche les paramètres du serveur."]
+async fn show(ctx: &Context, msg: &Message) -> CommandResult {
+    let settings = match guild_settings(ctx, msg.guild_id.unwrap()).await {
+        Some(settings) => settings,
+        None => {
+            reply(ctx, msg, "Paramètres", "Impossible de lire les paramètres.").await;
+            return Ok(());
+        }
+    };
+    let features = Feature::ALL.iter()
+        .map(|feature| format!(
+            "{} `{}`", if settings.is_enabled(*feature) { "✅" } else { "❌" }, feature.name()
+        ))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    reply(ctx, msg, "Paramètres", &format!(
+        "Bienvenue : {}\nAnnonces : {} (matin `{}`, soir `{}`)\nSalons fermés la nuit : {}\nRôles exemptés : {}\nLangue : {}\n\n{}",
+        channel_mention(settings.welcome_channel),
+        channel_mention(settings.announcement_channel),
+        settings.morning_announcement,
+        settings.evening_announcement,
+        channel_list(&settings.night_channels),
+        role_list(&settings.night_exempt_roles),
+        settings.language,
+        features
+    )).await;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Choisit le salon des messages de bienvenue ou des annonces."]
+#[usage = "<welcome|announcements> #salon"]
+#[num_args(2)]
+async fn channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let kind = args.single::<String>()?;
+    let channel = match args.single::<ChannelId>() {
+        Ok(channel) => channel,
+        Err(_) => {
+            reply(ctx, msg, "Paramètres", "Mentionne le salon à utiliser.").await;
+            return Ok(());
+        }
+    };
+    if !is_guild_channel(ctx, msg.guild_id.unwrap(), channel).await {
+        reply(ctx, msg, "Paramètres", "Ce salon n'est pas un salon de ce serveur.").await;
+        return Ok(());
+    }
+
+    let mut settings = match guild_settings(ctx, msg.guild_id.unwrap()).await {
+        Some(settings) => settings,
+        None => {
+            reply(ctx, msg, "Paramètres", "Impossible de lire les paramètres.").await;
+            return Ok(());
+        }
+    };
+    match kind.as_str() {
+        "welcome" => settings.welcome_channel = Some(channel),
+        "announcements" => settings.announcement_channel = Some(channel),
+        _ => {
+            reply(ctx, msg, "Paramètres", "Les salons possibles sont `welcome` et `announcements`.").await;
+            return Ok(());
+        }
+    }
+
+    if save_settings(ctx, msg, &settings).await {
+        reply(ctx, msg, "Paramètres", &format!("Le salon `{}` est maintenant <#{}>.", kind, channel)).await;
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Active ou désactive une fonctionnalité du bot sur le serveur."]
+#[usage = "<fonctionnalité> <on|off>"]
+#[num_args(2)]
+async fn feature(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>()?;
+    let enabled = match args.single::<String>()?.as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            reply(ctx, msg, "Paramètres", "Précise `on` ou `off`.").await;
+            return Ok(());
+        }
+    };
+
+    let feature = match Feature::from_name(&name) {
+        Some(feature) => feature,
+        None => {
+            reply(ctx, msg, "Paramètres", &format!("La fonctionnalité `{}` n'existe pas.", name)).await;
+            return Ok(());
+        }
+    };
+
+    let mut settings = match guild_settings(ctx, msg.guild_id.unwrap()).await {
+        Some(settings) => settings,
+        None => {
+            reply(ctx, msg, "Paramètres", "Impossible de lire les paramètres.").await;
+            return Ok(());
+        }
+    };
+    settings.features.retain(|f| *f != feature);
+    if enabled {
+        settings.features.push(feature);
+    }
+
+    if save_settings(ctx, msg, &settings).await {
+        let state = if enabled { "activée" } else { "désactivée" };
+        reply(ctx, msg, "Paramètres", &format!("La fonctionnalité `{}` est {}.", name, state)).await;
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Change l'horaire d'une annonce de Monokuma, au format cron \
+(minute heure jour mois jour-de-la-semaine)."]
+#[usage = "<morning|evening> <expression cron>"]
+#[example = "morning 0 7 * * mon-fri"]
+#[min_args(2)]
+async fn schedule(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let kind = args.single::<String>()?;
+    let schedule = match Schedule::parse(args.rest()) {
+        Ok(schedule) => schedule,
+        Err(err) => {
+            reply(ctx, msg, "Paramètres", &format!("Horaire invalide : {}.", err)).await;
+            return Ok(());
+        }
+    };
+
+    let mut settings = match guild_settings(ctx, msg.guild_id.unwrap()).await {
+        Some(settings) => settings,
+        None => {
+            reply(ctx, msg, "Paramètres", "Impossible de lire les paramètres.").await;
+            return Ok(());
+        }
+    };
+    match kind.as_str() {
+        "morning" => settings.morning_announcement = schedule.clone(),
+        "evening" => settings.evening_announcement = schedule.clone(),
+        _ => {
+            reply(ctx, msg, "Paramètres", "Les annonces possibles sont `morning` et `evening`.").await;
+            return Ok(());
+        }
+    }
+
+    if save_settings(ctx, msg, &settings).await {
+        reply(ctx, msg, "Paramètres", &format!("L'annonce `{}` suit maintenant `{}`.", kind, schedule)).await;
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Ajoute ou retire un salon fermé pendant la nuit, entre les annonces du soir et du matin. \
+Active la fonctionnalité `night_mode` pour fermer les salons."]
+#[usage = "<add|remove> #salon"]
+#[example = "add #général"]
+#[num_args(2)]
+async fn night(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let action = args.single::<String>()?;
+    let channel = match args.single::<ChannelId>() {
+        Ok(channel) => channel,
+        Err(_) => {
+            reply(ctx, msg, "Paramètres", "Mentionne le salon à fermer la nuit.").await;
+            return Ok(());
+        }
+    };
+    // A channel that is gone can still be removed from the list.
+    if action == "add" && !is_guild_channel(ctx, msg.guild_id.unwrap(), channel).await {
+        reply(ctx, msg, "Paramètres", "Ce salon n'est pas un salon de ce serveur.").await;
+        return Ok(());
+    }
+
+    let mut settings = match guild_settings(ctx, msg.guild_id.unwrap()).await {
+        Some(settings) => settings,
+        None => {
+            reply(ctx, msg, "Paramètres", "Impossible de lire les paramètres.").await;
+            return Ok(());
+        }
+    };
+    let added = match edit_list(&mut settings.night_channels, &action, channel) {
+        Some(added) => added,
+        None => {
+            reply(ctx, msg, "Paramètres", "Précise `add` ou `remove`.").await;
+            return Ok(());
+        }
+    };
+
+    if save_settings(ctx, msg, &settings).await {
+        let state = if added { "sera fermé" } else { "ne sera plus fermé" };
+        reply(ctx, msg, "Paramètres", &format!("Le salon <#{}> {} pendant la nuit.", channel, state)).await;
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Ajoute ou retire un rôle qui peut encore parler la nuit, dans les salons fermés et sans se faire gronder par Monokuma."]
+#[usage = "<add|remove> @rôle"]
+#[example = "add @Modérateurs"]
+#[num_args(2)]
+async fn exempt(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let action = args.single::<String>()?;
+    let role = match args.single::<RoleId>() {
+        Ok(role) => role,
+        Err(_) => {
+            reply(ctx, msg, "Paramètres", "Mentionne le rôle à exempter.").await;
+            return Ok(());
+        }
+    };
+    // A role that is gone can still be removed from the list.
+    if action == "add" && !is_guild_role(ctx, msg.guild_id.unwrap(), role).await {
+        reply(ctx, msg, "Paramètres", "Ce rôle n'est pas un rôle de ce serveur.").await;
+        return Ok(());
+    }
+
+    let mut settings = match guild_settings(ctx, msg.guild_id.unwrap()).await {
+        Some(settings) => settings,
+        None => {
+            reply(ctx, msg, "Paramètres", "Impossible de lire les paramètres.").await;
+            return Ok(());
+        }
+    };
+    let added = match edit_list(&mut settings.night_exempt_roles, &action, role) {
+        Some(added) => added,
+        None => {
+            reply(ctx, msg, "Paramètres", "Précise `add` ou `remove`.").await;
+            return Ok(());
+        }
+    };
+
+    if save_settings(ctx, msg, &settings).await {
+        let state = if added { "peut" } else { "ne peut plus" };
+        reply(ctx, msg, "Paramètres", &format!("Le rôle <@&{}> {} parler pendant la nuit.", role, state)).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mirai_bot::commands::settings::edit_list;
+
+    #[test]
+    fn test_edit_list() {
+        let mut items = vec![1];
+        assert_eq!(edit_list(&mut items, "add", 2), Some(true));
+        assert_eq!(edit_list(&mut items, "add", 2), Some(true));
+        assert_eq!(items, vec![1, 2]);
+        assert_eq!(edit_list(&mut items, "remove", 1), Some(false));
+        assert_eq!(items, vec![2]);
+        assert_eq!(edit_list(&mut items, "toggle", 3), None);
+    }
+}
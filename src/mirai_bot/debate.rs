@@ -0,0 +1,425 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::builder::CreateComponents;
+use serenity::client::Context;
+use serenity::collector::ComponentInteractionCollectorBuilder;
+use serenity::futures::StreamExt;
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::id::{ChannelId, UserId};
+use serenity::prelude::TypeMapKey;
+use serenity::utils::Colour;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+use crate::bot::DiscordBot;
+use crate::debate::{Debate, MAX_MISSES, Scenario, Shot};
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::mirai_bot::sessions::ChannelSessions;
+use crate::sink::{Embed, send_embed};
+
+/// How long each statement stays on screen.
+pub const STATEMENT_WINDOW: Duration = Duration::from_secs(10);
+/// Times the statements are streamed before time runs out.
+pub const LOOPS: u32 = 2;
+/// Between the Truth Bullets and the first statement, to read them.
+const BRIEFING: Duration = Duration::from_secs(15);
+const BUTTON_PREFIX: &str = "debate";
+const TOO_LATE: &str = "Trop tard, vise la déclaration à l'écran !";
+
+/// What the commands reach of a running debate.
+pub struct Debating {
+    scenario: Arc<Scenario>,
+    shots: UnboundedSender<CommandShot>,
+    /// The statement that can be shot at, none during the briefing and between two statements.
+    on_screen: Option<usize>,
+}
+
+/// A bullet fired with the command, at the statement on screen when it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CommandShot {
+    user: UserId,
+    statement: Option<usize>,
+    bullet: usize,
+}
+
+/// The debates running, one per channel at most.
+pub type DebateSessions = ChannelSessions<Debating>;
+
+impl TypeMapKey for DebateSessions {
+    type Value = DebateSessions;
+}
+
+impl DebateSessions {
+    /// Reserves `channel`, `None` if a debate already runs there. The debate stops with
+    /// `parent` and receives the shots fired with the command.
+    fn open(
+        &self,
+        channel: ChannelId,
+        host: UserId,
+        scenario: Arc<Scenario>,
+        parent: &CancellationToken,
+    ) -> Option<(CancellationToken, UnboundedReceiver<CommandShot>)> {
+        let (shots, receiver) = mpsc::unbounded_channel();
+        let cancel = self.start(channel, host, parent, Debating { scenario, shots, on_screen: None })?;
+        Some((cancel, receiver))
+    }
+
+    pub fn scenario(&self, channel: ChannelId) -> Option<Arc<Scenario>> {
+        self.with(channel, |debating| debating.scenario.clone())
+    }
+
+    /// Shoots `bullet` at the statement on screen in `channel`, returns false if no debate
+    /// runs there.
+    pub fn shoot(&self, channel: ChannelId, user: UserId, bullet: usize) -> bool {
+        self.with(channel, |debating| {
+            debating.shots.send(CommandShot { user, statement: debating.on_screen, bullet }).is_ok()
+        }).unwrap_or(false)
+    }
+
+    /// Sets the statement the shots fired with the command are aimed at.
+    fn show(&self, channel: ChannelId, statement: Option<usize>) {
+        self.with(channel, |debating| debating.on_screen = statement);
+    }
+}
+
+fn button_id(statement: usize, bullet: usize) -> String {
+    format!("{}:{}:{}", BUTTON_PREFIX, statement, bullet)
+}
+
+/// The statement and the bullet of a button.
+fn parse_button(id: &str) -> Option<(usize, usize)> {
+    let mut parts = id.split(':');
+    if parts.next() != Some(BUTTON_PREFIX) {
+        return None;
+    }
+    let statement = parts.next()?.parse().ok()?;
+    let bullet = parts.next()?.parse().ok()?;
+    parts.next().is_none().then_some((statement, bullet))
+}
+
+fn buttons<'a>(components: &'a mut CreateComponents, scenario: &Scenario, statement: usize) -> &'a mut CreateComponents {
+    components.create_action_row(|row| {
+        for (index, bullet) in scenario.bullets.iter().enumerate() {
+            row.create_button(|button| {
+                button.custom_id(button_id(statement, index)).label(&bullet.name).style(ButtonStyle::Danger)
+            });
+        }
+        row
+    })
+}
+
+fn title(scenario: &Scenario) -> String {
+    format!("Débat non-stop — {}", scenario.title)
+}
+
+fn briefing_embed(scenario: &Scenario, color: Colour, prefix: &str) -> Embed {
+    let bullets = scenario.bullets.iter().enumerate()
+        .map(|(index, bullet)| format!("{}. **{}** — {}", index + 1, bullet.name, bullet.description))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Embed {
+        title: Some(title(scenario)),
+        description: Some(format!(
+            "{}\n\n__Balles de vérité__\n{}\n\nTire sur le point faible en gras avec le bon bouton, ou avec `{}debate shoot <balle>` pendant que la déclaration est affichée.",
+            scenario.context, bullets, prefix
+        )),
+        color: Some(color),
+        footer: Some(format!("{} erreurs et tu es hors du débat.", MAX_MISSES)),
+        ..Default::default()
+    }
+}
+
+fn statement_embed(scenario: &Scenario, index: usize, pass: u32, color: Colour) -> Embed {
+    let statement = &scenario.statements[index];
+    Embed {
+        title: Some(statement.speaker.clone()),
+        description: Some(format!("« {} »", statement.marked())),
+        color: Some(color),
+        footer: Some(format!("Déclaration {}/{} · Tour {}/{}", index + 1, scenario.statements.len(), pass, LOOPS)),
+        ..Default::default()
+    }
+}
+
+/// What the channel sees of a hit or a miss, the other shots only concern the shooter.
+fn shot_embed(scenario: &Scenario, statement: usize, bullet: usize, user: UserId, shot: Shot, color: Colour) -> Option<Embed> {
+    let statement = &scenario.statements[statement];
+    let bullet = &scenario.bullets[bullet];
+    let weak_point = statement.weak_point.as_deref().unwrap_or(&statement.text);
+    let (title, description) = match shot {
+        Shot::Hit => ("C'est faux !".to_string(), format!(
+            "<@{}> réfute « {} » avec **{}** : {}\n\nLa contradiction est brisée, le débat est gagné !",
+            user, weak_point, bullet.name, bullet.description
+        )),
+        Shot::Miss(0) => ("Raté !".to_string(), format!(
+            "**{}** ne contredit pas « {} ». <@{}> n'a plus de tir et quitte le débat.", bullet.name, weak_point, user
+        )),
+        Shot::Miss(left) => ("Raté !".to_string(), format!(
+            "**{}** ne contredit pas « {} ». Plus que {} tir(s) pour <@{}>.", bullet.name, weak_point, left, user
+        )),
+        _ => return None,
+    };
+    Some(Embed { title: Some(title), description: Some(description), color: Some(color), ..Default::default() })
+}
+
+/// Why a shot did not count.
+fn shot_note(shot: Shot) -> Option<&'static str> {
+    match shot {
+        Shot::NoWeakPoint => Some("Il n'y a rien à réfuter dans cette déclaration, attends un point faible."),
+        Shot::OutOfShots => Some("Tu t'es trompé(e) trop souvent, tu ne peux plus tirer."),
+        Shot::Over => Some("La contradiction a déjà été trouvée."),
+        Shot::Hit | Shot::Miss(_) => None,
+    }
+}
+
+fn end_embed(debate: &Debate, color: Colour, cancelled: bool) -> Embed {
+    let (statement, bullet) = debate.scenario.answer();
+    let weak_point = statement.weak_point.as_deref().unwrap_or(&statement.text);
+    Embed {
+        title: Some(format!("{} — Temps écoulé", title(&debate.scenario))),
+        description: Some(format!(
+            "Personne n'a trouvé la contradiction… Il fallait réfuter « {} » de {} avec **{}**.",
+            weak_point, statement.speaker, bullet.name
+        )),
+        color: Some(color),
+        footer: cancelled.then(|| "Débat arrêté avant la fin.".to_string()),
+        ..Default::default()
+    }
+}
+
+/// Tells the shooter privately why a click did not count, or just acknowledges it.
+async fn answer_click(ctx: &Context, click: &MessageComponentInteraction, note: Option<&str>) {
+    let answered = click.create_interaction_response(&ctx.http, |response| match note {
+        Some(note) => response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|data| data.content(note).ephemeral(true)),
+        None => response.kind(InteractionResponseType::DeferredUpdateMessage),
+    }).await;
+    if let Err(err) = answered {
+        MiraiLogger::debug(format!("Could not answer the shot of {}: {}", click.user.id, err));
+    }
+}
+
+enum Fired {
+    Click(Arc<MessageComponentInteraction>),
+    Command(CommandShot),
+}
+
+/// Streams the statements of `scenario` in `channel` until someone breaks the contradiction,
+/// time runs out or the debate is cancelled.
+async fn run_debate(
+    ctx: &Context,
+    channel: ChannelId,
+    scenario: Arc<Scenario>,
+    bot: &DiscordBot,
+    sessions: &DebateSessions,
+    cancel: CancellationToken,
+    mut shots: UnboundedReceiver<CommandShot>,
+) -> Debate {
+    let color = bot.color;
+    let mut debate = Debate::new(scenario.clone());
+    if let Err(err) = send_embed(&ctx.http, channel, &briefing_embed(&scenario, color, &bot.prefix)).await {
+        MiraiLogger::error(format!("Could not start the debate on {}: {}", channel, err));
+        return debate;
+    }
+    tokio::select! {
+        _ = tokio::time::sleep(BRIEFING) => {}
+        _ = cancel.cancelled() => {}
+    }
+
+    let mut clicks = ComponentInteractionCollectorBuilder::new(ctx)
+        .channel_id(channel)
+        .filter(|click| click.data.custom_id.starts_with(BUTTON_PREFIX))
+        .build();
+
+    'debate: for pass in 1..=LOOPS {
+        for index in 0..scenario.statements.len() {
+            if cancel.is_cancelled() {
+                break 'debate;
+            }
+            let shootable = scenario.statements[index].weak_point.is_some();
+            let embed = statement_embed(&scenario, index, pass, color);
+            let sent = channel.send_message(&ctx.http, |m| {
+                m.embed(|e| {
+                    embed.build(e);
+                    e
+                });
+                if shootable {
+                    m.components(|c| buttons(c, &scenario, index));
+                }
+                m
+            }).await;
+            let mut message = match sent {
+                Ok(message) => message,
+                Err(err) => {
+                    MiraiLogger::error(format!("Could not send a debate statement on {}: {}", channel, err));
+                    break 'debate;
+                }
+            };
+            sessions.show(channel, Some(index));
+
+            let deadline = tokio::time::Instant::now() + STATEMENT_WINDOW;
+            while debate.winner().is_none() {
+                let fired = tokio::select! {
+                    _ = tokio::time::sleep_until(deadline) => break,
+                    _ = cancel.cancelled() => break,
+                    Some(click) = clicks.next() => Fired::Click(click),
+                    Some(shot) = shots.recv() => Fired::Command(shot),
+                };
+
+                let (user, statement, bullet) = match &fired {
+                    Fired::Click(click) => match parse_button(&click.data.custom_id) {
+                        Some((statement, bullet)) if statement == index && message.id == click.message.id => {
+                            (click.user.id, statement, bullet)
+                        }
+                        _ => {
+                            answer_click(ctx, click, Some(TOO_LATE)).await;
+                            continue;
+                        }
+                    },
+                    Fired::Command(shot) if shot.statement == Some(index) => (shot.user, index, shot.bullet),
+                    Fired::Command(shot) => {
+                        let embed = Embed { description: Some(format!("<@{}> {}", shot.user, TOO_LATE)), color: Some(color), ..Default::default() };
+                        if let Err(err) = send_embed(&ctx.http, channel, &embed).await {
+                            MiraiLogger::error(format!("Could not answer a debate shot on {}: {}", channel, err));
+                        }
+                        continue;
+                    }
+                };
+                let shot = debate.shoot(user, statement, bullet);
+                match &fired {
+                    Fired::Click(click) => answer_click(ctx, click, shot_note(shot)).await,
+                    Fired::Command(..) => if let Some(note) = shot_note(shot) {
+                        let embed = Embed { description: Some(format!("<@{}> {}", user, note)), color: Some(color), ..Default::default() };
+                        if let Err(err) = send_embed(&ctx.http, channel, &embed).await {
+                            MiraiLogger::error(format!("Could not answer a debate shot on {}: {}", channel, err));
+                        }
+                    },
+                }
+                if let Some(embed) = shot_embed(&scenario, statement, bullet, user, shot, color) {
+                    if let Err(err) = send_embed(&ctx.http, channel, &embed).await {
+                        MiraiLogger::error(format!("Could not announce a debate shot on {}: {}", channel, err));
+                    }
+                }
+            }
+
+            sessions.show(channel, None);
+            if shootable {
+                if let Err(err) = message.edit(&ctx.http, |m| m.components(|c| c)).await {
+                    MiraiLogger::debug(format!("Could not remove the buttons of {}: {}", message.id, err));
+                }
+            }
+            if debate.winner().is_some() {
+                break 'debate;
+            }
+        }
+    }
+
+    if debate.winner().is_none() {
+        if let Err(err) = send_embed(&ctx.http, channel, &end_embed(&debate, color, cancel.is_cancelled())).await {
+            MiraiLogger::error(format!("Could not end the debate on {}: {}", channel, err));
+        }
+    }
+    debate
+}
+
+/// Runs a debate on `scenario` in the background, `None` if one already runs in `channel`.
+/// The receiver gets the shots once the debate is over.
+pub async fn start_debate(ctx: &Context, channel: ChannelId, host: UserId, scenario: Arc<Scenario>) -> Option<oneshot::Receiver<Debate>> {
+    let (sessions, bot) = {
+        let data = ctx.data.read().await;
+        let sessions = data.get::<DebateSessions>().expect("Did not find DebateSessions").clone();
+        (sessions, data.get::<DiscordBot>().expect("Did not find DiscordBot").clone())
+    };
+    let (cancel, shots) = sessions.open(channel, host, scenario.clone(), &bot.shutdown.child_token())?;
+
+    let ctx = ctx.clone();
+    let (sender, receiver) = oneshot::channel();
+    let shutdown = bot.shutdown.clone();
+    shutdown.spawn(async move {
+        MiraiLogger::info(format!("Starting the debate {} on {}", scenario.id, channel));
+        let debate = run_debate(&ctx, channel, scenario, &bot, &sessions, cancel, shots).await;
+        sessions.finish(channel);
+        let _ = sender.send(debate);
+    });
+    Some(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serenity::model::id::{ChannelId, UserId};
+    use serenity::utils::Colour;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::debate::{Debate, DebateBank, Shot};
+    use crate::mirai_bot::debate::{button_id, CommandShot, DebateSessions, end_embed, parse_button, shot_embed, shot_note, statement_embed};
+
+    #[test]
+    fn test_buttons() {
+        assert_eq!(parse_button(&button_id(3, 1)), Some((3, 1)));
+        assert_eq!(parse_button("debate:3"), None);
+        assert_eq!(parse_button("debate:3:1:2"), None);
+        assert_eq!(parse_button("previous"), None);
+    }
+
+    #[test]
+    fn test_embeds() {
+        let bank = DebateBank::load("data/debates").unwrap();
+        let scenario = bank.get("academie-cuisine").unwrap();
+        let color = Colour::new(0);
+
+        let statement = statement_embed(&scenario, 0, 2, color);
+        assert_eq!(statement.title.as_deref(), Some("Mondo Owada"));
+        assert_eq!(
+            statement.description.as_deref(),
+            Some("« C'est simple, le coupable a pris le couteau **ce matin pendant le petit-déjeuner** ! »")
+        );
+        assert_eq!(statement.footer.as_deref(), Some("Déclaration 1/4 · Tour 2/2"));
+
+        let hit = shot_embed(&scenario, 0, 0, UserId(1), Shot::Hit, color).unwrap();
+        assert_eq!(hit.title.as_deref(), Some("C'est faux !"));
+        let miss = shot_embed(&scenario, 1, 2, UserId(1), Shot::Miss(2), color).unwrap();
+        assert_eq!(
+            miss.description.as_deref(),
+            Some("**Témoignage d'Hifumi** ne contredit pas « cacher l'arme ». Plus que 2 tir(s) pour <@1>.")
+        );
+        assert!(shot_embed(&scenario, 1, 0, UserId(1), Shot::NoWeakPoint, color).is_none());
+        assert!(shot_note(Shot::Hit).is_none());
+        assert!(shot_note(Shot::OutOfShots).is_some());
+
+        let end = end_embed(&Debate::new(scenario), color, false);
+        assert_eq!(
+            end.description.as_deref(),
+            Some("Personne n'a trouvé la contradiction… Il fallait réfuter « ce matin pendant le petit-déjeuner » de Mondo Owada avec **Inventaire de la cuisine**.")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sessions() {
+        let bank = DebateBank::load("data/debates").unwrap();
+        let scenario = bank.all()[0].clone();
+        let sessions = DebateSessions::default();
+        let shutdown = CancellationToken::new();
+
+        let (cancel, mut shots) = sessions.open(ChannelId(1), UserId(3), scenario.clone(), &shutdown).unwrap();
+        assert!(sessions.open(ChannelId(1), UserId(4), scenario.clone(), &shutdown).is_none());
+        assert_eq!(sessions.host(ChannelId(1)), Some(UserId(3)));
+        assert!(Arc::ptr_eq(&sessions.scenario(ChannelId(1)).unwrap(), &scenario));
+
+        assert!(sessions.shoot(ChannelId(1), UserId(4), 1));
+        assert!(!sessions.shoot(ChannelId(2), UserId(4), 1));
+        assert_eq!(shots.recv().await, Some(CommandShot { user: UserId(4), statement: None, bullet: 1 }));
+        sessions.show(ChannelId(1), Some(2));
+        assert!(sessions.shoot(ChannelId(1), UserId(4), 0));
+        assert_eq!(shots.recv().await, Some(CommandShot { user: UserId(4), statement: Some(2), bullet: 0 }));
+
+        shutdown.cancel();
+        assert!(cancel.is_cancelled());
+        sessions.finish(ChannelId(1));
+        assert!(!sessions.stop(ChannelId(1)));
+    }
+}
@@ -0,0 +1,286 @@
+use serenity::client::Context;
+use serenity::framework::standard::{CommandGroup, OnlyIn};
+use serenity::model::id::{GuildId, UserId};
+use serenity::utils::Colour;
+
+use crate::bot::DiscordBot;
+use crate::mirai_bot::commands::required_level;
+use crate::mirai_bot::message_handler::{cooldown, GROUPS};
+use crate::mirai_bot::pagination::Pages;
+use crate::permissions::{BotPermissions, PermissionLevel};
+use crate::sink::{Embed, EmbedField};
+
+const HELP_TITLE: &str = "Aide";
+const COMMANDS_PER_PAGE: usize = 10;
+
+/// What the help shows of a prefix command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandDoc {
+    /// With the prefix of its group, like `settings channel`.
+    pub name: String,
+    pub description: Option<String>,
+    pub usage: Option<String>,
+    pub examples: Vec<String>,
+    pub aliases: Vec<String>,
+    /// Seconds to wait between two uses.
+    pub cooldown: Option<u64>,
+    /// What the checks of the command and of its group require.
+    pub levels: Vec<PermissionLevel>,
+    pub guild_only: bool,
+}
+
+/// A command group, shown as a category of the help.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryDoc {
+    pub name: String,
+    pub description: Option<String>,
+    pub commands: Vec<CommandDoc>,
+}
+
+/// The attributes of the `#[command]` macro keep their trailing new lines.
+fn trimmed(text: Option<&str>) -> Option<String> {
+    text.map(str::trim).filter(|text| !text.is_empty()).map(str::to_string)
+}
+
+/// The documentation of every command of `groups`, read from their attributes.
+pub fn document(groups: &[&'static CommandGroup]) -> Vec<CategoryDoc> {
+    groups.iter().filter(|group| group.options.help_available).map(|group| {
+        let prefix = group.options.prefixes.first();
+        let group_levels: Vec<PermissionLevel> = group.options.checks.iter()
+            .filter_map(|check| required_level(check))
+            .collect();
+        let group_guild_only = group.options.only_in == OnlyIn::Guild;
+
+        let commands = group.options.commands.iter()
+            .filter(|command| command.options.help_available)
+            .map(|command| {
+                let options = command.options;
+                let name = match prefix {
+                    Some(prefix) => format!("{} {}", prefix, options.names[0]),
+                    None => options.names[0].to_string(),
+                };
+                let mut levels = group_levels.clone();
+                levels.extend(options.checks.iter().filter_map(|check| required_level(check)));
+
+                CommandDoc {
+                    name,
+                    description: trimmed(options.desc),
+                    usage: trimmed(options.usage),
+                    examples: options.examples.iter().map(|example| example.to_string()).collect(),
+                    aliases: options.names[1..].iter().map(|alias| alias.to_string()).collect(),
+                    cooldown: options.bucket.and_then(cooldown),
+                    levels,
+                    guild_only: group_guild_only || options.only_in == OnlyIn::Guild,
+                }
+            })
+            .collect();
+
+        CategoryDoc { name: group.name.to_string(), description: trimmed(group.options.description), commands }
+    }).collect()
+}
+
+/// Looks a command up by its full name, its name without the group prefix or an alias.
+pub fn find_command<'a>(categories: &'a [CategoryDoc], name: &str) -> Option<&'a CommandDoc> {
+    let name = name.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase();
+    let commands = || categories.iter().flat_map(|category| category.commands.iter());
+
+    commands().find(|command| command.name == name)
+        .or_else(|| commands().find(|command| {
+            command.name.rsplit(' ').next() == Some(name.as_str()) || command.aliases.contains(&name)
+        }))
+}
+
+fn level_name(level: &PermissionLevel) -> String {
+    match level {
+        PermissionLevel::BotOwner => "Créateur du bot".to_string(),
+        PermissionLevel::BotAdmin => "Administrateur du bot".to_string(),
+        PermissionLevel::Guild(permissions) => {
+            format!("Permission « {} »", permissions.get_permission_names().join(", "))
+        }
+    }
+}
+
+fn command_line(command: &CommandDoc, prefix: &str) -> String {
+    match &command.usage {
+        Some(usage) => format!("`{}{} {}`", prefix, command.name, usage),
+        None => format!("`{}{}`", prefix, command.name),
+    }
+}
+
+/// One or more pages per category, listing its commands.
+pub fn overview_pages(categories: &[CategoryDoc], prefix: &str, color: Colour) -> Vec<Embed> {
+    let footer = format!("Tape {}help <commande> pour le détail d'une commande.", prefix);
+
+    categories.iter().flat_map(|category| {
+        category.commands.chunks(COMMANDS_PER_PAGE).map(|commands| {
+            let lines: Vec<String> = commands.iter().map(|command| format!(
+                "{} — {}",
+                command_line(command, prefix),
+                command.description.as_deref().unwrap_or("Pas de description.")
+            )).collect();
+            let description = match &category.description {
+                Some(description) => format!("{}\n\n{}", description, lines.join("\n")),
+                None => lines.join("\n"),
+            };
+
+            Embed {
+                title: Some(format!("{} — {}", HELP_TITLE, category.name)),
+                description: Some(description),
+                color: Some(color),
+                footer: Some(footer.clone()),
+                ..Default::default()
+            }
+        }).collect::<Vec<Embed>>()
+    }).collect()
+}
+
+fn field(name: &str, value: String, inline: bool) -> EmbedField {
+    EmbedField { name: name.to_string(), value, inline }
+}
+
+/// Everything about one command.
+pub fn command_page(command: &CommandDoc, prefix: &str, color: Colour) -> Embed {
+    let mut fields = vec![field("Utilisation", command_line(command, prefix), true)];
+    if !command.examples.is_empty() {
+        let examples: Vec<String> = command.examples.iter()
+            .map(|example| format!("`{}{} {}`", prefix, command.name, example))
+            .collect();
+        fields.push(field("Exemples", examples.join("\n"), true));
+    }
+    if !command.aliases.is_empty() {
+        let aliases: Vec<String> = command.aliases.iter().map(|alias| format!("`{}`", alias)).collect();
+        fields.push(field("Alias", aliases.join(", "), true));
+    }
+    if let Some(seconds) = command.cooldown {
+        fields.push(field("Délai", format!("{} seconde(s) entre deux utilisations", seconds), true));
+    }
+    if !command.levels.is_empty() {
+        let levels: Vec<String> = command.levels.iter().map(level_name).collect();
+        fields.push(field("Permissions", levels.join("\n"), false));
+    }
+    let available = match command.guild_only {
+        true => "Uniquement sur un serveur",
+        false => "Sur un serveur et en message privé",
+    };
+    fields.push(field("Disponible", available.to_string(), false));
+
+    Embed {
+        title: Some(format!("{}{}", prefix, command.name)),
+        description: Some(command.description.clone().unwrap_or_else(|| "Pas de description.".to_string())),
+        color: Some(color),
+        fields,
+        ..Default::default()
+    }
+}
+
+/// The commands `user` can run where they asked for the help.
+async fn visible_categories(ctx: &Context, user: UserId, guild_id: Option<GuildId>) -> Vec<CategoryDoc> {
+    let permissions = ctx.data.read().await.get::<BotPermissions>()
+        .expect("Did not find BotPermissions").clone();
+
+    let mut categories = document(&GROUPS);
+    for category in &mut categories {
+        let mut visible = Vec::new();
+        for command in category.commands.drain(..) {
+            if command.guild_only && guild_id.is_none() {
+                continue;
+            }
+            let mut allowed = true;
+            for level in &command.levels {
+                if permissions.check(ctx, user, guild_id, *level).await.is_err() {
+                    allowed = false;
+                    break;
+                }
+            }
+            if allowed {
+                visible.push(command);
+            }
+        }
+        category.commands = visible;
+    }
+    categories.retain(|category| !category.commands.is_empty());
+    categories
+}
+
+/// The help asked by `user`, about `query` if it names a command. Commands they cannot run are
+/// left out, as if they did not exist.
+pub async fn help_pages(ctx: &Context, user: UserId, guild_id: Option<GuildId>, query: &str) -> Pages {
+    let (prefix, color) = {
+        let data = ctx.data.read().await;
+        let bot = data.get::<DiscordBot>().expect("Did not find DiscordBot");
+        (bot.prefix.clone(), bot.color)
+    };
+    let categories = visible_categories(ctx, user, guild_id).await;
+
+    if query.trim().is_empty() {
+        return Pages::new(overview_pages(&categories, &prefix, color));
+    }
+    let page = match find_command(&categories, query) {
+        Some(command) => command_page(command, &prefix, color),
+        None => Embed {
+            title: Some(HELP_TITLE.to_string()),
+            description: Some(format!(
+                "La commande `{}` n'existe pas. Tape `{}help` pour voir les commandes.", query.trim(), prefix
+            )),
+            color: Some(color),
+            ..Default::default()
+        },
+    };
+    Pages::new(vec![page])
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::Permissions;
+    use serenity::utils::Colour;
+
+    use crate::mirai_bot::help::{command_page, document, find_command, overview_pages};
+    use crate::mirai_bot::message_handler::GROUPS;
+    use crate::permissions::PermissionLevel;
+
+    #[test]
+    fn test_document() {
+        let categories = document(&GROUPS);
+        let names: Vec<&str> = categories.iter().map(|category| category.name.as_str()).collect();
+        assert_eq!(names, vec!["Général", "Divertissement", "Fiches", "Quiz", "Procès de classe", "Débat non-stop", "Modération", "Administration", "Paramètres"]);
+
+        let roll = find_command(&categories, "dice").unwrap();
+        assert_eq!(roll.name, "roll");
+        assert_eq!(roll.description.as_deref(), Some("Lance des dés."));
+        assert_eq!(roll.cooldown, Some(3));
+
+        let slowmode = find_command(&categories, "slowmode").unwrap();
+        assert!(slowmode.guild_only);
+        assert_eq!(slowmode.levels, vec![
+            PermissionLevel::Guild(Permissions::MANAGE_MESSAGES),
+            PermissionLevel::Guild(Permissions::MANAGE_CHANNELS),
+        ]);
+
+        assert_eq!(find_command(&categories, "settings  Channel").unwrap().name, "settings channel");
+        assert_eq!(find_command(&categories, "schedule").unwrap().name, "settings schedule");
+        assert!(find_command(&categories, "flip table").is_none());
+    }
+
+    #[test]
+    fn test_pages() {
+        let categories = document(&GROUPS);
+        let color = Colour::new(0x123456);
+
+        let pages = overview_pages(&categories, "!", color);
+        assert_eq!(pages.len(), categories.len());
+        assert_eq!(pages[1].title.as_deref(), Some("Aide — Divertissement"));
+        assert!(pages[1].description.as_ref().unwrap().contains("`!roll [dés]` — Lance des dés."));
+
+        let clear = command_page(find_command(&categories, "purge").unwrap(), "!", color);
+        let fields: Vec<(&str, &str)> = clear.fields.iter()
+            .map(|field| (field.name.as_str(), field.value.as_str()))
+            .collect();
+        assert_eq!(fields, vec![
+            ("Utilisation", "`!clear <nombre>`"),
+            ("Exemples", "`!clear 20`"),
+            ("Alias", "`purge`"),
+            ("Permissions", "Permission « Manage Messages »"),
+            ("Disponible", "Uniquement sur un serveur"),
+        ]);
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+
+use serenity::client::Context;
+use serenity::framework::StandardFramework;
+use serenity::framework::standard::{
+    Args, CommandGroup, CommandResult, DispatchError, HelpOptions, Reason,
+};
+use serenity::framework::standard::macros::{help, hook};
+use serenity::model::channel::Message;
+use serenity::model::id::UserId;
+
+use crate::bot::DiscordBot;
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::mirai_bot::commands::{
+    admin::ADMIN_GROUP, class_trial::CLASSTRIAL_GROUP, debate::DEBATE_GROUP, flashcards::FLASHCARDS_GROUP, fun::FUN_GROUP, general::GENERAL_GROUP, moderation::MODERATION_GROUP, quiz::QUIZ_GROUP, reply,
+    settings::SETTINGS_GROUP,
+};
+use crate::mirai_bot::help::help_pages;
+use crate::mirai_bot::pagination::send_pages;
+
+const ERROR_TITLE: &str = "Erreur";
+const DENIED_TITLE: &str = "Accès refusé";
+
+/// The command groups, in the order the help lists them.
+pub static GROUPS: [&CommandGroup; 9] = [
+    &GENERAL_GROUP, &FUN_GROUP, &FLASHCARDS_GROUP, &QUIZ_GROUP, &CLASSTRIAL_GROUP, &DEBATE_GROUP, &MODERATION_GROUP, &ADMIN_GROUP, &SETTINGS_GROUP,
+];
+
+/// Unknown commands get a suggestion when a wrong, missing, extra or swapped character away
+/// from a real one, the others are only logged.
+const MAX_TYPO_DISTANCE: usize = 1;
+
+/// The buckets of the commands, with the seconds to wait between two uses.
+const COOLDOWNS: [(&str, u64); 1] = [("fun", 3)];
+
+pub fn cooldown(bucket: &str) -> Option<u64> {
+    COOLDOWNS.iter().find(|(name, _)| *name == bucket).map(|(_, seconds)| *seconds)
+}
+
+/// The prefix commands of the bot, with the help command and the error replies.
+pub async fn command_framework(prefix: &str) -> StandardFramework {
+    let mut framework = StandardFramework::new()
+        .configure(|c| c.with_whitespace(true).prefix(prefix))
+        .before(before)
+        .after(after)
+        .unrecognised_command(unrecognised_command)
+        .on_dispatch_error(dispatch_error)
+        .help(&HELP);
+
+    for (bucket, seconds) in COOLDOWNS {
+        framework = framework.bucket(bucket, |b| b.delay(seconds)).await;
+    }
+    for group in GROUPS {
+        framework = framework.group(group);
+    }
+    framework
+}
+
+async fn prefix(ctx: &Context) -> String {
+    ctx.data.read().await.get::<DiscordBot>()
+        .expect("Did not find DiscordBot").prefix.clone()
+}
+
+async fn help_hint(ctx: &Context, command_name: &str) -> String {
+    format!("Tape `{}help {}` pour voir comment l'utiliser.", prefix(ctx).await, command_name)
+}
+
+#[help]
+async fn help(
+    ctx: &Context,
+    msg: &Message,
+    args: Args,
+    _: &'static HelpOptions,
+    _: &[&'static CommandGroup],
+    _: HashSet<UserId>,
+) -> CommandResult {
+    send_pages(ctx, msg, help_pages(ctx, msg.author.id, msg.guild_id, args.rest()).await).await;
+    Ok(())
+}
+
+#[hook]
+async fn before(_ctx: &Context, msg: &Message, command_name: &str) -> bool {
+    MiraiLogger::debug(format!("{} runs {}: {}", msg.author.id, command_name, msg.content));
+    true
+}
+
+#[hook]
+async fn after(ctx: &Context, msg: &Message, command_name: &str, result: CommandResult) {
+    if let Err(err) = result {
+        MiraiLogger::error(format!("Command {} failed: {}", command_name, err));
+        reply(ctx, msg, ERROR_TITLE, &format!(
+            "La commande `{}` a échoué. {}", command_name, help_hint(ctx, command_name).await
+        )).await;
+    }
+}
+
+/// The names a prefix message can start with: the group prefixes, the commands without one and the help.
+fn command_names() -> impl Iterator<Item = &'static str> {
+    GROUPS.iter()
+        .flat_map(|group| match group.options.prefixes {
+            [] => group.options.commands.iter().flat_map(|command| command.options.names.iter()).copied().collect(),
+            prefixes => prefixes.to_vec(),
+        })
+        .chain(HELP.options.names.iter().copied())
+}
+
+/// The edit distance between `a` and `b`, where swapping two neighbouring characters is one edit.
+fn typo_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // distances[i][j] is the distance between the first i characters of a and the first j of b.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = substitution.min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// The command `name` is probably a typo of.
+fn closest_command(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    command_names()
+        .map(|command| (typo_distance(&name, command), command))
+        .filter(|(distance, _)| *distance <= MAX_TYPO_DISTANCE)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, command)| command)
+}
+
+/// Messages that only happen to start with the prefix are ignored, typos of a command get a suggestion.
+#[hook]
+async fn unrecognised_command(ctx: &Context, msg: &Message, command_name: &str) {
+    match closest_command(command_name) {
+        Some(command) => {
+            let prefix = prefix(ctx).await;
+            reply(ctx, msg, ERROR_TITLE, &format!(
+                "`{}` n'est pas une commande. Tu voulais dire `{}{}` ? Tape `{}help` pour voir les commandes.",
+                command_name, prefix, command, prefix
+            )).await;
+        }
+        None => MiraiLogger::debug(format!("{} sent an unknown command: {}", msg.author.id, msg.content)),
+    }
+}
+
+#[hook]
+pub async fn dispatch_error(ctx: &Context, msg: &Message, error: DispatchError, command_name: &str) {
+    match error {
+        DispatchError::CheckFailed(_, Reason::UserAndLog { user, log }) => {
+            MiraiLogger::warn(format!("Check failed for {}: {}", command_name, log));
+            reply(ctx, msg, DENIED_TITLE, &user).await;
+        }
+        DispatchError::CheckFailed(_, Reason::User(user)) => {
+            reply(ctx, msg, DENIED_TITLE, &user).await;
+        }
+        DispatchError::LackingPermissions(permissions) => {
+            reply(ctx, msg, DENIED_TITLE, &format!("Il te manque les permissions suivantes : {}.", permissions)).await;
+        }
+        DispatchError::OnlyForGuilds => {
+            reply(ctx, msg, ERROR_TITLE, "Cette commande ne marche que sur un serveur.").await;
+        }
+        DispatchError::OnlyForDM => {
+            reply(ctx, msg, ERROR_TITLE, "Cette commande ne marche qu'en message privé.").await;
+        }
+        DispatchError::NotEnoughArguments { min, given } => {
+            reply(ctx, msg, ERROR_TITLE, &format!(
+                "Il manque des arguments : {} attendu(s), {} donné(s). {}",
+                min, given, help_hint(ctx, command_name).await
+            )).await;
+        }
+        DispatchError::TooManyArguments { max, given } => {
+            reply(ctx, msg, ERROR_TITLE, &format!(
+                "Trop d'arguments : {} au plus, {} donné(s). {}",
+                max, given, help_hint(ctx, command_name).await
+            )).await;
+        }
+        DispatchError::Ratelimited(info) if info.is_first_try => {
+            reply(ctx, msg, ERROR_TITLE, &format!(
+                "Doucement ! Réessaie dans {} seconde(s).", info.rate_limit.as_secs().max(1)
+            )).await;
+        }
+        error => {
+            MiraiLogger::warn(format!("Could not dispatch {}: {:?}", command_name, error));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mirai_bot::message_handler::{closest_command, typo_distance};
+
+    #[test]
+    fn test_typo_distance() {
+        assert_eq!(typo_distance("ping", "ping"), 0);
+        assert_eq!(typo_distance("pnig", "ping"), 1);
+        assert_eq!(typo_distance("pin", "ping"), 1);
+        assert_eq!(typo_distance("dance", "dice"), 2);
+        assert_eq!(typo_distance("", "coin"), 4);
+    }
+
+    #[test]
+    fn test_closest_command() {
+        assert_eq!(closest_command("pnig"), Some("ping"));
+        assert_eq!(closest_command("Quizz"), Some("quiz"));
+        assert_eq!(closest_command("hepl"), Some("help"));
+        assert_eq!(closest_command("fli"), Some("flip"));
+        assert_eq!(closest_command("dance"), None);
+        assert_eq!(closest_command("lol"), None);
+    }
+}
@@ -1,7 +1,17 @@
 pub(crate) mod on_new_member;
-mod message_handler;
-mod color;
+pub(crate) mod message_handler;
+pub(crate) mod color;
 mod image;
 pub(crate) mod monokuma_announcement;
+pub(crate) mod night_mode;
+pub(crate) mod night_watch;
 pub(crate) mod guild;
-
+pub(crate) mod commands;
+pub(crate) mod slash;
+pub(crate) mod pagination;
+pub(crate) mod help;
+pub(crate) mod review;
+pub(crate) mod quiz;
+pub(crate) mod class_trial;
+pub(crate) mod debate;
+pub(crate) mod sessions;
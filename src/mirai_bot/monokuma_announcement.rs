@@ -1,194 +1,333 @@
-use std::string::ToString;
-use chrono::{Timelike, TimeZone, Utc};
-use date_component::date_component::calculate;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use chrono::DateTime;
 use rand::Rng;
-use serenity::builder::CreateEmbedAuthor;
-use serenity::http::{Http};
-use serenity::model::prelude::ChannelId;
-use serenity::model::Timestamp;
+use serenity::cache::Cache;
+use serenity::client::Context;
+use serenity::model::prelude::{ChannelId, GuildId};
+use serenity::prelude::TypeMapKey;
+use serenity::utils::Colour;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use crate::announcement::{AnnouncementKind, AnnouncementTemplates, GuildInfo, Placeholders};
+use crate::bot::DiscordBot;
+use crate::jobs::{Job, Scheduler};
 use crate::log::{MiraiLog, MiraiLogger};
-use crate::mirai_bot::color::MIRAI_BOT_COLOR;
+use crate::mirai_bot::night_watch::{NightOwls, NightWatch};
+use crate::settings::Feature;
+use crate::shutdown::Shutdown;
+use crate::sink::{Embed, SerenitySink, SharedSink};
+use crate::utils::guild_fcts::guild_settings;
 
-use crate::utils::time::{local_timestamp_now, sync_at, UTC_OFFSET};
-
-const MONOKUMA_AVATAR: &str = "https://avatars.githubusercontent.com/u/13270208?v=4";
-
-const MONOKUMA_IMGS: [&str; 7] = [
-    "https://vignette.wikia.nocookie.net/bloodbrothersgame/images/5/53/Monokuma.jpg/revision/latest/scale-to-width-down/640?cb=20131210191609",
-    "http://2.bp.blogspot.com/-E5L7PG07qbk/U7zPtDHk_9I/AAAAAAAAAt4/UzoKWesIqWE/s1600/Danganronpa-Episode-07-Monokuma.jpg",
-    "https://i.pinimg.com/236x/cc/c5/b1/ccc5b19b6d41e45d108e57433b5c4469.jpg",
-    "http://i.imgur.com/T5s569W.gif",
-    "https://i.imgur.com/K14wGy5.jpg?1",
-    "https://i.imgur.com/aH1xD9S.gif",
-    "https://c.tenor.com/svobtzY8wm4AAAAC/monokuma-danganronpa.gif"
-];
-
-fn get_random_in_str_array(arr: Box<[&str]>) -> &str {
+pub fn get_random_in_str_array<'a>(arr: &[&'a str]) -> &'a str {
     let mut rng = rand::thread_rng();
 
-    return arr[rng.gen_range(0..arr.len())];
+    arr[rng.gen_range(0..arr.len())]
 }
 
-async fn send_monokuma_morning_announcement(http: &Http, channel: ChannelId) -> bool {
-    let date1 = Utc.ymd(2016, 4, 10).and_hms(10, 0, 0);
-    let date2 =  Utc::now();
-    let date_interval = calculate(&date1, &date2);
-    let footer = format!("Ainsi débute le jour {} à l'Académie du Pic de l'Espoir", date_interval.interval_days);
-
-    if let Err(err) = channel.send_message(http, |msg| {
-        msg.embed(|embed| {
-            let mut msg_author = CreateEmbedAuthor::default();
-            msg_author.name("Monokuma".to_string());
-            msg_author.icon_url(MONOKUMA_AVATAR);
-            embed.set_author(msg_author);
-            embed.color(MIRAI_BOT_COLOR);
-            embed.field(
-                "Bonjour, tout le monde !", "Il est maintenant 7h du matin
-et la période de nuit est officiellement terminée !
-Il est l'heure de se lever !\n\n
-Préparez-vous à accueillir un autre jour meeeeerveilleux !", false);
-            embed.image(get_random_in_str_array(Box::new(MONOKUMA_IMGS)));
-            embed.footer(|f| {
-                f.text(footer);
-                f
-            });
-            embed
-        });
-        msg
-    }).await {
-        MiraiLogger::error(format!("Could not send monokuma evening announcement: {}", err));
-        return false;
-    }
-
-    true
+/// Reads the name and the member count of the guilds from the cache.
+pub struct CacheGuildInfo(pub Arc<Cache>);
+
+impl GuildInfo for CacheGuildInfo {
+    fn describe(&self, guild: GuildId) -> Option<(String, u64)> {
+        self.0.guild_field(guild, |guild| (guild.name.clone(), guild.member_count))
+    }
 }
 
-async fn send_monokuma_evening_announcement(http: &Http, channel: ChannelId) -> bool {
-    if let Err(err) = channel.send_message(http, |msg| {
-        msg.embed(|embed| {
-            let mut msg_author = CreateEmbedAuthor::default();
-            msg_author.name("Monokuma".to_string());
-            msg_author.icon_url(MONOKUMA_AVATAR);
-            embed.set_author(msg_author);
-            embed.color(MIRAI_BOT_COLOR);
-            embed.field(
-                "Mm, ahem, ceci est une annonce de l'école.", "Il est maintenant 22 h.\n\n
-Autrement dit, c'est officiellement la période de nuit.
-Les salons discord vont bientôt être fermés, et y discuter à
-partir de maintenant est strictement interdit.
-Maintenant, faites de beaux rêves ! Le marchand de sable va bientôt passer...", false);
-            embed.image(get_random_in_str_array(Box::new(MONOKUMA_IMGS)));
-            embed
-        });
-        msg
-    }).await {
-        MiraiLogger::error(format!("Could not send monokuma evening announcement: {}", err));
-        return false;
-    }
-
-    true
+/// Where the announcements of a guild go and what they are made of.
+#[derive(Clone)]
+pub struct Announcer {
+    pub sink: SharedSink,
+    pub channel: ChannelId,
+    pub color: Colour,
+    pub guild: GuildId,
+    pub guild_info: Arc<dyn GuildInfo>,
+    pub templates: AnnouncementTemplates,
 }
 
-pub async fn setup_monokuma_announcement(channel: ChannelId) -> (tokio::task::JoinHandle<()>,
-                                                              tokio::task::JoinHandle<()>) {
-    let http = Http::new(std::env::var("bot_token").expect("Wrong bot token").as_str());
-    let another_http = Http::new(std::env::var("bot_token").expect("Wrong bot token").as_str());
-    let chan = channel.clone();
-
-    let morning_handle = tokio::task::spawn(async move {
-        let running = true;
-        let time_limit = None;
-        let now = local_timestamp_now();
-        let morning_monokuma_time = match now.hour() + UTC_OFFSET < 7 {
-            true => {
-                Timestamp::from(now.with_hour(7).unwrap().with_minute(0).unwrap())
-            }
-            false => {
-                Timestamp::from(
-                    now.with_hour(7).unwrap().with_minute(0).unwrap() +
-                        chrono::Duration::days(1)
-                )
+impl Announcer {
+    fn embed(&self, kind: AnnouncementKind, time: DateTime<chrono_tz::Tz>) -> Option<Embed> {
+        let mut rng = rand::thread_rng();
+        let template = self.templates.pick(kind, &mut rng)?;
+        let placeholders = Placeholders { time, guild: self.guild_info.describe(self.guild) };
+        Some(template.render(&placeholders, self.color, &mut rng))
+    }
+
+    /// Sends the announcement of `kind` for `time`, returns false if it could not be sent.
+    async fn announce(&self, kind: AnnouncementKind, time: DateTime<chrono_tz::Tz>) -> bool {
+        let embed = match self.embed(kind, time) {
+            Some(embed) => embed,
+            None => {
+                MiraiLogger::error(format!("No {} announcement template to send", kind.name()));
+                return false;
             }
         };
+        if let Err(err) = self.sink.send_embed(self.channel, embed).await {
+            MiraiLogger::error(format!("Could not send monokuma {} announcement: {}", kind.name(), err));
+            return false;
+        }
 
-        MiraiLogger::debug(format!("Morning announcement expected at {}", morning_monokuma_time));
-        let mut morning_announcement = sync_at(
-            &running,
-            &time_limit,
-            morning_monokuma_time,
-            Some(std::time::Duration::new(60 * 60 * 24, 0))).await.unwrap();
+        true
+    }
+}
+
+/// Spawns the morning and evening announcement jobs, whose runs are recorded by the
+/// scheduler so that a restart catches up the missed announcements. Cancelling `cancel` lets
+/// an announcement being sent finish before the jobs stop. The morning announcement is
+/// followed by the `night_owls` of the night it ends, if any.
+pub async fn setup_monokuma_announcement(
+    announcer: Announcer,
+    morning: Job,
+    evening: Job,
+    night_owls: Option<NightOwls>,
+    scheduler: &Scheduler,
+    cancel: CancellationToken,
+) -> AnnouncementHandles {
+    let another_announcer = announcer.clone();
+    let timezone = morning.timezone;
 
-        while running {
-            send_monokuma_morning_announcement(&http, chan).await;
-            morning_announcement.tick().await;
+    let morning_handle = scheduler.spawn(morning, cancel.clone(), move |run| {
+        let announcer = announcer.clone();
+        let night_owls = night_owls.clone();
+        async move {
+            if announcer.announce(AnnouncementKind::Morning, run.with_timezone(&timezone)).await {
+                if let Some(night_owls) = night_owls {
+                    night_owls.announce(announcer.sink.as_ref(), announcer.channel, announcer.color, &announcer.templates, run).await;
+                }
+            }
         }
     });
 
-    let evening_handle = tokio::task::spawn(async move {
-        let running = true;
-        let time_limit = None;
-        let now = local_timestamp_now();
-        let evening_monokuma_time = match now.hour() + UTC_OFFSET < 22 {
-            true => {
-                Timestamp::from(now.with_hour(22).unwrap().with_minute(0).unwrap())
+    let timezone = evening.timezone;
+    let evening_handle = scheduler.spawn(evening, cancel, move |run| {
+        let announcer = another_announcer.clone();
+        async move {
+            announcer.announce(AnnouncementKind::Evening, run.with_timezone(&timezone)).await;
+        }
+    });
+
+    (morning_handle, evening_handle)
+}
+
+/// Name under which the runs of an announcement of a guild are persisted.
+fn job_name(guild_id: GuildId, kind: &str) -> String {
+    format!("monokuma_{}_{}", kind, guild_id)
+}
+
+type AnnouncementHandles = (JoinHandle<()>, JoinHandle<()>);
+
+/// Announcement tasks currently running, one pair per guild. Every guild gets a child of
+/// the shutdown token so it can be stopped on its own.
+#[derive(Clone)]
+pub struct MonokumaAnnouncements {
+    shutdown: Shutdown,
+    scheduler: Scheduler,
+    tasks: Arc<Mutex<HashMap<GuildId, CancellationToken>>>,
+}
+
+impl TypeMapKey for MonokumaAnnouncements {
+    type Value = MonokumaAnnouncements;
+}
+
+impl MonokumaAnnouncements {
+    pub fn new(shutdown: Shutdown, scheduler: Scheduler) -> Self {
+        Self { shutdown, scheduler, tasks: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    pub fn is_running(&self, guild_id: GuildId) -> bool {
+        self.tasks.lock().expect("Announcement tasks lock poisoned").contains_key(&guild_id)
+    }
+
+    fn insert(&self, guild_id: GuildId, cancel: CancellationToken) {
+        let previous = self.tasks.lock().expect("Announcement tasks lock poisoned")
+            .insert(guild_id, cancel);
+
+        if let Some(previous) = previous {
+            previous.cancel();
+        }
+    }
+
+    /// Stops the announcements of the guild, returns false if none were running.
+    pub fn stop(&self, guild_id: GuildId) -> bool {
+        match self.tasks.lock().expect("Announcement tasks lock poisoned").remove(&guild_id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
             }
-            false => {
-                Timestamp::from(now.with_hour(22).unwrap().with_minute(0).unwrap() +
-                    chrono::Duration::days(1))
+            None => false,
+        }
+    }
+
+    /// Stops the announcements of a guild the bot left and forgets their past runs.
+    pub fn forget(&self, guild_id: GuildId) {
+        self.stop(guild_id);
+        for kind in ["morning", "evening"] {
+            if let Err(err) = self.scheduler.forget(&job_name(guild_id, kind)) {
+                MiraiLogger::error(format!(
+                    "Could not remove the {} announcement job of guild {}: {}", kind, guild_id, err
+                ));
             }
-        };
+        }
+    }
 
-        MiraiLogger::debug(format!("Evening announcement expected at {}", evening_monokuma_time));
-        let mut evening_announcement = sync_at(
-            &running,
-            &time_limit,
-            evening_monokuma_time,
-            Some(std::time::Duration::new(60 * 60 * 24, 0))).await.unwrap();
+    /// (Re)starts the announcements of a guild according to its settings. Guilds that are not
+    /// targeted by the bot, that disabled the feature or whose settings cannot be read have their
+    /// announcements stopped.
+    pub async fn start(&self, ctx: &Context, guild_id: GuildId, system_channel: Option<ChannelId>) {
+        let bot = ctx.data.read().await.get::<DiscordBot>()
+            .expect("Did not find DiscordBot").clone();
+        let settings = match guild_settings(ctx, guild_id).await {
+            Some(settings) if bot.targets_guild(guild_id) && settings.is_enabled(Feature::MonokumaAnnouncements) => settings,
+            _ => {
+                self.stop(guild_id);
+                return;
+            }
+        };
 
-        while running {
-            send_monokuma_evening_announcement(&another_http, chan).await;
-            evening_announcement.tick().await;
+        match settings.announcement_channel.or(system_channel) {
+            Some(channel) => {
+                MiraiLogger::info(format!("Starting Monokuma announcements of guild {} on {}", guild_id, channel));
+                let night_owls = match settings.is_enabled(Feature::NightWatch) {
+                    true => Some(ctx.data.read().await.get::<NightWatch>()
+                        .expect("Did not find NightWatch").owls(guild_id)),
+                    false => None,
+                };
+                let templates = ctx.data.read().await.get::<AnnouncementTemplates>()
+                    .expect("Did not find AnnouncementTemplates").clone();
+                let announcer = Announcer {
+                    sink: SerenitySink::shared(ctx.http.clone()),
+                    channel,
+                    color: settings.color.unwrap_or(bot.color),
+                    guild: guild_id,
+                    guild_info: Arc::new(CacheGuildInfo(ctx.cache.clone())),
+                    templates,
+                };
+                let cancel = self.shutdown.child_token();
+                let (morning, evening) = setup_monokuma_announcement(
+                    announcer,
+                    Job::new(job_name(guild_id, "morning"), settings.morning_announcement, bot.timezone, bot.catch_up),
+                    Job::new(job_name(guild_id, "evening"), settings.evening_announcement, bot.timezone, bot.catch_up),
+                    night_owls,
+                    &self.scheduler,
+                    cancel.clone(),
+                ).await;
+                self.shutdown.register(morning);
+                self.shutdown.register(evening);
+                self.insert(guild_id, cancel);
+            }
+            None => {
+                MiraiLogger::warn(format!("No announcement channel for guild {}", guild_id));
+                self.stop(guild_id);
+            }
         }
-    });
-
-    (morning_handle, evening_handle)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use chrono::Timelike;
-    use serenity::http::Http;
-    use serenity::model::id::ChannelId;
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Timelike, Utc};
+    use chrono_tz::Europe::Paris;
+    use serenity::model::id::{ChannelId, GuildId};
     use serenity::model::Timestamp;
+    use tokio_util::sync::CancellationToken;
 
-    use crate::mirai_bot::monokuma_announcement::{get_random_in_str_array, MONOKUMA_IMGS, send_monokuma_evening_announcement, send_monokuma_morning_announcement};
-    use crate::utils::time::local_timestamp_now;
+    use crate::announcement::{AnnouncementKind, AnnouncementTemplates, GuildInfo};
+    use crate::database::Database;
+    use crate::jobs::{CatchUp, Job, JobStore, Scheduler};
+    use crate::mirai_bot::color::MIRAI_BOT_COLOR;
+    use crate::mirai_bot::monokuma_announcement::{Announcer, get_random_in_str_array, MonokumaAnnouncements, setup_monokuma_announcement};
+    use crate::shutdown::Shutdown;
+    use crate::sink::recording::RecordingSink;
+    use crate::utils::time::{local_timestamp_now, Schedule, SystemClock, wait_until};
+    use crate::utils::time::clock::fake::FakeClock;
 
-    #[tokio::test]
-    async fn test_get_random_in_array() {
-        println!("{}", get_random_in_str_array(Box::new(MONOKUMA_IMGS)));
+    #[test]
+    fn test_get_random_in_array() {
+        let values = ["Monokuma", "Monomi"];
+        assert!(values.contains(&get_random_in_str_array(&values)));
+    }
+
+    struct Academy;
+
+    impl GuildInfo for Academy {
+        fn describe(&self, _guild: GuildId) -> Option<(String, u64)> {
+            Some(("Académie du Pic de l'Espoir".to_string(), 16))
+        }
+    }
+
+    fn announcer(sink: Arc<RecordingSink>) -> Announcer {
+        Announcer {
+            sink,
+            channel: ChannelId(1),
+            color: MIRAI_BOT_COLOR,
+            guild: GuildId(1),
+            guild_info: Arc::new(Academy),
+            templates: AnnouncementTemplates::new("nowhere"),
+        }
     }
 
     #[tokio::test]
     async fn test_morning_announcement() {
-        let http = Http::new(std::env::var("test_bot_token").expect("Wrong test bot token").as_str
-        ());
-        let test_channel_id: ChannelId = ChannelId(std::env::var("test_bot_channel_id").expect
-        ("Wrong test \
-        channel id").as_str().parse::<u64>().expect("Could not parse channel id"));
+        let sink = Arc::new(RecordingSink::default());
+        assert!(announcer(sink.clone()).announce(AnnouncementKind::Morning, Paris.ymd(2022, 10, 1).and_hms(7, 0, 0)).await);
 
-        send_monokuma_morning_announcement(&http, test_channel_id).await;
+        let sent = sink.sent();
+        assert_eq!(sent.len(), 1);
+        let (channel, embed) = &sent[0];
+        assert_eq!(*channel, ChannelId(1));
+        assert_eq!(embed.author.as_ref().unwrap().name, "Monokuma");
+        assert_eq!(embed.color, Some(MIRAI_BOT_COLOR));
+        assert_eq!(embed.fields[0].name, "Bonjour, tout le monde !");
+        assert!(embed.fields[0].value.starts_with("Il est maintenant 7h du matin"));
+        assert!(AnnouncementTemplates::new("nowhere").images().contains(embed.image.as_ref().unwrap()));
+        assert_eq!(
+            embed.footer.as_deref(),
+            Some("Ainsi débute le jour 2364 à l'Académie du Pic de l'Espoir")
+        );
     }
 
     #[tokio::test]
     async fn test_evening_announcement() {
-        let http = Http::new(std::env::var("test_bot_token").expect("Wrong test bot token").as_str
-        ());
-        let test_channel_id: ChannelId = ChannelId(std::env::var("test_bot_channel_id").expect
-        ("Wrong test \
-        channel id").as_str().parse::<u64>().expect("Could not parse channel id"));
+        let sink = Arc::new(RecordingSink::default());
+        assert!(announcer(sink.clone()).announce(AnnouncementKind::Evening, Paris.ymd(2022, 10, 1).and_hms(22, 30, 0)).await);
 
-        send_monokuma_evening_announcement(&http, test_channel_id).await;
+        let (_, embed) = &sink.sent()[0];
+        assert_eq!(embed.fields[0].name, "Mm, ahem, ceci est une annonce de l'école.");
+        assert!(embed.fields[0].value.starts_with("Il est maintenant 22h30."));
+        assert_eq!(embed.footer, None);
+    }
+
+    #[tokio::test]
+    async fn test_announcements_on_schedule() {
+        let clock = FakeClock::new(Paris.ymd(2022, 10, 1).and_hms(6, 59, 0).with_timezone(&Utc));
+        let store = JobStore::new(Database::in_memory().unwrap()).unwrap();
+        let scheduler = Scheduler::new(store.clone(), clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        let cancel = CancellationToken::new();
+
+        let (morning, evening) = setup_monokuma_announcement(
+            announcer(sink.clone()),
+            Job::new("morning".to_string(), Schedule::daily(7, 0), Paris, CatchUp::Once),
+            Job::new("evening".to_string(), Schedule::daily(22, 0), Paris, CatchUp::Once),
+            None,
+            &scheduler,
+            cancel.clone(),
+        ).await;
+        wait_until(|| store.get("morning").unwrap().is_some() && store.get("evening").unwrap().is_some()).await;
+
+        for (hour, sent) in [(7, 1), (22, 2)] {
+            clock.set(Paris.ymd(2022, 10, 1).and_hms(hour, 0, 0).with_timezone(&Utc));
+            wait_until(|| sink.sent().len() >= sent).await;
+        }
+        cancel.cancel();
+        morning.await.unwrap();
+        evening.await.unwrap();
+
+        let sent = sink.sent();
+        assert!(sent[0].1.fields[0].value.starts_with("Il est maintenant 7h du matin"));
+        assert!(sent[1].1.fields[0].value.starts_with("Il est maintenant 22h."));
     }
 
     #[tokio::test]
@@ -204,4 +343,24 @@ mod tests {
         let time = local_timestamp_now();
         println!("{}", time);
     }
-}
\ No newline at end of file
+
+    fn scheduler() -> Scheduler {
+        Scheduler::new(JobStore::new(Database::in_memory().unwrap()).unwrap(), SystemClock::shared())
+    }
+
+    #[tokio::test]
+    async fn test_stop_announcements() {
+        let shutdown = Shutdown::new();
+        let announcements = MonokumaAnnouncements::new(shutdown.clone(), scheduler());
+        let cancel = shutdown.child_token();
+        announcements.insert(GuildId(1), cancel.clone());
+
+        assert!(announcements.is_running(GuildId(1)));
+        assert!(!announcements.is_running(GuildId(2)));
+        assert!(announcements.stop(GuildId(1)));
+        assert!(!announcements.stop(GuildId(1)));
+        assert!(!announcements.is_running(GuildId(1)));
+        assert!(cancel.is_cancelled());
+        assert!(!shutdown.is_cancelled());
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::Duration;
+
+use serenity::async_trait;
+use serenity::client::Context;
+use serenity::http::{Http, StatusCode};
+use serenity::model::channel::Channel;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::prelude::TypeMapKey;
+use tokio_util::sync::CancellationToken;
+
+use crate::bot::DiscordBot;
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::night_mode::{ChannelOverwrites, is_night, lock, NightLockStore, Overwrite, Target, unlock};
+use crate::settings::{Feature, GuildSettings};
+use crate::shutdown::Shutdown;
+use crate::utils::guild_fcts::guild_settings;
+use crate::utils::time::{SharedClock, sleep_until};
+
+/// Reads and changes the overwrites of the channels through the REST API.
+pub struct SerenityOverwrites {
+    http: Arc<Http>,
+}
+
+impl SerenityOverwrites {
+    pub fn shared(http: Arc<Http>) -> Arc<dyn ChannelOverwrites> {
+        Arc::new(Self { http })
+    }
+}
+
+#[async_trait]
+impl ChannelOverwrites for SerenityOverwrites {
+    async fn get(&self, channel: ChannelId) -> serenity::Result<Option<Vec<Overwrite>>> {
+        match self.http.get_channel(channel.0).await {
+            Ok(Channel::Guild(channel)) => Ok(Some(
+                channel.permission_overwrites.iter().filter_map(Overwrite::from_serenity).collect()
+            )),
+            Ok(_) => Ok(None),
+            Err(serenity::Error::Http(err)) if err.status_code() == Some(StatusCode::NOT_FOUND) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn set(&self, channel: ChannelId, overwrite: Overwrite) -> serenity::Result<()> {
+        channel.create_permission(&self.http, &overwrite.to_serenity()).await
+    }
+
+    async fn delete(&self, channel: ChannelId, target: Target) -> serenity::Result<()> {
+        channel.delete_permission(&self.http, target.to_serenity()).await
+    }
+}
+
+/// Minutes before trying again to unlock channels, doubled after every failure.
+const RETRY_MINUTES: i64 = 1;
+const MAX_RETRY_MINUTES: i64 = 15;
+
+/// Gives back their overwrites to the locked channels of `guild` that are not in `keep`.
+/// Returns false if some of them stay locked.
+async fn unlock_all(discord: &dyn ChannelOverwrites, store: &NightLockStore, guild: GuildId, keep: &[ChannelId]) -> bool {
+    let locked = match store.locked(guild) {
+        Ok(locked) => locked,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not read the locked channels of guild {}: {}", guild, err));
+            return false;
+        }
+    };
+    let mut unlocked = true;
+    for channel in locked.into_iter().filter(|channel| !keep.contains(channel)) {
+        match unlock(discord, store, channel).await {
+            true => MiraiLogger::info(format!("Unlocked {} of guild {} for the day", channel, guild)),
+            false => unlocked = false,
+        }
+    }
+    unlocked
+}
+
+/// Locks the night channels of `settings` at night and unlocks them in the morning, until
+/// `cancel` is cancelled. The channels are put in the right state as soon as it starts, so
+/// that a restart unlocks the channels whose night ended while the bot was down. Channels
+/// that could not be unlocked are tried again a bit later rather than at the next schedule.
+async fn run_night_mode(
+    discord: Arc<dyn ChannelOverwrites>,
+    store: NightLockStore,
+    clock: SharedClock,
+    settings: GuildSettings,
+    timezone: chrono_tz::Tz,
+    cancel: CancellationToken,
+) {
+    let guild = settings.guild_id;
+    let (evening, morning) = (&settings.evening_announcement, &settings.morning_announcement);
+    let mut retry = Duration::minutes(RETRY_MINUTES);
+
+    loop {
+        let now = clock.now();
+        let unlocked = match is_night(now, evening, morning, timezone) {
+            true => {
+                let unlocked = unlock_all(discord.as_ref(), &store, guild, &settings.night_channels).await;
+                for channel in &settings.night_channels {
+                    if lock(discord.as_ref(), &store, guild, *channel, &settings.night_exempt_roles, now).await {
+                        MiraiLogger::info(format!("Locked {} of guild {} for the night", channel, guild));
+                    }
+                }
+                unlocked
+            }
+            false => unlock_all(discord.as_ref(), &store, guild, &[]).await,
+        };
+
+        let mut next = [evening.next_after(now, timezone), morning.next_after(now, timezone)].into_iter().flatten().min();
+        match unlocked {
+            true => retry = Duration::minutes(RETRY_MINUTES),
+            false => {
+                MiraiLogger::warn(format!("Channels of guild {} stay locked, trying again in {} minute(s)", guild, retry.num_minutes()));
+                next = next.map(|next| next.min(now + retry));
+                retry = (retry * 2).min(Duration::minutes(MAX_RETRY_MINUTES));
+            }
+        }
+        match next {
+            Some(next) => {
+                if !sleep_until(clock.as_ref(), next, &cancel).await {
+                    break;
+                }
+            }
+            None => {
+                MiraiLogger::warn(format!("The announcements of guild {} never fire, night mode stops", guild));
+                break;
+            }
+        }
+    }
+}
+
+/// Night mode tasks currently running, one per guild, stopped when the bot shuts down.
+#[derive(Clone)]
+pub struct NightMode {
+    store: NightLockStore,
+    clock: SharedClock,
+    shutdown: Shutdown,
+    tasks: Arc<Mutex<HashMap<GuildId, CancellationToken>>>,
+}
+
+impl TypeMapKey for NightMode {
+    type Value = NightMode;
+}
+
+impl NightMode {
+    pub fn new(store: NightLockStore, clock: SharedClock, shutdown: Shutdown) -> Self {
+        Self { store, clock, shutdown, tasks: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    pub fn is_running(&self, guild: GuildId) -> bool {
+        self.tasks.lock().expect("Night mode tasks lock poisoned").contains_key(&guild)
+    }
+
+    /// Stops the night mode of the guild, returns false if it was not running. Its locked
+    /// channels stay so until it starts again.
+    pub fn stop(&self, guild: GuildId) -> bool {
+        match self.tasks.lock().expect("Night mode tasks lock poisoned").remove(&guild) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops the night mode of a guild the bot left and forgets its locks.
+    pub fn forget(&self, guild: GuildId) {
+        self.stop(guild);
+        if let Err(err) = self.store.forget(guild) {
+            MiraiLogger::error(format!("Could not forget the night locks of guild {}: {}", guild, err));
+        }
+    }
+
+    fn spawn(&self, discord: Arc<dyn ChannelOverwrites>, settings: GuildSettings, timezone: chrono_tz::Tz) {
+        let guild = settings.guild_id;
+        let cancel = self.shutdown.child_token();
+        let previous = self.tasks.lock().expect("Night mode tasks lock poisoned").insert(guild, cancel.clone());
+        if let Some(previous) = previous {
+            previous.cancel();
+        }
+        self.shutdown.spawn(run_night_mode(discord, self.store.clone(), self.clock.clone(), settings, timezone, cancel));
+    }
+
+    /// (Re)starts the night mode of a guild according to its settings. Guilds that are not
+    /// targeted by the bot, that disabled it, have no night channel or whose settings cannot be
+    /// read get their channels unlocked.
+    pub async fn start(&self, ctx: &Context, guild: GuildId) {
+        let bot = ctx.data.read().await.get::<DiscordBot>()
+            .expect("Did not find DiscordBot").clone();
+        let discord = SerenityOverwrites::shared(ctx.http.clone());
+
+        if let Some(settings) = guild_settings(ctx, guild).await
+            .filter(|settings| bot.targets_guild(guild) && settings.is_enabled(Feature::NightMode) && !settings.night_channels.is_empty()) {
+            MiraiLogger::info(format!("Starting the night mode of guild {} on {} channel(s)", guild, settings.night_channels.len()));
+            self.spawn(discord, settings, bot.timezone);
+            return;
+        }
+
+        self.stop(guild);
+        let store = self.store.clone();
+        self.shutdown.spawn(async move {
+            unlock_all(discord.as_ref(), &store, guild, &[]).await;
+        });
+    }
+}
+
+/// Mentions of `roles`, as the settings show them.
+pub fn role_list(roles: &[RoleId]) -> String {
+    match roles.is_empty() {
+        true => "aucun".to_string(),
+        false => roles.iter().map(|role| format!("<@&{}>", role)).collect::<Vec<_>>().join(", "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+    use chrono_tz::Europe::Paris;
+    use serenity::model::id::{ChannelId, GuildId, RoleId};
+    use serenity::model::Permissions;
+
+    use crate::database::Database;
+    use crate::mirai_bot::night_mode::{NightMode, role_list};
+    use crate::night_mode::{LOCKED, NightLockStore, Overwrite, Target};
+    use crate::night_mode::fake::FakeChannels;
+    use crate::settings::{Feature, GuildSettings};
+    use crate::shutdown::Shutdown;
+    use crate::utils::time::clock::fake::FakeClock;
+    use crate::utils::time::wait_until;
+
+    #[tokio::test]
+    async fn test_night_mode() {
+        let (guild, channel) = (GuildId(1), ChannelId(10));
+        let day = vec![Overwrite { target: Target::Role(RoleId(3)), allow: Permissions::SEND_MESSAGES, deny: Permissions::empty() }];
+        let discord = Arc::new(FakeChannels::default());
+        discord.insert(channel, day.clone());
+
+        let store = NightLockStore::new(Database::in_memory().unwrap()).unwrap();
+        // The bot went down in the middle of a night, the lock is still there in the morning.
+        store.save(guild, channel, &day, Utc.ymd(2022, 9, 30).and_hms(20, 0, 0)).unwrap();
+        let clock = FakeClock::new(Paris.ymd(2022, 10, 1).and_hms(8, 0, 0).with_timezone(&Utc));
+        let night_mode = NightMode::new(store.clone(), clock.clone(), Shutdown::new());
+
+        let mut settings = GuildSettings::new(guild);
+        settings.features.push(Feature::NightMode);
+        settings.night_channels = vec![channel];
+        night_mode.spawn(discord.clone(), settings, Paris);
+        assert!(night_mode.is_running(guild));
+        wait_until(|| store.locked(guild).unwrap().is_empty()).await;
+
+        clock.set(Paris.ymd(2022, 10, 1).and_hms(22, 0, 0).with_timezone(&Utc));
+        wait_until(|| !store.locked(guild).unwrap().is_empty()).await;
+        wait_until(|| discord.overwrites(channel).len() == 2).await;
+        assert!(discord.overwrites(channel).contains(
+            &Overwrite { target: Target::Role(RoleId(1)), allow: Permissions::empty(), deny: LOCKED }
+        ));
+
+        // Discord is down in the morning, the channel is unlocked as soon as it is back
+        // rather than at the next announcement.
+        discord.set_failing(true);
+        clock.set(Paris.ymd(2022, 10, 2).and_hms(7, 0, 0).with_timezone(&Utc));
+        wait_until(|| discord.failures() > 0).await;
+        discord.set_failing(false);
+        clock.set(Paris.ymd(2022, 10, 2).and_hms(7, 1, 0).with_timezone(&Utc));
+        wait_until(|| store.locked(guild).unwrap().is_empty()).await;
+        assert_eq!(discord.overwrites(channel), day);
+
+        assert!(night_mode.stop(guild));
+        assert!(!night_mode.is_running(guild));
+    }
+
+    #[test]
+    fn test_role_list() {
+        assert_eq!(role_list(&[]), "aucun");
+        assert_eq!(role_list(&[RoleId(1), RoleId(2)]), "<@&1>, <@&2>");
+    }
+}
@@ -0,0 +1,263 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use serenity::client::Context;
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::prelude::TypeMapKey;
+use serenity::utils::Colour;
+
+use crate::bot::DiscordBot;
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::announcement::AnnouncementTemplates;
+use crate::mirai_bot::monokuma_announcement::get_random_in_str_array;
+use crate::night_mode::is_night;
+use crate::night_watch::{Cooldown, NightWatchStore};
+use crate::settings::{Feature, GuildSettings};
+use crate::sink::{Embed, EmbedAuthor, EmbedField, MessageSink, SerenitySink};
+use crate::utils::guild_fcts::guild_settings;
+
+/// What Monokuma answers to the members talking at night, `{membre}` is replaced by a
+/// mention of the member.
+pub const SCOLDINGS: [&str; 6] = [
+    "Upupupu… {membre}, tu sais pourtant que parler la nuit est strictement interdit !",
+    "Hé ho, {membre} ! C'est la période de nuit, retourne au lit avant que je sorte les sanctions !",
+    "{membre}, encore debout ? Les élèves qui ne dorment pas finissent toujours par faire des bêtises…",
+    "Puhuhu, {membre} croit que je ne vois rien la nuit ? Mes caméras de surveillance ne dorment jamais !",
+    "Attention {membre}, enfreindre le règlement de l'école peut coûter très cher… Au lit !",
+    "*Bâillement*… {membre}, même un ours comme moi a besoin de sommeil. Silence, et dodo !",
+];
+/// Minutes before Monokuma scolds the same member in the same channel again.
+const COOLDOWN_MINUTES: i64 = 10;
+/// Night owls shown by the morning announcement.
+const NIGHT_OWLS: usize = 5;
+
+fn scolding_embed(color: Colour, author: Option<EmbedAuthor>, line: &str, user: UserId, punishments: u32) -> Embed {
+    Embed {
+        author,
+        color: Some(color),
+        description: Some(line.replace("{membre}", &format!("<@{}>", user))),
+        footer: Some(format!("Infraction n°{} au règlement de nuit", punishments)),
+        ..Default::default()
+    }
+}
+
+fn night_owls_embed(color: Colour, author: Option<EmbedAuthor>, owls: &[(UserId, u32)]) -> Option<Embed> {
+    if owls.is_empty() {
+        return None;
+    }
+    let ranking = owls.iter().enumerate()
+        .map(|(index, (user, messages))| format!("{}. <@{}> — {} message(s)", index + 1, user, messages))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(Embed {
+        author,
+        color: Some(color),
+        fields: vec![EmbedField {
+            name: "Les pires oiseaux de nuit".to_string(),
+            value: format!("Ces élèves n'ont pas respecté le couvre-feu cette nuit :\n{}", ranking),
+            inline: false,
+        }],
+        ..Default::default()
+    })
+}
+
+/// The night owls of a guild, shown after its morning announcement.
+#[derive(Clone)]
+pub struct NightOwls {
+    pub store: NightWatchStore,
+    pub guild: GuildId,
+}
+
+impl NightOwls {
+    /// Sends the members who posted the most during the night ending at `night`, nothing
+    /// when everyone slept, by the author of the `templates`. Returns false if they could not
+    /// be sent.
+    pub async fn announce(
+        &self,
+        sink: &dyn MessageSink,
+        channel: ChannelId,
+        color: Colour,
+        templates: &AnnouncementTemplates,
+        night: DateTime<Utc>,
+    ) -> bool {
+        let owls = match self.store.worst(self.guild, night, NIGHT_OWLS) {
+            Ok(owls) => owls,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not read the night owls of guild {}: {}", self.guild, err));
+                return false;
+            }
+        };
+        if let Some(embed) = night_owls_embed(color, templates.author(), &owls) {
+            if let Err(err) = sink.send_embed(channel, embed).await {
+                MiraiLogger::error(format!("Could not send the night owls of guild {}: {}", self.guild, err));
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Counts the messages posted at night and has Monokuma scold their authors, once in a
+/// while per member and channel.
+#[derive(Clone)]
+pub struct NightWatch {
+    store: NightWatchStore,
+    cooldown: Arc<Cooldown>,
+}
+
+impl TypeMapKey for NightWatch {
+    type Value = NightWatch;
+}
+
+impl NightWatch {
+    pub fn new(store: NightWatchStore) -> Self {
+        Self { store, cooldown: Arc::new(Cooldown::new(Duration::minutes(COOLDOWN_MINUTES))) }
+    }
+
+    pub fn owls(&self, guild: GuildId) -> NightOwls {
+        NightOwls { store: self.store.clone(), guild }
+    }
+
+    /// Stops watching a guild the bot left and forgets its night owls.
+    pub fn forget(&self, guild: GuildId) {
+        if let Err(err) = self.store.forget(guild) {
+            MiraiLogger::error(format!("Could not forget the night owls of guild {}: {}", guild, err));
+        }
+    }
+
+    /// Records a message of `user` posted in `channel` at `now`. Returns the number of night
+    /// messages of the member if it was posted at night and the member was not scolded there
+    /// lately.
+    fn watch(
+        &self,
+        settings: &GuildSettings,
+        timezone: chrono_tz::Tz,
+        channel: ChannelId,
+        user: UserId,
+        roles: &[RoleId],
+        now: DateTime<Utc>,
+    ) -> Option<u32> {
+        if !settings.is_enabled(Feature::NightWatch)
+            || roles.iter().any(|role| settings.night_exempt_roles.contains(role))
+            || !is_night(now, &settings.evening_announcement, &settings.morning_announcement, timezone) {
+            return None;
+        }
+
+        let night = settings.morning_announcement.next_after(now, timezone)?;
+        let punishments = match self.store.record(settings.guild_id, user, night) {
+            Ok(punishments) => punishments,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not record the night message of {}: {}", user, err));
+                return None;
+            }
+        };
+        self.cooldown.take(channel, user, now).then_some(punishments)
+    }
+
+    /// Watches a message received by the bot, those of other bots and outside of guilds are
+    /// ignored.
+    pub async fn on_message(&self, ctx: &Context, msg: &Message) {
+        let guild = match msg.guild_id {
+            Some(guild) if !msg.author.bot => guild,
+            _ => return,
+        };
+        let bot = ctx.data.read().await.get::<DiscordBot>()
+            .expect("Did not find DiscordBot").clone();
+        if !bot.targets_guild(guild) {
+            return;
+        }
+        let settings = match guild_settings(ctx, guild).await {
+            Some(settings) => settings,
+            None => return,
+        };
+        let roles = msg.member.as_ref().map(|member| member.roles.clone()).unwrap_or_default();
+
+        let punishments = self.watch(&settings, bot.timezone, msg.channel_id, msg.author.id, &roles, bot.clock.now());
+        if let Some(punishments) = punishments {
+            MiraiLogger::info(format!("Scolding {} for talking at night on {}", msg.author.id, msg.channel_id));
+            let line = get_random_in_str_array(&SCOLDINGS);
+            let author = ctx.data.read().await.get::<AnnouncementTemplates>()
+                .expect("Did not find AnnouncementTemplates").author();
+            let embed = scolding_embed(settings.color.unwrap_or(bot.color), author, line, msg.author.id, punishments);
+            if let Err(err) = SerenitySink::shared(ctx.http.clone()).send_embed(msg.channel_id, embed).await {
+                MiraiLogger::error(format!("Could not scold {}: {}", msg.author.id, err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use chrono_tz::Europe::Paris;
+    use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+
+    use crate::announcement::AnnouncementTemplates;
+    use crate::database::Database;
+    use crate::mirai_bot::color::MIRAI_BOT_COLOR;
+    use crate::mirai_bot::night_watch::{NightWatch, scolding_embed, SCOLDINGS};
+    use crate::night_watch::NightWatchStore;
+    use crate::settings::{Feature, GuildSettings};
+    use crate::sink::recording::RecordingSink;
+
+    fn watching() -> GuildSettings {
+        let mut settings = GuildSettings::new(GuildId(1));
+        settings.features.push(Feature::NightWatch);
+        settings.night_exempt_roles = vec![RoleId(5)];
+        settings
+    }
+
+    #[test]
+    fn test_watch() {
+        let watch = NightWatch::new(NightWatchStore::new(Database::in_memory().unwrap()).unwrap());
+        let settings = watching();
+        let at = |hour, minute| Paris.ymd(2022, 10, 1).and_hms(hour, minute, 0).with_timezone(&Utc);
+        let (channel, user) = (ChannelId(10), UserId(2));
+
+        assert_eq!(watch.watch(&settings, Paris, channel, user, &[], at(21, 0)), None);
+        assert_eq!(watch.watch(&GuildSettings::new(GuildId(1)), Paris, channel, user, &[], at(23, 0)), None);
+        assert_eq!(watch.watch(&settings, Paris, channel, user, &[RoleId(5)], at(23, 0)), None);
+        assert_eq!(watch.watch(&settings, Paris, channel, user, &[], at(23, 0)), Some(1));
+
+        // Still counted, but Monokuma does not answer every message.
+        assert_eq!(watch.watch(&settings, Paris, channel, user, &[], at(23, 5)), None);
+        assert_eq!(watch.watch(&settings, Paris, ChannelId(11), user, &[], at(23, 5)), Some(3));
+    }
+
+    #[test]
+    fn test_scolding_embed() {
+        let author = AnnouncementTemplates::new("nowhere").author();
+        let embed = scolding_embed(MIRAI_BOT_COLOR, author, SCOLDINGS[1], UserId(2), 3);
+        assert_eq!(embed.author.unwrap().name, "Monokuma");
+        assert_eq!(
+            embed.description.as_deref(),
+            Some("Hé ho, <@2> ! C'est la période de nuit, retourne au lit avant que je sorte les sanctions !")
+        );
+        assert_eq!(embed.footer.as_deref(), Some("Infraction n°3 au règlement de nuit"));
+    }
+
+    #[tokio::test]
+    async fn test_night_owls() {
+        let watch = NightWatch::new(NightWatchStore::new(Database::in_memory().unwrap()).unwrap());
+        let settings = watching();
+        let night = Paris.ymd(2022, 10, 2).and_hms(7, 0, 0).with_timezone(&Utc);
+        let owls = watch.owls(GuildId(1));
+        let sink = RecordingSink::default();
+        let templates = AnnouncementTemplates::new("nowhere");
+
+        assert!(owls.announce(&sink, ChannelId(1), MIRAI_BOT_COLOR, &templates, night).await);
+        assert!(sink.sent().is_empty());
+
+        for (user, minute) in [(2, 0), (3, 0), (3, 1)] {
+            let now = Paris.ymd(2022, 10, 2).and_hms(1, minute, 0).with_timezone(&Utc);
+            watch.watch(&settings, Paris, ChannelId(10), UserId(user), &[], now);
+        }
+        assert!(owls.announce(&sink, ChannelId(1), MIRAI_BOT_COLOR, &templates, night).await);
+        let (channel, embed) = &sink.sent()[0];
+        assert_eq!(*channel, ChannelId(1));
+        assert_eq!(embed.fields[0].name, "Les pires oiseaux de nuit");
+        assert!(embed.fields[0].value.ends_with("1. <@3> — 2 message(s)\n2. <@2> — 1 message(s)"));
+    }
+}
@@ -1,28 +1,55 @@
-use std::sync::Arc;
-
-
-use serenity::builder::CreateEmbedAuthor;
-use serenity::http::Http;
-use serenity::model::channel::Message;
 use serenity::model::guild::Member;
 use serenity::model::id::ChannelId;
-use crate::bot::BOT_TIMEZONE;
+use crate::bot::DiscordBot;
 
 use crate::log::{MiraiLog, MiraiLogger};
 
-use crate::mirai_bot::color::MIRAI_BOT_COLOR;
 use crate::mirai_bot::image::PROLOGUE_DR2_STUDENTS_IMG_LINK;
+use crate::settings::GuildSettings;
+use crate::sink::{Embed, EmbedAuthor, MessageSink};
 use crate::utils::time::FRENCH_TIME_FORMAT;
 
-pub async fn on_new_member(http: &Arc<Http>, system_channel: ChannelId, new_member: Member) -> serenity::Result<Message> {
-    let avatar_url = new_member.avatar_url().unwrap_or_default();
-    let mut author = CreateEmbedAuthor::default();
-    author.name(new_member.display_name());
-
-    if !avatar_url.is_empty() {
-        author.icon_url(avatar_url.clone());
+fn welcome_embed(bot: &DiscordBot, settings: &GuildSettings, new_member: &Member) -> Embed {
+    let avatar_url = new_member.avatar_url().filter(|url| !url.is_empty());
+
+    let footer = new_member.joined_at.map(|joined_at| {
+        let time = format!(
+            "{}", joined_at.with_timezone(&bot.timezone).format(FRENCH_TIME_FORMAT)
+        );
+        MiraiLogger::debug(format!(
+            "Formatted joined_at time of {} is: {}",
+            new_member.display_name(),
+            time
+        ));
+        format!(
+            "{} nous rejoint en cette date mémorable du {}",
+            new_member.display_name(),
+            time
+        )
+    });
+
+    Embed {
+        author: Some(EmbedAuthor {
+            name: new_member.display_name().to_string(),
+            icon_url: avatar_url.clone(),
+        }),
+        title: Some("Bienvenue".to_string()),
+        description: Some("*Viendrais-tu par hasard chercher ton bonheur avec Danganronpa 2 ?*".to_string()),
+        color: Some(settings.color.unwrap_or(bot.color)),
+        image: Some(PROLOGUE_DR2_STUDENTS_IMG_LINK.to_string()),
+        thumbnail: avatar_url,
+        footer,
+        ..Default::default()
     }
+}
 
+pub async fn on_new_member(
+    sink: &dyn MessageSink,
+    bot: &DiscordBot,
+    settings: &GuildSettings,
+    system_channel: ChannelId,
+    new_member: &Member,
+) -> serenity::Result<()> {
     MiraiLogger::debug(format!(
         "Try to send welcome member message to [{}] - {} at {} on {}",
         new_member.user.id,
@@ -30,50 +57,54 @@ pub async fn on_new_member(http: &Arc<Http>, system_channel: ChannelId, new_memb
         system_channel,
         new_member.guild_id
     ));
-    system_channel.send_message(http, |msg| {
-        msg.embed(|embed| {
-            embed.color(MIRAI_BOT_COLOR);
-            embed.title("Bienvenue");
-            embed.description(
-                "*Viendrais-tu par hasard chercher ton bonheur avec Danganronpa 2 ?*"
-            );
-            embed.image(PROLOGUE_DR2_STUDENTS_IMG_LINK);
-            embed.set_author(author);
-
-            if !avatar_url.is_empty() {
-                embed.thumbnail(avatar_url);
-            }
-
-            if let Some(joined_at) = new_member.joined_at {
-                let time = format!(
-                    "{}", joined_at.with_timezone(&BOT_TIMEZONE).format(FRENCH_TIME_FORMAT)
-                );
-                MiraiLogger::debug(format!(
-                    "Formatted joined_at time of {} is: {}",
-                    new_member.display_name(),
-                    time
-                ));
-                embed.footer(|footer| {
-                    footer.text(format!(
-                        "{} nous rejoint en cette date mémorable du {}",
-                        new_member.display_name(),
-                        time
-                    ));
-                    footer
-                });
-            }
-
-            embed
-        });
-        msg
-    }).await
+    sink.send_embed(system_channel, welcome_embed(bot, settings, new_member)).await
 }
 
 #[cfg(test)]
 mod tests {
+    use serenity::model::guild::Member;
+    use serenity::model::id::{ChannelId, GuildId};
     use serenity::model::Timestamp;
-    use crate::bot::BOT_TIMEZONE;
-    use crate::mirai_bot::on_new_member::FRENCH_TIME_FORMAT;
+    use serenity::utils::Colour;
+    use crate::bot::{BOT_TIMEZONE, DiscordBot};
+    use crate::mirai_bot::on_new_member::{FRENCH_TIME_FORMAT, on_new_member};
+    use crate::settings::GuildSettings;
+    use crate::sink::recording::RecordingSink;
+
+    fn member() -> Member {
+        serde_json::from_value(serde_json::json!({
+            "guild_id": "1",
+            "user": {"id": "2", "username": "Hajime", "discriminator": "0001", "avatar": null},
+            "nick": "Hinata",
+            "roles": [],
+            "joined_at": "2022-10-01T20:30:00Z",
+            "deaf": false,
+            "mute": false,
+        })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_welcome_message() {
+        let sink = RecordingSink::default();
+        let bot = DiscordBot::new();
+        let mut settings = GuildSettings::new(GuildId(1));
+        settings.color = Some(Colour::from_rgb(1, 2, 3));
+
+        on_new_member(&sink, &bot, &settings, ChannelId(3), &member()).await.unwrap();
+
+        let sent = sink.sent();
+        assert_eq!(sent.len(), 1);
+        let (channel, embed) = &sent[0];
+        assert_eq!(*channel, ChannelId(3));
+        assert_eq!(embed.title.as_deref(), Some("Bienvenue"));
+        assert_eq!(embed.author.as_ref().unwrap().name, "Hinata");
+        assert_eq!(embed.thumbnail, None);
+        assert_eq!(embed.color, Some(Colour::from_rgb(1, 2, 3)));
+        assert_eq!(
+            embed.footer.as_deref(),
+            Some("Hinata nous rejoint en cette date mémorable du 01/10/2022 à 22h30m00s")
+        );
+    }
 
     #[test]
     fn test_date_format() {
@@ -83,17 +114,17 @@ mod tests {
         let local_date = date.with_timezone(&BOT_TIMEZONE);
         let formatted_local_date = local_date.format(FRENCH_TIME_FORMAT);
 
-        println!("{}", format!("{}", date));
-        println!("{}", format!("{}", date.date()));
-        println!("{}", format!("{}", date.time()));
+        println!("{}", date);
+        println!("{}", date.date());
+        println!("{}", date.time());
         println!();
-        println!("{}", format!("{}", local_date));
-        println!("{}", format!("{}", local_date.date()));
-        println!("{}", format!("{}", local_date.time()));
+        println!("{}", local_date);
+        println!("{}", local_date.date());
+        println!("{}", local_date.time());
 
         println!();
 
-        println!("{}", format!("{}", formatted_date));
-        println!("{}", format!("{}", formatted_local_date));
+        println!("{}", formatted_date);
+        println!("{}", formatted_local_date);
     }
 }
@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::client::Context;
+use serenity::futures::StreamExt;
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::channel::Message;
+use serenity::model::id::UserId;
+
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::sink::Embed;
+
+/// The buttons stop working after this long without being used.
+pub const PAGES_TIMEOUT: Duration = Duration::from_secs(120);
+
+const PREVIOUS: &str = "pages_previous";
+const NEXT: &str = "pages_next";
+
+/// Embeds browsed with buttons, only by the user who asked for them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pages {
+    pages: Vec<Embed>,
+    index: usize,
+}
+
+fn page_footer(footer: Option<&str>, index: usize, count: usize) -> Option<String> {
+    match (footer, count) {
+        (footer, 1) => footer.map(str::to_string),
+        (Some(footer), count) => Some(format!("{} · Page {}/{}", footer, index + 1, count)),
+        (None, count) => Some(format!("Page {}/{}", index + 1, count)),
+    }
+}
+
+impl Pages {
+    pub fn new(pages: Vec<Embed>) -> Self {
+        let pages = match pages.is_empty() {
+            true => vec![Embed::default()],
+            false => pages,
+        };
+        Self { pages, index: 0 }
+    }
+
+    fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The page shown, with its number in the footer.
+    pub fn current(&self) -> Embed {
+        let mut page = self.pages[self.index].clone();
+        page.footer = page_footer(page.footer.as_deref(), self.index, self.pages.len());
+        page
+    }
+
+    fn current_embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+        self.current().build(&mut embed);
+        embed
+    }
+
+    /// Moves to the page asked by a button, returns false for other buttons.
+    fn turn(&mut self, button: &str) -> bool {
+        match button {
+            PREVIOUS => self.index = self.index.saturating_sub(1),
+            NEXT => self.index = (self.index + 1).min(self.pages.len() - 1),
+            _ => return false,
+        }
+        true
+    }
+
+    fn buttons<'a>(&self, components: &'a mut CreateComponents) -> &'a mut CreateComponents {
+        if self.pages.len() > 1 {
+            components.create_action_row(|row| {
+                row.create_button(|button| {
+                    button.custom_id(PREVIOUS).label("◀").style(ButtonStyle::Secondary).disabled(self.index == 0)
+                });
+                row.create_button(|button| {
+                    button.custom_id(NEXT).label("▶").style(ButtonStyle::Secondary)
+                        .disabled(self.index + 1 == self.pages.len())
+                })
+            });
+        }
+        components
+    }
+}
+
+/// Answers `msg` with the pages.
+pub async fn send_pages(ctx: &Context, msg: &Message, pages: Pages) {
+    let sent = msg.channel_id.send_message(&ctx.http, |m| {
+        m.reference_message(msg);
+        m.set_embed(pages.current_embed());
+        m.components(|c| pages.buttons(c));
+        m
+    }).await;
+
+    match sent {
+        Ok(message) => browse(ctx.clone(), message, msg.author.id, pages),
+        Err(err) => MiraiLogger::error(format!("Could not send pages to {} on {}: {}", msg.author.id, msg.channel_id, err)),
+    }
+}
+
+/// Answers a slash command with the pages.
+pub async fn respond_pages(ctx: &Context, interaction: &ApplicationCommandInteraction, pages: Pages) {
+    let responded = interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|data| data.set_embed(pages.current_embed()).components(|c| pages.buttons(c)))
+    }).await;
+    if let Err(err) = responded {
+        MiraiLogger::error(format!("Could not answer /{} of {}: {}", interaction.data.name, interaction.user.id, err));
+        return;
+    }
+
+    if pages.len() > 1 {
+        match interaction.get_interaction_response(&ctx.http).await {
+            Ok(message) => browse(ctx.clone(), message, interaction.user.id, pages),
+            Err(err) => MiraiLogger::error(format!("Could not fetch the pages sent to {}: {}", interaction.user.id, err)),
+        }
+    }
+}
+
+/// Turns the pages of `message` when `user` clicks its buttons, until they time out.
+fn browse(ctx: Context, message: Message, user: UserId, mut pages: Pages) {
+    if pages.len() < 2 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut clicks = message.await_component_interactions(&ctx)
+            .author_id(user)
+            .timeout(PAGES_TIMEOUT)
+            .build();
+
+        while let Some(click) = clicks.next().await {
+            if !pages.turn(&click.data.custom_id) {
+                continue;
+            }
+
+            let updated = click.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|data| data.set_embed(pages.current_embed()).components(|c| pages.buttons(c)))
+            }).await;
+            if let Err(err) = updated {
+                MiraiLogger::error(format!("Could not turn the pages of {}: {}", message.id, err));
+            }
+        }
+
+        let mut message = message;
+        if let Err(err) = message.edit(&ctx.http, |m| m.components(|c| c)).await {
+            MiraiLogger::debug(format!("Could not remove the buttons of {}: {}", message.id, err));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mirai_bot::pagination::{NEXT, Pages, PREVIOUS};
+    use crate::sink::Embed;
+
+    fn page(title: &str) -> Embed {
+        Embed { title: Some(title.to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn test_pages() {
+        let mut pages = Pages::new(vec![page("un"), Embed { footer: Some("Aide".to_string()), ..page("deux") }]);
+        assert_eq!(pages.current().footer.as_deref(), Some("Page 1/2"));
+
+        assert!(!pages.turn("other"));
+        assert!(pages.turn(PREVIOUS));
+        assert_eq!(pages.current().title.as_deref(), Some("un"));
+        assert!(pages.turn(NEXT));
+        assert!(pages.turn(NEXT));
+        assert_eq!(pages.current().title.as_deref(), Some("deux"));
+        assert_eq!(pages.current().footer.as_deref(), Some("Aide · Page 2/2"));
+
+        let single = Pages::new(vec![Embed { footer: Some("Aide".to_string()), ..page("un") }]);
+        assert_eq!(single.current().footer.as_deref(), Some("Aide"));
+        assert_eq!(Pages::new(Vec::new()).len(), 1);
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::client::Context;
+use serenity::futures::StreamExt;
+use serenity::model::channel::{Message, ReactionType};
+use serenity::model::id::{ChannelId, UserId};
+use serenity::prelude::TypeMapKey;
+use serenity::utils::Colour;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+use crate::bot::DiscordBot;
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::quiz::{Question, Scoreboard};
+use crate::mirai_bot::sessions::ChannelSessions;
+use crate::sink::{Embed, MAX_DESCRIPTION_LENGTH, send_embed, shorten};
+
+/// How long players have to answer a question.
+pub const ANSWER_WINDOW: Duration = Duration::from_secs(20);
+/// Between the answer of a question and the next one.
+const PAUSE: Duration = Duration::from_secs(3);
+const CHOICE_EMOJIS: [&str; 4] = ["🇦", "🇧", "🇨", "🇩"];
+/// Longer choices are cut, for the prompt to fit with them in the description.
+const MAX_CHOICE_LENGTH: usize = 500;
+const MEDALS: [&str; 3] = ["🥇", "🥈", "🥉"];
+
+/// The quizzes running, one per channel at most, with who started them.
+pub type QuizSessions = ChannelSessions<()>;
+
+impl TypeMapKey for QuizSessions {
+    type Value = QuizSessions;
+}
+
+/// Questions asked one after the other in a channel.
+pub struct Quiz {
+    pub title: String,
+    pub questions: Vec<Question>,
+    pub window: Duration,
+    pub color: Colour,
+}
+
+fn question_embed(quiz: &Quiz, index: usize) -> Embed {
+    let question = &quiz.questions[index];
+    let description = match question.is_multiple_choice() {
+        true => {
+            let choices = question.choices.iter().zip(CHOICE_EMOJIS)
+                .map(|(choice, emoji)| format!("{} {}", emoji, shorten(choice, MAX_CHOICE_LENGTH)))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let room = MAX_DESCRIPTION_LENGTH - 2 - choices.chars().count();
+            format!("{}\n\n{}", shorten(&question.prompt, room), choices)
+        }
+        false => shorten(&question.prompt, MAX_DESCRIPTION_LENGTH),
+    };
+    let how = match question.is_multiple_choice() {
+        true => "Réponds avec une réaction",
+        false => "Réponds dans le salon",
+    };
+
+    Embed {
+        title: Some(format!("{} — Question {}/{}", quiz.title, index + 1, quiz.questions.len())),
+        description: Some(description),
+        color: Some(quiz.color),
+        footer: Some(format!("{}, tu as {} secondes.", how, quiz.window.as_secs())),
+        ..Default::default()
+    }
+}
+
+fn answer_embed(quiz: &Quiz, question: &Question, winners: &[UserId]) -> Embed {
+    let winners = match winners {
+        [] => "Personne n'a trouvé.".to_string(),
+        winners => format!(
+            "Bravo {} !", winners.iter().map(|user| format!("<@{}>", user)).collect::<Vec<_>>().join(", ")
+        ),
+    };
+    Embed {
+        title: Some(quiz.title.clone()),
+        description: Some(format!("La réponse était **{}**. {}", question.answer(), winners)),
+        color: Some(quiz.color),
+        ..Default::default()
+    }
+}
+
+/// The final ranking of a quiz.
+pub fn ranking_embed(title: &str, scoreboard: &Scoreboard, color: Colour) -> Embed {
+    let ranking = scoreboard.ranking();
+    let description = match ranking.is_empty() {
+        true => "Personne n'a marqué de point.".to_string(),
+        false => ranking.iter().enumerate()
+            .map(|(index, (user, points))| format!(
+                "{} <@{}> — {} point(s)", MEDALS.get(index).copied().unwrap_or("▫️"), user, points
+            ))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    Embed {
+        title: Some(format!("{} — Classement", title)),
+        description: Some(description),
+        color: Some(color),
+        ..Default::default()
+    }
+}
+
+/// The first player to write the answer in the channel.
+async fn collect_message(
+    ctx: &Context,
+    channel: ChannelId,
+    question: &Question,
+    window: Duration,
+    cancel: &CancellationToken,
+) -> Option<UserId> {
+    let mut replies = channel.await_replies(ctx)
+        .filter(|message: &Arc<Message>| !message.author.bot)
+        .timeout(window)
+        .build();
+
+    loop {
+        let message = tokio::select! {
+            message = replies.next() => message?,
+            _ = cancel.cancelled() => return None,
+        };
+        if question.is_answered_by(&message.content) {
+            if let Err(err) = message.react(ctx, ReactionType::Unicode("✅".to_string())).await {
+                MiraiLogger::debug(format!("Could not react to the answer {}: {}", message.id, err));
+            }
+            return Some(message.author.id);
+        }
+    }
+}
+
+/// The players whose first reaction to `message` was the right choice.
+async fn collect_reactions(
+    ctx: &Context,
+    message: &Message,
+    question: &Question,
+    window: Duration,
+    cancel: &CancellationToken,
+) -> Vec<UserId> {
+    // Collecting before adding the choices, players are fast.
+    let mut reactions = message.await_reactions(ctx)
+        .added(true)
+        .removed(false)
+        .timeout(window)
+        .build();
+    for emoji in CHOICE_EMOJIS.iter().take(question.choices.len()) {
+        if let Err(err) = message.react(ctx, ReactionType::Unicode(emoji.to_string())).await {
+            MiraiLogger::debug(format!("Could not add the choices to {}: {}", message.id, err));
+        }
+    }
+
+    let bot = ctx.cache.current_user_id();
+    let right = question.right_choice().map(|index| CHOICE_EMOJIS[index]);
+
+    let mut answered = HashSet::new();
+    let mut winners = Vec::new();
+    loop {
+        let reaction = tokio::select! {
+            reaction = reactions.next() => match reaction {
+                Some(reaction) => reaction,
+                None => break,
+            },
+            _ = cancel.cancelled() => break,
+        };
+        let reaction = reaction.as_inner_ref();
+        let user = match reaction.user_id {
+            Some(user) if user != bot => user,
+            _ => continue,
+        };
+        // Only the first choice of each player counts.
+        if !answered.insert(user) {
+            continue;
+        }
+        if let ReactionType::Unicode(emoji) = &reaction.emoji {
+            if Some(emoji.as_str()) == right {
+                winners.push(user);
+            }
+        }
+    }
+    winners
+}
+
+/// Asks the questions of `quiz` in `channel` and posts the ranking, unless cancelled.
+pub async fn run_quiz(ctx: &Context, channel: ChannelId, quiz: Quiz, cancel: CancellationToken) -> Scoreboard {
+    let mut scoreboard = Scoreboard::default();
+
+    for (index, question) in quiz.questions.iter().enumerate() {
+        let message = match send_embed(&ctx.http, channel, &question_embed(&quiz, index)).await {
+            Ok(message) => message,
+            Err(err) => {
+                MiraiLogger::error(format!("Could not ask a quiz question on {}: {}", channel, err));
+                break;
+            }
+        };
+
+        let winners = match question.is_multiple_choice() {
+            true => collect_reactions(ctx, &message, question, quiz.window, &cancel).await,
+            false => collect_message(ctx, channel, question, quiz.window, &cancel).await.into_iter().collect(),
+        };
+        if cancel.is_cancelled() {
+            break;
+        }
+        scoreboard.round(&winners);
+        if let Err(err) = send_embed(&ctx.http, channel, &answer_embed(&quiz, question, &winners)).await {
+            MiraiLogger::error(format!("Could not send a quiz answer on {}: {}", channel, err));
+        }
+
+        if index + 1 < quiz.questions.len() {
+            tokio::select! {
+                _ = tokio::time::sleep(PAUSE) => {}
+                _ = cancel.cancelled() => break,
+            }
+        }
+    }
+
+    let mut ranking = ranking_embed(&quiz.title, &scoreboard, quiz.color);
+    if cancel.is_cancelled() {
+        ranking.footer = Some("Quiz arrêté avant la fin.".to_string());
+    }
+    if let Err(err) = send_embed(&ctx.http, channel, &ranking).await {
+        MiraiLogger::error(format!("Could not send the quiz ranking on {}: {}", channel, err));
+    }
+    scoreboard
+}
+
+/// Runs `quiz` in the background, `None` if one already runs in `channel`. The receiver gets
+/// the scoreboard once the ranking is posted.
+pub async fn start_quiz(ctx: &Context, channel: ChannelId, host: UserId, quiz: Quiz) -> Option<oneshot::Receiver<Scoreboard>> {
+    let (sessions, shutdown) = {
+        let data = ctx.data.read().await;
+        let sessions = data.get::<QuizSessions>().expect("Did not find QuizSessions").clone();
+        (sessions, data.get::<DiscordBot>().expect("Did not find DiscordBot").shutdown.clone())
+    };
+    let cancel = sessions.start(channel, host, &shutdown.child_token(), ())?;
+
+    let ctx = ctx.clone();
+    let (sender, receiver) = oneshot::channel();
+    shutdown.spawn(async move {
+        MiraiLogger::info(format!("Starting a quiz of {} question(s) on {}", quiz.questions.len(), channel));
+        let scoreboard = run_quiz(&ctx, channel, quiz, cancel).await;
+        sessions.finish(channel);
+        // Nobody waits for the scoreboard of a flashcard quiz.
+        let _ = sender.send(scoreboard);
+    });
+    Some(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serenity::model::id::UserId;
+    use serenity::utils::Colour;
+
+    use crate::mirai_bot::quiz::{MAX_CHOICE_LENGTH, question_embed, Quiz, ranking_embed};
+    use crate::quiz::{Question, Scoreboard};
+    use crate::sink::MAX_DESCRIPTION_LENGTH;
+
+    #[test]
+    fn test_embeds() {
+        let quiz = Quiz {
+            title: "Quiz".to_string(),
+            questions: vec![Question {
+                prompt: "Qui est le directeur ?".to_string(),
+                answers: vec!["Monokuma".to_string()],
+                choices: vec!["Monomi".to_string(), "Monokuma".to_string()],
+            }],
+            window: Duration::from_secs(20),
+            color: Colour::new(0),
+        };
+        let question = question_embed(&quiz, 0);
+        assert_eq!(question.title.as_deref(), Some("Quiz — Question 1/1"));
+        assert_eq!(question.description.as_deref(), Some("Qui est le directeur ?\n\n🇦 Monomi\n🇧 Monokuma"));
+
+        let mut scoreboard = Scoreboard::default();
+        assert_eq!(ranking_embed("Quiz", &scoreboard, quiz.color).description.as_deref(), Some("Personne n'a marqué de point."));
+        scoreboard.score(UserId(2));
+        scoreboard.score(UserId(1));
+        scoreboard.score(UserId(1));
+        assert_eq!(
+            ranking_embed("Quiz", &scoreboard, quiz.color).description.as_deref(),
+            Some("🥇 <@1> — 2 point(s)\n🥈 <@2> — 1 point(s)")
+        );
+
+        let long = Quiz {
+            questions: vec![Question {
+                prompt: "?".repeat(3000),
+                answers: vec!["a".repeat(1000)],
+                choices: vec!["a".repeat(1000), "b".repeat(1000), "c".repeat(1000), "d".repeat(1000)],
+            }],
+            ..quiz
+        };
+        let description = question_embed(&long, 0).description.unwrap();
+        assert_eq!(description.chars().count(), MAX_DESCRIPTION_LENGTH);
+        assert!(description.ends_with(&format!("🇩 {}…", "d".repeat(MAX_CHOICE_LENGTH - 1))));
+    }
+
+}
@@ -0,0 +1,275 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::client::Context;
+use serenity::futures::StreamExt;
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::channel::Message;
+use serenity::utils::Colour;
+
+use crate::bot::DiscordBot;
+use crate::flashcards::{Card, Deck, FlashcardStore, Grade, Review};
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::sink::{Embed, EmbedField};
+
+/// A session ends after this long without an answer, the cards graded so far are kept.
+pub const REVIEW_TIMEOUT: Duration = Duration::from_secs(300);
+/// Most cards reviewed in one session.
+pub const SESSION_SIZE: usize = 20;
+
+const TITLE: &str = "Révision";
+const SHOW: &str = "review_show";
+const STOP: &str = "review_stop";
+const GRADE_PREFIX: &str = "review_grade_";
+
+/// What a user clicked during a review.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewAction {
+    Show,
+    Grade(Grade),
+    Stop,
+}
+
+impl ReviewAction {
+    pub fn from_button(custom_id: &str) -> Option<Self> {
+        match custom_id {
+            SHOW => Some(ReviewAction::Show),
+            STOP => Some(ReviewAction::Stop),
+            id => id.strip_prefix(GRADE_PREFIX).and_then(Grade::from_name).map(ReviewAction::Grade),
+        }
+    }
+}
+
+/// The cards a user goes through, one side then the other, grading each of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewSession {
+    deck: String,
+    cards: Vec<(Card, Review)>,
+    index: usize,
+    revealed: bool,
+    stopped: bool,
+}
+
+impl ReviewSession {
+    pub fn new(deck: &Deck, cards: Vec<(Card, Review)>) -> Self {
+        Self { deck: deck.name.clone(), cards, index: 0, revealed: false, stopped: false }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.stopped || self.index >= self.cards.len()
+    }
+
+    pub fn reviewed(&self) -> usize {
+        self.index
+    }
+
+    /// Applies a click, returns the card to save with its new review when it was graded.
+    pub fn apply(&mut self, action: ReviewAction, now: DateTime<Utc>) -> Option<(Card, Review)> {
+        if self.is_done() {
+            return None;
+        }
+        match action {
+            ReviewAction::Show => {
+                self.revealed = true;
+                None
+            }
+            // Grading is only offered once the answer is shown.
+            ReviewAction::Grade(_) if !self.revealed => None,
+            ReviewAction::Grade(grade) => {
+                let (card, review) = &self.cards[self.index];
+                let graded = (card.clone(), review.grade(grade, now));
+                self.index += 1;
+                self.revealed = false;
+                Some(graded)
+            }
+            ReviewAction::Stop => {
+                self.stopped = true;
+                None
+            }
+        }
+    }
+
+    pub fn page(&self, color: Colour) -> Embed {
+        let title = format!("{} — {}", TITLE, self.deck);
+        if self.is_done() {
+            return Embed {
+                title: Some(title),
+                description: Some(format!(
+                    "Révision terminée : {} fiche(s) revue(s) sur {}.", self.reviewed(), self.cards.len()
+                )),
+                color: Some(color),
+                ..Default::default()
+            };
+        }
+
+        let (card, _) = &self.cards[self.index];
+        let mut fields = vec![EmbedField { name: "Question".to_string(), value: card.front.clone(), inline: false }];
+        if self.revealed {
+            fields.push(EmbedField { name: "Réponse".to_string(), value: card.back.clone(), inline: false });
+        }
+        Embed {
+            title: Some(title),
+            color: Some(color),
+            fields,
+            footer: Some(format!("Fiche {}/{}", self.index + 1, self.cards.len())),
+            ..Default::default()
+        }
+    }
+
+    fn embed(&self, color: Colour) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+        self.page(color).build(&mut embed);
+        embed
+    }
+
+    fn buttons<'a>(&self, components: &'a mut CreateComponents) -> &'a mut CreateComponents {
+        if self.is_done() {
+            return components;
+        }
+        components.create_action_row(|row| {
+            match self.revealed {
+                false => {
+                    row.create_button(|button| button.custom_id(SHOW).label("Voir la réponse").style(ButtonStyle::Primary));
+                }
+                true => {
+                    for grade in Grade::ALL {
+                        let style = match grade {
+                            Grade::Again => ButtonStyle::Danger,
+                            _ => ButtonStyle::Secondary,
+                        };
+                        row.create_button(|button| {
+                            button.custom_id(format!("{}{}", GRADE_PREFIX, grade.name())).label(grade.label()).style(style)
+                        });
+                    }
+                }
+            }
+            row.create_button(|button| button.custom_id(STOP).label("Arrêter").style(ButtonStyle::Secondary))
+        })
+    }
+}
+
+/// Reviews the due cards of `deck` with the author of `msg`, in the channel of `msg`.
+/// Returns false if there is nothing to review.
+pub async fn start_review(ctx: &Context, msg: &Message, store: FlashcardStore, deck: Deck) -> bool {
+    let (color, clock) = {
+        let data = ctx.data.read().await;
+        let bot = data.get::<DiscordBot>().expect("Did not find DiscordBot");
+        (bot.color, bot.clock.clone())
+    };
+    let user = msg.author.id;
+
+    let cards = match store.due_cards(user, &deck, clock.now(), SESSION_SIZE) {
+        Ok(cards) => cards,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not read the due cards of deck {} for {}: {}", deck.id, user, err));
+            return true;
+        }
+    };
+    if cards.is_empty() {
+        return false;
+    }
+
+    let mut session = ReviewSession::new(&deck, cards);
+    let sent = msg.channel_id.send_message(&ctx.http, |m| {
+        m.reference_message(msg);
+        m.set_embed(session.embed(color));
+        m.components(|c| session.buttons(c));
+        m
+    }).await;
+    let message = match sent {
+        Ok(message) => message,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not start a review for {} on {}: {}", user, msg.channel_id, err));
+            return true;
+        }
+    };
+
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        let mut clicks = message.await_component_interactions(&ctx)
+            .author_id(user)
+            .timeout(REVIEW_TIMEOUT)
+            .build();
+
+        while let Some(click) = clicks.next().await {
+            let action = match ReviewAction::from_button(&click.data.custom_id) {
+                Some(action) => action,
+                None => continue,
+            };
+            if let Some((card, review)) = session.apply(action, clock.now()) {
+                if let Err(err) = store.save_review(user, &card, &review) {
+                    MiraiLogger::error(format!("Could not save the review of card {} by {}: {}", card.id, user, err));
+                }
+            }
+
+            let updated = click.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|data| data.set_embed(session.embed(color)).components(|c| session.buttons(c)))
+            }).await;
+            if let Err(err) = updated {
+                MiraiLogger::error(format!("Could not update the review {}: {}", message.id, err));
+            }
+            if session.is_done() {
+                break;
+            }
+        }
+
+        MiraiLogger::debug(format!("{} reviewed {} card(s) of deck {}", user, session.reviewed(), deck.id));
+        if !session.is_done() {
+            session.apply(ReviewAction::Stop, clock.now());
+            let mut message = message;
+            if let Err(err) = message.edit(&ctx.http, |m| m.set_embed(session.embed(color)).components(|c| c)).await {
+                MiraiLogger::debug(format!("Could not close the review {}: {}", message.id, err));
+            }
+        }
+    });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serenity::model::id::UserId;
+    use serenity::utils::Colour;
+
+    use crate::flashcards::{Card, Deck, Grade, Review};
+    use crate::mirai_bot::review::{ReviewAction, ReviewSession};
+
+    #[test]
+    fn test_review_session() {
+        let now = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+        let deck = Deck { id: 1, owner: UserId(1), name: "Chimie".to_string() };
+        let card = |id: i64, front: &str, back: &str| {
+            (Card { id, deck_id: 1, front: front.to_string(), back: back.to_string() }, Review::new(now))
+        };
+        let mut session = ReviewSession::new(&deck, vec![card(1, "H2O", "Eau"), card(2, "NaCl", "Sel")]);
+        let color = Colour::new(0);
+
+        assert_eq!(session.page(color).fields.len(), 1);
+        assert_eq!(session.apply(ReviewAction::Grade(Grade::Good), now), None);
+        assert_eq!(session.apply(ReviewAction::Show, now), None);
+        assert_eq!(session.page(color).fields[1].value, "Eau");
+
+        let (graded, review) = session.apply(ReviewAction::Grade(Grade::Good), now).unwrap();
+        assert_eq!((graded.id, review.interval), (1, 1));
+        assert_eq!(session.page(color).footer.as_deref(), Some("Fiche 2/2"));
+
+        session.apply(ReviewAction::Stop, now);
+        assert!(session.is_done());
+        assert_eq!(session.apply(ReviewAction::Show, now), None);
+        assert_eq!(
+            session.page(color).description.as_deref(),
+            Some("Révision terminée : 1 fiche(s) revue(s) sur 2.")
+        );
+    }
+
+    #[test]
+    fn test_review_buttons() {
+        assert_eq!(ReviewAction::from_button("review_show"), Some(ReviewAction::Show));
+        assert_eq!(ReviewAction::from_button("review_grade_easy"), Some(ReviewAction::Grade(Grade::Easy)));
+        assert_eq!(ReviewAction::from_button("review_grade_perfect"), None);
+        assert_eq!(ReviewAction::from_button("pages_next"), None);
+    }
+}
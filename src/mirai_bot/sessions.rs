@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serenity::model::id::{ChannelId, UserId};
+use tokio_util::sync::CancellationToken;
+
+struct Session<T> {
+    host: UserId,
+    cancel: CancellationToken,
+    state: T,
+}
+
+/// The games running, one per channel at most, with who started them and what the
+/// commands need to reach them.
+pub struct ChannelSessions<T> {
+    running: Arc<Mutex<HashMap<ChannelId, Session<T>>>>,
+}
+
+impl<T> Clone for ChannelSessions<T> {
+    fn clone(&self) -> Self {
+        Self { running: self.running.clone() }
+    }
+}
+
+impl<T> Default for ChannelSessions<T> {
+    fn default() -> Self {
+        Self { running: Arc::new(Mutex::new(HashMap::new())) }
+    }
+}
+
+impl<T> ChannelSessions<T> {
+    /// Reserves `channel`, `None` if a game already runs there. The game stops with `parent`.
+    pub fn start(&self, channel: ChannelId, host: UserId, parent: &CancellationToken, state: T) -> Option<CancellationToken> {
+        let mut running = self.running.lock().expect("Sessions mutex poisoned");
+        if running.contains_key(&channel) {
+            return None;
+        }
+        let cancel = parent.child_token();
+        running.insert(channel, Session { host, cancel: cancel.clone(), state });
+        Some(cancel)
+    }
+
+    /// Who started the game running in `channel`.
+    pub fn host(&self, channel: ChannelId) -> Option<UserId> {
+        self.running.lock().expect("Sessions mutex poisoned").get(&channel).map(|session| session.host)
+    }
+
+    /// Runs `f` on the state of the game in `channel`, `None` if no game runs there.
+    pub fn with<R>(&self, channel: ChannelId, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.running.lock().expect("Sessions mutex poisoned").get_mut(&channel).map(|session| f(&mut session.state))
+    }
+
+    /// Returns false if no game runs in `channel`.
+    pub fn stop(&self, channel: ChannelId) -> bool {
+        match self.running.lock().expect("Sessions mutex poisoned").get(&channel) {
+            Some(session) => {
+                session.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Frees `channel` once its game is over.
+    pub fn finish(&self, channel: ChannelId) {
+        self.running.lock().expect("Sessions mutex poisoned").remove(&channel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::{ChannelId, UserId};
+    use tokio_util::sync::CancellationToken;
+
+    use crate::mirai_bot::sessions::ChannelSessions;
+
+    #[test]
+    fn test_sessions() {
+        let sessions = ChannelSessions::default();
+        let shutdown = CancellationToken::new();
+        let cancel = sessions.start(ChannelId(1), UserId(3), &shutdown, 0).unwrap();
+        assert!(sessions.start(ChannelId(1), UserId(4), &shutdown, 0).is_none());
+        assert_eq!(sessions.host(ChannelId(1)), Some(UserId(3)));
+
+        assert_eq!(sessions.with(ChannelId(1), |count| { *count += 1; *count }), Some(1));
+        assert_eq!(sessions.with(ChannelId(2), |count| *count), None);
+
+        assert!(!sessions.stop(ChannelId(2)));
+        assert!(sessions.stop(ChannelId(1)));
+        assert!(cancel.is_cancelled());
+        sessions.finish(ChannelId(1));
+        assert!(sessions.start(ChannelId(1), UserId(4), &shutdown, 0).is_some());
+
+        shutdown.cancel();
+        assert!(sessions.with(ChannelId(1), |_| ()).is_some());
+    }
+}
@@ -0,0 +1,252 @@
+use serenity::client::Context;
+use serenity::framework::standard::Command;
+use serenity::futures::future::BoxFuture;
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::id::GuildId;
+use serenity::model::Permissions;
+
+use crate::bot::DiscordBot;
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::mirai_bot::commands::{fun, general, Reply, required_level};
+use crate::mirai_bot::help::help_pages;
+use crate::mirai_bot::pagination::{Pages, respond_pages};
+use crate::permissions::{BotPermissions, PermissionDenied, PermissionLevel};
+
+pub(crate) mod options;
+pub(crate) mod sync;
+
+pub use options::{OptionError, SlashArgs};
+pub use sync::{CommandScope, CommandSpec, OptionSpec, sync_commands};
+
+const ERROR_TITLE: &str = "Erreur";
+
+/// Where the bot registers its slash commands, from the `slash_commands` configuration key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SlashScope {
+    Off,
+    #[default]
+    Global,
+    /// In each guild the bot works in, for the changes to show up at once.
+    Guilds,
+}
+
+impl SlashScope {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(SlashScope::Off),
+            "global" => Some(SlashScope::Global),
+            "guilds" => Some(SlashScope::Guilds),
+            _ => None,
+        }
+    }
+}
+
+/// What a slash command answers: one embed, or pages browsed with buttons.
+pub enum SlashReply {
+    Reply(Reply),
+    Pages(Pages),
+}
+
+impl From<Reply> for SlashReply {
+    fn from(reply: Reply) -> Self {
+        SlashReply::Reply(reply)
+    }
+}
+
+pub type SlashResult = Result<SlashReply, OptionError>;
+pub type SlashRun = for<'a> fn(&'a Context, &'a ApplicationCommandInteraction, SlashArgs<'a>) -> BoxFuture<'a, SlashResult>;
+
+/// A slash command running the same code as the prefix command it is made from.
+pub struct SlashCommand {
+    pub spec: CommandSpec,
+    pub run: SlashRun,
+    /// What the user needs to run it, checked before `run`.
+    pub levels: Vec<PermissionLevel>,
+}
+
+impl SlashCommand {
+    /// Takes the name, description and checks of the prefix `command`.
+    pub fn new(command: &'static Command, run: SlashRun) -> Self {
+        // The `description` attribute keeps a trailing new line, Discord would trim it and the
+        // command would look changed at every sync.
+        let description = command.options.desc.unwrap_or_default().trim();
+        let levels: Vec<PermissionLevel> = command.options.checks.iter()
+            .filter_map(|check| required_level(check))
+            .collect();
+        let spec = CommandSpec::new(command.options.names[0], description)
+            .set_default_permissions(default_permissions(&levels));
+        Self { spec, run, levels }
+    }
+
+    /// For the commands that are not made from a prefix command.
+    pub fn named(name: &str, description: &str, run: SlashRun) -> Self {
+        Self { spec: CommandSpec::new(name, description), run, levels: Vec::new() }
+    }
+
+    pub fn add_option(mut self, option: OptionSpec) -> Self {
+        self.spec = self.spec.add_option(option);
+        self
+    }
+}
+
+/// The guild permissions `levels` ask for, so that Discord hides the command from the members
+/// without them. The bot levels are only checked when the command runs.
+fn default_permissions(levels: &[PermissionLevel]) -> Option<Permissions> {
+    levels.iter()
+        .filter_map(|level| match level {
+            PermissionLevel::Guild(permissions) => Some(*permissions),
+            _ => None,
+        })
+        .reduce(|all, permissions| all | permissions)
+}
+
+/// Checks every level of `command` against the user of `interaction`.
+async fn check_levels(ctx: &Context, interaction: &ApplicationCommandInteraction, command: &SlashCommand) -> Result<(), PermissionDenied> {
+    let permissions = ctx.data.read().await.get::<BotPermissions>()
+        .expect("Did not find BotPermissions").clone();
+    let guild_permissions = interaction.member.as_ref().and_then(|member| member.permissions);
+
+    command.levels.iter().try_for_each(|level| permissions.check_level(interaction.user.id, *level, guild_permissions))
+}
+
+pub fn slash_commands() -> Vec<SlashCommand> {
+    vec![
+        SlashCommand::new(&general::PING_COMMAND, |_, _, _| Box::pin(async { Ok(general::ping_reply().into()) })),
+        SlashCommand::new(&general::INFO_COMMAND, |ctx, _, _| Box::pin(async move { Ok(general::info_reply(ctx).await.into()) })),
+        SlashCommand::new(&general::TIME_COMMAND, |ctx, _, _| Box::pin(async move { Ok(general::time_reply(ctx).await.into()) })),
+        SlashCommand::new(&fun::ROLL_COMMAND, |_, _, args| Box::pin(async move {
+            Ok(fun::roll_reply(&args.get::<String>("dice")?.unwrap_or_default()).into())
+        })).add_option(OptionSpec::new("dice", "Les dés à lancer, par exemple `2d6`.", CommandOptionType::String)),
+        SlashCommand::new(&fun::CHOOSE_COMMAND, |_, _, args| Box::pin(async move {
+            Ok(fun::choose_reply(&args.required::<String>("choices")?).into())
+        })).add_option(
+            OptionSpec::new("choices", "Les propositions, séparées par `|`.", CommandOptionType::String).set_required(true)
+        ),
+        SlashCommand::new(&fun::COIN_COMMAND, |_, _, _| Box::pin(async { Ok(fun::coin_reply().into()) })),
+        SlashCommand::named("help", "Affiche les commandes du bot.", |ctx, interaction, args| Box::pin(async move {
+            let query = args.get::<String>("command")?.unwrap_or_default();
+            Ok(SlashReply::Pages(help_pages(ctx, interaction.user.id, interaction.guild_id, &query).await))
+        })).add_option(OptionSpec::new("command", "La commande à détailler.", CommandOptionType::String)),
+    ]
+}
+
+/// Where the commands are synced for `scope`, and whether they are wanted there. The scope
+/// that is not used is emptied, the commands left there by a previous configuration would
+/// show up twice otherwise.
+fn sync_scopes(scope: SlashScope, guilds: &[GuildId]) -> Vec<(CommandScope, bool)> {
+    let in_guilds = |wanted| guilds.iter().map(move |guild_id| (CommandScope::Guild(*guild_id), wanted));
+    match scope {
+        SlashScope::Off => Vec::new(),
+        SlashScope::Global => std::iter::once((CommandScope::Global, true)).chain(in_guilds(false)).collect(),
+        SlashScope::Guilds => in_guilds(true).chain(std::iter::once((CommandScope::Global, false))).collect(),
+    }
+}
+
+/// Syncs the slash commands with Discord, in `guilds` if they are registered per guild.
+pub async fn register_commands(ctx: &Context, scope: SlashScope, guilds: &[GuildId]) {
+    let specs: Vec<CommandSpec> = slash_commands().into_iter().map(|command| command.spec).collect();
+
+    for (scope, wanted) in sync_scopes(scope, guilds) {
+        let specs = if wanted { specs.as_slice() } else { &[] };
+        match sync_commands(&ctx.http, scope, specs).await {
+            Ok(plan) if plan.is_empty() => {
+                MiraiLogger::debug(format!("Slash commands of {:?} are up to date", scope));
+            }
+            Ok(plan) => {
+                MiraiLogger::info(format!(
+                    "Synced slash commands of {:?}: {} created, {} updated, {} deleted",
+                    scope, plan.create.len(), plan.update.len(), plan.delete.len()
+                ));
+            }
+            Err(err) => {
+                MiraiLogger::error(format!("Could not sync slash commands of {:?}: {}", scope, err));
+            }
+        }
+    }
+}
+
+async fn respond(ctx: &Context, interaction: &ApplicationCommandInteraction, reply: &Reply, ephemeral: bool) {
+    let color = ctx.data.read().await.get::<DiscordBot>()
+        .expect("Did not find DiscordBot").color;
+
+    if let Err(err) = interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|data| {
+                data.ephemeral(ephemeral).embed(|embed| {
+                    embed.color(color);
+                    embed.title(&reply.title);
+                    embed.description(&reply.description);
+                    embed
+                })
+            })
+    }).await {
+        MiraiLogger::error(format!("Could not answer /{} of {}: {}", interaction.data.name, interaction.user.id, err));
+    }
+}
+
+/// Runs the slash command of `interaction`, errors are only shown to the user who ran it.
+pub async fn dispatch(ctx: &Context, interaction: &ApplicationCommandInteraction) {
+    let name = interaction.data.name.as_str();
+    let command = match slash_commands().into_iter().find(|command| command.spec.name == name) {
+        Some(command) => command,
+        None => {
+            MiraiLogger::warn(format!("Received unknown slash command {}", name));
+            respond(ctx, interaction, &Reply::new(ERROR_TITLE, "Cette commande n'existe plus."), true).await;
+            return;
+        }
+    };
+
+    if let Err(denied) = check_levels(ctx, interaction, &command).await {
+        MiraiLogger::debug(format!("{} was denied /{}: {:?}", interaction.user.id, name, denied));
+        respond(ctx, interaction, &Reply::new(ERROR_TITLE, denied.to_string()), true).await;
+        return;
+    }
+
+    MiraiLogger::debug(format!("{} runs /{}", interaction.user.id, name));
+    match (command.run)(ctx, interaction, SlashArgs::new(&interaction.data.options)).await {
+        Ok(SlashReply::Reply(reply)) => respond(ctx, interaction, &reply, false).await,
+        Ok(SlashReply::Pages(pages)) => respond_pages(ctx, interaction, pages).await,
+        Err(err) => respond(ctx, interaction, &Reply::new(ERROR_TITLE, err.to_string()), true).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::GuildId;
+    use serenity::model::Permissions;
+
+    use crate::mirai_bot::slash::{CommandScope, default_permissions, SlashScope, sync_scopes};
+    use crate::permissions::PermissionLevel;
+
+    #[test]
+    fn test_default_permissions() {
+        assert_eq!(default_permissions(&[]), None);
+        assert_eq!(default_permissions(&[PermissionLevel::BotAdmin]), None);
+        assert_eq!(
+            default_permissions(&[
+                PermissionLevel::Guild(Permissions::MANAGE_MESSAGES),
+                PermissionLevel::BotOwner,
+                PermissionLevel::Guild(Permissions::MANAGE_CHANNELS),
+            ]),
+            Some(Permissions::MANAGE_MESSAGES | Permissions::MANAGE_CHANNELS)
+        );
+    }
+
+    #[test]
+    fn test_sync_scopes() {
+        let guilds = [GuildId(1), GuildId(2)];
+        assert!(sync_scopes(SlashScope::Off, &guilds).is_empty());
+        assert_eq!(sync_scopes(SlashScope::Global, &guilds), vec![
+            (CommandScope::Global, true),
+            (CommandScope::Guild(GuildId(1)), false),
+            (CommandScope::Guild(GuildId(2)), false),
+        ]);
+        assert_eq!(sync_scopes(SlashScope::Guilds, &guilds), vec![
+            (CommandScope::Guild(GuildId(1)), true),
+            (CommandScope::Guild(GuildId(2)), true),
+            (CommandScope::Global, false),
+        ]);
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt;
+
+use serde_json::Value;
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::id::{ChannelId, UserId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionError {
+    Missing(String),
+    Invalid(String),
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionError::Missing(name) => write!(f, "L'option `{}` est obligatoire.", name),
+            OptionError::Invalid(name) => write!(f, "La valeur de l'option `{}` est invalide.", name),
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+/// A type a slash command option can be read as.
+pub trait FromOption: Sized {
+    const KIND: CommandOptionType;
+
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromOption for String {
+    const KIND: CommandOptionType = CommandOptionType::String;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_str().map(str::to_string)
+    }
+}
+
+impl FromOption for i64 {
+    const KIND: CommandOptionType = CommandOptionType::Integer;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_i64()
+    }
+}
+
+impl FromOption for bool {
+    const KIND: CommandOptionType = CommandOptionType::Boolean;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+/// Ids are sent as strings.
+fn id_value(value: &Value) -> Option<u64> {
+    value.as_str().and_then(|id| id.parse().ok())
+}
+
+impl FromOption for UserId {
+    const KIND: CommandOptionType = CommandOptionType::User;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        id_value(value).map(UserId)
+    }
+}
+
+impl FromOption for ChannelId {
+    const KIND: CommandOptionType = CommandOptionType::Channel;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        id_value(value).map(ChannelId)
+    }
+}
+
+/// The options a slash command was called with, read by name.
+#[derive(Debug, Clone, Copy)]
+pub struct SlashArgs<'a> {
+    options: &'a [CommandDataOption],
+}
+
+impl<'a> SlashArgs<'a> {
+    pub fn new(options: &'a [CommandDataOption]) -> Self {
+        Self { options }
+    }
+
+    /// The option called `name`, `None` if it was not given.
+    pub fn get<T: FromOption>(&self, name: &str) -> Result<Option<T>, OptionError> {
+        let option = match self.options.iter().find(|option| option.name == name) {
+            Some(option) => option,
+            None => return Ok(None),
+        };
+
+        match (&option.value, option.kind == T::KIND) {
+            (None, _) => Ok(None),
+            (Some(value), true) => T::from_value(value).map(Some).ok_or_else(|| OptionError::Invalid(name.to_string())),
+            (Some(_), false) => Err(OptionError::Invalid(name.to_string())),
+        }
+    }
+
+    pub fn required<T: FromOption>(&self, name: &str) -> Result<T, OptionError> {
+        self.get(name)?.ok_or_else(|| OptionError::Missing(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use serenity::model::application::interaction::application_command::CommandDataOption;
+    use serenity::model::id::UserId;
+
+    use crate::mirai_bot::slash::options::{OptionError, SlashArgs};
+
+    #[test]
+    fn test_slash_args() {
+        let options: Vec<CommandDataOption> = serde_json::from_value(json!([
+            {"name": "dice", "type": 3, "value": "2d6"},
+            {"name": "count", "type": 4, "value": 3},
+            {"name": "member", "type": 6, "value": "42"},
+        ])).unwrap();
+        let args = SlashArgs::new(&options);
+
+        assert_eq!(args.get::<String>("dice"), Ok(Some("2d6".to_string())));
+        assert_eq!(args.required::<i64>("count"), Ok(3));
+        assert_eq!(args.required::<UserId>("member"), Ok(UserId(42)));
+        assert_eq!(args.get::<bool>("hidden"), Ok(None));
+        assert_eq!(args.required::<String>("choices"), Err(OptionError::Missing("choices".to_string())));
+        assert_eq!(args.get::<i64>("dice"), Err(OptionError::Invalid("dice".to_string())));
+    }
+}
@@ -0,0 +1,212 @@
+use serde_json::Value;
+use serenity::builder::CreateApplicationCommand;
+use serenity::http::Http;
+use serenity::model::application::command::{Command, CommandOption, CommandOptionType, CommandType};
+use serenity::model::id::{CommandId, GuildId};
+use serenity::model::Permissions;
+
+/// An option of a slash command, as registered on Discord.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionSpec {
+    pub name: String,
+    pub description: String,
+    pub kind: CommandOptionType,
+    pub required: bool,
+}
+
+impl OptionSpec {
+    pub fn new(name: &str, description: &str, kind: CommandOptionType) -> Self {
+        Self { name: name.to_string(), description: description.to_string(), kind, required: false }
+    }
+
+    pub fn set_required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+
+    fn from_option(option: &CommandOption) -> Self {
+        Self::new(&option.name, &option.description, option.kind).set_required(option.required)
+    }
+}
+
+/// A slash command as registered on Discord, compared with what is already registered to
+/// only send the changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub name: String,
+    pub description: String,
+    pub options: Vec<OptionSpec>,
+    /// Who sees the command in the guilds until their admins change it, everyone if `None`.
+    pub default_permissions: Option<Permissions>,
+}
+
+impl CommandSpec {
+    pub fn new(name: &str, description: &str) -> Self {
+        Self { name: name.to_string(), description: description.to_string(), options: Vec::new(), default_permissions: None }
+    }
+
+    pub fn add_option(mut self, option: OptionSpec) -> Self {
+        self.options.push(option);
+        self
+    }
+
+    pub fn set_default_permissions(mut self, permissions: Option<Permissions>) -> Self {
+        self.default_permissions = permissions;
+        self
+    }
+
+    fn from_command(command: &Command) -> Self {
+        Self {
+            name: command.name.clone(),
+            description: command.description.clone(),
+            options: command.options.iter().map(OptionSpec::from_option).collect(),
+            default_permissions: command.default_member_permissions,
+        }
+    }
+
+    fn build<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        command.name(&self.name).description(&self.description);
+        match self.default_permissions {
+            Some(permissions) => {
+                command.default_member_permissions(permissions);
+            }
+            // An edited command would keep its previous permissions otherwise.
+            None => {
+                command.0.insert("default_member_permissions", Value::Null);
+            }
+        }
+        for option in &self.options {
+            command.create_option(|o| {
+                o.name(&option.name).description(&option.description).kind(option.kind).required(option.required)
+            });
+        }
+        command
+    }
+}
+
+/// Where slash commands are registered: global commands take a while to show up, guild
+/// ones are there at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandScope {
+    Global,
+    Guild(GuildId),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncPlan {
+    pub create: Vec<CommandSpec>,
+    pub update: Vec<(CommandId, CommandSpec)>,
+    pub delete: Vec<(CommandId, String)>,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.update.is_empty() && self.delete.is_empty()
+    }
+}
+
+/// What to send so that the `registered` commands become the `wanted` ones.
+pub fn plan_sync(registered: &[(CommandId, CommandSpec)], wanted: &[CommandSpec]) -> SyncPlan {
+    let mut plan = SyncPlan::default();
+
+    for spec in wanted {
+        match registered.iter().find(|(_, registered)| registered.name == spec.name) {
+            None => plan.create.push(spec.clone()),
+            Some((id, registered)) if registered != spec => plan.update.push((*id, spec.clone())),
+            Some(_) => {}
+        }
+    }
+    for (id, registered) in registered {
+        if !wanted.iter().any(|spec| spec.name == registered.name) {
+            plan.delete.push((*id, registered.name.clone()));
+        }
+    }
+
+    plan
+}
+
+async fn registered_commands(http: &Http, scope: CommandScope) -> serenity::Result<Vec<(CommandId, CommandSpec)>> {
+    let commands = match scope {
+        CommandScope::Global => Command::get_global_application_commands(http).await?,
+        CommandScope::Guild(guild_id) => guild_id.get_application_commands(http).await?,
+    };
+
+    Ok(commands.iter()
+        .filter(|command| command.kind == CommandType::ChatInput)
+        .map(|command| (command.id, CommandSpec::from_command(command)))
+        .collect())
+}
+
+/// Registers the `wanted` commands in `scope`, only creating, editing and deleting the ones
+/// that changed. Returns what was done.
+pub async fn sync_commands(http: &Http, scope: CommandScope, wanted: &[CommandSpec]) -> serenity::Result<SyncPlan> {
+    let plan = plan_sync(&registered_commands(http, scope).await?, wanted);
+
+    for spec in &plan.create {
+        match scope {
+            CommandScope::Global => {
+                Command::create_global_application_command(http, |c| spec.build(c)).await?;
+            }
+            CommandScope::Guild(guild_id) => {
+                guild_id.create_application_command(http, |c| spec.build(c)).await?;
+            }
+        }
+    }
+    for (id, spec) in &plan.update {
+        match scope {
+            CommandScope::Global => {
+                Command::edit_global_application_command(http, *id, |c| spec.build(c)).await?;
+            }
+            CommandScope::Guild(guild_id) => {
+                guild_id.edit_application_command(http, *id, |c| spec.build(c)).await?;
+            }
+        }
+    }
+    for (id, _) in &plan.delete {
+        match scope {
+            CommandScope::Global => Command::delete_global_application_command(http, *id).await?,
+            CommandScope::Guild(guild_id) => guild_id.delete_application_command(http, *id).await?,
+        }
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::application::command::CommandOptionType;
+    use serenity::model::id::CommandId;
+    use serenity::model::Permissions;
+
+    use crate::mirai_bot::slash::sync::{CommandSpec, OptionSpec, plan_sync, SyncPlan};
+
+    #[test]
+    fn test_plan_sync() {
+        let ping = CommandSpec::new("ping", "Vérifie que le bot répond.");
+        let roll = CommandSpec::new("roll", "Lance des dés.")
+            .add_option(OptionSpec::new("dice", "Les dés à lancer.", CommandOptionType::String));
+        let coin = CommandSpec::new("coin", "Lance une pièce.");
+        let old_roll = CommandSpec::new("roll", "Lance des dés.");
+        let old_command = CommandSpec::new("flip", "Retourne une table.");
+
+        let registered = vec![
+            (CommandId(1), ping.clone()),
+            (CommandId(2), old_roll),
+            (CommandId(3), old_command),
+        ];
+        let plan = plan_sync(&registered, &[ping.clone(), roll.clone(), coin.clone()]);
+
+        assert_eq!(plan, SyncPlan {
+            create: vec![coin.clone()],
+            update: vec![(CommandId(2), roll.clone())],
+            delete: vec![(CommandId(3), "flip".to_string())],
+        });
+
+        let registered = vec![(CommandId(1), ping.clone()), (CommandId(2), roll.clone()), (CommandId(4), coin.clone())];
+        assert!(plan_sync(&registered, &[ping.clone(), roll, coin]).is_empty());
+
+        let restricted = ping.clone().set_default_permissions(Some(Permissions::MANAGE_MESSAGES));
+        let plan = plan_sync(&[(CommandId(1), ping)], std::slice::from_ref(&restricted));
+        assert_eq!(plan.update, vec![(CommandId(1), restricted)]);
+    }
+}
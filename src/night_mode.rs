@@ -0,0 +1,510 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{OptionalExtension, params};
+use rusqlite::types::Type;
+use serenity::async_trait;
+use serenity::model::channel::{PermissionOverwrite, PermissionOverwriteType};
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::model::Permissions;
+
+use crate::database::{Database, time_to_column};
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::utils::time::Schedule;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS night_locks (
+    channel_id INTEGER PRIMARY KEY,
+    guild_id INTEGER NOT NULL,
+    locked_at TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS night_lock_overwrites (
+    channel_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    target_id INTEGER NOT NULL,
+    allow INTEGER NOT NULL,
+    deny INTEGER NOT NULL,
+    PRIMARY KEY (channel_id, kind, target_id)
+);";
+
+/// What nobody but the exempt roles can do in a locked channel.
+pub const LOCKED: Permissions = Permissions::SEND_MESSAGES.union(Permissions::SEND_MESSAGES_IN_THREADS);
+
+/// Whom a permission overwrite applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Target {
+    Role(RoleId),
+    Member(UserId),
+}
+
+impl Target {
+    fn kind(&self) -> &'static str {
+        match self {
+            Target::Role(_) => "role",
+            Target::Member(_) => "member",
+        }
+    }
+
+    fn id(&self) -> u64 {
+        match self {
+            Target::Role(role) => role.0,
+            Target::Member(user) => user.0,
+        }
+    }
+
+    fn from_columns(kind: &str, id: u64) -> Option<Self> {
+        match kind {
+            "role" => Some(Target::Role(RoleId(id))),
+            "member" => Some(Target::Member(UserId(id))),
+            _ => None,
+        }
+    }
+
+    pub fn to_serenity(self) -> PermissionOverwriteType {
+        match self {
+            Target::Role(role) => PermissionOverwriteType::Role(role),
+            Target::Member(user) => PermissionOverwriteType::Member(user),
+        }
+    }
+}
+
+/// The permissions of a channel allowed and denied to a role or a member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overwrite {
+    pub target: Target,
+    pub allow: Permissions,
+    pub deny: Permissions,
+}
+
+impl Overwrite {
+    pub fn from_serenity(overwrite: &PermissionOverwrite) -> Option<Self> {
+        let target = match overwrite.kind {
+            PermissionOverwriteType::Role(role) => Target::Role(role),
+            PermissionOverwriteType::Member(user) => Target::Member(user),
+            _ => return None,
+        };
+        Some(Self { target, allow: overwrite.allow, deny: overwrite.deny })
+    }
+
+    pub fn to_serenity(self) -> PermissionOverwrite {
+        PermissionOverwrite { allow: self.allow, deny: self.deny, kind: self.target.to_serenity() }
+    }
+}
+
+/// Whether it is night at `now`: the next morning announcement comes before the next
+/// evening one. A schedule that never fires means it is always day.
+pub fn is_night(now: DateTime<Utc>, evening: &Schedule, morning: &Schedule, timezone: chrono_tz::Tz) -> bool {
+    match (evening.next_after(now, timezone), morning.next_after(now, timezone)) {
+        (Some(evening), Some(morning)) => morning < evening,
+        _ => false,
+    }
+}
+
+/// The overwrites of a channel during the night, from the ones it had during the day:
+/// `@everyone` is denied the locked permissions, the overwrites allowing them stop doing so
+/// and the exempt roles are allowed them.
+pub fn night_overwrites(day: &[Overwrite], everyone: RoleId, exempt: &[RoleId]) -> Vec<Overwrite> {
+    let is_exempt = |target: Target| matches!(target, Target::Role(role) if role != everyone && exempt.contains(&role));
+    let mut night: Vec<Overwrite> = day.iter()
+        .map(|overwrite| match is_exempt(overwrite.target) {
+            true => *overwrite,
+            false => Overwrite { allow: overwrite.allow - LOCKED, ..*overwrite },
+        })
+        .collect();
+
+    let targets = std::iter::once(Target::Role(everyone))
+        .chain(exempt.iter().map(|role| Target::Role(*role)).filter(|target| is_exempt(*target)));
+    for target in targets {
+        let index = match night.iter().position(|overwrite| overwrite.target == target) {
+            Some(index) => index,
+            None => {
+                night.push(Overwrite { target, allow: Permissions::empty(), deny: Permissions::empty() });
+                night.len() - 1
+            }
+        };
+        let overwrite = &mut night[index];
+        match is_exempt(target) {
+            true => {
+                overwrite.allow |= LOCKED;
+                overwrite.deny -= LOCKED;
+            }
+            false => overwrite.deny |= LOCKED,
+        }
+    }
+    night
+}
+
+/// What to send to Discord to go from the `current` overwrites to the `wanted` ones.
+pub fn changes(current: &[Overwrite], wanted: &[Overwrite]) -> (Vec<Overwrite>, Vec<Target>) {
+    let set = wanted.iter().copied().filter(|overwrite| !current.contains(overwrite)).collect();
+    let delete = current.iter()
+        .map(|overwrite| overwrite.target)
+        .filter(|target| wanted.iter().all(|overwrite| overwrite.target != *target))
+        .collect();
+    (set, delete)
+}
+
+/// The overwrites the locked channels had before the night, in the `night_lock*` tables, so
+/// that a restart in the middle of the night still gives them back in the morning.
+#[derive(Clone)]
+pub struct NightLockStore {
+    database: Database,
+}
+
+impl NightLockStore {
+    pub fn new(database: Database) -> rusqlite::Result<Self> {
+        database.with_connection(|connection| connection.execute_batch(SCHEMA))?;
+        Ok(Self { database })
+    }
+
+    /// Remembers the overwrites of `channel` before its lock. A channel locked already keeps
+    /// the overwrites it had before the first lock, returns false then.
+    pub fn save(&self, guild: GuildId, channel: ChannelId, overwrites: &[Overwrite], now: DateTime<Utc>) -> rusqlite::Result<bool> {
+        self.database.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            let inserted = transaction.execute(
+                "INSERT OR IGNORE INTO night_locks (channel_id, guild_id, locked_at) VALUES (?1, ?2, ?3)",
+                params![channel.0 as i64, guild.0 as i64, time_to_column(now)],
+            )?;
+            if inserted == 0 {
+                return Ok(false);
+            }
+            for overwrite in overwrites {
+                transaction.execute(
+                    "INSERT INTO night_lock_overwrites (channel_id, kind, target_id, allow, deny) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        channel.0 as i64,
+                        overwrite.target.kind(),
+                        overwrite.target.id() as i64,
+                        overwrite.allow.bits() as i64,
+                        overwrite.deny.bits() as i64,
+                    ],
+                )?;
+            }
+            transaction.commit()?;
+            Ok(true)
+        })
+    }
+
+    /// The overwrites `channel` had before the night, `None` if it is not locked.
+    pub fn get(&self, channel: ChannelId) -> rusqlite::Result<Option<Vec<Overwrite>>> {
+        self.database.with_connection(|connection| {
+            let locked = connection.query_row(
+                "SELECT 1 FROM night_locks WHERE channel_id = ?1", params![channel.0 as i64], |_| Ok(())
+            ).optional()?;
+            if locked.is_none() {
+                return Ok(None);
+            }
+
+            let mut statement = connection.prepare(
+                "SELECT kind, target_id, allow, deny FROM night_lock_overwrites WHERE channel_id = ?1 ORDER BY kind, target_id"
+            )?;
+            let overwrites = statement.query_map(params![channel.0 as i64], |row| {
+                let kind = row.get::<_, String>(0)?;
+                let target = Target::from_columns(&kind, row.get::<_, i64>(1)? as u64).ok_or_else(|| {
+                    rusqlite::Error::FromSqlConversionFailure(0, Type::Text, format!("unknown overwrite kind {}", kind).into())
+                })?;
+                Ok(Overwrite {
+                    target,
+                    allow: Permissions::from_bits_truncate(row.get::<_, i64>(2)? as u64),
+                    deny: Permissions::from_bits_truncate(row.get::<_, i64>(3)? as u64),
+                })
+            })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(Some(overwrites))
+        })
+    }
+
+    /// The channels of `guild` locked right now.
+    pub fn locked(&self, guild: GuildId) -> rusqlite::Result<Vec<ChannelId>> {
+        self.database.with_connection(|connection| {
+            let mut statement = connection.prepare("SELECT channel_id FROM night_locks WHERE guild_id = ?1 ORDER BY channel_id")?;
+            let channels = statement.query_map(params![guild.0 as i64], |row| Ok(ChannelId(row.get::<_, i64>(0)? as u64)))?
+                .collect();
+            channels
+        })
+    }
+
+    /// Forgets the lock of `channel`, once its overwrites are back.
+    pub fn release(&self, channel: ChannelId) -> rusqlite::Result<()> {
+        self.database.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            transaction.execute("DELETE FROM night_lock_overwrites WHERE channel_id = ?1", params![channel.0 as i64])?;
+            transaction.execute("DELETE FROM night_locks WHERE channel_id = ?1", params![channel.0 as i64])?;
+            transaction.commit()
+        })
+    }
+
+    /// Forgets the locks of a guild the bot left, it cannot unlock them anymore.
+    pub fn forget(&self, guild: GuildId) -> rusqlite::Result<()> {
+        self.database.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            transaction.execute(
+                "DELETE FROM night_lock_overwrites WHERE channel_id IN (SELECT channel_id FROM night_locks WHERE guild_id = ?1)",
+                params![guild.0 as i64],
+            )?;
+            transaction.execute("DELETE FROM night_locks WHERE guild_id = ?1", params![guild.0 as i64])?;
+            transaction.commit()
+        })
+    }
+}
+
+/// Where the overwrites of the channels are read and changed, Discord itself or a fake in
+/// tests.
+#[async_trait]
+pub trait ChannelOverwrites: Send + Sync {
+    /// The overwrites of `channel`, `None` if the channel does not exist anymore.
+    async fn get(&self, channel: ChannelId) -> serenity::Result<Option<Vec<Overwrite>>>;
+    async fn set(&self, channel: ChannelId, overwrite: Overwrite) -> serenity::Result<()>;
+    async fn delete(&self, channel: ChannelId, target: Target) -> serenity::Result<()>;
+}
+
+/// Locks `channel` for the night after saving its overwrites, which a lock left over by a
+/// crash keeps. Returns false if the channel could not be locked.
+pub async fn lock(
+    discord: &dyn ChannelOverwrites,
+    store: &NightLockStore,
+    guild: GuildId,
+    channel: ChannelId,
+    exempt: &[RoleId],
+    now: DateTime<Utc>,
+) -> bool {
+    let current = match discord.get(channel).await {
+        Ok(Some(current)) => current,
+        Ok(None) => {
+            MiraiLogger::warn(format!("Night channel {} of guild {} does not exist anymore", channel, guild));
+            return false;
+        }
+        Err(err) => {
+            MiraiLogger::error(format!("Could not read the permissions of {} to lock it: {}", channel, err));
+            return false;
+        }
+    };
+    // Saved before changing anything, a crash in the middle of the lock must not lose them.
+    let day = match store.save(guild, channel, &current, now).and_then(|_| store.get(channel)) {
+        Ok(Some(day)) => day,
+        Ok(None) => return false,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not save the permissions of {} before locking it: {}", channel, err));
+            return false;
+        }
+    };
+
+    // Overwrites added during the night are left alone until the morning.
+    let (set, _) = changes(&current, &night_overwrites(&day, RoleId(guild.0), exempt));
+    for overwrite in set {
+        if let Err(err) = discord.set(channel, overwrite).await {
+            MiraiLogger::error(format!("Could not lock {} for {:?}: {}", channel, overwrite.target, err));
+            return false;
+        }
+    }
+    true
+}
+
+/// Gives `channel` back the overwrites it had before the night, exactly. Returns false if
+/// it stays locked.
+pub async fn unlock(discord: &dyn ChannelOverwrites, store: &NightLockStore, channel: ChannelId) -> bool {
+    let day = match store.get(channel) {
+        Ok(Some(day)) => day,
+        Ok(None) => return true,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not read the permissions {} had before the night: {}", channel, err));
+            return false;
+        }
+    };
+    let current = match discord.get(channel).await {
+        Ok(current) => current,
+        Err(err) => {
+            MiraiLogger::error(format!("Could not read the permissions of {} to unlock it: {}", channel, err));
+            return false;
+        }
+    };
+
+    // Nothing to give back to a deleted channel.
+    if let Some(current) = current {
+        let (set, delete) = changes(&current, &day);
+        for overwrite in set {
+            if let Err(err) = discord.set(channel, overwrite).await {
+                MiraiLogger::error(format!("Could not unlock {} for {:?}: {}", channel, overwrite.target, err));
+                return false;
+            }
+        }
+        for target in delete {
+            if let Err(err) = discord.delete(channel, target).await {
+                MiraiLogger::error(format!("Could not unlock {} for {:?}: {}", channel, target, err));
+                return false;
+            }
+        }
+    }
+
+    if let Err(err) = store.release(channel) {
+        MiraiLogger::error(format!("Could not forget the lock of {}: {}", channel, err));
+        return false;
+    }
+    true
+}
+
+/// Channels kept in memory, for tests.
+#[cfg(test)]
+pub mod fake {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use serenity::async_trait;
+    use serenity::model::id::ChannelId;
+
+    use crate::night_mode::{ChannelOverwrites, Overwrite, Target};
+
+    #[derive(Default)]
+    pub struct FakeChannels {
+        overwrites: Mutex<HashMap<ChannelId, Vec<Overwrite>>>,
+        failing: AtomicBool,
+        failures: AtomicUsize,
+    }
+
+    impl FakeChannels {
+        pub fn insert(&self, channel: ChannelId, overwrites: Vec<Overwrite>) {
+            self.overwrites.lock().unwrap().insert(channel, overwrites);
+        }
+
+        /// Makes the changes fail like Discord being down, until called with false.
+        pub fn set_failing(&self, failing: bool) {
+            self.failing.store(failing, Ordering::SeqCst);
+        }
+
+        /// How many changes failed so far.
+        pub fn failures(&self) -> usize {
+            self.failures.load(Ordering::SeqCst)
+        }
+
+        /// Whether the change fails, counting it if so.
+        fn fails(&self) -> bool {
+            let failing = self.failing.load(Ordering::SeqCst);
+            if failing {
+                self.failures.fetch_add(1, Ordering::SeqCst);
+            }
+            failing
+        }
+
+        pub fn remove(&self, channel: ChannelId) {
+            self.overwrites.lock().unwrap().remove(&channel);
+        }
+
+        /// The overwrites of `channel`, sorted by target.
+        pub fn overwrites(&self, channel: ChannelId) -> Vec<Overwrite> {
+            let mut overwrites = self.overwrites.lock().unwrap()[&channel].clone();
+            overwrites.sort_by_key(|overwrite| overwrite.target);
+            overwrites
+        }
+    }
+
+    #[async_trait]
+    impl ChannelOverwrites for FakeChannels {
+        async fn get(&self, channel: ChannelId) -> serenity::Result<Option<Vec<Overwrite>>> {
+            Ok(self.overwrites.lock().unwrap().get(&channel).cloned())
+        }
+
+        async fn set(&self, channel: ChannelId, overwrite: Overwrite) -> serenity::Result<()> {
+            if self.fails() {
+                return Err(serenity::Error::Other("Discord is down"));
+            }
+            let mut channels = self.overwrites.lock().unwrap();
+            let overwrites = channels.get_mut(&channel).expect("Unknown channel");
+            overwrites.retain(|existing| existing.target != overwrite.target);
+            overwrites.push(overwrite);
+            Ok(())
+        }
+
+        async fn delete(&self, channel: ChannelId, target: Target) -> serenity::Result<()> {
+            if self.fails() {
+                return Err(serenity::Error::Other("Discord is down"));
+            }
+            self.overwrites.lock().unwrap().get_mut(&channel).expect("Unknown channel")
+                .retain(|existing| existing.target != target);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use chrono_tz::Europe::Paris;
+    use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+    use serenity::model::Permissions;
+
+    use crate::database::Database;
+    use crate::night_mode::{is_night, lock, LOCKED, night_overwrites, NightLockStore, Overwrite, Target, unlock};
+    use crate::night_mode::fake::FakeChannels;
+    use crate::utils::time::Schedule;
+
+    fn overwrite(target: Target, allow: Permissions, deny: Permissions) -> Overwrite {
+        Overwrite { target, allow, deny }
+    }
+
+    #[test]
+    fn test_is_night() {
+        let (evening, morning) = (Schedule::daily(22, 0), Schedule::daily(7, 0));
+        for (hour, night) in [(6, true), (7, false), (12, false), (21, false), (22, true), (23, true)] {
+            let now = Paris.ymd(2022, 10, 1).and_hms(hour, 0, 0).with_timezone(&Utc);
+            assert_eq!(is_night(now, &evening, &morning, Paris), night, "at {}h", hour);
+        }
+    }
+
+    #[test]
+    fn test_night_overwrites() {
+        let (everyone, staff, muted, member) = (RoleId(1), RoleId(2), RoleId(3), UserId(4));
+        let day = vec![
+            overwrite(Target::Role(everyone), Permissions::SEND_MESSAGES | Permissions::ADD_REACTIONS, Permissions::empty()),
+            overwrite(Target::Role(muted), Permissions::empty(), Permissions::SEND_MESSAGES),
+            overwrite(Target::Member(member), Permissions::SEND_MESSAGES, Permissions::empty()),
+        ];
+        let night = night_overwrites(&day, everyone, &[staff, everyone]);
+        assert_eq!(night, vec![
+            overwrite(Target::Role(everyone), Permissions::ADD_REACTIONS, LOCKED),
+            overwrite(Target::Role(muted), Permissions::empty(), Permissions::SEND_MESSAGES),
+            overwrite(Target::Member(member), Permissions::empty(), Permissions::empty()),
+            overwrite(Target::Role(staff), LOCKED, Permissions::empty()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_lock_and_unlock() {
+        let store = NightLockStore::new(Database::in_memory().unwrap()).unwrap();
+        let discord = FakeChannels::default();
+        let (guild, channel, staff) = (GuildId(1), ChannelId(10), RoleId(2));
+        let day = vec![
+            overwrite(Target::Role(RoleId(3)), Permissions::empty(), Permissions::SEND_MESSAGES),
+            overwrite(Target::Member(UserId(4)), Permissions::SEND_MESSAGES, Permissions::empty()),
+        ];
+        discord.insert(channel, day.clone());
+        let now = Utc.ymd(2022, 10, 1).and_hms(20, 0, 0);
+
+        assert!(lock(&discord, &store, guild, channel, &[staff], now).await);
+        assert_eq!(store.locked(guild).unwrap(), vec![channel]);
+        let locked = discord.overwrites(channel);
+        assert!(locked.contains(&overwrite(Target::Role(RoleId(1)), Permissions::empty(), LOCKED)));
+        assert!(locked.contains(&overwrite(Target::Role(staff), LOCKED, Permissions::empty())));
+
+        // Locking again, after a restart, keeps the overwrites of the day.
+        assert!(lock(&discord, &store, guild, channel, &[staff], now).await);
+        assert_eq!(store.get(channel).unwrap().unwrap().len(), 2);
+        assert_eq!(discord.overwrites(channel), locked);
+
+        assert!(unlock(&discord, &store, channel).await);
+        assert_eq!(discord.overwrites(channel), day);
+        assert!(store.locked(guild).unwrap().is_empty());
+        assert!(unlock(&discord, &store, channel).await);
+
+        // A channel deleted during the night is forgotten.
+        assert!(!lock(&discord, &store, guild, ChannelId(11), &[], now).await);
+        assert!(lock(&discord, &store, guild, channel, &[], now).await);
+        discord.remove(channel);
+        assert!(unlock(&discord, &store, channel).await);
+        assert_eq!(store.get(channel).unwrap(), None);
+
+        assert!(store.save(guild, channel, &day, now).unwrap());
+        assert!(!store.save(guild, channel, &[], now).unwrap());
+        store.forget(guild).unwrap();
+        assert!(store.locked(guild).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::params;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use crate::database::{Database, time_to_column};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS night_offences (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    night TEXT NOT NULL,
+    messages INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, user_id, night)
+);";
+
+fn night_column(night: DateTime<Utc>) -> String {
+    time_to_column(night)
+}
+
+/// The messages members posted at night, in the `night_offences` table. A night is known by
+/// the morning announcement that ends it.
+#[derive(Clone)]
+pub struct NightWatchStore {
+    database: Database,
+}
+
+impl NightWatchStore {
+    pub fn new(database: Database) -> rusqlite::Result<Self> {
+        database.with_connection(|connection| connection.execute_batch(SCHEMA))?;
+        Ok(Self { database })
+    }
+
+    /// Counts a message of `user` during the night ending at `night`, returns the number of
+    /// messages the member ever posted at night on the guild.
+    pub fn record(&self, guild: GuildId, user: UserId, night: DateTime<Utc>) -> rusqlite::Result<u32> {
+        self.database.with_connection(|connection| {
+            connection.execute(
+                "INSERT INTO night_offences (guild_id, user_id, night, messages) VALUES (?1, ?2, ?3, 1)
+                 ON CONFLICT (guild_id, user_id, night) DO UPDATE SET messages = messages + 1",
+                params![guild.0 as i64, user.0 as i64, night_column(night)],
+            )?;
+            connection.query_row(
+                "SELECT SUM(messages) FROM night_offences WHERE guild_id = ?1 AND user_id = ?2",
+                params![guild.0 as i64, user.0 as i64],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    /// The members who posted the most during the night ending at `night`, with their number
+    /// of messages.
+    pub fn worst(&self, guild: GuildId, night: DateTime<Utc>, limit: usize) -> rusqlite::Result<Vec<(UserId, u32)>> {
+        self.database.with_connection(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT user_id, messages FROM night_offences WHERE guild_id = ?1 AND night = ?2
+                 ORDER BY messages DESC, user_id LIMIT ?3"
+            )?;
+            let worst = statement.query_map(params![guild.0 as i64, night_column(night), limit as i64], |row| {
+                Ok((UserId(row.get::<_, i64>(0)? as u64), row.get(1)?))
+            })?
+                .collect();
+            worst
+        })
+    }
+
+    /// Forgets the night messages of a guild the bot left.
+    pub fn forget(&self, guild: GuildId) -> rusqlite::Result<()> {
+        self.database.with_connection(|connection| {
+            connection.execute("DELETE FROM night_offences WHERE guild_id = ?1", params![guild.0 as i64])?;
+            Ok(())
+        })
+    }
+}
+
+/// Lets something happen once per member and channel every `period`.
+pub struct Cooldown {
+    period: Duration,
+    last: Mutex<HashMap<(ChannelId, UserId), DateTime<Utc>>>,
+}
+
+impl Cooldown {
+    pub fn new(period: Duration) -> Self {
+        Self { period, last: Mutex::new(HashMap::new()) }
+    }
+
+    /// Whether `user` may be answered in `channel` at `now`, the cooldown starts again if so.
+    pub fn take(&self, channel: ChannelId, user: UserId, now: DateTime<Utc>) -> bool {
+        let mut last = self.last.lock().expect("Cooldown lock poisoned");
+        last.retain(|_, at| now - *at < self.period);
+        match last.contains_key(&(channel, user)) {
+            true => false,
+            false => {
+                last.insert((channel, user), now);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use serenity::model::id::{ChannelId, GuildId, UserId};
+
+    use crate::database::Database;
+    use crate::night_watch::{Cooldown, NightWatchStore};
+
+    #[test]
+    fn test_night_watch_store() {
+        let store = NightWatchStore::new(Database::in_memory().unwrap()).unwrap();
+        let (guild, first, second) = (GuildId(1), Utc.ymd(2022, 10, 2).and_hms(5, 0, 0), Utc.ymd(2022, 10, 3).and_hms(5, 0, 0));
+
+        assert_eq!(store.record(guild, UserId(1), first).unwrap(), 1);
+        assert_eq!(store.record(guild, UserId(2), first).unwrap(), 1);
+        assert_eq!(store.record(guild, UserId(2), first).unwrap(), 2);
+        assert_eq!(store.record(guild, UserId(1), second).unwrap(), 2);
+        assert_eq!(store.record(GuildId(2), UserId(1), first).unwrap(), 1);
+
+        assert_eq!(store.worst(guild, first, 5).unwrap(), vec![(UserId(2), 2), (UserId(1), 1)]);
+        assert_eq!(store.worst(guild, first, 1).unwrap(), vec![(UserId(2), 2)]);
+        assert_eq!(store.worst(guild, second, 5).unwrap(), vec![(UserId(1), 1)]);
+
+        store.forget(guild).unwrap();
+        assert!(store.worst(guild, first, 5).unwrap().is_empty());
+        assert_eq!(store.worst(GuildId(2), first, 5).unwrap(), vec![(UserId(1), 1)]);
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let cooldown = Cooldown::new(Duration::minutes(10));
+        let now = Utc.ymd(2022, 10, 1).and_hms(23, 0, 0);
+
+        assert!(cooldown.take(ChannelId(1), UserId(1), now));
+        assert!(!cooldown.take(ChannelId(1), UserId(1), now + Duration::minutes(5)));
+        assert!(cooldown.take(ChannelId(2), UserId(1), now + Duration::minutes(5)));
+        assert!(cooldown.take(ChannelId(1), UserId(2), now + Duration::minutes(5)));
+        assert!(cooldown.take(ChannelId(1), UserId(1), now + Duration::minutes(10)));
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt;
+use std::sync::Arc;
+
+use rusqlite::params;
+use serenity::client::Context;
+use serenity::model::id::{GuildId, UserId};
+use serenity::model::Permissions;
+use serenity::model::Timestamp;
+use serenity::prelude::TypeMapKey;
+
+use crate::database::Database;
+use crate::log::{MiraiLog, MiraiLogger};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS bot_admins (
+    user_id INTEGER PRIMARY KEY,
+    added_by INTEGER NOT NULL,
+    added_at TEXT NOT NULL
+);";
+
+/// What a user needs to run a restricted command, from the least to the most restrictive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionLevel {
+    Guild(Permissions),
+    BotAdmin,
+    BotOwner,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PermissionDenied {
+    NotOwner,
+    NotAdmin,
+    NotInGuild,
+    MissingPermissions(Permissions),
+}
+
+impl fmt::Display for PermissionDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PermissionDenied::NotOwner => write!(f, "Seul le créateur du bot peut faire ça."),
+            PermissionDenied::NotAdmin => write!(f, "Seuls les administrateurs du bot peuvent faire ça."),
+            PermissionDenied::NotInGuild => write!(f, "Cette commande ne marche que sur un serveur."),
+            PermissionDenied::MissingPermissions(permissions) => {
+                write!(f, "Il te manque les permissions suivantes : {}.", permissions)
+            }
+        }
+    }
+}
+
+/// Bot admins added with the admin commands, on top of the ones from the configuration.
+pub struct AdminStore {
+    database: Database,
+}
+
+impl AdminStore {
+    pub fn new(database: Database) -> rusqlite::Result<Self> {
+        database.with_connection(|connection| connection.execute_batch(SCHEMA))?;
+        Ok(Self { database })
+    }
+
+    pub fn list(&self) -> rusqlite::Result<Vec<UserId>> {
+        self.database.with_connection(|connection| {
+            let mut statement = connection.prepare("SELECT user_id FROM bot_admins ORDER BY added_at")?;
+            let admins = statement.query_map([], |row| row.get::<_, i64>(0))?
+                .map(|id| id.map(|id| UserId(id as u64)))
+                .collect();
+            admins
+        })
+    }
+
+    /// Returns false if the user already was an admin.
+    pub fn add(&self, user_id: UserId, added_by: UserId) -> rusqlite::Result<bool> {
+        self.database.with_connection(|connection| {
+            connection.execute(
+                "INSERT OR IGNORE INTO bot_admins (user_id, added_by, added_at) VALUES (?1, ?2, ?3)",
+                params![user_id.0 as i64, added_by.0 as i64, Timestamp::now().to_rfc3339()],
+            )
+        }).map(|inserted| inserted > 0)
+    }
+
+    /// Returns false if the user was not an admin.
+    pub fn remove(&self, user_id: UserId) -> rusqlite::Result<bool> {
+        self.database.with_connection(|connection| {
+            connection.execute("DELETE FROM bot_admins WHERE user_id = ?1", params![user_id.0 as i64])
+        }).map(|deleted| deleted > 0)
+    }
+}
+
+/// Who can do what on the bot, stored in the client's `TypeMap`.
+#[derive(Clone)]
+pub struct BotPermissions {
+    owner: Option<UserId>,
+    config_admins: Vec<UserId>,
+    store: Arc<AdminStore>,
+}
+
+impl TypeMapKey for BotPermissions {
+    type Value = BotPermissions;
+}
+
+impl BotPermissions {
+    pub fn new(owner: Option<UserId>, config_admins: Vec<UserId>, store: AdminStore) -> Self {
+        Self { owner, config_admins, store: Arc::new(store) }
+    }
+
+    pub fn is_owner(&self, user_id: UserId) -> bool {
+        self.owner == Some(user_id)
+    }
+
+    pub fn is_config_admin(&self, user_id: UserId) -> bool {
+        self.config_admins.contains(&user_id)
+    }
+
+    pub fn is_admin(&self, user_id: UserId) -> bool {
+        self.is_owner(user_id) || self.admins().contains(&user_id)
+    }
+
+    /// Admins from the configuration followed by the ones added with commands.
+    pub fn admins(&self) -> Vec<UserId> {
+        let mut admins = self.config_admins.clone();
+        match self.store.list() {
+            Ok(stored) => admins.extend(stored.into_iter().filter(|id| !self.config_admins.contains(id))),
+            Err(err) => MiraiLogger::error(format!("Could not read the bot admins: {}", err)),
+        }
+        admins
+    }
+
+    pub fn add_admin(&self, user_id: UserId, added_by: UserId) -> rusqlite::Result<bool> {
+        if self.is_config_admin(user_id) {
+            return Ok(false);
+        }
+        self.store.add(user_id, added_by)
+    }
+
+    pub fn remove_admin(&self, user_id: UserId) -> rusqlite::Result<bool> {
+        self.store.remove(user_id)
+    }
+
+    /// Checks a level against what is known of the user. `guild_permissions` are the user's
+    /// permissions in the guild the command comes from, if any. Bot admins need them like
+    /// any other member.
+    pub fn check_level(
+        &self,
+        user_id: UserId,
+        level: PermissionLevel,
+        guild_permissions: Option<Permissions>,
+    ) -> Result<(), PermissionDenied> {
+        match level {
+            PermissionLevel::BotOwner if self.is_owner(user_id) => Ok(()),
+            PermissionLevel::BotOwner => Err(PermissionDenied::NotOwner),
+            PermissionLevel::BotAdmin if self.is_admin(user_id) => Ok(()),
+            PermissionLevel::BotAdmin => Err(PermissionDenied::NotAdmin),
+            PermissionLevel::Guild(required) => match guild_permissions {
+                None => Err(PermissionDenied::NotInGuild),
+                Some(permissions) if permissions.administrator() || permissions.contains(required) => Ok(()),
+                Some(permissions) => Err(PermissionDenied::MissingPermissions(required - permissions)),
+            },
+        }
+    }
+
+    /// Same as `check_level`, looking up the member's permissions in the cached guild.
+    pub async fn check(
+        &self,
+        ctx: &Context,
+        user_id: UserId,
+        guild_id: Option<GuildId>,
+        level: PermissionLevel,
+    ) -> Result<(), PermissionDenied> {
+        let guild_permissions = match (level, guild_id) {
+            (PermissionLevel::Guild(_), Some(guild_id)) => {
+                match guild_id.to_guild_cached(&ctx.cache) {
+                    Some(guild) => guild.member_permissions(ctx, user_id).await.ok(),
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+
+        self.check_level(user_id, level, guild_permissions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::UserId;
+    use serenity::model::Permissions;
+
+    use crate::database::Database;
+    use crate::permissions::{AdminStore, BotPermissions, PermissionDenied, PermissionLevel};
+
+    fn permissions() -> BotPermissions {
+        let store = AdminStore::new(Database::in_memory().unwrap()).unwrap();
+        BotPermissions::new(Some(UserId(1)), vec![UserId(2)], store)
+    }
+
+    #[test]
+    fn test_admins() {
+        let permissions = permissions();
+        assert!(permissions.is_admin(UserId(1)));
+        assert!(permissions.is_admin(UserId(2)));
+        assert!(!permissions.is_admin(UserId(3)));
+
+        assert!(permissions.add_admin(UserId(3), UserId(1)).unwrap());
+        assert!(!permissions.add_admin(UserId(3), UserId(1)).unwrap());
+        assert!(!permissions.add_admin(UserId(2), UserId(1)).unwrap());
+        assert!(permissions.is_admin(UserId(3)));
+        assert_eq!(permissions.admins(), vec![UserId(2), UserId(3)]);
+
+        assert!(permissions.remove_admin(UserId(3)).unwrap());
+        assert!(!permissions.remove_admin(UserId(3)).unwrap());
+        assert!(!permissions.is_admin(UserId(3)));
+    }
+
+    #[test]
+    fn test_check_level() {
+        let permissions = permissions();
+        let kick = PermissionLevel::Guild(Permissions::KICK_MEMBERS);
+
+        assert_eq!(permissions.check_level(UserId(1), PermissionLevel::BotOwner, None), Ok(()));
+        assert_eq!(
+            permissions.check_level(UserId(2), PermissionLevel::BotOwner, None),
+            Err(PermissionDenied::NotOwner)
+        );
+        assert_eq!(permissions.check_level(UserId(2), PermissionLevel::BotAdmin, None), Ok(()));
+        assert_eq!(
+            permissions.check_level(UserId(3), PermissionLevel::BotAdmin, None),
+            Err(PermissionDenied::NotAdmin)
+        );
+
+        // Bot admins moderate the bot, not the guilds.
+        assert_eq!(permissions.check_level(UserId(2), kick, None), Err(PermissionDenied::NotInGuild));
+        assert_eq!(
+            permissions.check_level(UserId(1), kick, Some(Permissions::SEND_MESSAGES)),
+            Err(PermissionDenied::MissingPermissions(Permissions::KICK_MEMBERS))
+        );
+        assert_eq!(permissions.check_level(UserId(3), kick, None), Err(PermissionDenied::NotInGuild));
+        assert_eq!(
+            permissions.check_level(UserId(3), kick, Some(Permissions::KICK_MEMBERS | Permissions::SEND_MESSAGES)),
+            Ok(())
+        );
+        assert_eq!(permissions.check_level(UserId(3), kick, Some(Permissions::ADMINISTRATOR)), Ok(()));
+        assert_eq!(
+            permissions.check_level(UserId(3), kick, Some(Permissions::SEND_MESSAGES)),
+            Err(PermissionDenied::MissingPermissions(Permissions::KICK_MEMBERS))
+        );
+    }
+}
@@ -0,0 +1,96 @@
+/// Words left out when comparing answers, so that `la Tour Eiffel` matches `tour eiffel`.
+const ARTICLES: [&str; 9] = ["le", "la", "les", "l", "un", "une", "des", "the", "a"];
+
+fn strip_accent(c: char) -> char {
+    match c {
+        'à' | 'â' | 'ä' | 'á' | 'ã' => 'a',
+        'ç' => 'c',
+        'é' | 'è' | 'ê' | 'ë' => 'e',
+        'î' | 'ï' | 'í' | 'ì' => 'i',
+        'ô' | 'ö' | 'ó' | 'ò' | 'õ' => 'o',
+        'ù' | 'û' | 'ü' | 'ú' => 'u',
+        'ÿ' | 'ý' => 'y',
+        'ñ' => 'n',
+        c => c,
+    }
+}
+
+/// Lowercase words without accents, punctuation or articles.
+pub fn normalize(text: &str) -> String {
+    let text: String = text.to_lowercase().chars()
+        .map(strip_accent)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let meaningful: Vec<&str> = words.iter().copied().filter(|word| !ARTICLES.contains(word)).collect();
+
+    // An answer made of articles only is kept as it is.
+    match meaningful.is_empty() {
+        true => words.join(" "),
+        false => meaningful.join(" "),
+    }
+}
+
+/// Edits needed to turn `a` into `b`, counted in characters.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Typos allowed in an answer of `length` characters: none for short answers, where one
+/// letter often makes another word.
+fn allowed_typos(length: usize) -> usize {
+    (length / 5).min(3)
+}
+
+/// Whether `guess` is close enough to `answer` to count.
+pub fn matches(guess: &str, answer: &str) -> bool {
+    let (guess, answer) = (normalize(guess), normalize(answer));
+    if guess.is_empty() {
+        return false;
+    }
+    levenshtein(&guess, &answer) <= allowed_typos(answer.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::quiz::fuzzy::{levenshtein, matches, normalize};
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("  La Tour   Eiffel !"), "tour eiffel");
+        assert_eq!(normalize("L'Élève"), "eleve");
+        assert_eq!(normalize("Les"), "les");
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("été", "ete"), 2);
+        assert_eq!(levenshtein("monokuma", "monokuma"), 0);
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("monokuma", "Monokuma"));
+        assert!(matches("monokumaa", "Monokuma"));
+        assert!(matches("kyoko kirigri", "Kyoko Kirigiri"));
+        assert!(matches("l'hôpital", "hopital"));
+        assert!(!matches("eau", "sel"));
+        assert!(!matches("chat", "chut"));
+        assert!(!matches("", "sel"));
+        assert!(!matches("monomi", "Monokuma"));
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serenity::model::id::UserId;
+
+use crate::flashcards::Card;
+
+pub(crate) mod fuzzy;
+pub(crate) mod trivia;
+
+/// Most questions in a quiz.
+pub const MAX_QUESTIONS: usize = 20;
+pub const DEFAULT_QUESTIONS: usize = 5;
+/// Most choices of a multiple choice question, there is one reaction per choice.
+pub const MAX_CHOICES: usize = 4;
+
+/// A question, answered in a message or, when it has choices, with a reaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    pub prompt: String,
+    /// The accepted answers, the first one being shown once the question is over.
+    pub answers: Vec<String>,
+    /// The choices of a multiple choice question, empty for an open one.
+    pub choices: Vec<String>,
+}
+
+impl Question {
+    pub fn open(prompt: &str, answers: Vec<String>) -> Self {
+        Self { prompt: prompt.to_string(), answers, choices: Vec::new() }
+    }
+
+    /// `choices` hold the answer, which ends up at a random place.
+    pub fn multiple_choice<R: Rng>(prompt: &str, answer: &str, mut choices: Vec<String>, rng: &mut R) -> Self {
+        if !choices.iter().any(|choice| choice == answer) {
+            choices.push(answer.to_string());
+        }
+        choices.shuffle(rng);
+        Self { prompt: prompt.to_string(), answers: vec![answer.to_string()], choices }
+    }
+
+    pub fn is_multiple_choice(&self) -> bool {
+        !self.choices.is_empty()
+    }
+
+    pub fn answer(&self) -> &str {
+        self.answers.first().map(String::as_str).unwrap_or_default()
+    }
+
+    /// Whether a message answers an open question.
+    pub fn is_answered_by(&self, guess: &str) -> bool {
+        self.answers.iter().any(|answer| fuzzy::matches(guess, answer))
+    }
+
+    /// The index of the right choice.
+    pub fn right_choice(&self) -> Option<usize> {
+        self.choices.iter().position(|choice| choice == self.answer())
+    }
+}
+
+/// Questions on `count` random cards, asking for their back. Multiple choice questions take
+/// their wrong choices among the backs of the other cards.
+pub fn questions_from_cards<R: Rng>(cards: &[Card], count: usize, multiple_choice: bool, rng: &mut R) -> Vec<Question> {
+    let mut backs: Vec<&str> = cards.iter().map(|card| card.back.as_str()).collect();
+    backs.sort_unstable();
+    backs.dedup();
+
+    cards.choose_multiple(rng, count.min(cards.len())).map(|card| {
+        match multiple_choice {
+            false => Question::open(&card.front, vec![card.back.clone()]),
+            true => {
+                let wrong: Vec<String> = backs.iter()
+                    .filter(|back| **back != card.back)
+                    .copied()
+                    .collect::<Vec<&str>>()
+                    .choose_multiple(rng, MAX_CHOICES - 1)
+                    .map(|back| back.to_string())
+                    .collect();
+                Question::multiple_choice(&card.front, &card.back, wrong, rng)
+            }
+        }
+    }).collect()
+}
+
+/// Points of the players, ties going to whoever scored first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scoreboard {
+    points: HashMap<UserId, u32>,
+    /// Who found each question asked.
+    rounds: Vec<Vec<UserId>>,
+    /// When each player last scored, to break ties.
+    last_scored: HashMap<UserId, usize>,
+    scores: usize,
+}
+
+impl Scoreboard {
+    pub fn score(&mut self, user: UserId) {
+        *self.points.entry(user).or_default() += 1;
+        self.scores += 1;
+        self.last_scored.insert(user, self.scores);
+    }
+
+    /// Scores the players who found the last question asked.
+    pub fn round(&mut self, winners: &[UserId]) {
+        for winner in winners {
+            self.score(*winner);
+        }
+        self.rounds.push(winners.to_vec());
+    }
+
+    pub fn rounds(&self) -> &[Vec<UserId>] {
+        &self.rounds
+    }
+
+    pub fn points(&self, user: UserId) -> u32 {
+        self.points.get(&user).copied().unwrap_or_default()
+    }
+
+    /// From the best player to the worst.
+    pub fn ranking(&self) -> Vec<(UserId, u32)> {
+        let mut ranking: Vec<(UserId, u32)> = self.points.iter().map(|(user, points)| (*user, *points)).collect();
+        ranking.sort_by_key(|(user, points)| (std::cmp::Reverse(*points), self.last_scored[user]));
+        ranking
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serenity::model::id::UserId;
+
+    use crate::flashcards::Card;
+    use crate::quiz::{Question, questions_from_cards, Scoreboard};
+
+    fn card(id: i64, front: &str, back: &str) -> Card {
+        Card { id, deck_id: 1, front: front.to_string(), back: back.to_string() }
+    }
+
+    #[test]
+    fn test_questions_from_cards() {
+        let cards = vec![card(1, "H2O", "Eau"), card(2, "NaCl", "Sel"), card(3, "O2", "Dioxygène"), card(4, "CO2", "Dioxyde de carbone"), card(5, "H2", "Dihydrogène")];
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let open = questions_from_cards(&cards, 10, false, &mut rng);
+        assert_eq!(open.len(), 5);
+        assert!(open.iter().all(|question| !question.is_multiple_choice()));
+
+        let choices = questions_from_cards(&cards, 3, true, &mut rng);
+        assert_eq!(choices.len(), 3);
+        for question in choices {
+            assert_eq!(question.choices.len(), 4);
+            let right = question.right_choice().unwrap();
+            assert_eq!(question.choices[right], question.answer());
+        }
+    }
+
+    #[test]
+    fn test_answers() {
+        let question = Question::open("Qui est le directeur ?", vec!["Monokuma".to_string(), "Junko Enoshima".to_string()]);
+        assert!(question.is_answered_by("monokumma"));
+        assert!(question.is_answered_by("junko enoshima"));
+        assert!(!question.is_answered_by("Makoto"));
+        assert_eq!(question.answer(), "Monokuma");
+    }
+
+    #[test]
+    fn test_ranking() {
+        let mut scoreboard = Scoreboard::default();
+        scoreboard.score(UserId(1));
+        scoreboard.score(UserId(2));
+        scoreboard.score(UserId(2));
+        scoreboard.score(UserId(3));
+        scoreboard.score(UserId(1));
+
+        assert_eq!(scoreboard.ranking(), vec![(UserId(2), 2), (UserId(1), 2), (UserId(3), 1)]);
+        assert_eq!(scoreboard.points(UserId(4)), 0);
+
+        scoreboard.round(&[]);
+        scoreboard.round(&[UserId(3), UserId(4)]);
+        assert_eq!(scoreboard.rounds(), &[vec![], vec![UserId(3), UserId(4)]]);
+        assert_eq!(scoreboard.ranking()[2], (UserId(3), 2));
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use rusqlite::params;
+use serde::Deserialize;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::prelude::TypeMapKey;
+
+use crate::database::{Database, time_to_column};
+use crate::quiz::{MAX_CHOICES, Question};
+use crate::utils::data::{DataFileError, load_toml_dir};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS trivia_asked (
+    channel_id INTEGER NOT NULL,
+    question_id TEXT NOT NULL,
+    asked_at TEXT NOT NULL,
+    PRIMARY KEY (channel_id, question_id)
+);
+CREATE TABLE IF NOT EXISTS trivia_stats (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    game TEXT NOT NULL,
+    correct INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, user_id, game)
+);";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Game {
+    Dr1,
+    Dr2,
+    V3,
+}
+
+impl Game {
+    pub const ALL: [Game; 3] = [Game::Dr1, Game::Dr2, Game::V3];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Game::Dr1 => "dr1",
+            Game::Dr2 => "dr2",
+            Game::V3 => "v3",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|game| game.name() == name)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Game::Dr1 => "Danganronpa: Trigger Happy Havoc",
+            Game::Dr2 => "Danganronpa 2: Goodbye Despair",
+            Game::V3 => "Danganronpa V3: Killing Harmony",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "facile",
+            Difficulty::Medium => "moyen",
+            Difficulty::Hard => "difficile",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|difficulty| difficulty.name() == name)
+    }
+}
+
+/// How much of a game a question gives away, from nothing to its ending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub enum Spoiler {
+    #[default]
+    None,
+    Story,
+    Ending,
+}
+
+impl Spoiler {
+    pub const ALL: [Spoiler; 3] = [Spoiler::None, Spoiler::Story, Spoiler::Ending];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Spoiler::None => "aucun",
+            Spoiler::Story => "histoire",
+            Spoiler::Ending => "fin",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|spoiler| spoiler.name() == name)
+    }
+}
+
+/// A question of the bank, its id staying the same from one version of the files to the next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriviaQuestion {
+    pub id: String,
+    pub game: Game,
+    pub difficulty: Difficulty,
+    pub spoiler: Spoiler,
+    pub prompt: String,
+    pub answers: Vec<String>,
+    pub choices: Vec<String>,
+}
+
+impl TriviaQuestion {
+    /// The question to ask, multiple choice if the bank gives choices.
+    pub fn question<R: Rng>(&self, rng: &mut R) -> Question {
+        match self.choices.is_empty() {
+            true => Question::open(&self.prompt, self.answers.clone()),
+            false => Question::multiple_choice(&self.prompt, &self.answers[0], self.choices.clone(), rng),
+        }
+    }
+}
+
+/// Raw content of a question file, which holds the questions of one game.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriviaFile {
+    game: String,
+    #[serde(default)]
+    questions: Vec<QuestionEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuestionEntry {
+    id: String,
+    difficulty: String,
+    spoiler: Option<String>,
+    prompt: String,
+    answers: Vec<String>,
+    #[serde(default)]
+    choices: Vec<String>,
+}
+
+/// Parses a question file, `path` being only used in the errors.
+pub fn parse_questions(path: &str, content: &str) -> Result<Vec<TriviaQuestion>, DataFileError> {
+    let file: TriviaFile = toml::from_str(content).map_err(|err| DataFileError::Parse(path.to_string(), err))?;
+    let invalid = |reason: String| DataFileError::Invalid(path.to_string(), reason);
+    let game = Game::from_name(file.game.trim())
+        .ok_or_else(|| invalid(format!("{} is not one of dr1, dr2 or v3", file.game)))?;
+
+    file.questions.into_iter().map(|entry| {
+        let id = entry.id.trim().to_string();
+        let difficulty = Difficulty::from_name(entry.difficulty.trim()).ok_or_else(|| invalid(format!(
+            "{}: {} is not one of facile, moyen or difficile", id, entry.difficulty
+        )))?;
+        let spoiler = match entry.spoiler {
+            Some(name) => Spoiler::from_name(name.trim()).ok_or_else(|| invalid(format!(
+                "{}: {} is not one of aucun, histoire or fin", id, name
+            )))?,
+            None => Spoiler::None,
+        };
+        let answers: Vec<String> = entry.answers.iter()
+            .map(|answer| answer.trim().to_string())
+            .filter(|answer| !answer.is_empty())
+            .collect();
+
+        if id.is_empty() {
+            return Err(invalid("a question has no id".to_string()));
+        }
+        if entry.prompt.trim().is_empty() || answers.is_empty() {
+            return Err(invalid(format!("{} needs a prompt and an answer", id)));
+        }
+        if !entry.choices.is_empty() && (entry.choices.len() > MAX_CHOICES || !entry.choices.contains(&answers[0])) {
+            return Err(invalid(format!("{} needs at most {} choices, its first answer among them", id, MAX_CHOICES)));
+        }
+
+        Ok(TriviaQuestion {
+            id,
+            game,
+            difficulty,
+            spoiler,
+            prompt: entry.prompt.trim().to_string(),
+            answers,
+            choices: entry.choices,
+        })
+    }).collect()
+}
+
+/// Which questions of the bank to ask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TriviaFilter {
+    pub game: Option<Game>,
+    pub difficulty: Option<Difficulty>,
+    /// The most a question may give away.
+    pub spoiler: Spoiler,
+}
+
+impl TriviaFilter {
+    /// Reads words like `dr2 difficile fin 10` in any order, the number being how many
+    /// questions to ask. The error is the word that means nothing.
+    pub fn parse(text: &str) -> Result<(Self, Option<usize>), String> {
+        let mut filter = Self::default();
+        let mut count = None;
+        for word in text.split_whitespace() {
+            let lowercase = word.to_lowercase();
+            if let Some(game) = Game::from_name(&lowercase) {
+                filter.game = Some(game);
+            } else if let Some(difficulty) = Difficulty::from_name(&lowercase) {
+                filter.difficulty = Some(difficulty);
+            } else if let Some(spoiler) = Spoiler::from_name(&lowercase) {
+                filter.spoiler = spoiler;
+            } else if let Ok(number) = lowercase.parse::<usize>() {
+                count = Some(number);
+            } else {
+                return Err(word.to_string());
+            }
+        }
+        Ok((filter, count))
+    }
+
+    pub fn accepts(&self, question: &TriviaQuestion) -> bool {
+        self.game.is_none_or(|game| game == question.game)
+            && self.difficulty.is_none_or(|difficulty| difficulty == question.difficulty)
+            && question.spoiler <= self.spoiler
+    }
+}
+
+/// Every question read from the data files, shared by the commands.
+#[derive(Debug, Clone, Default)]
+pub struct TriviaBank {
+    questions: Arc<Vec<TriviaQuestion>>,
+}
+
+impl TypeMapKey for TriviaBank {
+    type Value = TriviaBank;
+}
+
+impl TriviaBank {
+    pub fn new(questions: Vec<TriviaQuestion>) -> Result<Self, DataFileError> {
+        let mut ids = HashSet::new();
+        if let Some(question) = questions.iter().find(|question| !ids.insert(question.id.as_str())) {
+            return Err(DataFileError::Invalid(
+                "the trivia bank".to_string(), format!("{} is the id of two questions", question.id)
+            ));
+        }
+        Ok(Self { questions: Arc::new(questions) })
+    }
+
+    /// Reads the `*.toml` files of `directory`, in the order of their names.
+    pub fn load<P: AsRef<Path>>(directory: P) -> Result<Self, DataFileError> {
+        Self::new(load_toml_dir(directory.as_ref(), parse_questions)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.questions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.questions.is_empty()
+    }
+
+    pub fn matching(&self, filter: &TriviaFilter) -> Vec<&TriviaQuestion> {
+        self.questions.iter().filter(|question| filter.accepts(question)).collect()
+    }
+}
+
+/// The questions asked in each channel, so that they do not come back too soon, and how
+/// many questions each member answered right, in the `trivia_*` tables.
+#[derive(Clone)]
+pub struct TriviaStore {
+    database: Database,
+}
+
+impl TypeMapKey for TriviaStore {
+    type Value = TriviaStore;
+}
+
+impl TriviaStore {
+    pub fn new(database: Database) -> rusqlite::Result<Self> {
+        database.with_connection(|connection| connection.execute_batch(SCHEMA))?;
+        Ok(Self { database })
+    }
+
+    /// Picks `count` of `candidates` that were not asked in `channel` yet and remembers them.
+    /// Once every candidate was asked, the channel starts over with the ones asked longest ago.
+    pub fn pick<R: Rng>(
+        &self,
+        channel: ChannelId,
+        candidates: &[&TriviaQuestion],
+        count: usize,
+        now: DateTime<Utc>,
+        rng: &mut R,
+    ) -> rusqlite::Result<Vec<TriviaQuestion>> {
+        self.database.with_connection(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT question_id FROM trivia_asked WHERE channel_id = ?1 ORDER BY asked_at"
+            )?;
+            let asked = statement.query_map(params![channel.0 as i64], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+
+            let mut fresh: Vec<&TriviaQuestion> = candidates.iter().copied()
+                .filter(|question| !asked.contains(&question.id))
+                .collect();
+            fresh.shuffle(rng);
+            let mut picked: Vec<&TriviaQuestion> = fresh.into_iter().take(count).collect();
+            for id in &asked {
+                if picked.len() >= count {
+                    break;
+                }
+                if let Some(question) = candidates.iter().find(|question| &question.id == id) {
+                    picked.push(question);
+                }
+            }
+
+            let asked_at = time_to_column(now);
+            let transaction = connection.unchecked_transaction()?;
+            for question in &picked {
+                transaction.execute(
+                    "INSERT OR REPLACE INTO trivia_asked (channel_id, question_id, asked_at) VALUES (?1, ?2, ?3)",
+                    params![channel.0 as i64, question.id, asked_at],
+                )?;
+            }
+            transaction.commit()?;
+            Ok(picked.into_iter().cloned().collect())
+        })
+    }
+
+    /// Counts the right answers of a quiz, `rounds` giving who found each question.
+    pub fn record(&self, guild: GuildId, rounds: &[(Game, Vec<UserId>)]) -> rusqlite::Result<()> {
+        self.database.with_connection(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            for (game, winners) in rounds {
+                for user in winners {
+                    transaction.execute(
+                        "INSERT INTO trivia_stats (guild_id, user_id, game, correct) VALUES (?1, ?2, ?3, 1)
+                         ON CONFLICT (guild_id, user_id, game) DO UPDATE SET correct = correct + 1",
+                        params![guild.0 as i64, user.0 as i64, game.name()],
+                    )?;
+                }
+            }
+            transaction.commit()
+        })
+    }
+
+    /// The right answers of `user` in `guild` for each game they answered.
+    pub fn stats(&self, guild: GuildId, user: UserId) -> rusqlite::Result<Vec<(Game, u32)>> {
+        self.database.with_connection(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT game, correct FROM trivia_stats WHERE guild_id = ?1 AND user_id = ?2"
+            )?;
+            let mut stats = statement.query_map(params![guild.0 as i64, user.0 as i64], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
+            })?
+                .filter_map(|row| match row {
+                    Ok((name, correct)) => Game::from_name(&name).map(|game| Ok((game, correct))),
+                    Err(err) => Some(Err(err)),
+                })
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            stats.sort();
+            Ok(stats)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serenity::model::id::{ChannelId, GuildId, UserId};
+
+    use crate::database::Database;
+    use crate::quiz::trivia::{Difficulty, Game, parse_questions, Spoiler, TriviaBank, TriviaFilter, TriviaStore};
+    use crate::utils::data::DataFileError;
+
+    const QUESTIONS: &str = r#"
+        game = "dr1"
+
+        [[questions]]
+        id = "dr1-directeur"
+        difficulty = "facile"
+        prompt = "Qui est le directeur ?"
+        answers = ["Monokuma"]
+        choices = ["Monomi", "Monokuma", "Monotaro"]
+
+        [[questions]]
+        id = "dr1-cerveau"
+        difficulty = "difficile"
+        spoiler = "fin"
+        prompt = "Qui est derrière Monokuma ?"
+        answers = ["Junko Enoshima", "Junko"]
+    "#;
+
+    #[test]
+    fn test_parse_questions() {
+        let questions = parse_questions("dr1.toml", QUESTIONS).unwrap();
+        assert_eq!(questions.len(), 2);
+        assert_eq!(questions[0].game, Game::Dr1);
+        assert_eq!(questions[0].spoiler, Spoiler::None);
+        assert_eq!(questions[1].difficulty, Difficulty::Hard);
+        assert_eq!(questions[1].spoiler, Spoiler::Ending);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(questions[0].question(&mut rng).choices.len(), 3);
+        assert!(!questions[1].question(&mut rng).is_multiple_choice());
+
+        assert!(matches!(parse_questions("a.toml", "game = \"dr4\""), Err(DataFileError::Invalid(_, _))));
+        assert!(matches!(
+            parse_questions("a.toml", &QUESTIONS.replace("\"Monomi\", \"Monokuma\"", "\"Monomi\"")),
+            Err(DataFileError::Invalid(_, _))
+        ));
+        assert!(matches!(parse_questions("a.toml", "game = "), Err(DataFileError::Parse(_, _))));
+
+        let mut twice = questions.clone();
+        twice.push(questions[0].clone());
+        assert!(TriviaBank::new(twice).is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let bank = TriviaBank::new(parse_questions("dr1.toml", QUESTIONS).unwrap()).unwrap();
+
+        let (filter, count) = TriviaFilter::parse("").unwrap();
+        assert_eq!((filter, count), (TriviaFilter::default(), None));
+        assert_eq!(bank.matching(&filter).len(), 1);
+
+        let (filter, count) = TriviaFilter::parse("DR1 fin 10").unwrap();
+        assert_eq!(filter.game, Some(Game::Dr1));
+        assert_eq!(count, Some(10));
+        assert_eq!(bank.matching(&filter).len(), 2);
+
+        let (filter, _) = TriviaFilter::parse("v3 fin").unwrap();
+        assert!(bank.matching(&filter).is_empty());
+        assert_eq!(TriviaFilter::parse("dr1 chapitre"), Err("chapitre".to_string()));
+    }
+
+    #[test]
+    fn test_data_files() {
+        let bank = TriviaBank::load("data/trivia").unwrap();
+        for game in Game::ALL {
+            let filter = TriviaFilter { game: Some(game), ..Default::default() };
+            assert!(!bank.matching(&filter).is_empty(), "no question without spoilers for {}", game.name());
+        }
+    }
+
+    #[test]
+    fn test_store() {
+        let store = TriviaStore::new(Database::in_memory().unwrap()).unwrap();
+        let questions = parse_questions("dr1.toml", QUESTIONS).unwrap();
+        let candidates: Vec<_> = questions.iter().collect();
+        let mut rng = StdRng::seed_from_u64(1);
+        let (channel, now) = (ChannelId(1), Utc.ymd(2022, 10, 1).and_hms(20, 0, 0));
+
+        let first = store.pick(channel, &candidates, 1, now, &mut rng).unwrap();
+        let second = store.pick(channel, &candidates, 1, now + chrono::Duration::minutes(1), &mut rng).unwrap();
+        assert_ne!(first, second);
+        // Every question was asked, the oldest one comes back.
+        let third = store.pick(channel, &candidates, 1, now + chrono::Duration::minutes(2), &mut rng).unwrap();
+        assert_eq!(third, first);
+        assert_eq!(store.pick(ChannelId(2), &candidates, 5, now, &mut rng).unwrap().len(), 2);
+
+        let (guild, makoto, kyoko) = (GuildId(1), UserId(1), UserId(2));
+        store.record(guild, &[(Game::Dr1, vec![makoto]), (Game::Dr2, vec![makoto, kyoko]), (Game::Dr1, vec![makoto])]).unwrap();
+        assert_eq!(store.stats(guild, makoto).unwrap(), vec![(Game::Dr1, 2), (Game::Dr2, 1)]);
+        assert_eq!(store.stats(guild, kyoko).unwrap(), vec![(Game::Dr2, 1)]);
+        assert!(store.stats(GuildId(2), makoto).unwrap().is_empty());
+    }
+}
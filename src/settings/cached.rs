@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use serenity::model::id::GuildId;
+
+use crate::settings::{GuildSettings, SettingsError, SettingsStore};
+
+/// Keeps what another store read in memory, since the settings are read on every message
+/// and only change through the settings commands, which save them here.
+pub struct CachedSettingsStore<S> {
+    store: S,
+    /// `None` for the guilds that have no settings stored.
+    cache: RwLock<HashMap<GuildId, Option<GuildSettings>>>,
+}
+
+impl<S: SettingsStore> CachedSettingsStore<S> {
+    pub fn new(store: S) -> Self {
+        Self { store, cache: RwLock::new(HashMap::new()) }
+    }
+}
+
+impl<S: SettingsStore> SettingsStore for CachedSettingsStore<S> {
+    fn get(&self, guild_id: GuildId) -> Result<Option<GuildSettings>, SettingsError> {
+        if let Some(settings) = self.cache.read().expect("Settings cache lock poisoned").get(&guild_id) {
+            return Ok(settings.clone());
+        }
+        // Read under the lock, a save in between would otherwise be overwritten by what it replaced.
+        let mut cache = self.cache.write().expect("Settings cache lock poisoned");
+        if let Some(settings) = cache.get(&guild_id) {
+            return Ok(settings.clone());
+        }
+        let settings = self.store.get(guild_id)?;
+        cache.insert(guild_id, settings.clone());
+        Ok(settings)
+    }
+
+    fn save(&self, settings: &GuildSettings) -> Result<(), SettingsError> {
+        let mut cache = self.cache.write().expect("Settings cache lock poisoned");
+        // Forgotten on failure, the store may or may not hold the new settings.
+        match self.store.save(settings) {
+            Ok(()) => cache.insert(settings.guild_id, Some(settings.clone())),
+            Err(err) => {
+                cache.remove(&settings.guild_id);
+                return Err(err);
+            }
+        };
+        Ok(())
+    }
+
+    fn remove(&self, guild_id: GuildId) -> Result<(), SettingsError> {
+        let mut cache = self.cache.write().expect("Settings cache lock poisoned");
+        match self.store.remove(guild_id) {
+            Ok(()) => cache.insert(guild_id, None),
+            Err(err) => {
+                cache.remove(&guild_id);
+                return Err(err);
+            }
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use serenity::model::id::GuildId;
+
+use crate::settings::{GuildSettings, SettingsError, SettingsStore};
+
+/// Settings kept in memory only, lost when the bot stops.
+#[derive(Default)]
+pub struct MemorySettingsStore {
+    settings: RwLock<HashMap<GuildId, GuildSettings>>,
+}
+
+impl SettingsStore for MemorySettingsStore {
+    fn get(&self, guild_id: GuildId) -> Result<Option<GuildSettings>, SettingsError> {
+        Ok(self.settings.read().expect("Settings lock poisoned").get(&guild_id).cloned())
+    }
+
+    fn save(&self, settings: &GuildSettings) -> Result<(), SettingsError> {
+        self.settings.write().expect("Settings lock poisoned")
+            .insert(settings.guild_id, settings.clone());
+        Ok(())
+    }
+
+    fn remove(&self, guild_id: GuildId) -> Result<(), SettingsError> {
+        self.settings.write().expect("Settings lock poisoned").remove(&guild_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::prelude::TypeMapKey;
+use serenity::utils::Colour;
+
+use crate::database::Database;
+use crate::utils::time::Schedule;
+
+pub(crate) mod cached;
+pub(crate) mod memory;
+pub(crate) mod sqlite;
+
+pub const DEFAULT_LANGUAGE: &str = "fr";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Feature {
+    Welcome,
+    MonokumaAnnouncements,
+    /// Locks the night channels between the evening and the morning announcements.
+    NightMode,
+    /// Has Monokuma scold the members talking at night and name the night owls in the morning.
+    NightWatch,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 4] = [Feature::Welcome, Feature::MonokumaAnnouncements, Feature::NightMode, Feature::NightWatch];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::Welcome => "welcome",
+            Feature::MonokumaAnnouncements => "monokuma_announcements",
+            Feature::NightMode => "night_mode",
+            Feature::NightWatch => "night_watch",
+        }
+    }
+
+    /// Whether a guild the bot has never seen has the feature, those changing the
+    /// permissions of the guild have to be asked for.
+    pub fn enabled_by_default(&self) -> bool {
+        !matches!(self, Feature::NightMode | Feature::NightWatch)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|feature| feature.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuildSettings {
+    pub guild_id: GuildId,
+    pub welcome_channel: Option<ChannelId>,
+    pub announcement_channel: Option<ChannelId>,
+    pub language: String,
+    pub features: Vec<Feature>,
+    pub color: Option<Colour>,
+    pub morning_announcement: Schedule,
+    pub evening_announcement: Schedule,
+    /// Channels where nobody can talk during the night, when night mode is enabled.
+    pub night_channels: Vec<ChannelId>,
+    /// Roles that can still talk in the night channels, without being scolded.
+    pub night_exempt_roles: Vec<RoleId>,
+}
+
+impl GuildSettings {
+    /// Settings of a guild the bot has never seen: every feature but the night mode and the
+    /// night watch is enabled, and messages go to the guild's system channel.
+    pub fn new(guild_id: GuildId) -> Self {
+        Self {
+            guild_id,
+            welcome_channel: None,
+            announcement_channel: None,
+            language: DEFAULT_LANGUAGE.to_string(),
+            features: Feature::ALL.iter().copied().filter(Feature::enabled_by_default).collect(),
+            color: None,
+            morning_announcement: Schedule::daily(7, 0),
+            evening_announcement: Schedule::daily(22, 0),
+            night_channels: Vec::new(),
+            night_exempt_roles: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Database(rusqlite::Error),
+    Corrupted(GuildId, String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Database(err) => write!(f, "database error: {}", err),
+            SettingsError::Corrupted(guild_id, reason) => {
+                write!(f, "corrupted settings for guild {}: {}", guild_id, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<rusqlite::Error> for SettingsError {
+    fn from(err: rusqlite::Error) -> Self { SettingsError::Database(err) }
+}
+
+pub trait SettingsStore: Send + Sync {
+    fn get(&self, guild_id: GuildId) -> Result<Option<GuildSettings>, SettingsError>;
+    fn save(&self, settings: &GuildSettings) -> Result<(), SettingsError>;
+    fn remove(&self, guild_id: GuildId) -> Result<(), SettingsError>;
+}
+
+/// Handle on the settings backend, stored in the client's `TypeMap`.
+#[derive(Clone)]
+pub struct GuildSettingsStore(Arc<dyn SettingsStore>);
+
+impl TypeMapKey for GuildSettingsStore {
+    type Value = GuildSettingsStore;
+}
+
+impl GuildSettingsStore {
+    pub fn in_memory() -> Self {
+        Self(Arc::new(memory::MemorySettingsStore::default()))
+    }
+
+    /// Settings stored in the database, and cached once read.
+    pub fn sqlite(database: Database) -> Result<Self, SettingsError> {
+        let store = sqlite::SqliteSettingsStore::new(database)?;
+        Ok(Self(Arc::new(cached::CachedSettingsStore::new(store))))
+    }
+
+    /// Returns the stored settings of the guild, or its defaults if it has none yet.
+    pub fn get_or_default(&self, guild_id: GuildId) -> Result<GuildSettings, SettingsError> {
+        Ok(self.get(guild_id)?.unwrap_or_else(|| GuildSettings::new(guild_id)))
+    }
+}
+
+impl Deref for GuildSettingsStore {
+    type Target = dyn SettingsStore;
+
+    fn deref(&self) -> &Self::Target { self.0.as_ref() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, mpsc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use serenity::model::id::{ChannelId, GuildId, RoleId};
+    use serenity::utils::Colour;
+
+    use crate::database::Database;
+    use crate::settings::{Feature, GuildSettings, GuildSettingsStore, SettingsError, SettingsStore};
+    use crate::settings::cached::CachedSettingsStore;
+    use crate::settings::memory::MemorySettingsStore;
+    use crate::settings::sqlite::SqliteSettingsStore;
+    use crate::utils::time::Schedule;
+
+    fn check_store(store: GuildSettingsStore) {
+        let guild_id = GuildId(1);
+        assert_eq!(store.get(guild_id).unwrap(), None);
+        assert_eq!(store.get_or_default(guild_id).unwrap(), GuildSettings::new(guild_id));
+
+        let mut settings = GuildSettings::new(guild_id);
+        settings.welcome_channel = Some(ChannelId(2));
+        settings.announcement_channel = Some(ChannelId(3));
+        settings.language = "en".to_string();
+        settings.features = vec![Feature::MonokumaAnnouncements];
+        settings.color = Some(Colour::from_rgb(1, 2, 3));
+        settings.morning_announcement = Schedule::parse("30 8 * * mon-fri").unwrap();
+        settings.evening_announcement = Schedule::daily(23, 0);
+        settings.night_channels = vec![ChannelId(5), ChannelId(6)];
+        settings.night_exempt_roles = vec![RoleId(7)];
+        store.save(&settings).unwrap();
+
+        assert_eq!(store.get(guild_id).unwrap(), Some(settings.clone()));
+        assert_eq!(store.get(GuildId(4)).unwrap(), None);
+
+        settings.features.clear();
+        store.save(&settings).unwrap();
+        assert!(!store.get_or_default(guild_id).unwrap().is_enabled(Feature::Welcome));
+
+        store.remove(guild_id).unwrap();
+        assert_eq!(store.get(guild_id).unwrap(), None);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        check_store(GuildSettingsStore::in_memory());
+    }
+
+    #[test]
+    fn test_sqlite_store() {
+        check_store(GuildSettingsStore(Arc::new(SqliteSettingsStore::new(Database::in_memory().unwrap()).unwrap())));
+    }
+
+    #[test]
+    fn test_sqlite_store_migration() {
+        let database = Database::in_memory().unwrap();
+        database.with_connection(|connection| connection.execute_batch("
+            CREATE TABLE guild_settings (
+                guild_id INTEGER PRIMARY KEY,
+                welcome_channel INTEGER,
+                announcement_channel INTEGER,
+                language TEXT NOT NULL,
+                features TEXT NOT NULL,
+                color INTEGER
+            );
+            INSERT INTO guild_settings (guild_id, language, features) VALUES (1, 'fr', 'welcome');"
+        )).unwrap();
+
+        let store = SqliteSettingsStore::new(database).unwrap();
+        let mut settings = GuildSettings::new(GuildId(1));
+        settings.features = vec![Feature::Welcome];
+        assert_eq!(store.get(GuildId(1)).unwrap(), Some(settings));
+    }
+
+    #[test]
+    fn test_cached_store() {
+        let database = Database::in_memory().unwrap();
+        let cached = GuildSettingsStore::sqlite(database.clone()).unwrap();
+        check_store(cached.clone());
+
+        // Reads are served from the cache, saves go through it.
+        let stored = GuildSettingsStore(Arc::new(SqliteSettingsStore::new(database).unwrap()));
+        let mut settings = GuildSettings::new(GuildId(1));
+        cached.save(&settings).unwrap();
+        settings.language = "en".to_string();
+        stored.save(&settings).unwrap();
+        assert_eq!(cached.get(GuildId(1)).unwrap().unwrap().language, "fr");
+
+        settings.language = "de".to_string();
+        cached.save(&settings).unwrap();
+        assert_eq!(cached.get(GuildId(1)).unwrap(), Some(settings.clone()));
+        assert_eq!(stored.get(GuildId(1)).unwrap(), Some(settings));
+    }
+
+    /// Pauses the reads until told to go on, after saying they started.
+    struct PausedReads {
+        store: MemorySettingsStore,
+        reading: Mutex<mpsc::Sender<()>>,
+        resume: Mutex<mpsc::Receiver<()>>,
+    }
+
+    impl SettingsStore for PausedReads {
+        fn get(&self, guild_id: GuildId) -> Result<Option<GuildSettings>, SettingsError> {
+            let settings = self.store.get(guild_id);
+            self.reading.lock().unwrap().send(()).unwrap();
+            self.resume.lock().unwrap().recv().unwrap();
+            settings
+        }
+
+        fn save(&self, settings: &GuildSettings) -> Result<(), SettingsError> {
+            self.store.save(settings)
+        }
+
+        fn remove(&self, guild_id: GuildId) -> Result<(), SettingsError> {
+            self.store.remove(guild_id)
+        }
+    }
+
+    #[test]
+    fn test_cached_store_read_during_save() {
+        let (reading, started) = mpsc::channel();
+        let (resume, resumed) = mpsc::channel();
+        let store = MemorySettingsStore::default();
+        store.save(&GuildSettings::new(GuildId(1))).unwrap();
+        let cached = Arc::new(CachedSettingsStore::new(PausedReads {
+            store,
+            reading: Mutex::new(reading),
+            resume: Mutex::new(resumed),
+        }));
+
+        let reader = thread::spawn({
+            let cached = cached.clone();
+            move || cached.get(GuildId(1)).unwrap()
+        });
+        started.recv().unwrap();
+
+        let mut settings = GuildSettings::new(GuildId(1));
+        settings.features.retain(|feature| *feature != Feature::Welcome);
+        let saver = thread::spawn({
+            let (cached, settings) = (cached.clone(), settings.clone());
+            move || cached.save(&settings).unwrap()
+        });
+        thread::sleep(Duration::from_millis(50));
+        resume.send(()).unwrap();
+        reader.join().unwrap();
+        saver.join().unwrap();
+
+        assert_eq!(cached.get(GuildId(1)).unwrap(), Some(settings));
+    }
+
+    #[test]
+    fn test_feature_names() {
+        for feature in Feature::ALL {
+            assert_eq!(Feature::from_name(feature.name()), Some(feature));
+        }
+        assert_eq!(Feature::from_name("flashcards_v0"), None);
+    }
+
+    #[test]
+    fn test_default_features() {
+        let settings = GuildSettings::new(GuildId(1));
+        assert!(settings.is_enabled(Feature::Welcome));
+        assert!(!settings.is_enabled(Feature::NightMode));
+    }
+}
@@ -0,0 +1,150 @@
+use rusqlite::{OptionalExtension, params};
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::utils::Colour;
+
+use crate::database::{add_column_if_missing, Database};
+use crate::settings::{Feature, GuildSettings, SettingsError, SettingsStore};
+use crate::utils::time::Schedule;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS guild_settings (
+    guild_id INTEGER PRIMARY KEY,
+    welcome_channel INTEGER,
+    announcement_channel INTEGER,
+    language TEXT NOT NULL,
+    features TEXT NOT NULL,
+    color INTEGER
+);";
+
+/// Settings persisted in the `guild_settings` table. Discord ids are stored as their
+/// two's complement `i64` since SQLite has no unsigned integers.
+pub struct SqliteSettingsStore {
+    database: Database,
+}
+
+impl SqliteSettingsStore {
+    pub fn new(database: Database) -> Result<Self, SettingsError> {
+        database.with_connection(|connection| {
+            connection.execute_batch(SCHEMA)?;
+            add_column_if_missing(connection, "guild_settings", "morning_announcement_schedule", "TEXT NOT NULL DEFAULT '0 7 * * *'")?;
+            add_column_if_missing(connection, "guild_settings", "evening_announcement_schedule", "TEXT NOT NULL DEFAULT '0 22 * * *'")?;
+            add_column_if_missing(connection, "guild_settings", "night_channels", "TEXT NOT NULL DEFAULT ''")?;
+            add_column_if_missing(connection, "guild_settings", "night_exempt_roles", "TEXT NOT NULL DEFAULT ''")
+        })?;
+        Ok(Self { database })
+    }
+}
+
+fn features_to_column(features: &[Feature]) -> String {
+    features.iter().map(|feature| feature.name()).collect::<Vec<_>>().join(",")
+}
+
+fn features_from_column(guild_id: GuildId, column: &str) -> Result<Vec<Feature>, SettingsError> {
+    column.split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| Feature::from_name(name).ok_or_else(|| {
+            SettingsError::Corrupted(guild_id, format!("unknown feature {}", name))
+        }))
+        .collect()
+}
+
+fn ids_to_column(ids: impl Iterator<Item = u64>) -> String {
+    ids.map(|id| id.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn ids_from_column(guild_id: GuildId, column: &str) -> Result<Vec<u64>, SettingsError> {
+    column.split(',')
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse().map_err(|_| SettingsError::Corrupted(guild_id, format!("invalid id {}", id))))
+        .collect()
+}
+
+fn schedule_from_column(guild_id: GuildId, expression: &str) -> Result<Schedule, SettingsError> {
+    Schedule::parse(expression).map_err(|err| {
+        SettingsError::Corrupted(guild_id, format!("invalid schedule {}: {}", expression, err))
+    })
+}
+
+impl SettingsStore for SqliteSettingsStore {
+    fn get(&self, guild_id: GuildId) -> Result<Option<GuildSettings>, SettingsError> {
+        let row = self.database.with_connection(|connection| {
+            connection.query_row(
+                "SELECT welcome_channel, announcement_channel, language, features, color,
+                 morning_announcement_schedule, evening_announcement_schedule,
+                 night_channels, night_exempt_roles
+                 FROM guild_settings WHERE guild_id = ?1",
+                params![guild_id.0 as i64],
+                |row| Ok((
+                    row.get::<_, Option<i64>>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<u32>>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, String>(6)?,
+                    row.get::<_, String>(7)?,
+                    row.get::<_, String>(8)?,
+                )),
+            ).optional()
+        })?;
+
+        match row {
+            None => Ok(None),
+            Some((
+                welcome_channel,
+                announcement_channel,
+                language,
+                features,
+                color,
+                morning_schedule,
+                evening_schedule,
+                night_channels,
+                night_exempt_roles,
+            )) => {
+                Ok(Some(GuildSettings {
+                    guild_id,
+                    welcome_channel: welcome_channel.map(|id| ChannelId(id as u64)),
+                    announcement_channel: announcement_channel.map(|id| ChannelId(id as u64)),
+                    language,
+                    features: features_from_column(guild_id, &features)?,
+                    color: color.map(Colour::new),
+                    morning_announcement: schedule_from_column(guild_id, &morning_schedule)?,
+                    evening_announcement: schedule_from_column(guild_id, &evening_schedule)?,
+                    night_channels: ids_from_column(guild_id, &night_channels)?.into_iter().map(ChannelId).collect(),
+                    night_exempt_roles: ids_from_column(guild_id, &night_exempt_roles)?.into_iter().map(RoleId).collect(),
+                }))
+            }
+        }
+    }
+
+    fn save(&self, settings: &GuildSettings) -> Result<(), SettingsError> {
+        self.database.with_connection(|connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO guild_settings
+                 (guild_id, welcome_channel, announcement_channel, language, features, color,
+                  morning_announcement_schedule, evening_announcement_schedule, night_channels, night_exempt_roles)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    settings.guild_id.0 as i64,
+                    settings.welcome_channel.map(|id| id.0 as i64),
+                    settings.announcement_channel.map(|id| id.0 as i64),
+                    settings.language,
+                    features_to_column(&settings.features),
+                    settings.color.map(|color| color.0),
+                    settings.morning_announcement.expression(),
+                    settings.evening_announcement.expression(),
+                    ids_to_column(settings.night_channels.iter().map(|channel| channel.0)),
+                    ids_to_column(settings.night_exempt_roles.iter().map(|role| role.0)),
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn remove(&self, guild_id: GuildId) -> Result<(), SettingsError> {
+        self.database.with_connection(|connection| {
+            connection.execute("DELETE FROM guild_settings WHERE guild_id = ?1", params![guild_id.0 as i64])
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::client::bridge::gateway::ShardManager;
+use serenity::prelude::{Mutex, RwLock, TypeMap};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::bot::DiscordBot;
+use crate::log::{MiraiLog, MiraiLogger};
+
+/// How long background tasks get to finish what they are sending.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Owns the token that stops the background tasks of the bot, and the handles of these
+/// tasks so that they can be awaited before disconnecting.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    token: CancellationToken,
+    tasks: Arc<std::sync::Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token cancelled on shutdown, that can also be cancelled on its own.
+    pub fn child_token(&self) -> CancellationToken {
+        self.token.child_token()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Keeps `handle` to await it on shutdown.
+    pub fn register(&self, handle: JoinHandle<()>) {
+        let mut tasks = self.tasks.lock().expect("Shutdown tasks lock poisoned");
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(handle);
+    }
+
+    /// Runs `task` in the background and awaits it on shutdown.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.register(tokio::spawn(task));
+    }
+
+    /// Cancels the token and waits for every registered task, the ones still running after
+    /// `timeout` are aborted.
+    pub async fn cancel_and_wait(&self, timeout: Duration) {
+        self.token.cancel();
+        let tasks: Vec<JoinHandle<()>> = self.tasks.lock().expect("Shutdown tasks lock poisoned")
+            .drain(..)
+            .collect();
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        for mut task in tasks {
+            if tokio::time::timeout_at(deadline, &mut task).await.is_err() {
+                MiraiLogger::warn("Background task did not stop in time, aborting it".to_string());
+                task.abort();
+            }
+        }
+    }
+}
+
+/// Resolves on SIGINT, or SIGTERM on unix systems.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => MiraiLogger::info("Received SIGINT".to_string()),
+                    _ = terminate.recv() => MiraiLogger::info("Received SIGTERM".to_string()),
+                }
+                return;
+            }
+            Err(err) => MiraiLogger::error(format!("Could not listen to SIGTERM: {}", err)),
+        }
+    }
+
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        MiraiLogger::error(format!("Could not listen to SIGINT: {}", err));
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Cancels the background tasks, waits for them and disconnects every shard, which makes
+/// `Client::start` return.
+pub async fn graceful_shutdown(data: Arc<RwLock<TypeMap>>, shard_manager: Arc<Mutex<ShardManager>>) {
+    MiraiLogger::info("Shutting down...".to_string());
+
+    let shutdown = data.read().await.get::<DiscordBot>().map(|bot| bot.shutdown.clone());
+    if let Some(shutdown) = shutdown {
+        shutdown.cancel_and_wait(SHUTDOWN_TIMEOUT).await;
+    }
+
+    shard_manager.lock().await.shutdown_all().await;
+    MiraiLogger::info("Bye!".to_string());
+    MiraiLogger::flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::shutdown::Shutdown;
+
+    #[tokio::test]
+    async fn test_cancel_and_wait() {
+        let shutdown = Shutdown::new();
+        let cancel = shutdown.child_token();
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        shutdown.spawn(async move {
+            cancel.cancelled().await;
+            sender.send(()).unwrap();
+        });
+        let stuck = Arc::new(());
+        shutdown.spawn({
+            let stuck = stuck.clone();
+            async move {
+                std::future::pending::<()>().await;
+                drop(stuck);
+            }
+        });
+
+        shutdown.cancel_and_wait(Duration::from_millis(50)).await;
+        assert!(shutdown.is_cancelled());
+        assert!(receiver.await.is_ok());
+        tokio::task::yield_now().await;
+        assert_eq!(Arc::strong_count(&stuck), 1);
+    }
+}
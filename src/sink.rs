@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+use serenity::utils::Colour;
+
+/// Discord refuses embeds with longer descriptions.
+pub const MAX_DESCRIPTION_LENGTH: usize = 4096;
+/// Discord refuses embeds with longer field values.
+pub const MAX_FIELD_LENGTH: usize = 1024;
+
+/// Cuts `text` to `max` characters at most, an ellipsis marking the cut.
+pub fn shorten(text: &str, max: usize) -> String {
+    match text.chars().count() > max {
+        true => format!("{}…", text.chars().take(max - 1).collect::<String>()),
+        false => text.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbedAuthor {
+    pub name: String,
+    pub icon_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
+}
+
+/// What the bot puts in an embed, kept as plain data so that tests can look at it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Embed {
+    pub author: Option<EmbedAuthor>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub color: Option<Colour>,
+    pub fields: Vec<EmbedField>,
+    pub image: Option<String>,
+    pub thumbnail: Option<String>,
+    pub footer: Option<String>,
+}
+
+impl Embed {
+    pub fn build(&self, embed: &mut CreateEmbed) {
+        if let Some(author) = &self.author {
+            embed.author(|a| {
+                a.name(&author.name);
+                if let Some(icon_url) = &author.icon_url {
+                    a.icon_url(icon_url);
+                }
+                a
+            });
+        }
+        if let Some(title) = &self.title {
+            embed.title(title);
+        }
+        if let Some(description) = &self.description {
+            embed.description(description);
+        }
+        if let Some(color) = self.color {
+            embed.color(color);
+        }
+        for field in &self.fields {
+            embed.field(&field.name, &field.value, field.inline);
+        }
+        if let Some(image) = &self.image {
+            embed.image(image);
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            embed.thumbnail(thumbnail);
+        }
+        if let Some(footer) = &self.footer {
+            embed.footer(|f| f.text(footer));
+        }
+    }
+}
+
+/// Where the features send their messages, Discord itself or a recorder in tests.
+#[async_trait]
+pub trait MessageSink: Send + Sync {
+    async fn send_embed(&self, channel: ChannelId, embed: Embed) -> serenity::Result<()>;
+}
+
+pub type SharedSink = Arc<dyn MessageSink>;
+
+/// Sends the messages to Discord through the REST API.
+pub struct SerenitySink {
+    http: Arc<Http>,
+}
+
+impl SerenitySink {
+    pub fn shared(http: Arc<Http>) -> SharedSink {
+        Arc::new(Self { http })
+    }
+}
+
+#[async_trait]
+impl MessageSink for SerenitySink {
+    async fn send_embed(&self, channel: ChannelId, embed: Embed) -> serenity::Result<()> {
+        send_embed(&self.http, channel, &embed).await?;
+        Ok(())
+    }
+}
+
+/// Sends `embed` alone in `channel`, for the features that need the message back.
+pub async fn send_embed(http: &Http, channel: ChannelId, embed: &Embed) -> serenity::Result<Message> {
+    channel.send_message(http, |msg| msg.embed(|e| {
+        embed.build(e);
+        e
+    })).await
+}
+
+/// Keeps the messages instead of sending them, for tests.
+#[cfg(test)]
+pub mod recording {
+    use std::sync::Mutex;
+
+    use serenity::async_trait;
+    use serenity::model::id::ChannelId;
+
+    use crate::sink::{Embed, MessageSink};
+
+    #[derive(Default)]
+    pub struct RecordingSink {
+        sent: Mutex<Vec<(ChannelId, Embed)>>,
+    }
+
+    impl RecordingSink {
+        /// Every embed sent so far with its channel, oldest first.
+        pub fn sent(&self) -> Vec<(ChannelId, Embed)> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl MessageSink for RecordingSink {
+        async fn send_embed(&self, channel: ChannelId, embed: Embed) -> serenity::Result<()> {
+            self.sent.lock().unwrap().push((channel, embed));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sink::shorten;
+
+    #[test]
+    fn test_shorten() {
+        assert_eq!(shorten("Eau", 3), "Eau");
+        assert_eq!(shorten("Chlorure de sodium", 9), "Chlorure…");
+        assert_eq!(shorten("Hôpital", 4), "Hôp…");
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Why a data file could not be used, with its path.
+#[derive(Debug)]
+pub enum DataFileError {
+    Io(String, std::io::Error),
+    Parse(String, toml::de::Error),
+    Invalid(String, String),
+}
+
+impl fmt::Display for DataFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataFileError::Io(path, err) => write!(f, "could not read {}: {}", path, err),
+            DataFileError::Parse(path, err) => write!(f, "could not parse {}: {}", path, err),
+            DataFileError::Invalid(path, reason) => write!(f, "invalid data in {}: {}", path, reason),
+        }
+    }
+}
+
+impl std::error::Error for DataFileError {}
+
+/// The `*.toml` files of `directory`, in the order of their names.
+pub fn toml_files(directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths = std::fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().is_some_and(|extension| extension == "toml"));
+    paths.sort();
+    Ok(paths)
+}
+
+/// Reads the `*.toml` files of `directory` in the order of their names, and gathers what
+/// `parse` makes of them. `parse` gets the path of a file, for its errors, and its content.
+pub fn load_toml_dir<T, F>(directory: &Path, parse: F) -> Result<Vec<T>, DataFileError>
+where
+    F: Fn(&str, &str) -> Result<Vec<T>, DataFileError>,
+{
+    let paths = toml_files(directory).map_err(|err| DataFileError::Io(directory.display().to_string(), err))?;
+    let mut items = Vec::new();
+    for path in paths {
+        let name = path.display().to_string();
+        let content = std::fs::read_to_string(&path).map_err(|err| DataFileError::Io(name.clone(), err))?;
+        items.extend(parse(&name, &content)?);
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::data::{DataFileError, load_toml_dir};
+
+    fn parse_words(path: &str, content: &str) -> Result<Vec<String>, DataFileError> {
+        match content.is_empty() {
+            true => Err(DataFileError::Invalid(path.to_string(), "empty file".to_string())),
+            false => Ok(content.split_whitespace().map(str::to_string).collect()),
+        }
+    }
+
+    #[test]
+    fn test_load_toml_dir() {
+        let directory = std::env::temp_dir().join(format!("mirai_data_{}", uuid::Uuid::new_v4()));
+        assert!(matches!(load_toml_dir(&directory, parse_words), Err(DataFileError::Io(..))));
+
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("b.toml"), "trois").unwrap();
+        std::fs::write(directory.join("a.toml"), "un deux").unwrap();
+        std::fs::write(directory.join("notes.txt"), "ignoré").unwrap();
+        assert_eq!(load_toml_dir(&directory, parse_words).unwrap(), vec!["un", "deux", "trois"]);
+
+        std::fs::write(directory.join("c.toml"), "").unwrap();
+        assert!(matches!(load_toml_dir(&directory, parse_words), Err(DataFileError::Invalid(path, _)) if path.ends_with("c.toml")));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,6 +1,11 @@
 use std::sync::Arc;
 use serenity::cache::Cache;
-use serenity::model::id::{ChannelId, GuildId};
+use serenity::client::Context;
+use serenity::model::channel::Channel;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+
+use crate::log::{MiraiLog, MiraiLogger};
+use crate::settings::{GuildSettings, GuildSettingsStore};
 
 pub fn find_guild_system_channel(cache: &Arc<Cache>, guild_id: GuildId) -> Option<ChannelId> {
     match cache.guild(guild_id) {
@@ -9,4 +14,46 @@ pub fn find_guild_system_channel(cache: &Arc<Cache>, guild_id: GuildId) -> Optio
             guild.system_channel_id
         }
     }
-}
\ No newline at end of file
+}
+
+/// Reads the settings of a guild from the store in `ctx.data`. `None` when the store cannot
+/// be read, as the defaults would turn back on what the guild disabled.
+pub async fn guild_settings(ctx: &Context, guild_id: GuildId) -> Option<GuildSettings> {
+    let store = ctx.data.read().await.get::<GuildSettingsStore>()
+        .expect("Did not find GuildSettingsStore").clone();
+
+    match store.get_or_default(guild_id) {
+        Ok(settings) => Some(settings),
+        Err(err) => {
+            MiraiLogger::error(format!("Could not read settings of guild {}: {}", guild_id, err));
+            None
+        }
+    }
+}
+
+/// Whether `channel` is a channel of `guild_id`, looked up in the cache or else asked to
+/// Discord. Unknown channels and those of other guilds are not.
+pub async fn is_guild_channel(ctx: &Context, guild_id: GuildId, channel: ChannelId) -> bool {
+    match channel.to_channel(ctx).await {
+        Ok(Channel::Guild(channel)) => channel.guild_id == guild_id,
+        Ok(_) => false,
+        Err(err) => {
+            MiraiLogger::debug(format!("Could not find channel {}: {}", channel, err));
+            false
+        }
+    }
+}
+
+/// Whether `role` is a role of `guild_id`, looked up in the cache or else asked to Discord.
+pub async fn is_guild_role(ctx: &Context, guild_id: GuildId, role: RoleId) -> bool {
+    if ctx.cache.role(guild_id, role).is_some() {
+        return true;
+    }
+    match guild_id.roles(ctx).await {
+        Ok(roles) => roles.contains_key(&role),
+        Err(err) => {
+            MiraiLogger::debug(format!("Could not list roles of guild {}: {}", guild_id, err));
+            false
+        }
+    }
+}
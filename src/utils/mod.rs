@@ -1,2 +1,3 @@
+pub(crate) mod data;
 pub(crate) mod guild_fcts;
 pub(crate) mod time;
\ No newline at end of file
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serenity::async_trait;
+
+/// Source of the current time, so that schedulers can run on a fake clock in tests.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Waits until `duration` has elapsed on this clock.
+    async fn sleep(&self, duration: std::time::Duration);
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The real time, with `tokio::time` timers.
+pub struct SystemClock;
+
+impl SystemClock {
+    pub fn shared() -> SharedClock {
+        Arc::new(SystemClock)
+    }
+}
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    async fn sleep(&self, duration: std::time::Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// A clock that only moves when told to, for tests.
+#[cfg(test)]
+pub mod fake {
+    use std::sync::Arc;
+
+    use chrono::{DateTime, Duration, Utc};
+    use serenity::async_trait;
+    use tokio::sync::watch;
+
+    use crate::utils::time::Clock;
+
+    /// Sleepers wake up as soon as the clock is moved past their deadline.
+    pub struct FakeClock {
+        now: watch::Sender<DateTime<Utc>>,
+    }
+
+    impl FakeClock {
+        pub fn new(now: DateTime<Utc>) -> Arc<Self> {
+            Arc::new(Self { now: watch::channel(now).0 })
+        }
+
+        pub fn set(&self, now: DateTime<Utc>) {
+            self.now.send_replace(now);
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            let now = self.now();
+            self.set(now + duration);
+        }
+    }
+
+    #[async_trait]
+    impl Clock for FakeClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.now.borrow()
+        }
+
+        async fn sleep(&self, duration: std::time::Duration) {
+            let deadline = match Duration::from_std(duration).ok().and_then(|d| self.now().checked_add_signed(d)) {
+                Some(deadline) => deadline,
+                None => return std::future::pending().await,
+            };
+
+            let mut now = self.now.subscribe();
+            while *now.borrow_and_update() < deadline {
+                if now.changed().await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
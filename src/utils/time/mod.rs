@@ -0,0 +1,107 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, TimeZone, Utc};
+use serenity::model::Timestamp;
+use tokio_util::sync::CancellationToken;
+use crate::bot::BOT_TIMEZONE;
+
+pub(crate) mod clock;
+pub(crate) mod schedule;
+
+pub use clock::{Clock, SharedClock, SystemClock};
+pub use schedule::Schedule;
+
+pub const FRENCH_TIME_FORMAT: &str = "%d/%m/%Y à %Hh%Mm%Ss";
+
+/// Longest nap taken while waiting for an occurrence, so that a change of the system clock
+/// (NTP correction, suspended host) delays a firing by at most this much.
+const MAX_SLEEP: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Resolves a wall-clock time in `timezone`. Ambiguous times (when the clock goes back) are
+/// the earliest of the two instants, times skipped when the clock goes forward are pushed
+/// back minute by minute until the end of the gap.
+fn resolve_local_time(naive: NaiveDateTime, timezone: chrono_tz::Tz) -> DateTime<Utc> {
+    let mut naive = naive;
+    loop {
+        match timezone.from_local_datetime(&naive) {
+            LocalResult::Single(time) => return time.with_timezone(&Utc),
+            LocalResult::Ambiguous(earliest, _) => return earliest.with_timezone(&Utc),
+            LocalResult::None => naive += Duration::minutes(1),
+        }
+    }
+}
+
+/// Sleeps until `target` on `clock`, returns false if `cancel` was cancelled first.
+pub async fn sleep_until(clock: &dyn Clock, target: DateTime<Utc>, cancel: &CancellationToken) -> bool {
+    loop {
+        let remaining = match (target - clock.now()).to_std() {
+            Ok(remaining) if !remaining.is_zero() => remaining,
+            _ => return !cancel.is_cancelled(),
+        };
+
+        tokio::select! {
+            _ = clock.sleep(remaining.min(MAX_SLEEP)) => {}
+            _ = cancel.cancelled() => return false,
+        }
+    }
+}
+
+pub fn local_timestamp_now() -> Timestamp {
+    let t = Timestamp::now().with_timezone(&BOT_TIMEZONE);
+    Timestamp::from(t)
+}
+
+/// Lets the other tasks run until `check` holds, panics if it still does not after a few seconds.
+#[cfg(test)]
+pub async fn wait_until(mut check: impl FnMut() -> bool) {
+    let wait = async {
+        while !check() {
+            tokio::task::yield_now().await;
+        }
+    };
+    if tokio::time::timeout(std::time::Duration::from_secs(5), wait).await.is_err() {
+        panic!("Timed out waiting for the condition");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Timelike, Utc};
+    use tokio_util::sync::CancellationToken;
+    use crate::utils::time::{Clock, FRENCH_TIME_FORMAT, local_timestamp_now, sleep_until, SystemClock};
+    use crate::utils::time::clock::fake::FakeClock;
+
+    #[tokio::test]
+    async fn test_local_timestamp() {
+        println!("{}", local_timestamp_now());
+        println!("{}", local_timestamp_now().format(FRENCH_TIME_FORMAT));
+        println!("HOUR:{}", local_timestamp_now().hour());
+    }
+
+    #[tokio::test]
+    async fn test_sleep_until() {
+        let cancel = CancellationToken::new();
+        assert!(sleep_until(&SystemClock, Utc::now() + chrono::Duration::milliseconds(20), &cancel).await);
+        assert!(sleep_until(&SystemClock, Utc::now() - chrono::Duration::seconds(1), &cancel).await);
+
+        cancel.cancel();
+        assert!(!sleep_until(&SystemClock, Utc::now() + chrono::Duration::hours(1), &cancel).await);
+    }
+
+    #[tokio::test]
+    async fn test_sleep_until_fake_clock() {
+        let clock = FakeClock::new(Utc.ymd(2022, 10, 1).and_hms(21, 0, 0));
+        let cancel = CancellationToken::new();
+        let target = Utc.ymd(2022, 10, 2).and_hms(5, 0, 0);
+
+        let sleeper = tokio::spawn({
+            let (clock, cancel) = (clock.clone(), cancel.clone());
+            async move { sleep_until(clock.as_ref(), target, &cancel).await }
+        });
+        clock.advance(chrono::Duration::hours(4));
+        tokio::task::yield_now().await;
+        assert!(!sleeper.is_finished());
+
+        clock.set(target);
+        assert!(sleeper.await.unwrap());
+        assert_eq!(clock.now(), target);
+    }
+}
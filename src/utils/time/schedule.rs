@@ -0,0 +1,325 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+
+use crate::utils::time::resolve_local_time;
+
+/// How far `Schedule::next_after` looks for an occurrence, enough for a 29th of February.
+const MAX_SEARCHED_DAYS: i64 = 366 * 8;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"
+];
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleError {
+    FieldCount(usize),
+    InvalidValue(&'static str, String),
+    OutOfRange(&'static str, u32),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::FieldCount(count) => {
+                write!(f, "expected 5 fields (minute hour day month weekday), got {}", count)
+            }
+            ScheduleError::InvalidValue(field, value) => write!(f, "invalid {} `{}`", field, value),
+            ScheduleError::OutOfRange(field, value) => write!(f, "{} {} is out of range", field, value),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+/// One field of the expression, as a bit set of the values it accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    bits: u64,
+    wildcard: bool,
+}
+
+impl Field {
+    fn contains(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+
+    fn parse(
+        text: &str,
+        name: &'static str,
+        min: u32,
+        max: u32,
+        names: &[&str],
+    ) -> Result<Self, ScheduleError> {
+        let parse_value = |value: &str| -> Result<u32, ScheduleError> {
+            let lowercase = value.to_lowercase();
+            let number = match names.iter().position(|n| *n == lowercase) {
+                Some(index) => index as u32 + min,
+                None => value.parse::<u32>()
+                    .map_err(|_| ScheduleError::InvalidValue(name, value.to_string()))?,
+            };
+
+            match number < min || number > max {
+                true => Err(ScheduleError::OutOfRange(name, number)),
+                false => Ok(number),
+            }
+        };
+
+        let mut bits = 0;
+        for part in text.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => {
+                    let step = step.parse::<u32>()
+                        .ok()
+                        .filter(|step| *step > 0)
+                        .ok_or_else(|| ScheduleError::InvalidValue(name, part.to_string()))?;
+                    (range, step)
+                }
+                None => (part, 1),
+            };
+
+            let (start, end) = match range.split_once('-') {
+                _ if range == "*" => (min, max),
+                Some((start, end)) => (parse_value(start)?, parse_value(end)?),
+                // "5/15" means from 5 to the end, every 15.
+                None if step > 1 => (parse_value(range)?, max),
+                None => {
+                    let value = parse_value(range)?;
+                    (value, value)
+                }
+            };
+
+            if start > end {
+                return Err(ScheduleError::InvalidValue(name, part.to_string()));
+            }
+            for value in (start..=end).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+
+        Ok(Self { bits, wildcard: text == "*" })
+    }
+}
+
+/// A cron-like schedule: `minute hour day-of-month month day-of-week`, e.g. `0 7 * * *` every
+/// day at 7h, `0 7 * * mon-fri` on weekdays, `30 9 1 * *` on the first day of every month.
+/// Fields accept `*`, lists, ranges and steps; months and days also accept their english
+/// abbreviations and Sunday is both 0 and 7. As with cron, a day matches if either the
+/// day of month or the day of week matches when both are restricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    expression: String,
+    minutes: Field,
+    hours: Field,
+    days_of_month: Field,
+    months: Field,
+    days_of_week: Field,
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Self, ScheduleError> {
+        let expression = expression.trim();
+        let expanded = match expression {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expression => expression,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(ScheduleError::FieldCount(fields.len()));
+        }
+
+        let mut days_of_week = Field::parse(fields[4], "day of week", 0, 7, &DAY_NAMES)?;
+        if days_of_week.contains(7) {
+            days_of_week.bits |= 1;
+        }
+
+        Ok(Self {
+            expression: expression.to_string(),
+            minutes: Field::parse(fields[0], "minute", 0, 59, &[])?,
+            hours: Field::parse(fields[1], "hour", 0, 23, &[])?,
+            days_of_month: Field::parse(fields[2], "day of month", 1, 31, &[])?,
+            months: Field::parse(fields[3], "month", 1, 12, &MONTH_NAMES)?,
+            days_of_week,
+        })
+    }
+
+    /// A schedule firing every day at `hour`:`minute`.
+    pub fn daily(hour: u32, minute: u32) -> Self {
+        Self::parse(&format!("{} {} * * *", minute, hour)).expect("Invalid daily schedule")
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if !self.months.contains(date.month()) {
+            return false;
+        }
+
+        let day_of_month = self.days_of_month.contains(date.day());
+        let day_of_week = self.days_of_week.contains(date.weekday().num_days_from_sunday());
+        match (self.days_of_month.wildcard, self.days_of_week.wildcard) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+
+    /// First occurrence strictly after `after`, evaluated on the wall clock of `timezone`.
+    /// Returns `None` for schedules that never fire, like the 30th of February.
+    pub fn next_after(&self, after: DateTime<Utc>, timezone: chrono_tz::Tz) -> Option<DateTime<Utc>> {
+        let first_date = after.with_timezone(&timezone).naive_local().date();
+
+        for offset in 0..MAX_SEARCHED_DAYS {
+            let date = first_date + Duration::days(offset);
+            if !self.matches_date(date) {
+                continue;
+            }
+
+            for hour in (0..24).filter(|hour| self.hours.contains(*hour)) {
+                for minute in (0..60).filter(|minute| self.minutes.contains(*minute)) {
+                    let occurrence = resolve_local_time(date.and_hms(hour, minute, 0), timezone);
+                    if occurrence > after {
+                        return Some(occurrence);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Self::parse(s) }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::utils::time::schedule::{Schedule, ScheduleError};
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Schedule::parse("0 7 * *"), Err(ScheduleError::FieldCount(4)));
+        assert_eq!(Schedule::parse("60 7 * * *"), Err(ScheduleError::OutOfRange("minute", 60)));
+        assert_eq!(Schedule::parse("0 7 0 * *"), Err(ScheduleError::OutOfRange("day of month", 0)));
+        assert!(matches!(Schedule::parse("0 seven * * *"), Err(ScheduleError::InvalidValue("hour", _))));
+        assert!(matches!(Schedule::parse("*/0 * * * *"), Err(ScheduleError::InvalidValue("minute", _))));
+        assert!(matches!(Schedule::parse("0 7 * * fri-mon"), Err(ScheduleError::InvalidValue(..))));
+        assert_eq!(Schedule::parse("@daily").unwrap().expression(), "@daily");
+    }
+
+    #[test]
+    fn test_daily() {
+        let paris = chrono_tz::Europe::Paris;
+        let schedule = Schedule::daily(7, 0);
+
+        assert_eq!(schedule, Schedule::parse("0 7 * * *").unwrap());
+        assert_eq!(
+            schedule.next_after(Utc.ymd(2022, 9, 10).and_hms(4, 30, 0), paris),
+            Some(Utc.ymd(2022, 9, 10).and_hms(5, 0, 0))
+        );
+        assert_eq!(
+            schedule.next_after(Utc.ymd(2022, 9, 10).and_hms(5, 0, 0), paris),
+            Some(Utc.ymd(2022, 9, 11).and_hms(5, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_weekdays() {
+        let utc = chrono_tz::UTC;
+        let schedule = Schedule::parse("0 7 * * MON-FRI").unwrap();
+
+        // 10/09/2022 is a Saturday.
+        assert_eq!(
+            schedule.next_after(Utc.ymd(2022, 9, 9).and_hms(8, 0, 0), utc),
+            Some(Utc.ymd(2022, 9, 12).and_hms(7, 0, 0))
+        );
+        assert_eq!(Schedule::parse("0 7 * * 1-5").unwrap().next_after(
+            Utc.ymd(2022, 9, 9).and_hms(8, 0, 0), utc
+        ), Some(Utc.ymd(2022, 9, 12).and_hms(7, 0, 0)));
+    }
+
+    #[test]
+    fn test_first_day_of_month_and_steps() {
+        let utc = chrono_tz::UTC;
+
+        assert_eq!(
+            Schedule::parse("30 9 1 * *").unwrap().next_after(Utc.ymd(2022, 9, 10).and_hms(0, 0, 0), utc),
+            Some(Utc.ymd(2022, 10, 1).and_hms(9, 30, 0))
+        );
+        assert_eq!(
+            Schedule::parse("*/20 8-9 * * *").unwrap().next_after(Utc.ymd(2022, 9, 10).and_hms(8, 45, 0), utc),
+            Some(Utc.ymd(2022, 9, 10).and_hms(9, 0, 0))
+        );
+        assert_eq!(
+            Schedule::parse("0 0 29 feb *").unwrap().next_after(Utc.ymd(2022, 9, 10).and_hms(0, 0, 0), utc),
+            Some(Utc.ymd(2024, 2, 29).and_hms(0, 0, 0))
+        );
+        assert_eq!(Schedule::parse("0 0 30 2 *").unwrap().next_after(Utc::now(), utc), None);
+    }
+
+    #[test]
+    fn test_day_of_month_or_day_of_week() {
+        let utc = chrono_tz::UTC;
+        // The 13th of every month and every Friday.
+        let schedule = Schedule::parse("0 0 13 * 5").unwrap();
+
+        assert_eq!(
+            schedule.next_after(Utc.ymd(2022, 9, 10).and_hms(0, 0, 0), utc),
+            Some(Utc.ymd(2022, 9, 13).and_hms(0, 0, 0))
+        );
+        assert_eq!(
+            schedule.next_after(Utc.ymd(2022, 9, 13).and_hms(0, 0, 0), utc),
+            Some(Utc.ymd(2022, 9, 16).and_hms(0, 0, 0))
+        );
+        // Sunday is both 0 and 7.
+        assert_eq!(Schedule::parse("0 0 * * 7").unwrap().next_after(
+            Utc.ymd(2022, 9, 10).and_hms(0, 0, 0), utc
+        ), Some(Utc.ymd(2022, 9, 11).and_hms(0, 0, 0)));
+    }
+
+    #[test]
+    fn test_timezone_and_dst() {
+        let paris = chrono_tz::Europe::Paris;
+        let ten_pm = Schedule::daily(22, 0);
+
+        // The clocks go forward on 27/03/2022 and back on 30/10/2022.
+        assert_eq!(
+            ten_pm.next_after(Utc.ymd(2022, 3, 26).and_hms(21, 0, 0), paris),
+            Some(Utc.ymd(2022, 3, 27).and_hms(20, 0, 0))
+        );
+        assert_eq!(
+            ten_pm.next_after(Utc.ymd(2022, 10, 29).and_hms(20, 0, 0), paris),
+            Some(Utc.ymd(2022, 10, 30).and_hms(21, 0, 0))
+        );
+
+        let schedule = Schedule::parse("30 2 * * *").unwrap();
+
+        // 02:30 does not exist on 27/03/2022 in Paris and happens twice on 30/10/2022.
+        assert_eq!(
+            schedule.next_after(Utc.ymd(2022, 3, 26).and_hms(12, 0, 0), paris),
+            Some(Utc.ymd(2022, 3, 27).and_hms(1, 0, 0))
+        );
+        let first = schedule.next_after(Utc.ymd(2022, 10, 29).and_hms(12, 0, 0), paris).unwrap();
+        assert_eq!(first, Utc.ymd(2022, 10, 30).and_hms(0, 30, 0));
+        assert_eq!(schedule.next_after(first, paris), Some(Utc.ymd(2022, 10, 31).and_hms(1, 30, 0)));
+    }
+}
@@ -0,0 +1,353 @@
+//! A local stand-in for the Discord REST API and gateway. The bot is pointed at it with
+//! `DiscordBot::set_api_url`, tests then dispatch gateway events and look at the requests
+//! the bot made.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_tungstenite::tungstenite::Message;
+use futures::{SinkExt, StreamExt};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+pub const BOT_ID: u64 = 1000;
+const API_PREFIX: &str = "/api/v10";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A REST request made by the bot, `path` is relative to the API root.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Value,
+}
+
+#[derive(Default)]
+struct State {
+    requests: Mutex<Vec<Request>>,
+    responses: Mutex<HashMap<(String, String), Value>>,
+    identify: Mutex<Option<Value>>,
+    session: Mutex<Option<mpsc::UnboundedSender<Value>>>,
+    sequence: AtomicU64,
+    message_ids: AtomicU64,
+}
+
+pub struct FakeDiscord {
+    pub api_url: String,
+    state: Arc<State>,
+}
+
+impl FakeDiscord {
+    pub async fn start() -> Self {
+        let state = Arc::new(State::default());
+
+        let gateway = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let gateway_url = format!("ws://{}", gateway.local_addr().unwrap());
+        tokio::spawn(serve_gateway(gateway, state.clone()));
+
+        let api = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let api_address: SocketAddr = api.local_addr().unwrap();
+        let service_state = state.clone();
+        let server = Server::from_tcp(api).unwrap().serve(make_service_fn(move |_| {
+            let state = service_state.clone();
+            let gateway_url = gateway_url.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle_request(state.clone(), gateway_url.clone(), request)
+                }))
+            }
+        }));
+        tokio::spawn(server);
+
+        Self { api_url: format!("http://{}", api_address), state }
+    }
+
+    /// Answers `method path` with `body` instead of a 404.
+    pub fn respond(&self, method: &str, path: &str, body: Value) {
+        self.state.responses.lock().unwrap().insert((method.to_string(), path.to_string()), body);
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Waits for the bot to identify on the gateway and returns the identify payload.
+    pub async fn wait_for_identify(&self) -> Value {
+        wait_for(|| self.state.identify.lock().unwrap().clone()).await
+    }
+
+    /// Waits for a request matching `method` and `path`.
+    pub async fn wait_for_request(&self, method: &str, path: &str) -> Request {
+        wait_for(|| self.requests().into_iter().find(|request| request.method == method && request.path == path))
+            .await
+    }
+
+    /// Waits for a message posted on `channel_id` with an embed titled `title`.
+    pub async fn wait_for_embed(&self, channel_id: u64, title: &str) -> Value {
+        let path = format!("/channels/{}/messages", channel_id);
+        wait_for(|| self.requests().into_iter()
+            .find(|request| request.method == "POST" && request.path == path && request.body["embeds"][0]["title"] == title)
+            .map(|request| request.body["embeds"][0].clone()))
+            .await
+    }
+
+    /// Sends a dispatch event to the bot on the current gateway session.
+    pub fn dispatch(&self, event: &str, data: Value) {
+        let sequence = self.state.sequence.fetch_add(1, Ordering::SeqCst) + 1;
+        let session = self.state.session.lock().unwrap().clone().expect("The bot is not connected");
+        session.send(json!({"op": 0, "t": event, "s": sequence, "d": data})).unwrap();
+    }
+
+    pub fn ready(&self, guild_ids: &[u64]) {
+        let mut bot_user = user(BOT_ID, "Mirai", true);
+        bot_user["mfa_enabled"] = json!(false);
+        bot_user["verified"] = json!(true);
+
+        self.dispatch("READY", json!({
+            "v": 10,
+            "user": bot_user,
+            "guilds": guild_ids.iter().map(|id| json!({"id": id.to_string(), "unavailable": true})).collect::<Vec<_>>(),
+            "session_id": "fake_session",
+            "application": {"id": BOT_ID.to_string(), "flags": 0},
+        }));
+    }
+}
+
+async fn wait_for<T, F>(mut check: F) -> T
+    where F: FnMut() -> Option<T> {
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            if let Some(value) = check() {
+                return value;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("Timed out waiting for the bot")
+}
+
+async fn handle_request(
+    state: Arc<State>,
+    gateway_url: String,
+    request: hyper::Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = request.method().to_string();
+    let path = request.uri().path().trim_start_matches(API_PREFIX).to_string();
+    let bytes = hyper::body::to_bytes(request.into_body()).await.unwrap_or_default();
+    let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    state.requests.lock().unwrap().push(Request { method: method.clone(), path: path.clone(), body: body.clone() });
+
+    let response = state.responses.lock().unwrap().get(&(method.clone(), path.clone())).cloned();
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let response = match (response, method.as_str(), segments.as_slice()) {
+        (Some(response), _, _) => Some(response),
+        (None, "GET", ["gateway"]) => Some(json!({"url": gateway_url})),
+        (None, "POST", ["channels", channel_id, "messages"]) => {
+            let id = 5000 + state.message_ids.fetch_add(1, Ordering::SeqCst);
+            Some(message(id, channel_id.parse().unwrap(), None, user(BOT_ID, "Mirai", true), ""))
+        }
+        (None, "GET", ["applications", _, "commands"] | ["applications", _, "guilds", _, "commands"]) => {
+            Some(json!([]))
+        }
+        (None, "POST", ["applications", _, "commands"] | ["applications", _, "guilds", _, "commands"]) => {
+            let id = 6000 + state.message_ids.fetch_add(1, Ordering::SeqCst);
+            Some(command(id, &body))
+        }
+        (None, "PATCH", ["applications", _, "commands", id] | ["applications", _, "guilds", _, "commands", id]) => {
+            Some(command(id.parse().unwrap(), &body))
+        }
+        (None, "DELETE", _) | (None, "POST", ["interactions", _, _, "callback"]) => {
+            let mut no_content = Response::new(Body::empty());
+            *no_content.status_mut() = StatusCode::NO_CONTENT;
+            return Ok(no_content);
+        }
+        _ => None,
+    };
+
+    Ok(match response {
+        Some(response) => Response::new(Body::from(response.to_string())),
+        None => {
+            let mut not_found = Response::new(Body::from(json!({"message": "Unknown", "code": 0}).to_string()));
+            *not_found.status_mut() = StatusCode::NOT_FOUND;
+            not_found
+        }
+    })
+}
+
+/// Accepts the bot's gateway connections: says hello, acknowledges heartbeats, records the
+/// identify payload and forwards the dispatched events.
+async fn serve_gateway(listener: TcpListener, state: Arc<State>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        let mut socket = match async_tungstenite::tokio::accept_async(stream).await {
+            Ok(socket) => socket,
+            Err(_) => continue,
+        };
+        let (sender, mut events) = mpsc::unbounded_channel();
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            let hello = json!({"op": 10, "d": {"heartbeat_interval": 45000}});
+            if socket.send(Message::Text(hello.to_string())).await.is_err() {
+                return;
+            }
+
+            loop {
+                tokio::select! {
+                    message = socket.next() => {
+                        let payload: Value = match message {
+                            Some(Ok(Message::Text(text))) => serde_json::from_str(&text).unwrap_or(Value::Null),
+                            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                            Some(Ok(_)) => continue,
+                        };
+                        match payload["op"].as_u64() {
+                            Some(1) => {
+                                let ack = json!({"op": 11});
+                                if socket.send(Message::Text(ack.to_string())).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Some(2) => {
+                                *state.session.lock().unwrap() = Some(sender.clone());
+                                *state.identify.lock().unwrap() = Some(payload["d"].clone());
+                            }
+                            _ => {}
+                        }
+                    }
+                    event = events.recv() => match event {
+                        Some(event) => {
+                            if socket.send(Message::Text(event.to_string())).await.is_err() {
+                                break;
+                            }
+                        }
+                        None => break,
+                    },
+                }
+            }
+        });
+    }
+}
+
+pub fn user(id: u64, name: &str, bot: bool) -> Value {
+    json!({"id": id.to_string(), "username": name, "discriminator": "0001", "avatar": null, "bot": bot})
+}
+
+pub fn member(guild_id: u64, user: Value) -> Value {
+    json!({
+        "guild_id": guild_id.to_string(),
+        "user": user,
+        "roles": [],
+        "joined_at": "2022-10-01T20:30:00Z",
+        "deaf": false,
+        "mute": false,
+    })
+}
+
+/// A guild with a single text channel, which is also its system channel.
+pub fn guild(id: u64, owner_id: u64, channel_id: u64) -> Value {
+    json!({
+        "id": id.to_string(),
+        "name": "Académie du Pic de l'Espoir",
+        "icon": null,
+        "splash": null,
+        "owner_id": owner_id.to_string(),
+        "afk_timeout": 300,
+        "verification_level": 0,
+        "default_message_notifications": 0,
+        "explicit_content_filter": 0,
+        "roles": [{
+            "id": id.to_string(),
+            "name": "@everyone",
+            "color": 0,
+            "hoist": false,
+            "position": 0,
+            "permissions": "104324673",
+            "managed": false,
+            "mentionable": false,
+        }],
+        "emojis": [],
+        "features": [],
+        "mfa_level": 0,
+        "system_channel_id": channel_id.to_string(),
+        "system_channel_flags": 0,
+        "preferred_locale": "fr",
+        "nsfw_level": 0,
+        "premium_tier": 0,
+        "stickers": [],
+        "joined_at": "2022-10-01T00:00:00Z",
+        "large": false,
+        "member_count": 1,
+        "members": [member(id, user(owner_id, "Makoto", false))],
+        "presences": [],
+        "voice_states": [],
+        "threads": [],
+        "channels": [{
+            "id": channel_id.to_string(),
+            "guild_id": id.to_string(),
+            "type": 0,
+            "name": "general",
+            "position": 0,
+            "permission_overwrites": [],
+            "nsfw": false,
+        }],
+    })
+}
+
+/// A registered slash command made from the body the bot sent.
+pub fn command(id: u64, body: &Value) -> Value {
+    json!({
+        "id": id.to_string(),
+        "type": 1,
+        "application_id": BOT_ID.to_string(),
+        "name": body["name"],
+        "description": body["description"],
+        "options": body.get("options").cloned().unwrap_or_else(|| json!([])),
+        "default_member_permissions": null,
+        "version": "1",
+    })
+}
+
+/// A slash command run by `author` in the guild channel.
+pub fn interaction(id: u64, channel_id: u64, guild_id: u64, author: Value, name: &str, options: Value) -> Value {
+    json!({
+        "id": id.to_string(),
+        "application_id": BOT_ID.to_string(),
+        "type": 2,
+        "data": {"id": "6000", "name": name, "type": 1, "options": options},
+        "guild_id": guild_id.to_string(),
+        "channel_id": channel_id.to_string(),
+        "member": member(guild_id, author),
+        "token": "interaction_token",
+        "version": 1,
+        "locale": "fr",
+        "guild_locale": "fr",
+    })
+}
+
+pub fn message(id: u64, channel_id: u64, guild_id: Option<u64>, author: Value, content: &str) -> Value {
+    let mut message = json!({
+        "id": id.to_string(),
+        "channel_id": channel_id.to_string(),
+        "author": author,
+        "content": content,
+        "timestamp": "2022-10-01T20:30:00Z",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "attachments": [],
+        "embeds": [],
+        "pinned": false,
+        "type": 0,
+    });
+    if let Some(guild_id) = guild_id {
+        message["guild_id"] = json!(guild_id.to_string());
+    }
+    message
+}
@@ -1,4 +1,289 @@
-#[test]
-fn test_bot() {
-    assert_eq!(1, 1);
-}
\ No newline at end of file
+mod fake_discord;
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use r_playground_er::bot::DiscordBot;
+use r_playground_er::flashcards::FlashcardStore;
+use r_playground_er::shutdown::graceful_shutdown;
+use serde_json::json;
+use serenity::client::bridge::gateway::ShardManager;
+use serenity::model::id::UserId;
+use serenity::prelude::{Mutex, RwLock, TypeMap};
+use tokio::task::JoinHandle;
+
+use crate::fake_discord::{BOT_ID, FakeDiscord, command, guild, interaction, member, message, user};
+
+const GUILD_ID: u64 = 1;
+const CHANNEL_ID: u64 = 10;
+const OWNER_ID: u64 = 2;
+
+struct RunningBot {
+    discord: FakeDiscord,
+    data: Arc<RwLock<TypeMap>>,
+    shard_manager: Arc<Mutex<ShardManager>>,
+    client: JoinHandle<()>,
+}
+
+/// Connects a bot working in every guild to a fresh fake Discord.
+async fn start_bot() -> RunningBot {
+    let discord = FakeDiscord::start().await;
+    let mut bot = DiscordBot::new()
+        .set_token("fake_token")
+        .set_api_url(&discord.api_url)
+        .set_guilds(Vec::new());
+    assert!(bot.setup_client().await);
+
+    let mut client = bot.client.take().unwrap();
+    let data = client.data.clone();
+    let shard_manager = client.shard_manager.clone();
+    let client = tokio::spawn(async move {
+        client.start().await.unwrap();
+    });
+
+    let identify = discord.wait_for_identify().await;
+    assert_eq!(identify["token"], "Bot fake_token");
+    RunningBot { discord, data, shard_manager, client }
+}
+
+#[tokio::test]
+async fn test_ready_fetches_guilds() {
+    let RunningBot { discord, .. } = start_bot().await;
+    discord.respond("GET", "/guilds/1", guild(GUILD_ID, OWNER_ID, CHANNEL_ID));
+
+    discord.ready(&[GUILD_ID]);
+    discord.wait_for_request("GET", "/guilds/1").await;
+}
+
+#[tokio::test]
+async fn test_member_join_sends_welcome() {
+    let RunningBot { discord, .. } = start_bot().await;
+    discord.ready(&[]);
+    discord.dispatch("GUILD_CREATE", guild(GUILD_ID, OWNER_ID, CHANNEL_ID));
+    discord.dispatch("GUILD_MEMBER_ADD", member(GUILD_ID, user(3, "Nagito", false)));
+
+    let request = discord.wait_for_request("POST", "/channels/10/messages").await;
+    let embed = &request.body["embeds"][0];
+    assert_eq!(embed["title"], "Bienvenue");
+    assert_eq!(embed["author"]["name"], "Nagito");
+    assert_eq!(
+        embed["footer"]["text"],
+        "Nagito nous rejoint en cette date mémorable du 01/10/2022 à 22h30m00s"
+    );
+}
+
+#[tokio::test]
+async fn test_settings_command() {
+    let RunningBot { discord, .. } = start_bot().await;
+    discord.ready(&[]);
+    discord.dispatch("GUILD_CREATE", guild(GUILD_ID, OWNER_ID, CHANNEL_ID));
+    discord.dispatch("MESSAGE_CREATE", message(
+        20, CHANNEL_ID, Some(GUILD_ID), user(OWNER_ID, "Makoto", false), "/settings show"
+    ));
+
+    let request = discord.wait_for_request("POST", "/channels/10/messages").await;
+    let embed = &request.body["embeds"][0];
+    assert_eq!(embed["title"], "Paramètres");
+    assert!(embed["description"].as_str().unwrap().contains("`welcome`"));
+    assert_eq!(request.body["message_reference"], json!({"message_id": "20", "channel_id": "10", "guild_id": "1"}));
+}
+
+#[tokio::test]
+async fn test_settings_channel_of_another_guild() {
+    let RunningBot { discord, .. } = start_bot().await;
+    discord.ready(&[]);
+    discord.dispatch("GUILD_CREATE", guild(GUILD_ID + 1, OWNER_ID + 1, 30));
+    let embed = run_command(&discord, "/settings channel welcome <#30>").await;
+
+    assert_eq!(embed["title"], "Paramètres");
+    assert_eq!(embed["description"], "Ce salon n'est pas un salon de ce serveur.");
+}
+
+#[tokio::test]
+async fn test_night_channel_of_another_guild() {
+    let RunningBot { discord, .. } = start_bot().await;
+    discord.ready(&[]);
+    discord.dispatch("GUILD_CREATE", guild(GUILD_ID + 1, OWNER_ID + 1, 30));
+    let embed = run_command(&discord, "/settings night add <#30>").await;
+
+    assert_eq!(embed["description"], "Ce salon n'est pas un salon de ce serveur.");
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown() {
+    let RunningBot { discord, data, shard_manager, client } = start_bot().await;
+    discord.ready(&[]);
+
+    graceful_shutdown(data, shard_manager).await;
+    tokio::time::timeout(std::time::Duration::from_secs(10), client).await
+        .expect("The client did not stop")
+        .unwrap();
+}
+
+/// Sends `content` as the guild owner and returns the embed the bot answered with.
+async fn run_command(discord: &FakeDiscord, content: &str) -> serde_json::Value {
+    discord.ready(&[]);
+    discord.dispatch("GUILD_CREATE", guild(GUILD_ID, OWNER_ID, CHANNEL_ID));
+    discord.dispatch("MESSAGE_CREATE", message(
+        20, CHANNEL_ID, Some(GUILD_ID), user(OWNER_ID, "Makoto", false), content
+    ));
+
+    let request = discord.wait_for_request("POST", "/channels/10/messages").await;
+    request.body["embeds"][0].clone()
+}
+
+#[tokio::test]
+async fn test_general_command() {
+    let RunningBot { discord, .. } = start_bot().await;
+    let embed = run_command(&discord, "/ping").await;
+
+    assert_eq!(embed["title"], "Ping");
+    assert_eq!(embed["description"], "Pong !");
+}
+
+#[tokio::test]
+async fn test_unknown_command() {
+    let RunningBot { discord, .. } = start_bot().await;
+    let embed = run_command(&discord, "/pnig").await;
+
+    assert_eq!(embed["title"], "Erreur");
+    assert_eq!(embed["description"], "`pnig` n'est pas une commande. Tu voulais dire `/ping` ? Tape `/help` pour voir les commandes.");
+}
+
+#[tokio::test]
+async fn test_missing_arguments() {
+    let RunningBot { discord, .. } = start_bot().await;
+    let embed = run_command(&discord, "/clear").await;
+
+    assert_eq!(embed["title"], "Erreur");
+    assert_eq!(
+        embed["description"],
+        "Il manque des arguments : 1 attendu(s), 0 donné(s). Tape `/help clear` pour voir comment l'utiliser."
+    );
+}
+
+#[tokio::test]
+async fn test_help() {
+    let RunningBot { discord, .. } = start_bot().await;
+    discord.ready(&[]);
+    discord.dispatch("GUILD_CREATE", guild(GUILD_ID, OWNER_ID, CHANNEL_ID));
+    discord.dispatch("MESSAGE_CREATE", message(
+        20, CHANNEL_ID, Some(GUILD_ID), user(OWNER_ID, "Makoto", false), "/help"
+    ));
+    let request = discord.wait_for_request("POST", "/channels/10/messages").await;
+
+    let embed = &request.body["embeds"][0];
+    assert_eq!(embed["title"], "Aide — Général");
+    assert!(embed["description"].as_str().unwrap().contains("`/ping` — Vérifie que le bot répond."));
+    // The owner of the guild is not an admin of the bot, the admin commands are left out.
+    assert_eq!(embed["footer"]["text"], "Tape /help <commande> pour le détail d'une commande. · Page 1/8");
+    assert_eq!(request.body["components"][0]["components"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_command_help() {
+    let RunningBot { discord, .. } = start_bot().await;
+    let embed = run_command(&discord, "/help dice").await;
+
+    assert_eq!(embed["title"], "/roll");
+    assert_eq!(embed["description"], "Lance des dés.");
+    assert_eq!(embed["fields"][0], json!({"inline": true, "name": "Utilisation", "value": "`/roll [dés]`"}));
+    assert_eq!(embed["fields"][3], json!({
+        "inline": true, "name": "Délai", "value": "3 seconde(s) entre deux utilisations"
+    }));
+
+    let RunningBot { discord, .. } = start_bot().await;
+    let embed = run_command(&discord, "/help admin add").await;
+    assert_eq!(embed["description"], "La commande `admin add` n'existe pas. Tape `/help` pour voir les commandes.");
+}
+
+#[tokio::test]
+async fn test_flashcard_review() {
+    let RunningBot { discord, data, .. } = start_bot().await;
+    let store = data.read().await.get::<FlashcardStore>().unwrap().clone();
+    let deck = store.create_deck(UserId(OWNER_ID), "Chimie", Utc::now()).unwrap();
+    store.add_card(&deck, "H2O", "Eau").unwrap();
+
+    let embed = run_command(&discord, "/cards review chimie").await;
+    assert_eq!(embed["title"], "Révision — Chimie");
+    assert_eq!(embed["fields"], json!([{"inline": false, "name": "Question", "value": "H2O"}]));
+    assert_eq!(embed["footer"]["text"], "Fiche 1/1");
+}
+
+#[tokio::test]
+async fn test_quiz() {
+    let RunningBot { discord, data, .. } = start_bot().await;
+    let store = data.read().await.get::<FlashcardStore>().unwrap().clone();
+    let deck = store.create_deck(UserId(OWNER_ID), "Chimie", Utc::now()).unwrap();
+    store.add_card(&deck, "H2O", "Eau").unwrap();
+
+    let embed = run_command(&discord, "/quiz deck Chimie 1").await;
+    assert_eq!(embed["title"], "Quiz — Chimie — Question 1/1");
+    assert_eq!(embed["description"], "H2O");
+
+    discord.dispatch("MESSAGE_CREATE", message(
+        21, CHANNEL_ID, Some(GUILD_ID), user(OWNER_ID + 1, "Kyoko", false), "de l'eau ?"
+    ));
+    discord.dispatch("MESSAGE_CREATE", message(
+        22, CHANNEL_ID, Some(GUILD_ID), user(OWNER_ID + 2, "Byakuya", false), "l'eau"
+    ));
+    let ranking = discord.wait_for_embed(CHANNEL_ID, "Quiz — Chimie — Classement").await;
+    assert_eq!(ranking["description"], format!("🥇 <@{}> — 1 point(s)", OWNER_ID + 2));
+}
+
+#[tokio::test]
+async fn test_slash_commands_sync() {
+    let RunningBot { discord, .. } = start_bot().await;
+    let commands_path = format!("/applications/{}/commands", BOT_ID);
+    discord.respond("GET", &commands_path, json!([
+        command(1, &json!({"name": "ping", "description": "Vérifie que le bot répond."})),
+        command(2, &json!({"name": "roll", "description": "Lance des dés."})),
+        command(3, &json!({"name": "flip", "description": "Retourne une table."})),
+    ]));
+
+    discord.ready(&[]);
+    discord.wait_for_request("DELETE", &format!("{}/3", commands_path)).await;
+
+    let requests = discord.requests();
+    let created: Vec<&str> = requests.iter()
+        .filter(|request| request.method == "POST" && request.path == commands_path)
+        .map(|request| request.body["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(created, vec!["info", "time", "choose", "coin", "help"]);
+
+    let updated: Vec<&crate::fake_discord::Request> = requests.iter()
+        .filter(|request| request.method == "PATCH")
+        .collect();
+    assert_eq!(updated.len(), 1);
+    assert_eq!(updated[0].path, format!("{}/2", commands_path));
+    assert_eq!(updated[0].body["options"][0]["name"], "dice");
+}
+
+#[tokio::test]
+async fn test_slash_command() {
+    let RunningBot { discord, .. } = start_bot().await;
+    discord.ready(&[]);
+    discord.dispatch("GUILD_CREATE", guild(GUILD_ID, OWNER_ID, CHANNEL_ID));
+    discord.dispatch("INTERACTION_CREATE", interaction(
+        30, CHANNEL_ID, GUILD_ID, user(OWNER_ID, "Makoto", false), "choose",
+        json!([{"name": "choices", "type": 3, "value": "espoir | espoir"}]),
+    ));
+
+    let request = discord.wait_for_request("POST", "/interactions/30/interaction_token/callback").await;
+    assert_eq!(request.body["type"], 4);
+    assert_eq!(request.body["data"]["embeds"][0]["title"], "Choix");
+    assert_eq!(request.body["data"]["embeds"][0]["description"], "Je choisis… **espoir** !");
+}
+
+#[tokio::test]
+async fn test_slash_command_missing_option() {
+    let RunningBot { discord, .. } = start_bot().await;
+    discord.ready(&[]);
+    discord.dispatch("INTERACTION_CREATE", interaction(
+        31, CHANNEL_ID, GUILD_ID, user(OWNER_ID, "Makoto", false), "choose", json!([]),
+    ));
+
+    let request = discord.wait_for_request("POST", "/interactions/31/interaction_token/callback").await;
+    assert_eq!(request.body["data"]["embeds"][0]["description"], "L'option `choices` est obligatoire.");
+    assert_eq!(request.body["data"]["flags"], 64);
+}